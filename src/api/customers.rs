@@ -17,13 +17,23 @@ use crate::{
     },
 };
 
-/** `GET /customers/<id>` */
-#[get("/<id>")]
-pub async fn get(id: CustomerId, app: &State<App>) -> Result<Json<CustomerWithOrders>, Error> {
+/** `GET /customers/<id>?<include_gifts>` */
+#[get("/<id>?<include_gifts>")]
+pub async fn get(
+    id: CustomerId,
+    include_gifts: Option<bool>,
+    app: &State<App>,
+) -> Result<Json<CustomerWithOrders>, Error> {
     app.transaction(|app| async move {
         let query = app.get_customer_with_orders_query();
 
-        match query.execute(GetCustomerWithOrders { id }).await? {
+        match query
+            .execute(GetCustomerWithOrders {
+                id,
+                include_gifts: include_gifts.unwrap_or(false),
+            })
+            .await?
+        {
             Some(customer) => Ok(Json(customer)),
             None => Err(Error::NotFound(error::msg("customer not found"))),
         }
@@ -26,6 +26,8 @@ pub enum Error {
     NotFound(#[source] Box<dyn error::Error + Send + Sync>),
     #[error("the user input was invalid")]
     BadRequest(#[source] Box<dyn error::Error + Send + Sync>),
+    #[error("an entity was changed concurrently")]
+    Conflict(#[source] Box<dyn error::Error + Send + Sync>),
     #[error("an unexpected error occurred")]
     Other(#[source] Box<dyn error::Error + Send + Sync>),
 }
@@ -36,25 +38,34 @@ pub fn msg(err: impl fmt::Display) -> Box<dyn error::Error + Send + Sync> {
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
     fn respond_to(self, _: &Request) -> response::Result<'o> {
-        let (status, err) = match self {
+        // The full error is only logged, callers just get a message that's safe to show them
+        let (status, msg) = match self {
             Error::NotFound(err) => {
                 debug!("request failed with {:?}", err);
 
-                (http::Status::NotFound, err)
+                (http::Status::NotFound, client_message(&*err))
             }
             Error::BadRequest(err) => {
                 debug!("request failed with {:?}", err);
 
-                (http::Status::BadRequest, err)
+                (http::Status::BadRequest, client_message(&*err))
+            }
+            Error::Conflict(err) => {
+                debug!("request failed with {:?}", err);
+
+                (http::Status::Conflict, client_message(&*err))
             }
             Error::Other(err) => {
                 error!("request failed with {:?}", err);
 
-                (http::Status::InternalServerError, err)
+                (
+                    http::Status::InternalServerError,
+                    INTERNAL_ERROR_MESSAGE.to_owned(),
+                )
             }
         };
 
-        let err = serde_json::to_vec(&SerializeError { msg: &err }).unwrap_or_else(|_| Vec::new());
+        let err = serde_json::to_vec(&SerializeError { msg: &msg }).unwrap_or_else(|_| Vec::new());
 
         Response::build()
             .sized_body(None::<usize>, Cursor::new(err))
@@ -64,13 +75,25 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
     }
 }
 
+const INTERNAL_ERROR_MESSAGE: &str = "an internal error occurred";
+
+/** Get the message for an error caused by the caller, without any context it was given. */
+fn client_message(err: &(dyn error::Error + Send + Sync + 'static)) -> String {
+    match err.downcast_ref::<domain::Error>() {
+        Some(err) => err.client_message(),
+        None => err.to_string(),
+    }
+}
+
 impl From<domain::Error> for Error {
     fn from(err: domain::Error) -> Self {
-        use crate::domain::ErrorKind::*;
+        use crate::domain::StatusHint::*;
 
-        match err.split() {
-            (BadInput, err) => Error::BadRequest(err),
-            (_, err) => Error::Other(err),
+        match err.status_hint() {
+            BadRequest => Error::BadRequest(Box::new(err)),
+            NotFound => Error::NotFound(Box::new(err)),
+            Conflict => Error::Conflict(Box::new(err)),
+            Internal => Error::Other(Box::new(err)),
         }
     }
 }
@@ -97,7 +120,7 @@ where
 #[catch(500)]
 pub(super) fn internal_error(_: &Request) -> content::RawJson<Vec<u8>> {
     let err = serde_json::to_vec(&SerializeError {
-        msg: &"an internal error occurred",
+        msg: &INTERNAL_ERROR_MESSAGE,
     })
     .unwrap_or_else(|_| Vec::new());
 
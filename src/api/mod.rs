@@ -2,7 +2,10 @@
 Rocket app configuration.
 */
 
-use rocket::Build;
+use rocket::{
+    fairing::AdHoc,
+    Build,
+};
 
 use crate::domain::App;
 
@@ -21,11 +24,23 @@ The rocket can either be launched or passed to a local client for testing.
 pub fn init() -> rocket::Rocket<Build> {
     info!("starting up");
 
+    let app = App::default();
+
+    // Fail on startup if the app is misconfigured rather than on the first request
+    app.self_check().expect("the app is misconfigured");
+
     rocket::build()
-        .manage(App::default())
+        .manage(app)
         .mount(
             "/products",
-            routes![products::get, products::create, products::set_title],
+            routes![
+                products::get,
+                products::create,
+                products::set_title,
+                products::update,
+                products::deactivate,
+                products::adjust_prices
+            ],
         )
         .mount(
             "/orders",
@@ -33,4 +48,13 @@ pub fn init() -> rocket::Rocket<Build> {
         )
         .mount("/customers", routes![customers::get, customers::create])
         .register("/", catchers![error::not_found, error::internal_error])
+        .attach(AdHoc::on_shutdown("flush stores", |rocket| {
+            Box::pin(async move {
+                if let Some(app) = rocket.state::<App>() {
+                    if let Err(err) = app.shutdown() {
+                        error!("failed to shut down cleanly: {}", err);
+                    }
+                }
+            })
+        }))
 }
\ No newline at end of file
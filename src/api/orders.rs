@@ -36,6 +36,12 @@ pub async fn get(id: OrderId, app: &State<App>) -> Result<Json<OrderWithProducts
 #[derive(Deserialize)]
 pub struct Create {
     pub customer: CustomerId,
+    /** A token that stops the same order from being created twice if the request is repeated. */
+    #[serde(default)]
+    pub client_token: Option<String>,
+    /** Whether the customer is a guest that hasn't been stored, like the owner of an anonymous cart. */
+    #[serde(default)]
+    pub guest: bool,
 }
 
 /** `PUT /orders` */
@@ -45,12 +51,12 @@ pub async fn create(data: Json<Create>, app: &State<App>) -> Result<Created<Json
         let id = app.order_id();
         let command = app.create_order_command();
 
-        let id = id.get()?;
-
-        command
+        let id = command
             .execute(CreateOrder {
-                id,
+                id: id.get()?,
                 customer_id: data.customer,
+                client_token: data.client_token.clone(),
+                guest: data.guest,
             })
             .await?;
 
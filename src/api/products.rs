@@ -24,13 +24,23 @@ pub struct Get {
     pub price: Currency,
 }
 
-/** `GET /products/<id>` */
-#[get("/<id>")]
-pub async fn get(id: ProductId, app: &State<App>) -> Result<Json<Get>, Error> {
+/** `GET /products/<id>?<include_inactive>` */
+#[get("/<id>?<include_inactive>")]
+pub async fn get(
+    id: ProductId,
+    include_inactive: Option<bool>,
+    app: &State<App>,
+) -> Result<Json<Get>, Error> {
     app.transaction(|app| async move {
         let query = app.get_product_query();
 
-        match query.execute(GetProduct { id }).await? {
+        match query
+            .execute(GetProduct {
+                id,
+                include_inactive: include_inactive.unwrap_or(false),
+            })
+            .await?
+        {
             Some(product) => {
                 let product = product.into_data();
 
@@ -90,4 +100,72 @@ pub async fn set_title(id: ProductId, title: String, app: &State<App>) -> Result
         Ok(())
     })
     .await
+}
+
+#[derive(Deserialize)]
+pub struct Update {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub price: Option<Currency>,
+}
+
+/** `PATCH /products/<id>` */
+#[patch("/<id>", format = "application/json", data = "<data>")]
+pub async fn update(id: ProductId, data: Json<Update>, app: &State<App>) -> Result<(), Error> {
+    app.transaction(|app| async move {
+        let command = app.update_product_command();
+
+        command
+            .execute(UpdateProduct {
+                id,
+                title: data.0.title,
+                price: data.0.price,
+            })
+            .await?;
+
+        Ok(())
+    })
+    .await
+}
+
+/** `POST /products/<id>/deactivate` */
+#[post("/<id>/deactivate")]
+pub async fn deactivate(id: ProductId, app: &State<App>) -> Result<(), Error> {
+    app.transaction(|app| async move {
+        let command = app.deactivate_product_command();
+
+        command.execute(DeactivateProduct { id }).await?;
+
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct AdjustPrices {
+    pub percent: i32,
+    #[serde(default)]
+    pub filter: Option<ProductFilter>,
+}
+
+/** `POST /products/prices` */
+#[post("/prices", format = "application/json", data = "<data>")]
+pub async fn adjust_prices(
+    data: Json<AdjustPrices>,
+    app: &State<App>,
+) -> Result<Json<usize>, Error> {
+    app.transaction(|app| async move {
+        let command = app.adjust_all_prices_command();
+
+        let updated = command
+            .execute(AdjustAllPrices {
+                percent: data.0.percent,
+                filter: data.0.filter,
+            })
+            .await?;
+
+        Ok(Json(updated))
+    })
+    .await
 }
\ No newline at end of file
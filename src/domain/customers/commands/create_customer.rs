@@ -2,6 +2,7 @@
 
 use crate::domain::{
     customers::*,
+    events::*,
     infra::*,
     Error,
 };
@@ -20,11 +21,15 @@ async fn execute(
     command: CreateCustomer,
     transaction: ActiveTransaction,
     store: impl CustomerStore,
+    events: Events,
 ) -> Result<(), Error> {
     debug!("creating customer `{}`", command.id);
 
     let customer = {
-        if store.get_customer(command.id)?.is_some() {
+        if store
+            .get_customer(transaction.read(), command.id)?
+            .is_some()
+        {
             err!("customer `{}` already exists", command.id)?
         } else {
             Customer::new(command.id)?
@@ -33,6 +38,9 @@ async fn execute(
 
     store.set_customer(transaction.get(), customer)?;
 
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::CustomerCreated { id }));
+
     info!("customer `{}` created", command.id);
 
     Ok(())
@@ -44,8 +52,13 @@ impl Resolver {
         self.command(|resolver, command: CreateCustomer| async move {
             let store = resolver.customer_store();
             let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
+
+            let context = format!("create_customer customer={}", command.id);
 
-            execute(command, active_transaction, store).await
+            execute(command, active_transaction, store, events)
+                .await
+                .map_err(|err| err.context(context))
         })
     }
 }
@@ -64,12 +77,22 @@ mod tests {
             id: CustomerId::new(),
         };
 
-        execute(create.clone(), ActiveTransaction::none(), &store)
-            .await
-            .unwrap();
-
-        assert!(execute(create, ActiveTransaction::none(), &store)
-            .await
-            .is_err());
+        execute(
+            create.clone(),
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        assert!(execute(
+            create,
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new()
+        )
+        .await
+        .is_err());
     }
 }
\ No newline at end of file
@@ -11,4 +11,4 @@ pub use self::{
     queries::*,
 };
 
-pub(self) use self::model::store::CustomerStore;
\ No newline at end of file
+pub use self::model::store::CustomerStore;
\ No newline at end of file
@@ -23,6 +23,7 @@ pub struct CustomerData {
 }
 
 /** A customer. */
+#[derive(Clone)]
 pub struct Customer {
     data: CustomerData,
 }
@@ -40,6 +41,15 @@ impl Customer {
         self.data
     }
 
+    /**
+    Build a customer from data that came from outside the domain.
+
+    There's nothing on a customer to check yet besides its id.
+    */
+    pub(in crate::domain) fn checked(data: CustomerData) -> Result<Self, Error> {
+        Ok(Customer::from_data(data))
+    }
+
     pub fn new(id: impl IdProvider<CustomerData>) -> Result<Self, Error> {
         let id = id.get()?;
 
@@ -49,6 +59,15 @@ impl Customer {
             _private: (),
         }))
     }
+
+    /**
+    Create a guest customer, like the owner of an anonymous cart.
+
+    Guests aren't stored, so orders can be created for them without a customer record.
+    */
+    pub fn guest(id: impl IdProvider<CustomerData>) -> Result<Self, Error> {
+        Customer::new(id)
+    }
 }
 
 impl Entity for Customer {
@@ -56,10 +75,72 @@ impl Entity for Customer {
     type Version = CustomerVersion;
     type Data = CustomerData;
     type Error = Error;
+
+    fn id(&self) -> CustomerId {
+        self.data.id
+    }
+
+    fn version(&self) -> CustomerVersion {
+        self.data.version
+    }
+}
+
+impl InMemoryEntity for Customer {
+    fn from_data(data: CustomerData) -> Self {
+        Customer::from_data(data)
+    }
+
+    fn into_data_with_version(self, version: CustomerVersion) -> CustomerData {
+        CustomerData {
+            version,
+            ..self.data
+        }
+    }
+}
+
+impl IdTag for CustomerData {
+    const TAG: &'static str = "customer";
 }
 
 impl Resolver {
     pub fn customer_id(&self) -> impl IdProvider<CustomerData> {
-        NextId::<CustomerData>::new()
+        self.resolve(&self.customers_resolver.customer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        store::CustomerStore,
+        *,
+    };
+
+    use crate::domain::orders::{
+        NextOrderId,
+        Order,
+    };
+
+    #[test]
+    fn entity_accessors_match_data() {
+        let customer = Customer::new(NextCustomerId::new()).unwrap();
+
+        assert_eq!(customer.to_data().id, customer.id());
+        assert_eq!(customer.to_data().version, customer.version());
+    }
+
+    #[test]
+    fn guest_can_place_an_order_without_being_stored() {
+        let store = store::in_memory_store(Default::default());
+
+        let guest = Customer::guest(NextCustomerId::new()).unwrap();
+
+        assert!(store
+            .get_customer(ActiveTransaction::auto_commit().read(), guest.id())
+            .unwrap()
+            .is_none());
+
+        let order = Order::new(NextOrderId::new(), &guest).unwrap();
+
+        assert_eq!(guest.id(), order.to_data().0.customer_id);
     }
 }
\ No newline at end of file
@@ -1,8 +1,18 @@
 /*! Persistent customer storage. */
 
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+};
+
 use crate::{
     domain::{
         customers::*,
+        infra::{
+            Flush,
+            InMemoryRepository,
+            Repository,
+        },
         Error,
     },
     store::*,
@@ -10,42 +20,48 @@ use crate::{
 
 /** A place to persist and fetch customers. */
 #[auto_impl(&, Arc)]
-pub(in crate::domain) trait CustomerStore {
-    fn get_customer(&self, id: CustomerId) -> Result<Option<Customer>, Error>;
+pub trait CustomerStore: Flush {
+    /** Get a customer as it's seen by a transaction, including its own uncommitted changes. */
+    fn get_customer(
+        &self,
+        transaction: &Transaction,
+        id: CustomerId,
+    ) -> Result<Option<Customer>, Error>;
     fn set_customer(&self, transaction: &Transaction, customer: Customer) -> Result<(), Error>;
+
+    /** Visit every committed customer, in no particular order, until `f` breaks. */
+    fn scan(&self, f: &mut dyn FnMut(&CustomerData) -> ControlFlow<()>) -> Result<(), Error>;
 }
 
-pub(in crate::domain) struct InMemoryStore(TransactionValueStore<CustomerData>);
+/** A shared customer store that can be stored in a resolver. */
+pub(in crate::domain) type DynCustomerStore = Arc<dyn CustomerStore + Send + Sync>;
+
+pub(in crate::domain) struct InMemoryStore(InMemoryRepository<CustomerData>);
 
 impl CustomerStore for InMemoryStore {
-    fn get_customer(&self, id: CustomerId) -> Result<Option<Customer>, Error> {
-        if let Some((version, data)) = self.0.get(id) {
-            assert_eq!(version, data.version.into());
-
-            Ok(Some(Customer::from_data(data)))
-        } else {
-            Ok(None)
-        }
+    fn get_customer(
+        &self,
+        transaction: &Transaction,
+        id: CustomerId,
+    ) -> Result<Option<Customer>, Error> {
+        self.0.get(transaction, id)
     }
 
     fn set_customer(&self, transaction: &Transaction, customer: Customer) -> Result<(), Error> {
-        let mut data = customer.into_data();
-        let id = data.id;
+        self.0.save(transaction, customer)
+    }
 
-        self.0.set(
-            transaction,
-            id,
-            Some(data.version),
-            data.version.next(),
-            data,
-        )?;
+    fn scan(&self, f: &mut dyn FnMut(&CustomerData) -> ControlFlow<()>) -> Result<(), Error> {
+        self.0.scan(f);
 
         Ok(())
     }
 }
 
+impl Flush for InMemoryStore {}
+
 pub(in crate::domain) fn in_memory_store(transaction_store: TransactionStore) -> InMemoryStore {
-    InMemoryStore(TransactionValueStore::new(transaction_store))
+    InMemoryStore(InMemoryRepository::new(transaction_store))
 }
 
 #[cfg(test)]
@@ -66,7 +82,10 @@ mod tests {
             .unwrap();
 
         // Get the customer from the store
-        let found = store.get_customer(id).unwrap().unwrap();
+        let found = store
+            .get_customer(&Transaction::none(), id)
+            .unwrap()
+            .unwrap();
         assert_eq!(id, found.data.id);
     }
 
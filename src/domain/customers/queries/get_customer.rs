@@ -16,8 +16,12 @@ impl QueryArgs for GetCustomer {
     type Output = Result<Option<Customer>, Error>;
 }
 
-async fn execute(query: GetCustomer, store: impl CustomerStore) -> Result<Option<Customer>, Error> {
-    let customer = store.get_customer(query.id)?;
+async fn execute(
+    query: GetCustomer,
+    transaction: ActiveTransaction,
+    store: impl CustomerStore,
+) -> Result<Option<Customer>, Error> {
+    let customer = store.get_customer(transaction.read(), query.id)?;
 
     Ok(customer)
 }
@@ -27,8 +31,9 @@ impl Resolver {
     pub fn get_customer_query(&self) -> impl Query<GetCustomer> {
         self.query(|resolver, query: GetCustomer| async move {
             let store = resolver.customer_store();
+            let transaction = resolver.active_transaction();
 
-            execute(query, store).await
+            execute(query, transaction, store).await
         })
     }
 }
\ No newline at end of file
@@ -11,6 +11,9 @@ use crate::domain::{
 #[derive(Deserialize)]
 pub struct GetCustomerWithOrders {
     pub id: CustomerId,
+    /** Whether to also include gift orders where the customer is the recipient. */
+    #[serde(default)]
+    pub include_gifts: bool,
 }
 
 /** An order with a order summary for each of its line items. */
@@ -32,16 +35,20 @@ impl QueryArgs for GetCustomerWithOrders {
 
 async fn execute(
     query: GetCustomerWithOrders,
+    transaction: ActiveTransaction,
     store: impl CustomerStore,
     orders_query: impl Query<GetOrderSummariesForCustomer>,
 ) -> Result<Option<CustomerWithOrders>, Error> {
-    let customer = match store.get_customer(query.id)? {
+    let customer = match store.get_customer(transaction.read(), query.id)? {
         Some(customer) => customer.into_data(),
         None => return Ok(None),
     };
 
     let orders = orders_query
-        .execute(GetOrderSummariesForCustomer { id: query.id })
+        .execute(GetOrderSummariesForCustomer {
+            id: query.id,
+            include_gifts: query.include_gifts,
+        })
         .await?;
 
     Ok(Some(CustomerWithOrders {
@@ -58,9 +65,10 @@ impl Resolver {
     pub fn get_customer_with_orders_query(&self) -> impl Query<GetCustomerWithOrders> {
         self.query(|resolver, query: GetCustomerWithOrders| async move {
             let store = resolver.customer_store();
+            let transaction = resolver.active_transaction();
             let orders_query = resolver.get_order_summaries_for_customer_query();
 
-            execute(query, store, orders_query).await
+            execute(query, transaction, store, orders_query).await
         })
     }
 }
\ No newline at end of file
@@ -1,14 +1,26 @@
 /*! Contains the `CustomersResolver` type. */
 
-use std::sync::Arc;
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+};
 
-use crate::domain::{
-    customers::model::store::{
-        self,
-        CustomerStore,
-        InMemoryStore,
+use crate::{
+    domain::{
+        customers::model::{
+            store::{
+                self,
+                CustomerStore,
+                DynCustomerStore,
+            },
+            Customer,
+            CustomerData,
+            NextCustomerId,
+        },
+        infra::*,
+        Error,
     },
-    infra::*,
+    store::Transaction,
 };
 
 /**
@@ -18,21 +30,180 @@ The `CustomersResolver` type wraps private implementation details and exposes th
 */
 #[derive(Clone)]
 pub(in crate::domain) struct CustomersResolver {
-    customer_store: Register<Arc<InMemoryStore>>,
+    customer_store: Register<DynCustomerStore>,
+    pub(in crate::domain::customers) customer_id: Register<DynIdProvider<CustomerData>>,
 }
 
 impl Default for CustomersResolver {
     fn default() -> Self {
         CustomersResolver {
             customer_store: Register::once(|resolver| {
-                Arc::new(store::in_memory_store(resolver.transaction_store()))
+                Arc::new(store::in_memory_store(resolver.transaction_store())) as DynCustomerStore
+            }),
+            customer_id: Register::factory(|_| {
+                Arc::new(NextCustomerId::new()) as DynIdProvider<CustomerData>
             }),
         }
     }
 }
 
+impl CustomersResolver {
+    pub(in crate::domain) fn with_customer_store<S>(
+        &self,
+        store: impl Fn(&Resolver) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: CustomerStore + Send + Sync + 'static,
+    {
+        CustomersResolver {
+            customer_store: Register::once(move |resolver| {
+                Arc::new(store(resolver)) as DynCustomerStore
+            }),
+            ..self.clone()
+        }
+    }
+
+    pub(in crate::domain) fn with_id_strategy(&self, strategy: IdStrategy) -> Self {
+        CustomersResolver {
+            customer_id: Register::factory(move |_| {
+                Arc::new(NextCustomerId::with_strategy(strategy)) as DynIdProvider<CustomerData>
+            }),
+            ..self.clone()
+        }
+    }
+
+    pub(in crate::domain) fn with_seeded_ids(&self, seed: u64) -> Self {
+        CustomersResolver {
+            customer_id: Register::once(move |_| {
+                Arc::new(SeededIdProvider::new(seed)) as DynIdProvider<CustomerData>
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+impl App {
+    /**
+    Use a different store for customers.
+
+    The store is created the first time it's needed. An in-memory store is used by default.
+    Applications can keep customers somewhere else by implementing `CustomerStore`.
+    */
+    pub fn with_customer_store<S>(
+        self,
+        store: impl Fn(&Resolver) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: CustomerStore + Send + Sync + 'static,
+    {
+        App {
+            root_resolver: Resolver {
+                customers_resolver: self
+                    .root_resolver
+                    .customers_resolver
+                    .with_customer_store(store),
+                ..self.root_resolver.by_ref()
+            },
+        }
+    }
+}
+
 impl Resolver {
+    pub(in crate::domain) fn shutdown_customers(&self, shutdown: &mut Shutdown) {
+        shutdown.flushes("customer_store", self.customer_store());
+    }
+
+    pub(in crate::domain) fn self_check_customers(&self, check: &mut SelfCheck) {
+        check.resolves("customer_store", || self.customer_store());
+        check.resolves("customer_id", || self.customer_id());
+    }
+
+    /** Get the data for every committed customer, ordered by id. */
+    pub(in crate::domain) fn export_customers(&self) -> Result<Vec<CustomerData>, Error> {
+        let mut customers = vec![];
+
+        self.customer_store().scan(&mut |customer| {
+            customers.push(customer.clone());
+            ControlFlow::Continue(())
+        })?;
+
+        customers.sort_by_key(|customer| customer.id);
+
+        Ok(customers)
+    }
+
+    /** Save a customer that was exported from another resolver, keeping its id. */
+    pub(in crate::domain) fn import_customer(
+        &self,
+        transaction: &Transaction,
+        customer: CustomerData,
+    ) -> Result<(), Error> {
+        self.customer_store()
+            .set_customer(transaction, Customer::checked(customer)?)
+    }
+
     pub(in crate::domain::customers) fn customer_store(&self) -> impl CustomerStore {
         self.resolve(&self.customers_resolver.customer_store)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        error,
+    };
+
+    struct ReadOnlyStore;
+
+    impl CustomerStore for ReadOnlyStore {
+        fn get_customer(&self, _: &Transaction, _: CustomerId) -> Result<Option<Customer>, Error> {
+            Ok(None)
+        }
+
+        fn set_customer(&self, _: &Transaction, _: Customer) -> Result<(), Error> {
+            Err(error::msg("the store is read-only"))
+        }
+
+        fn scan(&self, _: &mut dyn FnMut(&CustomerData) -> ControlFlow<()>) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl Flush for ReadOnlyStore {
+        fn flush(&self) -> Result<(), Error> {
+            Err(error::msg("the store is read-only"))
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_use_custom_customer_store() {
+        let app = App::new().with_customer_store(|_| ReadOnlyStore);
+
+        let r = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_customer_command()
+                    .execute(CreateCustomer {
+                        id: CustomerId::new(),
+                    })
+                    .await
+            })
+            .await;
+
+        assert!(r.unwrap_err().to_string().contains("read-only"));
+    }
+
+    #[test]
+    fn shutdown_names_stores_that_failed_to_flush() {
+        let app = App::new().with_customer_store(|_| ReadOnlyStore);
+
+        let err = app.shutdown().unwrap_err().to_string();
+
+        assert!(err.contains("customer_store"));
+        assert!(err.contains("read-only"));
+        assert!(!err.contains("order_store"));
+    }
 }
\ No newline at end of file
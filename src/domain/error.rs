@@ -10,30 +10,78 @@ The error type is a simple collector that surfaces enough detail to the API so i
 can pick the right status code to return. It doesn't know anything about HTTP or
 status codes itself.
 
-As a collector, the error type doesn't implement Rust's `Error` trait itself,
-otherwise it wouldn't be able to collect any other kind of error.
+The error only displays its own message, prefixed by any context it was given on the way
+up. Any underlying error that caused it is available as its source instead.
 */
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
+    context: Vec<String>,
     inner: Box<dyn error::Error + Send + Sync>,
+    source: Option<Box<dyn error::Error + Send + Sync>>,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for context in &self.context {
+            write!(f, "{}: ", context)?;
+        }
+
         fmt::Display::fmt(&self.inner, f)
     }
 }
 
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.source {
+            Some(ref source) => Some(&**source),
+            None => self.inner.source(),
+        }
+    }
+}
+
 /**
 The kind of an error captured.
 */
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorKind {
     /** A command or query was given bad input. */
     BadInput,
-    /** Some other kind of error. */
-    Other,
+    /** An entity that was needed doesn't exist. */
+    NotFound,
+    /** A change conflicts with the current state of an entity. */
+    Conflict,
+    /** Something went wrong that isn't the caller's fault. */
+    Internal,
+}
+
+/**
+A hint for the status an application should report for an error.
+
+The hints mirror HTTP status codes without depending on HTTP.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusHint {
+    /** The caller should fix their input. Mirrors `400`. */
+    BadRequest,
+    /** The entity doesn't exist. Mirrors `404`. */
+    NotFound,
+    /** The caller should fetch the entity and try again. Mirrors `409`. */
+    Conflict,
+    /** The failure isn't the caller's fault. Mirrors `500`. */
+    Internal,
+}
+
+impl StatusHint {
+    /** Get the HTTP status code this hint mirrors. */
+    pub fn code(&self) -> u16 {
+        match self {
+            StatusHint::BadRequest => 400,
+            StatusHint::NotFound => 404,
+            StatusHint::Conflict => 409,
+            StatusHint::Internal => 500,
+        }
+    }
 }
 
 /**
@@ -43,8 +91,10 @@ This message may make its way to end-users so it should be friendly.
 */
 pub fn msg(err: impl fmt::Display) -> Error {
     Error {
-        kind: ErrorKind::Other,
+        kind: ErrorKind::Internal,
+        context: vec![],
         inner: err.to_string().into(),
+        source: None,
     }
 }
 
@@ -56,27 +106,187 @@ This message may make its way to end-users so it should be friendly.
 pub fn bad_input(msg: impl fmt::Display) -> Error {
     Error {
         kind: ErrorKind::BadInput,
+        context: vec![],
         inner: msg.to_string().into(),
+        source: None,
+    }
+}
+
+/**
+Create an error for an entity that doesn't exist.
+
+The entity and its id are carried by the error.
+*/
+pub fn not_found(entity: &'static str, id: impl fmt::Display) -> Error {
+    Error {
+        kind: ErrorKind::NotFound,
+        context: vec![],
+        inner: Box::new(EntityNotFound {
+            entity,
+            id: id.to_string(),
+        }),
+        source: None,
+    }
+}
+
+/** The details of an entity that doesn't exist. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityNotFound {
+    pub entity: &'static str,
+    pub id: String,
+}
+
+impl fmt::Display for EntityNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} `{}` not found", self.entity, self.id)
+    }
+}
+
+impl error::Error for EntityNotFound {}
+
+/**
+Create an error for a change that conflicts with the current state of an entity.
+
+This message may make its way to end-users so it should be friendly.
+*/
+pub fn conflict(msg: impl fmt::Display) -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        context: vec![],
+        inner: msg.to_string().into(),
+        source: None,
+    }
+}
+
+/**
+Create an error for a change made to a stale version of an entity.
+
+The versions are carried by the error so callers can decide whether to fetch the entity again.
+*/
+pub fn version_conflict(
+    entity: &'static str,
+    id: impl fmt::Display,
+    expected: u64,
+    actual: u64,
+) -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        context: vec![],
+        inner: Box::new(VersionConflict {
+            entity,
+            id: id.to_string(),
+            expected,
+            actual,
+        }),
+        source: None,
+    }
+}
+
+/** The details of a change made to a stale version of an entity. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    pub entity: &'static str,
+    pub id: String,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "version conflict on {} `{}`: expected version {} but found version {}",
+            self.entity, self.id, self.expected, self.actual
+        )
     }
 }
 
+impl error::Error for VersionConflict {}
+
 impl Error {
     /**
-    Split an error into its kind and value.
+    Create an error with an underlying error that caused it.
+
+    The message is what the error displays, the source is only available by walking the error chain.
+    */
+    pub fn with_source(
+        kind: ErrorKind,
+        msg: impl Into<Box<dyn error::Error + Send + Sync>>,
+        source: impl Into<Box<dyn error::Error + Send + Sync>>,
+    ) -> Self {
+        Error {
+            kind,
+            context: vec![],
+            inner: msg.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /**
+    Attach some context to the error, like the command that was running when it happened.
+
+    Context is displayed before the error's message, with the context attached last shown first.
+    The kind and source of the error are unchanged.
+    */
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        self.context.insert(0, context.into());
+        self
+    }
+
+    /**
+    Get the kind of error.
     */
-    pub(crate) fn split(self) -> (ErrorKind, Box<dyn error::Error + Send + Sync>) {
-        (self.kind, self.inner)
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /**
+    Get a hint for the status to report for this error.
+    */
+    pub fn status_hint(&self) -> StatusHint {
+        match self.kind {
+            ErrorKind::BadInput => StatusHint::BadRequest,
+            ErrorKind::NotFound => StatusHint::NotFound,
+            ErrorKind::Conflict => StatusHint::Conflict,
+            ErrorKind::Internal => StatusHint::Internal,
+        }
+    }
+
+    /**
+    Get a message for this error that's safe to show to callers.
+
+    Errors caused by the caller have their message returned without any context.
+    Internal errors get a generic message so their details aren't leaked.
+    */
+    pub fn client_message(&self) -> String {
+        match self.status_hint() {
+            StatusHint::Internal => "an internal error occurred".to_owned(),
+            _ => self.inner.to_string(),
+        }
+    }
+
+    /**
+    Get the details of a missing entity, if that's what caused this error.
+    */
+    pub fn entity_not_found(&self) -> Option<&EntityNotFound> {
+        self.inner.downcast_ref()
+    }
+
+    /**
+    Get the details of a version conflict, if that's what caused this error.
+    */
+    pub fn version_conflict(&self) -> Option<&VersionConflict> {
+        self.inner.downcast_ref()
     }
 }
 
-impl<E> From<E> for Error
-where
-    E: Into<Box<dyn error::Error + Send + Sync>>,
-{
-    fn from(err: E) -> Error {
+impl From<Box<dyn error::Error + Send + Sync>> for Error {
+    fn from(err: Box<dyn error::Error + Send + Sync>) -> Error {
         Error {
-            kind: ErrorKind::Other,
-            inner: err.into(),
+            kind: ErrorKind::Internal,
+            context: vec![],
+            inner: err,
+            source: None,
         }
     }
 }
@@ -86,4 +296,63 @@ macro_rules! err {
         error!($($err)*);
         Err(crate::domain::error::msg(format!($($err)*)))
     }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_hint_for_each_kind() {
+        let cases = [
+            (bad_input("quantity must be greater than 0"), 400),
+            (not_found("order", "1"), 404),
+            (conflict("order `1` already exists"), 409),
+            (version_conflict("order", "1", 0, 1), 409),
+            (msg("something went wrong"), 500),
+        ];
+
+        for (err, code) in cases {
+            assert_eq!(code, err.status_hint().code());
+        }
+    }
+
+    #[test]
+    fn client_message_for_client_errors() {
+        let cases = [
+            bad_input("quantity must be greater than 0"),
+            not_found("order", "1"),
+            conflict("order `1` already exists"),
+        ];
+
+        for err in cases {
+            assert_eq!(err.to_string(), err.client_message());
+        }
+    }
+
+    #[test]
+    fn context_is_displayed_outermost_first() {
+        let err = not_found("order", "1")
+            .context("get_order")
+            .context("place_order order=1");
+
+        assert_eq!(
+            "place_order order=1: get_order: order `1` not found",
+            err.to_string()
+        );
+
+        // The kind and details of the error are kept
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.entity_not_found().is_some());
+
+        assert_eq!("order `1` not found", err.client_message());
+    }
+
+    #[test]
+    fn client_message_redacts_internal_errors() {
+        let err = Error::with_source(ErrorKind::Internal, "RwLock poisoned", msg("a panic"));
+
+        assert_eq!(StatusHint::Internal, err.status_hint());
+        assert!(!err.client_message().contains("RwLock"));
+    }
 }
\ No newline at end of file
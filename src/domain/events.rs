@@ -0,0 +1,265 @@
+/*!
+Contains the `Events` bus for domain events.
+
+Commands publish events when their changes are committed. Anything interested in what's happening
+in the domain can subscribe to them without the commands needing to know about it.
+*/
+
+use std::{
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::{
+        Arc,
+        RwLock,
+    },
+};
+
+use crate::domain::{
+    customers::CustomerId,
+    infra::*,
+    orders::OrderId,
+    products::ProductId,
+};
+
+/** Something that happened in the domain. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum DomainEvent {
+    CustomerCreated {
+        id: CustomerId,
+    },
+    OrderCreated {
+        id: OrderId,
+        customer_id: CustomerId,
+    },
+    OrderPlaced {
+        id: OrderId,
+    },
+    OrderPaid {
+        id: OrderId,
+    },
+    /** The quantity of a product in an order was set, either by adding it or updating it. */
+    OrderProductSet {
+        id: OrderId,
+        product_id: ProductId,
+        price: Currency,
+        quantity: u32,
+    },
+    OrderProductRemoved {
+        id: OrderId,
+        product_id: ProductId,
+    },
+    OrderDeleted {
+        id: OrderId,
+    },
+    ProductCreated {
+        id: ProductId,
+    },
+    ProductDeactivated {
+        id: ProductId,
+    },
+    ProductTitleSet {
+        id: ProductId,
+    },
+    ProductUpdated {
+        id: ProductId,
+    },
+}
+
+type Subscriber = Arc<dyn Fn(DomainEvent) + Send + Sync>;
+
+/**
+A bus for domain events.
+
+Events are delivered synchronously to each subscriber in the order they subscribed.
+A subscriber that panics doesn't stop the event from being delivered to the others.
+Clones of the bus share the same subscribers.
+*/
+#[derive(Clone, Default)]
+pub struct Events {
+    subscribers: Arc<RwLock<Vec<Subscriber>>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Events::default()
+    }
+
+    /**
+    Subscribe to all events published after this call.
+    */
+    pub fn subscribe(&self, f: impl Fn(DomainEvent) + Send + Sync + 'static) {
+        self.subscribers.write().unwrap().push(Arc::new(f));
+    }
+
+    /**
+    Publish an event to all subscribers.
+
+    Commands should publish events from `ActiveTransaction::on_commit`, so they're only seen
+    once the changes they describe are observable.
+    */
+    pub(in crate::domain) fn publish(&self, event: DomainEvent) {
+        // Take a copy of the subscribers so they can subscribe while handling an event
+        let subscribers = self.subscribers.read().unwrap().clone();
+
+        for subscriber in subscribers {
+            let event = event.clone();
+
+            if panic::catch_unwind(AssertUnwindSafe(|| subscriber(event))).is_err() {
+                warn!("a subscriber panicked while handling an event");
+            }
+        }
+    }
+}
+
+impl App {
+    /**
+    Publish events to an existing bus.
+
+    This lets subscribers be registered before the app is constructed.
+    */
+    pub fn with_events(self, events: Events) -> Self {
+        App {
+            root_resolver: Resolver {
+                events: Register::once(move |_| events.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        }
+    }
+
+    /** Get the bus events are published to. */
+    pub fn events(&self) -> Events {
+        self.root_resolver.events()
+    }
+}
+
+impl Resolver {
+    /** Get the bus events are published to. */
+    pub fn events(&self) -> Events {
+        self.resolve(&self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    use crate::domain::{
+        customers::CreateCustomer,
+        error,
+        orders::CreateOrder,
+        Error,
+    };
+
+    fn collect(events: &Events) -> Arc<Mutex<Vec<DomainEvent>>> {
+        let collected = Arc::new(Mutex::new(vec![]));
+
+        events.subscribe({
+            let collected = collected.clone();
+
+            move |event| collected.lock().unwrap().push(event)
+        });
+
+        collected
+    }
+
+    fn orders_created(collected: &Mutex<Vec<DomainEvent>>) -> Vec<DomainEvent> {
+        collected
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, DomainEvent::OrderCreated { .. }))
+            .cloned()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn subscribers_see_each_event_once() {
+        let events = Events::new();
+        let before = collect(&events);
+
+        let app = App::new().with_events(events);
+        let after = collect(&app.events());
+
+        let customer_id = CustomerId::new();
+        let order_id = OrderId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await
+        })
+        .await
+        .unwrap();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        let expected = vec![DomainEvent::OrderCreated {
+            id: order_id,
+            customer_id,
+        }];
+
+        assert_eq!(expected, orders_created(&before));
+        assert_eq!(expected, orders_created(&after));
+
+        // Events are delivered in the order they were published
+        assert_eq!(
+            Some(&DomainEvent::CustomerCreated { id: customer_id }),
+            before.lock().unwrap().first()
+        );
+    }
+
+    #[tokio::test]
+    async fn events_are_not_published_if_cancelled() {
+        let app = App::new();
+        let collected = collect(&app.events());
+
+        let id = CustomerId::new();
+
+        let r: Result<(), Error> = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_customer_command()
+                    .execute(CreateCustomer { id })
+                    .await?;
+
+                Err(error::msg("failed after creating the customer"))
+            })
+            .await;
+
+        assert!(r.is_err());
+        assert!(collected.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn panicking_subscriber_does_not_stop_others() {
+        let events = Events::new();
+
+        events.subscribe(|_| panic!("failed to handle event"));
+        let collected = collect(&events);
+
+        let id = CustomerId::new();
+        events.publish(DomainEvent::CustomerCreated { id });
+
+        assert_eq!(
+            vec![DomainEvent::CustomerCreated { id }],
+            *collected.lock().unwrap()
+        );
+    }
+}
\ No newline at end of file
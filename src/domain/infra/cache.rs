@@ -0,0 +1,339 @@
+/*! Contains the `CachedQuery` type for memoizing query results. */
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use crate::domain::{
+    events::{
+        DomainEvent,
+        Events,
+    },
+    infra::*,
+    Error,
+};
+
+/**
+Query input that can be used to cache results.
+
+Inputs with the same key are expected to produce the same result until an event invalidates it.
+*/
+pub trait QueryCacheKey {
+    type Key: Hash + Eq + Clone + Send;
+    type Value: Clone + Send;
+
+    /** The key to cache the result of this input under. */
+    fn cache_key(&self) -> Self::Key;
+
+    /**
+    The keys of results that are no longer valid because of an event.
+
+    Results for inputs that can change need to be invalidated when they do, otherwise they'll be
+    served stale until they expire.
+    */
+    fn invalidated_by(event: &DomainEvent) -> Vec<Self::Key>;
+}
+
+struct Entry<TArgs: QueryCacheKey> {
+    value: TArgs::Value,
+    cached_at: SystemTime,
+}
+
+/**
+A cache of query results.
+
+Results expire once they're older than the cache's time to live. When the cache is full the
+oldest result is evicted to make room for a new one.
+*/
+pub(in crate::domain) struct QueryCache<TArgs: QueryCacheKey> {
+    entries: Mutex<HashMap<TArgs::Key, Entry<TArgs>>>,
+    ttl: Duration,
+    capacity: usize,
+    clock: DynClock,
+}
+
+impl<TArgs: QueryCacheKey> QueryCache<TArgs> {
+    pub(in crate::domain) fn new(clock: DynClock, ttl: Duration, capacity: usize) -> Self {
+        QueryCache {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+            capacity,
+            clock,
+        }
+    }
+
+    /**
+    Invalidate results in the cache whenever an event published to the bus changes them.
+    */
+    pub(in crate::domain) fn invalidate_on(self: &Arc<Self>, events: &Events)
+    where
+        TArgs: 'static,
+        Self: Send + Sync,
+    {
+        // Hold a weak reference so subscribing doesn't keep the cache alive
+        let cache = Arc::downgrade(self);
+
+        events.subscribe(move |event| {
+            if let Some(cache) = cache.upgrade() {
+                for key in TArgs::invalidated_by(&event) {
+                    cache.invalidate(&key);
+                }
+            }
+        });
+    }
+
+    fn get(&self, key: &TArgs::Key) -> Option<TArgs::Value> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let expired = self.is_expired(entries.get(key)?);
+
+        if expired {
+            entries.remove(key);
+
+            None
+        } else {
+            entries.get(key).map(|entry| entry.value.clone())
+        }
+    }
+
+    fn insert(&self, key: TArgs::Key, value: TArgs::Value) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.cached_at)
+                .map(|(key, _)| key.clone());
+
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+
+        if self.capacity > 0 {
+            entries.insert(
+                key,
+                Entry {
+                    value,
+                    cached_at: self.clock.now(),
+                },
+            );
+        }
+    }
+
+    pub(in crate::domain) fn invalidate(&self, key: &TArgs::Key) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn is_expired(&self, entry: &Entry<TArgs>) -> bool {
+        self.clock
+            .now()
+            .duration_since(entry.cached_at)
+            .map(|age| age >= self.ttl)
+            .unwrap_or(false)
+    }
+}
+
+/**
+A query that caches the results of another one.
+
+Only successful results are cached. Errors are always returned from the wrapped query.
+*/
+pub struct CachedQuery<TArgs: QueryCacheKey, TQuery> {
+    query: TQuery,
+    cache: Option<Arc<QueryCache<TArgs>>>,
+}
+
+impl<TArgs: QueryCacheKey, TQuery> CachedQuery<TArgs, TQuery> {
+    pub(in crate::domain) fn new(query: TQuery, cache: Arc<QueryCache<TArgs>>) -> Self {
+        CachedQuery {
+            query,
+            cache: Some(cache),
+        }
+    }
+
+    /** A query that always runs the wrapped one, without reading or filling a cache. */
+    pub(in crate::domain) fn uncached(query: TQuery) -> Self {
+        CachedQuery { query, cache: None }
+    }
+}
+
+#[async_trait]
+impl<TArgs, TQuery> Query<TArgs> for CachedQuery<TArgs, TQuery>
+where
+    TArgs: QueryArgs<Output = Result<<TArgs as QueryCacheKey>::Value, Error>>
+        + QueryCacheKey
+        + Send
+        + 'static,
+    TQuery: Query<TArgs> + Sync,
+    QueryCache<TArgs>: Send + Sync,
+{
+    async fn execute(&self, input: TArgs) -> TArgs::Output {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.query.execute(input).await,
+        };
+
+        let key = input.cache_key();
+
+        if let Some(value) = cache.get(&key) {
+            return Ok(value);
+        }
+
+        let value = self.query.execute(input).await?;
+
+        cache.insert(key, value.clone());
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    use super::*;
+
+    struct GetSquare(u32);
+
+    impl QueryArgs for GetSquare {
+        type Output = Result<u32, Error>;
+    }
+
+    impl QueryCacheKey for GetSquare {
+        type Key = u32;
+        type Value = u32;
+
+        fn cache_key(&self) -> u32 {
+            self.0
+        }
+
+        fn invalidated_by(_: &DomainEvent) -> Vec<u32> {
+            vec![]
+        }
+    }
+
+    struct Fixture {
+        clock: Arc<FixedClock>,
+        cache: Arc<QueryCache<GetSquare>>,
+        executed: Arc<AtomicUsize>,
+    }
+
+    impl Fixture {
+        fn new(ttl: Duration, capacity: usize) -> Self {
+            let clock = Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH));
+
+            Fixture {
+                cache: Arc::new(QueryCache::new(clock.clone(), ttl, capacity)),
+                clock,
+                executed: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn query(&self) -> impl Query<GetSquare> {
+            let executed = self.executed.clone();
+
+            CachedQuery::new(
+                move |query: GetSquare| {
+                    executed.fetch_add(1, Ordering::SeqCst);
+
+                    async move { Ok(query.0 * query.0) }
+                },
+                self.cache.clone(),
+            )
+        }
+
+        fn executed(&self) -> usize {
+            self.executed.load(Ordering::SeqCst)
+        }
+    }
+
+    #[tokio::test]
+    async fn hits_and_misses() {
+        let fixture = Fixture::new(Duration::from_secs(10), 10);
+        let query = fixture.query();
+
+        assert_eq!(4, query.execute(GetSquare(2)).await.unwrap());
+        assert_eq!(4, query.execute(GetSquare(2)).await.unwrap());
+
+        assert_eq!(1, fixture.executed());
+
+        assert_eq!(9, query.execute(GetSquare(3)).await.unwrap());
+
+        assert_eq!(2, fixture.executed());
+    }
+
+    #[tokio::test]
+    async fn results_expire_after_ttl() {
+        let fixture = Fixture::new(Duration::from_secs(10), 10);
+        let query = fixture.query();
+
+        query.execute(GetSquare(2)).await.unwrap();
+
+        fixture
+            .clock
+            .set(SystemTime::UNIX_EPOCH + Duration::from_secs(9));
+        query.execute(GetSquare(2)).await.unwrap();
+
+        assert_eq!(1, fixture.executed());
+
+        fixture
+            .clock
+            .set(SystemTime::UNIX_EPOCH + Duration::from_secs(10));
+        query.execute(GetSquare(2)).await.unwrap();
+
+        assert_eq!(2, fixture.executed());
+    }
+
+    #[tokio::test]
+    async fn oldest_result_is_evicted_at_capacity() {
+        let fixture = Fixture::new(Duration::from_secs(10), 2);
+        let query = fixture.query();
+
+        for (n, secs) in [(1, 0), (2, 1), (3, 2)] {
+            fixture
+                .clock
+                .set(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+            query.execute(GetSquare(n)).await.unwrap();
+        }
+
+        assert_eq!(3, fixture.executed());
+
+        // The most recent results are still cached
+        query.execute(GetSquare(2)).await.unwrap();
+        query.execute(GetSquare(3)).await.unwrap();
+
+        assert_eq!(3, fixture.executed());
+
+        // The oldest one was evicted
+        query.execute(GetSquare(1)).await.unwrap();
+
+        assert_eq!(4, fixture.executed());
+    }
+
+    #[tokio::test]
+    async fn invalidated_results_are_refetched() {
+        let fixture = Fixture::new(Duration::from_secs(10), 10);
+        let query = fixture.query();
+
+        query.execute(GetSquare(2)).await.unwrap();
+
+        fixture.cache.invalidate(&2);
+
+        query.execute(GetSquare(2)).await.unwrap();
+
+        assert_eq!(2, fixture.executed());
+    }
+}
\ No newline at end of file
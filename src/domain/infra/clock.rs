@@ -0,0 +1,95 @@
+/*! Contains the `Clock` type for getting the current time. */
+
+use std::{
+    future::{
+        self,
+        Future,
+    },
+    pin::Pin,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use crate::domain::infra::Resolver;
+
+/**
+A source of the current time.
+
+Items that need to know the current time should depend on a `Clock` rather than
+calling `SystemTime::now` directly, so tests can control it.
+*/
+#[auto_impl(&, Arc)]
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+
+    /**
+    Wait for some time to pass.
+
+    Items that need to wait, like backing off before retrying, should wait on their clock so
+    tests don't need to.
+    */
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/** A future returned by `Clock::sleep`. */
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/** A shared `Clock` that can be stored in a resolver. */
+pub(in crate::domain) type DynClock = Arc<dyn Clock + Send + Sync>;
+
+/** A clock that uses the system time. */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/**
+A clock that always returns the same time until it's changed.
+
+This is useful in tests that need to control the time.
+Sleeping on the clock doesn't wait, it moves the time forward instead.
+*/
+#[derive(Debug)]
+pub struct FixedClock(Mutex<SystemTime>);
+
+impl FixedClock {
+    pub fn new(now: SystemTime) -> Self {
+        FixedClock(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.0.lock().unwrap() = now;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        *self.0.lock().unwrap() += duration;
+
+        Box::pin(future::ready(()))
+    }
+}
+
+impl Resolver {
+    pub(in crate::domain) fn clock(&self) -> DynClock {
+        self.resolve(&self.clock)
+    }
+}
\ No newline at end of file
@@ -4,7 +4,7 @@ A lossless representation of currency.
 This type encodes the currency using its smallest possible unit. This is a better approach
 than floating point numbers where imprecision can change the results of calculations.
 */
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Currency {
     USD(USD),
@@ -14,6 +14,53 @@ impl Currency {
     pub fn usd(cents: u64) -> Self {
         Currency::USD(USD::new(cents))
     }
+
+    /** The ISO 4217 code for the currency, like `USD`. */
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::USD(_) => "USD",
+        }
+    }
+
+    /** The value in the currency's smallest unit, like cents for USD. */
+    pub fn minor_units(&self) -> u64 {
+        match self {
+            Currency::USD(usd) => usd.cents,
+        }
+    }
+
+    /**
+    Change the value by a percentage, rounding to the nearest smallest unit.
+
+    A percent of `10` increases the value by a tenth, and a percent of `-100` brings it to zero.
+    Returns `None` if the result would be negative or too large to represent.
+    */
+    pub fn adjust_by_percent(self, percent: i32) -> Option<Self> {
+        let factor = u128::try_from(100 + i64::from(percent)).ok()?;
+
+        match self {
+            Currency::USD(usd) => {
+                // Add half a cent before dividing so the result is rounded rather than truncated
+                let cents = (u128::from(usd.cents) * factor + 50) / 100;
+
+                Some(Currency::usd(u64::try_from(cents).ok()?))
+            }
+        }
+    }
+
+    /** Multiply the value by a quantity, capping it at the largest value that can be represented. */
+    pub fn saturating_mul(self, quantity: u32) -> Self {
+        match self {
+            Currency::USD(usd) => Currency::usd(usd.cents.saturating_mul(u64::from(quantity))),
+        }
+    }
+
+    /** Add two values, capping the result at the largest value that can be represented. */
+    pub fn saturating_add(self, other: Self) -> Self {
+        match (self, other) {
+            (Currency::USD(a), Currency::USD(b)) => Currency::usd(a.cents.saturating_add(b.cents)),
+        }
+    }
 }
 
 /**
@@ -30,4 +77,59 @@ impl USD {
     pub fn new(cents: u64) -> Self {
         USD { cents }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saturating_arithmetic() {
+        assert_eq!(Currency::usd(300), Currency::usd(100).saturating_mul(3));
+        assert_eq!(
+            Currency::usd(250),
+            Currency::usd(100).saturating_add(Currency::usd(150))
+        );
+
+        assert_eq!(
+            Currency::usd(u64::MAX),
+            Currency::usd(u64::MAX / 2).saturating_mul(3)
+        );
+        assert_eq!(
+            Currency::usd(u64::MAX),
+            Currency::usd(u64::MAX).saturating_add(Currency::usd(1))
+        );
+    }
+
+    #[test]
+    fn adjust_by_percent() {
+        assert_eq!(
+            Some(Currency::usd(110)),
+            Currency::usd(100).adjust_by_percent(10)
+        );
+        assert_eq!(
+            Some(Currency::usd(75)),
+            Currency::usd(100).adjust_by_percent(-25)
+        );
+        assert_eq!(
+            Some(Currency::usd(0)),
+            Currency::usd(100).adjust_by_percent(-100)
+        );
+
+        // Fractions of a cent are rounded to the nearest cent
+        assert_eq!(
+            Some(Currency::usd(110)),
+            Currency::usd(99).adjust_by_percent(11)
+        );
+        assert_eq!(
+            Some(Currency::usd(109)),
+            Currency::usd(99).adjust_by_percent(10)
+        );
+    }
+
+    #[test]
+    fn adjust_by_percent_out_of_range() {
+        assert_eq!(None, Currency::usd(100).adjust_by_percent(-101));
+        assert_eq!(None, Currency::usd(u64::MAX).adjust_by_percent(1));
+    }
 }
\ No newline at end of file
@@ -15,4 +15,10 @@ pub(in crate::domain) trait Entity {
     type Data;
     /** Should be the `Err` variant for any `Result` returning methods on `Self`. */
     type Error;
+
+    /** Should be the same as the `id` on `Self::Data`. */
+    fn id(&self) -> Self::Id;
+
+    /** Should be the same as the `version` on `Self::Data`. */
+    fn version(&self) -> Self::Version;
 }
\ No newline at end of file
@@ -0,0 +1,17 @@
+/*! Contains the `Flush` trait for stores that hold on to writes. */
+
+use crate::domain::Error;
+
+/**
+A store that may hold on to writes before they're durable.
+
+Stores that write through straight away, like the in-memory ones, don't need to do anything,
+so flushing is a no-op by default.
+*/
+#[auto_impl(&, Arc)]
+pub trait Flush {
+    /** Make any writes the store is holding on to durable. */
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -1,11 +1,26 @@
-use crate::domain::infra::Resolver;
+use crate::domain::infra::{
+    CommandOutput,
+    Resolver,
+};
 
-use std::future::Future;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
 
+/** Input for a command, which determines what the command returns. */
 pub trait CommandArgs {
     type Output;
 }
 
+/**
+A command that changes the state of the domain.
+
+Commands and queries are async, so they can be awaited directly from async code like request
+handlers. The stores they depend on are in-memory and never block, so there's no separate
+async version of them, or of the commands and queries that use them.
+*/
 #[async_trait]
 pub trait Command<TArgs: CommandArgs> {
     async fn execute(self, input: TArgs) -> TArgs::Output;
@@ -23,10 +38,16 @@ where
     }
 }
 
+/** Input for a query, which determines what the query returns. */
 pub trait QueryArgs {
     type Output;
 }
 
+/**
+A query that reads the state of the domain without changing it.
+
+Unlike commands, queries can be executed more than once.
+*/
 #[async_trait]
 pub trait Query<TArgs: QueryArgs> {
     async fn execute(&self, input: TArgs) -> TArgs::Output;
@@ -44,6 +65,52 @@ where
     }
 }
 
+/** The future returned by a `DynCommand` or `DynQuery`. */
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/**
+A boxed command.
+
+Commands resolved from a `Resolver` each have their own type. Boxing them lets commands for the
+same input be stored and passed around without naming that type. A `DynCommand` is itself a
+`Command`, so it can be executed the same way.
+*/
+pub type DynCommand<TArgs> =
+    Box<dyn FnOnce(TArgs) -> BoxFuture<<TArgs as CommandArgs>::Output> + Send>;
+
+/**
+A boxed query.
+
+Like `DynCommand`, a `DynQuery` is itself a `Query`, so it can be executed any number of times.
+*/
+pub type DynQuery<TArgs> =
+    Box<dyn Fn(TArgs) -> BoxFuture<<TArgs as QueryArgs>::Output> + Send + Sync>;
+
+/** Box a command, so it can be stored alongside other commands for the same input. */
+pub fn boxed_command<TArgs, TCommand>(command: TCommand) -> DynCommand<TArgs>
+where
+    TArgs: CommandArgs + Send + 'static,
+    TCommand: Command<TArgs> + Send + 'static,
+{
+    Box::new(move |input| command.execute(input))
+}
+
+/** Box a query, so it can be stored alongside other queries for the same input. */
+pub fn boxed_query<TArgs, TQuery>(query: TQuery) -> DynQuery<TArgs>
+where
+    TArgs: QueryArgs + Send + 'static,
+    TQuery: Query<TArgs> + Send + Sync + 'static,
+{
+    // The future can outlive the boxed query, so it needs its own handle to the query
+    let query = Arc::new(query);
+
+    Box::new(move |input| {
+        let query = query.clone();
+
+        Box::pin(async move { query.execute(input).await })
+    })
+}
+
 impl Resolver {
     pub(in crate::domain) fn command<TArgs, TCommand, TFuture>(
         &self,
@@ -51,13 +118,18 @@ impl Resolver {
     ) -> impl Command<TArgs>
     where
         TArgs: CommandArgs + Send + 'static,
+        TArgs::Output: CommandOutput,
         TCommand: FnOnce(Resolver, TArgs) -> TFuture + Send,
         TFuture: Future<Output = TArgs::Output> + Send,
     {
         let resolver = self.by_ref();
         move |input: TArgs| {
             let resolver = resolver.by_ref();
-            command(resolver, input)
+            async move {
+                let command = command(resolver.by_ref(), input);
+
+                resolver.run_command_middleware::<TArgs, _>(command).await
+            }
         }
     }
 
@@ -80,8 +152,26 @@ impl Resolver {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use serde::de::DeserializeOwned;
+    use serde_json::{
+        json,
+        Value,
+    };
+
     use super::*;
 
+    use crate::domain::{
+        customers::*,
+        error,
+        infra::Currency,
+        orders::*,
+        products::*,
+        Error,
+        ErrorKind,
+    };
+
     #[tokio::test]
     async fn unsync_command_query() {
         struct AddValue {
@@ -120,4 +210,114 @@ mod tests {
         assert_eq!(1, query.execute(GetLen).await);
         assert_eq!(1, query.execute(GetLen).await);
     }
+
+    type Route = Box<dyn Fn(Value) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+    /** Route JSON input to a command that's resolved for each request. */
+    fn route<TArgs, T>(resolver: &Resolver, command: fn(&Resolver) -> DynCommand<TArgs>) -> Route
+    where
+        TArgs: CommandArgs<Output = Result<T, Error>> + DeserializeOwned + Send + 'static,
+        T: 'static,
+    {
+        let resolver = resolver.clone();
+
+        Box::new(move |input| {
+            let command = command(&resolver);
+
+            Box::pin(async move {
+                let input = serde_json::from_value(input).map_err(error::bad_input)?;
+
+                command.execute(input).await.map(|_| ())
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn dispatch_through_boxed_commands() {
+        let test = Resolver::for_tests();
+        let resolver = test.resolver();
+
+        let mut routes: HashMap<&'static str, Route> = HashMap::new();
+
+        routes.insert(
+            "create_customer",
+            route(&resolver, |r| boxed_command(r.create_customer_command())),
+        );
+        routes.insert(
+            "create_product",
+            route(&resolver, |r| boxed_command(r.create_product_command())),
+        );
+        routes.insert(
+            "create_order",
+            route(&resolver, |r| boxed_command(r.create_order_command())),
+        );
+        routes.insert(
+            "add_or_update_product",
+            route(&resolver, |r| {
+                boxed_command(r.add_or_update_product_command())
+            }),
+        );
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let order_id = OrderId::new();
+
+        for (name, input) in [
+            ("create_customer", json!({ "id": customer_id })),
+            (
+                "create_product",
+                json!({
+                    "id": product_id,
+                    "title": "A test product",
+                    "price": Currency::usd(100),
+                }),
+            ),
+            (
+                "create_order",
+                json!({ "id": order_id, "customer_id": customer_id }),
+            ),
+            (
+                "add_or_update_product",
+                json!({ "id": order_id, "product_id": product_id, "quantity": 3 }),
+            ),
+        ] {
+            routes[name](input).await.unwrap();
+        }
+
+        let order = resolver
+            .get_order_with_products_query()
+            .execute(GetOrderWithProducts { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, order.line_items.len());
+        assert_eq!(3, order.line_items[0].quantity);
+
+        // Input that doesn't match the command is rejected before it runs
+        let err = routes["create_order"](json!({ "id": order_id }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+    }
+
+    #[tokio::test]
+    async fn boxed_queries_can_be_executed_repeatedly() {
+        struct Double(i32);
+
+        impl QueryArgs for Double {
+            type Output = i32;
+        }
+
+        let queries: Vec<DynQuery<Double>> = vec![
+            boxed_query(|input: Double| async move { input.0 * 2 }),
+            boxed_query(|input: Double| async move { input.0 + input.0 }),
+        ];
+
+        for query in &queries {
+            assert_eq!(4, query.execute(Double(2)).await);
+            assert_eq!(6, query.execute(Double(3)).await);
+        }
+    }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@
 
 use serde::{
     de::{
+        self,
         Deserialize,
         Deserializer,
     },
@@ -23,11 +24,23 @@ use std::{
         Hasher,
     },
     marker::PhantomData,
+    str::FromStr,
+    sync::{
+        atomic::{
+            self,
+            AtomicU64,
+        },
+        Arc,
+        Mutex,
+    },
 };
 use uuid::Uuid;
 
 use crate::{
-    domain::error::Error,
+    domain::error::{
+        self,
+        Error,
+    },
     store,
 };
 
@@ -102,13 +115,166 @@ impl<T> Id<T> {
     pub fn new() -> Self {
         Id(Uuid::new_v4(), PhantomData)
     }
+
+    /**
+    Create a new time-ordered id.
+
+    Ids created later will sort after ids created earlier.
+    */
+    pub fn new_v7() -> Self {
+        Id(Uuid::now_v7(), PhantomData)
+    }
+
+    /**
+    Create an id that's deterministically derived from a name.
+
+    The same namespace and name will always produce the same id.
+    */
+    pub(in crate::domain) fn from_name(namespace: &Uuid, name: &[u8]) -> Self {
+        Id(Uuid::new_v5(namespace, name), PhantomData)
+    }
+
+    /**
+    Create a nil id.
+
+    Nil ids are rejected when parsing, so this should only be used for sentinel values.
+    */
+    pub fn nil() -> Self {
+        Id(Uuid::nil(), PhantomData)
+    }
+
+    fn try_from_uuid(id: Uuid) -> Result<Self, Error> {
+        if id.is_nil() {
+            return Err(error::bad_input("id must not be nil"));
+        }
+
+        Ok(Id(id, PhantomData))
+    }
+}
+
+/** The alphabet for short ids, in ascending order so short ids sort the same as ids. */
+const SHORT_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/** The number of base62 digits needed to encode any 128-bit value. */
+const SHORT_LEN: usize = 22;
+
+impl<T> Id<T> {
+    /**
+    Get a compact base62 representation of the id.
+
+    Short ids are always 22 characters long. The `Display` implementation is still the
+    canonical representation of an id, but the short form is friendlier in URLs.
+    */
+    pub fn to_short_string(&self) -> String {
+        let mut value = self.0.as_u128();
+        let mut short = [b'0'; SHORT_LEN];
+
+        for digit in short.iter_mut().rev() {
+            *digit = SHORT_ALPHABET[(value % 62) as usize];
+            value /= 62;
+        }
+
+        short.iter().map(|&digit| digit as char).collect()
+    }
+
+    /**
+    Parse an id from its compact base62 representation.
+
+    The input must be exactly 22 characters long and decode to a 128-bit value that isn't nil.
+    */
+    pub fn parse_short(short: &str) -> Result<Self, Error> {
+        if short.len() != SHORT_LEN {
+            return Err(error::bad_input(format!(
+                "short id must be {} characters",
+                SHORT_LEN
+            )));
+        }
+
+        let mut value = 0u128;
+        for c in short.bytes() {
+            let digit = match c {
+                b'0'..=b'9' => c - b'0',
+                b'A'..=b'Z' => c - b'A' + 10,
+                b'a'..=b'z' => c - b'a' + 36,
+                _ => return Err(error::bad_input("short id must be base62")),
+            };
+
+            value = value
+                .checked_mul(62)
+                .and_then(|value| value.checked_add(digit as u128))
+                .ok_or_else(|| error::bad_input("short id is out of range"))?;
+        }
+
+        Id::try_from_uuid(Uuid::from_u128(value))
+    }
+}
+
+/**
+A short name for a kind of id.
+
+Tags are used to tell different kinds of ids apart when they're displayed,
+like `order_67e55044-10b1-426f-9247-bb680e5fe0c8`.
+*/
+pub trait IdTag {
+    const TAG: &'static str;
+}
+
+/** Displays an id prefixed with its tag. */
+pub struct TaggedId<T>(Id<T>);
+
+impl<T: IdTag> fmt::Display for TaggedId<T> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_{}", T::TAG, self.0)
+    }
+}
+
+impl<T: IdTag> Id<T> {
+    /**
+    Display the id prefixed with its tag.
+
+    The `Display` implementation is still the canonical representation of an id,
+    but the tagged form is easier to read in logs.
+    */
+    pub fn tagged(&self) -> TaggedId<T> {
+        TaggedId(*self)
+    }
+
+    /**
+    Parse an id from its tagged representation.
+
+    The tag must match the kind of id being parsed, and the id can't be nil.
+    */
+    pub fn parse_tagged(tagged: &str) -> Result<Self, Error> {
+        // Ids never contain underscores, but tags might
+        let (tag, id) = tagged
+            .rsplit_once('_')
+            .ok_or_else(|| error::bad_input(format!("id must have a `{}_` prefix", T::TAG)))?;
+
+        if tag != T::TAG {
+            return Err(error::bad_input(format!(
+                "expected a `{}` id but got a `{}` id",
+                T::TAG,
+                tag
+            )));
+        }
+
+        Id::try_from_uuid(Uuid::parse_str(id).map_err(error::bad_input)?)
+    }
 }
 
 impl<'a, T> TryFrom<&'a str> for Id<T> {
     type Error = Error;
 
     fn try_from(id: &'a str) -> Result<Self, Self::Error> {
-        Ok(Id(Uuid::parse_str(id)?, PhantomData))
+        Id::try_from_uuid(Uuid::parse_str(id).map_err(error::bad_input)?)
+    }
+}
+
+impl<T> FromStr for Id<T> {
+    type Err = Error;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Id::try_from(id)
     }
 }
 
@@ -121,13 +287,90 @@ impl<T> Serialize for Id<T> {
     }
 }
 
+/**
+Deserializes ids from strings or bytes.
+
+Strings can be hyphenated or simple UUIDs in any case, and are parsed without being copied.
+Bytes need to be exactly 16 long, either as a byte buffer or a sequence of numbers.
+*/
+struct IdVisitor<T>(PhantomData<T>);
+
+impl<'de, T> de::Visitor<'de> for IdVisitor<T> {
+    type Value = Id<T>;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(
+            "an id as a hyphenated UUID like `67e55044-10b1-426f-9247-bb680e5fe0c8`, \
+            a simple UUID like `67e5504410b1426f9247bb680e5fe0c8`, or 16 bytes",
+        )
+    }
+
+    fn visit_str<E>(self, id: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let id =
+            Uuid::parse_str(id).map_err(|_| E::invalid_value(de::Unexpected::Str(id), &self))?;
+
+        Id::try_from_uuid(id).map_err(E::custom)
+    }
+
+    fn visit_bytes<E>(self, id: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let id = Uuid::from_slice(id).map_err(|_| E::invalid_length(id.len(), &self))?;
+
+        Id::try_from_uuid(id).map_err(E::custom)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut bytes = [0u8; 16];
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(de::Error::invalid_length(17, &self));
+        }
+
+        self.visit_bytes(&bytes)
+    }
+}
+
 impl<'de, T> Deserialize<'de> for Id<T> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let id = Uuid::deserialize(deserializer)?;
-        Ok(Id(id, PhantomData))
+        // Human-readable formats can describe themselves, so ids can be strings or sequences of bytes
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(IdVisitor(PhantomData))
+        } else {
+            deserializer.deserialize_bytes(IdVisitor(PhantomData))
+        }
+    }
+}
+
+/** Ids are stored in SQLite as hyphenated UUIDs in `TEXT` columns. */
+#[cfg(feature = "sqlite")]
+impl<T> rusqlite::types::ToSql for Id<T> {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.to_string().into())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<T> rusqlite::types::FromSql for Id<T> {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        Id::try_from(value.as_str()?)
+            .map_err(|err| rusqlite::types::FromSqlError::Other(Box::new(err)))
     }
 }
 
@@ -139,16 +382,52 @@ Items that need to generate an id should depend on an `IdProvider` rather than t
 #[auto_impl(&, Arc)]
 pub trait IdProvider<T> {
     fn get(&self) -> Result<Id<T>, Error>;
+
+    /**
+    Get a batch of new ids at once.
+
+    Providers that can reserve a range of ids more efficiently than generating them
+    one at a time should override this.
+    */
+    fn ids(&self, n: usize) -> Result<Vec<Id<T>>, Error> {
+        (0..n).map(|_| self.get()).collect()
+    }
 }
 
+/** A shared `IdProvider` that can be stored in a resolver. */
+pub(in crate::domain) type DynIdProvider<T> = Arc<dyn IdProvider<T> + Send + Sync>;
+
 impl<T> IdProvider<T> for Id<T> {
     fn get(&self) -> Result<Id<T>, Error> {
         Ok(*self)
     }
 }
 
-/** Generate a new `Id` randomly. */
-pub struct NextId<T>(PhantomData<T>);
+/**
+The strategy to use when generating new ids.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IdStrategy {
+    /** Random (v4) ids. */
+    #[default]
+    Random,
+    /**
+    Time-ordered (v7) ids.
+
+    Ids generated later will sort after ids generated earlier, so they're friendlier
+    to ordered storage and listings.
+    */
+    TimeOrdered,
+}
+
+/** Generate a new `Id` using some strategy. Ids are random by default. */
+pub struct NextId<T>(IdStrategy, PhantomData<T>);
+
+impl<T> Clone for NextId<T> {
+    fn clone(&self) -> Self {
+        NextId(self.0, PhantomData)
+    }
+}
 
 impl<T> Default for NextId<T> {
     fn default() -> Self {
@@ -158,11 +437,22 @@ impl<T> Default for NextId<T> {
 
 impl<T> NextId<T> {
     pub fn new() -> Self {
-        NextId(PhantomData)
+        Self::with_strategy(IdStrategy::default())
+    }
+
+    pub fn v7() -> Self {
+        Self::with_strategy(IdStrategy::TimeOrdered)
+    }
+
+    pub fn with_strategy(strategy: IdStrategy) -> Self {
+        NextId(strategy, PhantomData)
     }
 
     pub fn next(&self) -> Id<T> {
-        Id::new()
+        match self.0 {
+            IdStrategy::Random => Id::new(),
+            IdStrategy::TimeOrdered => Id::new_v7(),
+        }
     }
 }
 
@@ -170,4 +460,506 @@ impl<T> IdProvider<T> for NextId<T> {
     fn get(&self) -> Result<Id<T>, Error> {
         Ok(self.next())
     }
+
+    fn ids(&self, n: usize) -> Result<Vec<Id<T>>, Error> {
+        Ok((0..n).map(|_| self.next()).collect())
+    }
+}
+
+/**
+Generate a deterministic sequence of `Id`s from a seed.
+
+Two providers with the same seed will produce the same sequence of ids.
+Providers for kinds of ids with different tags will produce different sequences, even with the
+same seed. Sequences only depend on the tag, so they don't change if the type is renamed or moved.
+This is useful for reproducible fixtures, but shouldn't be used outside of tests.
+*/
+pub struct SeededIdProvider<T> {
+    seed: u64,
+    next: AtomicU64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> SeededIdProvider<T> {
+    pub fn new(seed: u64) -> Self {
+        SeededIdProvider {
+            seed,
+            next: AtomicU64::new(0),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: IdTag> SeededIdProvider<T> {
+    pub fn next(&self) -> Id<T> {
+        let next = self.next.fetch_add(1, atomic::Ordering::Relaxed);
+
+        self.nth(next)
+    }
+
+    fn nth(&self, n: u64) -> Id<T> {
+        // Ids are derived from the tag of the id, the seed, and the position in the sequence
+        let mut name = T::TAG.as_bytes().to_vec();
+        name.extend_from_slice(&self.seed.to_le_bytes());
+        name.extend_from_slice(&n.to_le_bytes());
+
+        Id::from_name(&Uuid::NAMESPACE_OID, &name)
+    }
+}
+
+impl<T: IdTag> IdProvider<T> for SeededIdProvider<T> {
+    fn get(&self) -> Result<Id<T>, Error> {
+        Ok(self.next())
+    }
+
+    fn ids(&self, n: usize) -> Result<Vec<Id<T>>, Error> {
+        // Reserve the whole range of the sequence at once
+        let start = self.next.fetch_add(n as u64, atomic::Ordering::Relaxed);
+
+        Ok((start..start + n as u64).map(|i| self.nth(i)).collect())
+    }
+}
+
+/**
+Take `Id`s from a pre-chosen sequence.
+
+Each id in the sequence is returned once, in order. Once the sequence is exhausted the provider
+returns an error instead of an id.
+This is useful in tests that need to know exactly which ids will be used.
+*/
+pub struct IterIdProvider<T> {
+    ids: Mutex<Box<dyn Iterator<Item = Id<T>> + Send>>,
+}
+
+impl<T> IterIdProvider<T> {
+    pub fn new<I>(ids: I) -> Self
+    where
+        I: IntoIterator<Item = Id<T>>,
+        I::IntoIter: Send + 'static,
+    {
+        IterIdProvider {
+            ids: Mutex::new(Box::new(ids.into_iter())),
+        }
+    }
+}
+
+impl<T> IdProvider<T> for IterIdProvider<T> {
+    fn get(&self) -> Result<Id<T>, Error> {
+        self.ids
+            .lock()
+            .unwrap()
+            .next()
+            .ok_or_else(|| error::msg("the sequence of ids is exhausted"))
+    }
+}
+
+/**
+A strategy that generates random ids for property tests.
+
+Ids are drawn from any 128-bit value except nil, because nil ids are rejected when they're parsed.
+*/
+#[cfg(any(test, feature = "proptest"))]
+pub fn id_strategy<T>() -> impl proptest::strategy::Strategy<Value = Id<T>> {
+    <Id<T> as proptest::arbitrary::Arbitrary>::arbitrary()
+}
+
+#[cfg(any(test, feature = "proptest"))]
+impl<T> proptest::arbitrary::Arbitrary for Id<T> {
+    type Parameters = ();
+    type Strategy = proptest::strategy::Map<std::ops::RangeInclusive<u128>, fn(u128) -> Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        proptest::strategy::Strategy::prop_map(1..=u128::MAX, |id| {
+            Id(Uuid::from_u128(id), PhantomData)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    use crate::domain::infra::App;
+
+    #[test]
+    fn v7_ids_are_ordered() {
+        let next = NextId::<()>::v7();
+
+        let ids: Vec<_> = (0..100).map(|_| next.next()).collect();
+
+        let mut sorted = ids.clone();
+        sorted.sort();
+
+        assert_eq!(ids, sorted);
+    }
+
+    #[test]
+    fn id_strategy_is_per_resolver() {
+        let app = App::new();
+
+        let random = app.root_resolver.by_ref();
+        let ordered = app.root_resolver.with_id_strategy(IdStrategy::TimeOrdered);
+
+        assert_eq!(4, random.product_id().get().unwrap().0.get_version_num());
+        assert_eq!(7, ordered.product_id().get().unwrap().0.get_version_num());
+    }
+
+    #[test]
+    fn seeded_ids_are_deterministic() {
+        let a = SeededIdProvider::<Tagged>::new(42);
+        let b = SeededIdProvider::<Tagged>::new(42);
+
+        let a: Vec<_> = (0..10).map(|_| a.next()).collect();
+        let b: Vec<_> = (0..10).map(|_| b.next()).collect();
+
+        assert_eq!(a, b);
+
+        let distinct: HashSet<_> = a.iter().collect();
+
+        assert_eq!(a.len(), distinct.len());
+    }
+
+    #[test]
+    fn seeded_ids_differ_by_kind() {
+        let a = SeededIdProvider::<Tagged>::new(42).next();
+        let b = SeededIdProvider::<OtherTagged>::new(42).next();
+
+        assert_ne!(a.0, b.0);
+    }
+
+    #[test]
+    fn seeded_ids_are_per_resolver() {
+        let app = App::new();
+
+        let a = app.root_resolver.with_seeded_ids(42);
+        let b = app.root_resolver.with_seeded_ids(42);
+
+        let a: Vec<_> = (0..10).map(|_| a.order_id().get().unwrap()).collect();
+        let b: Vec<_> = (0..10).map(|_| b.order_id().get().unwrap()).collect();
+
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn default_ids_match_single_calls() {
+        // Only implements `get`, so uses the default `ids`
+        struct Seeded(SeededIdProvider<Tagged>);
+
+        impl IdProvider<Tagged> for Seeded {
+            fn get(&self) -> Result<Id<Tagged>, Error> {
+                self.0.get()
+            }
+        }
+
+        let batch = Seeded(SeededIdProvider::new(42)).ids(5).unwrap();
+
+        let single = SeededIdProvider::new(42);
+        let single: Vec<_> = (0..5).map(|_| single.get().unwrap()).collect();
+
+        assert_eq!(single, batch);
+    }
+
+    #[test]
+    fn seeded_ids_reserve_a_range() {
+        let a = SeededIdProvider::<Tagged>::new(42);
+        let b = SeededIdProvider::<Tagged>::new(42);
+
+        let batch = a.ids(5).unwrap();
+        let single: Vec<_> = (0..5).map(|_| b.next()).collect();
+
+        assert_eq!(single, batch);
+        assert_eq!(a.next(), b.next());
+    }
+
+    #[test]
+    fn iter_ids_are_used_in_order() {
+        let expected = vec![Id::<i32>::new(), Id::new(), Id::new()];
+
+        let provider = IterIdProvider::new(expected.clone());
+
+        assert_eq!(expected[0], provider.get().unwrap());
+        assert_eq!(expected[1..], provider.ids(2).unwrap()[..]);
+
+        assert!(provider.get().is_err());
+    }
+
+    #[test]
+    fn overridden_ids_are_used() {
+        struct Batched<'a>(&'a atomic::AtomicUsize);
+
+        impl IdProvider<i32> for Batched<'_> {
+            fn get(&self) -> Result<Id<i32>, Error> {
+                Ok(Id::new())
+            }
+
+            fn ids(&self, n: usize) -> Result<Vec<Id<i32>>, Error> {
+                self.0.fetch_add(1, atomic::Ordering::Relaxed);
+
+                Ok((0..n).map(|_| Id::new()).collect())
+            }
+        }
+
+        fn ids<P: IdProvider<i32>>(provider: P) -> Vec<Id<i32>> {
+            provider.ids(3).unwrap()
+        }
+
+        let calls = atomic::AtomicUsize::default();
+        let provider = Batched(&calls);
+
+        // Call through a reference and an `Arc` to make sure the override is forwarded
+        assert_eq!(3, ids(&provider).len());
+        assert_eq!(3, ids(Arc::new(provider)).len());
+
+        assert_eq!(2, calls.load(atomic::Ordering::Relaxed));
+    }
+
+    #[test]
+    fn short_ids_round_trip() {
+        let edges = [
+            Id::<i32>(Uuid::from_u128(1), PhantomData),
+            Id(Uuid::from_u128(u128::MAX), PhantomData),
+        ];
+
+        for id in edges.into_iter().chain((0..1_000).map(|_| Id::new())) {
+            let short = id.to_short_string();
+
+            assert_eq!(SHORT_LEN, short.len());
+            assert_eq!(id, Id::parse_short(&short).unwrap());
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn id_round_trips_through_sqlite_as_text() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+
+        let id = Id::<i32>::new();
+
+        let (text, parsed): (String, Id<i32>) = connection
+            .query_row("SELECT ?1, ?1", [id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+
+        assert_eq!(id.to_string(), text);
+        assert_eq!(id, parsed);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn arbitrary_ids_round_trip_through_json(id in id_strategy::<i32>()) {
+            let json = serde_json::to_string(&id).unwrap();
+            let parsed: Id<i32> = serde_json::from_str(&json).unwrap();
+
+            proptest::prop_assert_eq!(id, parsed);
+        }
+    }
+
+    #[test]
+    fn short_ids_are_distinct() {
+        let ids: Vec<Id<i32>> = (0..1_000).map(|_| Id::new()).collect();
+
+        let mut shorts: Vec<_> = ids.iter().map(|id| id.to_short_string()).collect();
+        shorts.sort();
+        shorts.dedup();
+
+        assert_eq!(ids.len(), shorts.len());
+    }
+
+    #[test]
+    fn short_ids_sort_like_ids() {
+        let mut ids: Vec<Id<i32>> = (0..100).map(|_| Id::new()).collect();
+        ids.sort();
+
+        let shorts: Vec<_> = ids.iter().map(|id| id.to_short_string()).collect();
+
+        let mut sorted = shorts.clone();
+        sorted.sort();
+
+        assert_eq!(sorted, shorts);
+    }
+
+    #[test]
+    fn err_parse_invalid_short_id() {
+        // Too short, too long, and not base62
+        assert!(Id::<i32>::parse_short("abc").is_err());
+        assert!(Id::<i32>::parse_short("00000000000000000000000").is_err());
+        assert!(Id::<i32>::parse_short("000000000000000000000-0").is_err());
+
+        // Decodes to a value larger than 128 bits
+        assert!(Id::<i32>::parse_short("zzzzzzzzzzzzzzzzzzzzzz").is_err());
+    }
+
+    #[test]
+    fn err_parse_nil_short_id() {
+        let nil = Id::<i32>::nil().to_short_string();
+
+        assert!(Id::<i32>::parse_short(&nil).is_err());
+    }
+
+    struct Tagged;
+
+    impl IdTag for Tagged {
+        const TAG: &'static str = "tagged";
+    }
+
+    struct OtherTagged;
+
+    impl IdTag for OtherTagged {
+        const TAG: &'static str = "other_tagged";
+    }
+
+    #[test]
+    fn tagged_id_roundtrip() {
+        let id = Id::<Tagged>::new();
+
+        let tagged = id.tagged().to_string();
+
+        assert_eq!(format!("tagged_{}", id), tagged);
+        assert_eq!(id, Id::parse_tagged(&tagged).unwrap());
+    }
+
+    #[test]
+    fn tagged_id_with_underscore_roundtrip() {
+        let id = Id::<OtherTagged>::new();
+
+        assert_eq!(id, Id::parse_tagged(&id.tagged().to_string()).unwrap());
+    }
+
+    #[test]
+    fn err_parse_tagged_wrong_tag() {
+        let tagged = Id::<OtherTagged>::new().tagged().to_string();
+
+        assert!(Id::<Tagged>::parse_tagged(&tagged).is_err());
+    }
+
+    #[test]
+    fn err_parse_tagged_missing_tag() {
+        let id = Id::<Tagged>::new().to_string();
+
+        assert!(Id::<Tagged>::parse_tagged(&id).is_err());
+    }
+
+    #[test]
+    fn err_parse_nil_tagged_id() {
+        let nil = Id::<Tagged>::nil().tagged().to_string();
+
+        assert!(Id::<Tagged>::parse_tagged(&nil).is_err());
+    }
+
+    #[test]
+    fn parse_id() {
+        let id = Id::<i32>::new();
+
+        assert_eq!(id, Id::try_from(id.to_string().as_str()).unwrap());
+        assert_eq!(id, id.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn err_parse_nil_id() {
+        let nil = Id::<i32>::nil().to_string();
+
+        assert!(Id::<i32>::try_from(nil.as_str()).is_err());
+        assert!(nil.parse::<Id<i32>>().is_err());
+    }
+
+    mod deserialize {
+        use crate::domain::{
+            orders::{
+                AddOrUpdateProduct,
+                GetOrder,
+                OrderId,
+            },
+            products::ProductId,
+        };
+
+        const HYPHENATED: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+        /** Deserialize a `GetOrder` with the given JSON for its id. */
+        fn get_order(id: &str) -> Result<OrderId, serde_json::Error> {
+            serde_json::from_str::<GetOrder>(&format!(r#"{{"id":{}}}"#, id)).map(|query| query.id)
+        }
+
+        /** Deserialize an `AddOrUpdateProduct` with the given JSON for its product id. */
+        fn add_or_update_product(product_id: &str) -> Result<ProductId, serde_json::Error> {
+            serde_json::from_str::<AddOrUpdateProduct>(&format!(
+                r#"{{"id":"{}","product_id":{},"quantity":1}}"#,
+                HYPHENATED, product_id
+            ))
+            .map(|command| command.product_id)
+        }
+
+        #[test]
+        fn accepts_hyphenated() {
+            let id = get_order(&format!(r#""{}""#, HYPHENATED)).unwrap();
+
+            assert_eq!(HYPHENATED, id.to_string());
+        }
+
+        #[test]
+        fn accepts_simple() {
+            let id = get_order(r#""67e5504410b1426f9247bb680e5fe0c8""#).unwrap();
+
+            assert_eq!(HYPHENATED, id.to_string());
+        }
+
+        #[test]
+        fn accepts_uppercase() {
+            let id = add_or_update_product(&format!(r#""{}""#, HYPHENATED.to_uppercase())).unwrap();
+
+            assert_eq!(HYPHENATED, id.to_string());
+        }
+
+        #[test]
+        fn accepts_bytes() {
+            let bytes = uuid::Uuid::parse_str(HYPHENATED).unwrap().into_bytes();
+
+            let id = add_or_update_product(&serde_json::to_string(&bytes).unwrap()).unwrap();
+
+            assert_eq!(HYPHENATED, id.to_string());
+        }
+
+        #[test]
+        fn rejects_malformed_strings() {
+            for id in [
+                r#""not an id""#,
+                r#""67e55044-10b1-426f-9247""#,
+                r#""67e55044-10b1-426f-9247-bb680e5fe0cz""#,
+            ] {
+                let err = get_order(id).unwrap_err().to_string();
+
+                assert!(err.contains("invalid value"), "{}", err);
+                assert!(err.contains("hyphenated UUID"), "{}", err);
+            }
+        }
+
+        #[test]
+        fn rejects_nil() {
+            let err = get_order(r#""00000000-0000-0000-0000-000000000000""#)
+                .unwrap_err()
+                .to_string();
+
+            assert!(err.contains("nil"), "{}", err);
+        }
+
+        #[test]
+        fn rejects_wrong_number_of_bytes() {
+            for bytes in [vec![1u8; 15], vec![1u8; 17]] {
+                let err = add_or_update_product(&serde_json::to_string(&bytes).unwrap())
+                    .unwrap_err()
+                    .to_string();
+
+                assert!(err.contains("invalid length"), "{}", err);
+                assert!(err.contains("16 bytes"), "{}", err);
+            }
+        }
+
+        #[test]
+        fn rejects_other_types() {
+            let err = add_or_update_product("42").unwrap_err().to_string();
+
+            assert!(err.contains("invalid type"), "{}", err);
+            assert!(err.contains("hyphenated UUID"), "{}", err);
+        }
+    }
 }
\ No newline at end of file
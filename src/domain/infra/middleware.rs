@@ -0,0 +1,350 @@
+/*! Contains the `CommandMiddleware` trait for cross-cutting concerns around commands. */
+
+use std::{
+    any,
+    sync::Arc,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::{
+    domain::{
+        infra::*,
+        Error,
+    },
+    store::TransactionId,
+};
+
+/**
+The output of a command that middleware can inspect.
+
+Middleware only needs to know whether a command failed, not what it returned.
+*/
+pub trait CommandOutput {
+    fn err(&self) -> Option<&Error>;
+}
+
+impl<T> CommandOutput for Result<T, Error> {
+    fn err(&self) -> Option<&Error> {
+        self.as_ref().err()
+    }
+}
+
+/** Details about a single execution of a command. */
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    name: &'static str,
+    transaction: TransactionId,
+    started: Instant,
+}
+
+impl CommandContext {
+    /** The name of the command's input type. */
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /**
+    The id of the transaction the command is running in.
+
+    Commands run outside of `App::transaction` auto-commit, and get a new transaction each time
+    one is resolved. The id won't match the one the command logs itself in that case.
+    */
+    pub fn transaction(&self) -> TransactionId {
+        self.transaction
+    }
+
+    /** How long it's been since the command started. */
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/**
+Middleware that runs around every command resolved from a `Resolver`.
+
+Middleware is run as a stack. The first middleware added is the outermost, so its `before` is
+called first and its `after` is called last. Middleware can observe commands, but can't change
+their input or output.
+*/
+pub trait CommandMiddleware {
+    /** Called before the command runs. */
+    fn before(&self, command: &CommandContext);
+
+    /** Called after the command runs with the error it failed with, if any. */
+    fn after(&self, command: &CommandContext, err: Option<&Error>);
+}
+
+/** A shared `CommandMiddleware` that can be stored in a resolver. */
+pub(in crate::domain) type DynCommandMiddleware = Arc<dyn CommandMiddleware + Send + Sync>;
+
+/** Middleware that logs when commands start and finish. */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LoggingMiddleware;
+
+impl CommandMiddleware for LoggingMiddleware {
+    fn before(&self, command: &CommandContext) {
+        debug!(
+            "executing command `{}` (transaction `{}`)",
+            command.name(),
+            command.transaction()
+        );
+    }
+
+    fn after(&self, command: &CommandContext, err: Option<&Error>) {
+        match err {
+            None => debug!(
+                "executed command `{}` (transaction `{}`)",
+                command.name(),
+                command.transaction()
+            ),
+            Some(err) => warn!(
+                "command `{}` failed: {} (transaction `{}`)",
+                command.name(),
+                err,
+                command.transaction()
+            ),
+        }
+    }
+}
+
+/** Middleware that logs how long commands take. */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TimingMiddleware;
+
+impl CommandMiddleware for TimingMiddleware {
+    fn before(&self, _: &CommandContext) {}
+
+    fn after(&self, command: &CommandContext, _: Option<&Error>) {
+        info!(
+            "command `{}` took {:?} (transaction `{}`)",
+            command.name(),
+            command.elapsed(),
+            command.transaction()
+        );
+    }
+}
+
+impl App {
+    /**
+    Run some middleware around every command.
+
+    Middleware added first wraps middleware added after it.
+    */
+    pub fn with_command_middleware(
+        self,
+        middleware: impl CommandMiddleware + Send + Sync + 'static,
+    ) -> Self {
+        let mut stack = self.root_resolver.command_middleware();
+        stack.push(Arc::new(middleware));
+
+        App {
+            root_resolver: Resolver {
+                command_middleware: Register::once(move |_| stack.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        }
+    }
+}
+
+impl Resolver {
+    pub(in crate::domain) fn command_middleware(&self) -> Vec<DynCommandMiddleware> {
+        self.resolve(&self.command_middleware)
+    }
+
+    /**
+    Run a command through the middleware stack.
+    */
+    pub(in crate::domain) async fn run_command_middleware<TArgs, TFuture>(
+        &self,
+        command: TFuture,
+    ) -> TArgs::Output
+    where
+        TArgs: CommandArgs,
+        TArgs::Output: CommandOutput,
+        TFuture: ::std::future::Future<Output = TArgs::Output>,
+    {
+        let middleware = self.command_middleware();
+
+        if middleware.is_empty() {
+            return command.await;
+        }
+
+        let context = CommandContext {
+            name: any::type_name::<TArgs>(),
+            transaction: self.active_transaction().id(),
+            started: Instant::now(),
+        };
+
+        for middleware in &middleware {
+            middleware.before(&context);
+        }
+
+        let output = command.await;
+
+        for middleware in middleware.iter().rev() {
+            middleware.after(&context, output.err());
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    use crate::{
+        domain::{
+            customers::{
+                CreateCustomer,
+                CustomerId,
+            },
+            orders::{
+                CreateOrder,
+                OrderId,
+            },
+            ErrorKind,
+        },
+        logger,
+    };
+
+    struct Recording {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CommandMiddleware for Recording {
+        fn before(&self, _: &CommandContext) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} before", self.name));
+        }
+
+        fn after(&self, _: &CommandContext, err: Option<&Error>) {
+            self.calls.lock().unwrap().push(format!(
+                "{} after {}",
+                self.name,
+                if err.is_some() { "err" } else { "ok" }
+            ));
+        }
+    }
+
+    fn recording_app() -> (App, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(vec![]));
+
+        let app = App::new()
+            .with_command_middleware(Recording {
+                name: "outer",
+                calls: calls.clone(),
+            })
+            .with_command_middleware(Recording {
+                name: "inner",
+                calls: calls.clone(),
+            });
+
+        (app, calls)
+    }
+
+    #[tokio::test]
+    async fn middleware_runs_outermost_first() {
+        let (app, calls) = recording_app();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer {
+                    id: CustomerId::new(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "outer before",
+                "inner before",
+                "inner after ok",
+                "outer after ok"
+            ],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_propagate_through_middleware() {
+        let (app, calls) = recording_app();
+
+        let customer_id = CustomerId::new();
+
+        let err = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id: OrderId::new(),
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&customer_id.to_string()));
+
+        assert_eq!(
+            vec![
+                "outer before",
+                "inner before",
+                "inner after err",
+                "outer after err"
+            ],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn logging_and_timing_middleware_log_commands() {
+        logger::test_support::init();
+
+        let app = App::new()
+            .with_command_middleware(LoggingMiddleware)
+            .with_command_middleware(TimingMiddleware);
+
+        let transaction_id = app
+            .transaction(|resolver| async move {
+                let transaction_id = resolver.active_transaction().id();
+
+                resolver
+                    .create_customer_command()
+                    .execute(CreateCustomer {
+                        id: CustomerId::new(),
+                    })
+                    .await?;
+
+                Ok::<_, Error>(transaction_id)
+            })
+            .await
+            .unwrap();
+
+        let messages = logger::test_support::messages_containing(&transaction_id.to_string());
+
+        for expected in ["executing command", "executed command", "took"] {
+            assert!(
+                messages.iter().any(|message| message.contains(expected)),
+                "expected a message containing `{}` in {:?}",
+                expected,
+                messages
+            );
+        }
+    }
+}
\ No newline at end of file
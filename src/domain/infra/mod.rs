@@ -5,21 +5,44 @@ This module contains shared types like `Currency` and services like `Resolver` t
 domain modules can use.
 */
 
+pub(in crate::domain) mod cache;
+pub(in crate::domain) mod clock;
 pub(in crate::domain) mod currency;
 pub(in crate::domain) mod entity;
+pub(in crate::domain) mod flush;
 pub mod func;
 pub(in crate::domain) mod id;
+pub(in crate::domain) mod middleware;
+pub(in crate::domain) mod repository;
 pub(in crate::domain) mod resolver;
+pub(in crate::domain) mod retry;
+pub(in crate::domain) mod sequence;
+pub(in crate::domain) mod span;
+#[cfg(feature = "sqlite")]
+pub(in crate::domain) mod sqlite;
+#[cfg(test)]
+pub(in crate::domain) mod test_support;
 pub(in crate::domain) mod transaction;
 pub(in crate::domain) mod version;
+pub(in crate::domain) mod wal;
 
 pub use self::{
+    cache::*,
+    clock::*,
     currency::*,
+    flush::*,
     func::*,
     id::*,
+    middleware::*,
     resolver::*,
+    retry::*,
+    sequence::*,
     transaction::*,
     version::*,
 };
 
-pub(in crate::domain) use self::entity::*;
\ No newline at end of file
+pub(in crate::domain) use self::{
+    entity::*,
+    repository::*,
+    span::*,
+};
\ No newline at end of file
@@ -0,0 +1,214 @@
+/*!
+Contains the `Repository` trait for persisting any kind of entity.
+
+Each entity has its own store with methods that suit it. A repository is a smaller, uniform view
+over those stores, so code that works the same way for any entity doesn't need to know which
+store it's using.
+
+Entities that are stored as a single piece of data can share the `InMemoryRepository`, so their
+stores only need to adapt it to their own methods.
+*/
+
+use std::ops::ControlFlow;
+
+use crate::{
+    domain::{
+        infra::*,
+        Error,
+    },
+    store::{
+        Transaction,
+        TransactionStore,
+        TransactionValueStore,
+    },
+};
+
+/** A place to persist and fetch entities of one kind. */
+pub(in crate::domain) trait Repository<TEntity: Entity> {
+    /**
+    Get an entity as it's seen by a transaction.
+
+    That's the transaction's own uncommitted changes to it, or its latest committed version.
+    */
+    fn get(
+        &self,
+        transaction: &Transaction,
+        id: TEntity::Id,
+    ) -> Result<Option<TEntity>, TEntity::Error>;
+
+    /**
+    Save an entity as part of a transaction.
+
+    The save fails if the entity has been changed since it was fetched.
+    */
+    fn save(&self, transaction: &Transaction, entity: TEntity) -> Result<(), TEntity::Error>;
+}
+
+/** An entity that can be kept in an `InMemoryRepository`. */
+pub(in crate::domain) trait InMemoryEntity: Entity {
+    /** Should be the inverse of `into_data`. */
+    fn from_data(data: Self::Data) -> Self;
+
+    /** Should be the result of calling `self.into_data()`, with its version replaced. */
+    fn into_data_with_version(self, version: Self::Version) -> Self::Data;
+}
+
+/**
+An in-memory repository for entities whose data is `TData`.
+
+Entities are stored as their data, with its version moved on each time it's saved.
+*/
+pub(in crate::domain) struct InMemoryRepository<TData>(TransactionValueStore<TData>);
+
+impl<TData: Clone> InMemoryRepository<TData> {
+    pub(in crate::domain) fn new(transaction_store: TransactionStore) -> Self {
+        InMemoryRepository(TransactionValueStore::new(transaction_store))
+    }
+
+    /** Create a repository that locks its entities in shards instead of all at once. */
+    #[cfg(feature = "concurrent")]
+    pub(in crate::domain) fn concurrent(transaction_store: TransactionStore) -> Self {
+        InMemoryRepository(TransactionValueStore::concurrent(transaction_store))
+    }
+
+    /** Get the data for every entity that matches a predicate, as it's seen by a transaction. */
+    pub(in crate::domain) fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&TData) -> bool,
+    ) -> Vec<TData> {
+        self.0
+            .get_all(transaction, predicate)
+            .map(|(_, data)| data)
+            .collect()
+    }
+
+    /** Visit the data for every stored entity without cloning it, until `f` breaks. */
+    pub(in crate::domain) fn scan(&self, f: &mut dyn FnMut(&TData) -> ControlFlow<()>) {
+        self.0.scan(f)
+    }
+
+    /**
+    Put back an entity that was persisted somewhere else, like a file.
+
+    Unlike saving, the entity keeps the version it was persisted with and is visible straight away.
+    */
+    pub(in crate::domain) fn restore<TEntity>(&self, entity: TEntity) -> Result<(), Error>
+    where
+        TEntity:
+            InMemoryEntity<Id = Id<TData>, Version = Version<TData>, Data = TData, Error = Error>,
+        TData: IdTag,
+    {
+        let (id, version) = (entity.id(), entity.version());
+
+        self.0
+            .set(
+                &Transaction::none(),
+                id,
+                None::<Version<TData>>,
+                version,
+                entity.into_data_with_version(version),
+            )
+            .map_err(Version::stale_write(id))?;
+
+        Ok(())
+    }
+}
+
+impl<TEntity, TData> Repository<TEntity> for InMemoryRepository<TData>
+where
+    TEntity: InMemoryEntity<Id = Id<TData>, Version = Version<TData>, Data = TData, Error = Error>,
+    TData: IdTag + Clone,
+{
+    fn get(&self, transaction: &Transaction, id: Id<TData>) -> Result<Option<TEntity>, Error> {
+        if let Some((version, data)) = self.0.get(transaction, id) {
+            let entity = TEntity::from_data(data);
+
+            assert_eq!(version, entity.version().into());
+
+            Ok(Some(entity))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn save(&self, transaction: &Transaction, entity: TEntity) -> Result<(), Error> {
+        let (id, version) = (entity.id(), entity.version());
+        let next = version.next()?;
+
+        self.0
+            .set(
+                transaction,
+                id,
+                Some(version),
+                next,
+                entity.into_data_with_version(next),
+            )
+            .map_err(Version::stale_write(id))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(in crate::domain) mod test_support {
+    use std::fmt::Debug;
+
+    use super::*;
+
+    /** Check that an entity can be saved and fetched through a repository. */
+    pub(in crate::domain) fn assert_repository_round_trip<TEntity>(
+        repository: impl Repository<TEntity>,
+        entity: TEntity,
+    ) where
+        TEntity: Entity + Clone,
+        TEntity::Version: PartialEq + Debug,
+        TEntity::Error: Debug,
+    {
+        let transaction = Transaction::none();
+
+        assert!(repository.get(&transaction, entity.id()).unwrap().is_none());
+
+        repository.save(&transaction, entity.clone()).unwrap();
+
+        let saved = repository.get(&transaction, entity.id()).unwrap().unwrap();
+
+        // Saving an entity moves it to a new version
+        assert_ne!(entity.version(), saved.version());
+
+        // Saving the same version again is a conflict
+        assert!(repository.save(&Transaction::none(), entity).is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        test_support::assert_repository_round_trip,
+        *,
+    };
+
+    use crate::domain::{
+        customers::{
+            model::test_data::CustomerBuilder,
+            CustomerData,
+        },
+        products::{
+            model::test_data::ProductBuilder,
+            ProductData,
+        },
+    };
+
+    #[test]
+    fn entities_share_in_memory_repository() {
+        assert_repository_round_trip(
+            InMemoryRepository::<ProductData>::new(Default::default()),
+            ProductBuilder::new().build(),
+        );
+
+        assert_repository_round_trip(
+            InMemoryRepository::<CustomerData>::new(Default::default()),
+            CustomerBuilder::new().build(),
+        );
+    }
+}
\ No newline at end of file
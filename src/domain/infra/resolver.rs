@@ -1,14 +1,30 @@
 /*! Contains the root `Resolver` type. */
 
-use std::sync::Arc;
+use std::{
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    sync::Arc,
+};
 
 use once_cell::sync::OnceCell;
 
 use crate::domain::{
     customers::resolver::CustomersResolver,
-    infra::transaction::resolver::TransactionsResolver,
+    error,
+    events::Events,
+    infra::{
+        transaction::resolver::TransactionsResolver,
+        DynClock,
+        DynCommandMiddleware,
+        Flush,
+        IdStrategy,
+        SystemClock,
+    },
     orders::resolver::OrdersResolver,
     products::resolver::ProductsResolver,
+    Error,
 };
 
 /**
@@ -32,9 +48,68 @@ impl App {
                 products_resolver: Default::default(),
                 orders_resolver: Default::default(),
                 customers_resolver: Default::default(),
+                clock: Register::once(|_| Arc::new(SystemClock) as DynClock),
+                events: Register::once(|_| Events::new()),
+                command_middleware: Register::factory(|_| vec![]),
             },
         }
     }
+
+    /**
+    Use a different strategy for generating new ids.
+
+    Ids are random by default.
+    */
+    pub fn with_id_strategy(self, strategy: IdStrategy) -> Self {
+        App {
+            root_resolver: self.root_resolver.with_id_strategy(strategy),
+        }
+    }
+
+    /**
+    Generate deterministic ids from a seed instead of using a strategy.
+
+    This is useful for reproducible fixtures in tests.
+    Ids generated by apps with the same seed will be the same.
+    */
+    pub fn with_seeded_ids(self, seed: u64) -> Self {
+        App {
+            root_resolver: self.root_resolver.with_seeded_ids(seed),
+        }
+    }
+
+    /**
+    Keep products and orders in in-memory stores that lock them in shards.
+
+    Writers to different products or orders don't block each other, which helps when many
+    requests change the catalog or orders at once. Stores are in memory with a single lock
+    by default.
+    */
+    #[cfg(feature = "concurrent")]
+    pub fn with_concurrent_stores(self) -> Self {
+        App {
+            root_resolver: self.root_resolver.with_concurrent_stores(),
+        }
+    }
+
+    /**
+    Check that everything the app depends on can be resolved.
+
+    See `Resolver::self_check`.
+    */
+    pub fn self_check(&self) -> Result<(), Error> {
+        self.root_resolver.self_check()
+    }
+
+    /**
+    Flush the stores the app writes to before it stops.
+
+    The app is borrowed so it can be shut down from wherever it's been shared, like Rocket's state.
+    See `Resolver::shutdown`.
+    */
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.root_resolver.by_ref().shutdown()
+    }
 }
 
 /**
@@ -45,21 +120,203 @@ The goal of the resolver is to let consumers construct components without having
 The `Resolver` type wraps resolvers from other modules.
 Private implementation details live on the wrapped resolvers.
 Commands and queries are resolved from this `Resolver`.
+
+Resolvers are cheap to clone and can be shared between threads.
+Clones share the same registrations, so they resolve the same stores.
 */
+#[derive(Clone)]
 pub struct Resolver {
     pub(in crate::domain) transactions_resolver: TransactionsResolver,
     pub(in crate::domain) products_resolver: ProductsResolver,
     pub(in crate::domain) orders_resolver: OrdersResolver,
     pub(in crate::domain) customers_resolver: CustomersResolver,
+    pub(in crate::domain) clock: Register<DynClock>,
+    pub(in crate::domain) events: Register<Events>,
+    pub(in crate::domain) command_middleware: Register<Vec<DynCommandMiddleware>>,
 }
 
+/**
+The names of all commands and queries that can be resolved.
+
+This needs to be kept in sync with the commands and queries added to the `Resolver`,
+and with the ones constructed by `self_check`.
+*/
+const CAPABILITIES: &[&str] = &[
+    "add_or_update_product_command",
+    "adjust_all_prices_command",
+    "confirm_payment_command",
+    "consolidate_order_command",
+    "create_customer_command",
+    "create_order_command",
+    "create_product_command",
+    "deactivate_product_command",
+    "delete_order_command",
+    "move_line_item_command",
+    "place_order_command",
+    "set_discount_eligible_command",
+    "set_product_title_command",
+    "set_recipient_command",
+    "update_product_command",
+    "cached_get_product_query",
+    "can_place_order_query",
+    "export_products_query",
+    "generate_receipt_query",
+    "get_customer_purchased_products_query",
+    "get_customer_query",
+    "get_customer_with_orders_query",
+    "get_order_query",
+    "get_order_summaries_for_customer_query",
+    "get_order_with_products_query",
+    "get_orders_modified_between_query",
+    "get_product_query",
+    "get_product_summaries_query",
+    "get_top_products_query",
+    "preview_reorder_query",
+];
+
 impl Resolver {
+    /**
+    Get the names of all commands and queries that can be resolved.
+
+    This is useful for tooling that needs to know what the domain is capable of.
+    */
+    pub fn capabilities() -> &'static [&'static str] {
+        CAPABILITIES
+    }
+
+    /**
+    Check that everything the resolver depends on can be resolved.
+
+    Stores, id providers, and other dependencies are resolved once, along with every command and
+    query in `capabilities`. This is useful on startup to fail fast if the resolver is misconfigured,
+    rather than when the first request needs something that's missing.
+
+    All failures are collected into a single error.
+    */
+    pub fn self_check(&self) -> Result<(), Error> {
+        let check = self.run_self_check();
+
+        if check.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(error::msg(format!(
+                "failed to resolve {}",
+                check.failed.join(", ")
+            )))
+        }
+    }
+
+    /**
+    Flush every store the resolver writes to, so writes they're holding on to aren't lost.
+
+    This should be called once when the app stops. Every store is flushed, even if an earlier one
+    fails, and all failures are collected into a single error. Nothing is flushed when a resolver is
+    dropped, because clones share the same stores and dropping one doesn't mean the others are done.
+    */
+    pub fn shutdown(self) -> Result<(), Error> {
+        let mut shutdown = Shutdown::default();
+
+        self.shutdown_products(&mut shutdown);
+        self.shutdown_orders(&mut shutdown);
+        self.shutdown_customers(&mut shutdown);
+
+        if shutdown.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(error::msg(format!(
+                "failed to flush {}",
+                shutdown.failed.join(", ")
+            )))
+        }
+    }
+
+    fn run_self_check(&self) -> SelfCheck {
+        let mut check = SelfCheck::default();
+
+        check.resolves("transaction_store", || self.transaction_store());
+        check.resolves("active_transaction", || self.active_transaction());
+        check.resolves("clock", || self.clock());
+        check.resolves("events", || self.events());
+        check.resolves("command_middleware", || self.command_middleware());
+
+        self.self_check_products(&mut check);
+        self.self_check_orders(&mut check);
+        self.self_check_customers(&mut check);
+
+        macro_rules! capabilities {
+            ($($capability:ident),* $(,)?) => {
+                $(
+                    check.capability(stringify!($capability), || {
+                        let _ = self.$capability();
+                    });
+                )*
+            };
+        }
+
+        capabilities!(
+            add_or_update_product_command,
+            adjust_all_prices_command,
+            confirm_payment_command,
+            consolidate_order_command,
+            create_customer_command,
+            create_order_command,
+            create_product_command,
+            deactivate_product_command,
+            delete_order_command,
+            move_line_item_command,
+            place_order_command,
+            set_discount_eligible_command,
+            set_product_title_command,
+            set_recipient_command,
+            update_product_command,
+            cached_get_product_query,
+            can_place_order_query,
+            export_products_query,
+            generate_receipt_query,
+            get_customer_purchased_products_query,
+            get_customer_query,
+            get_customer_with_orders_query,
+            get_order_query,
+            get_order_summaries_for_customer_query,
+            get_order_with_products_query,
+            get_orders_modified_between_query,
+            get_product_query,
+            get_product_summaries_query,
+            get_top_products_query,
+            preview_reorder_query,
+        );
+
+        check
+    }
+
     pub(in crate::domain) fn by_ref(&self) -> Self {
+        self.clone()
+    }
+
+    pub(in crate::domain) fn with_id_strategy(&self, strategy: IdStrategy) -> Self {
         Resolver {
-            transactions_resolver: self.transactions_resolver.clone(),
-            products_resolver: self.products_resolver.clone(),
-            orders_resolver: self.orders_resolver.clone(),
-            customers_resolver: self.customers_resolver.clone(),
+            products_resolver: self.products_resolver.with_id_strategy(strategy),
+            orders_resolver: self.orders_resolver.with_id_strategy(strategy),
+            customers_resolver: self.customers_resolver.with_id_strategy(strategy),
+            ..self.by_ref()
+        }
+    }
+
+    #[cfg(feature = "concurrent")]
+    pub(in crate::domain) fn with_concurrent_stores(&self) -> Self {
+        Resolver {
+            products_resolver: self.products_resolver.with_concurrent_stores(),
+            orders_resolver: self.orders_resolver.with_concurrent_stores(),
+            ..self.by_ref()
+        }
+    }
+
+    pub(in crate::domain) fn with_seeded_ids(&self, seed: u64) -> Self {
+        Resolver {
+            products_resolver: self.products_resolver.with_seeded_ids(seed),
+            orders_resolver: self.orders_resolver.with_seeded_ids(seed),
+            customers_resolver: self.customers_resolver.with_seeded_ids(seed),
+            ..self.by_ref()
         }
     }
 
@@ -71,6 +328,50 @@ impl Resolver {
     }
 }
 
+/**
+The results of resolving everything in a `Resolver`.
+
+Resolving a dependency fails if it panics.
+*/
+#[derive(Default)]
+pub(in crate::domain) struct SelfCheck {
+    capabilities: Vec<&'static str>,
+    failed: Vec<&'static str>,
+}
+
+impl SelfCheck {
+    /** Check that a dependency can be resolved. */
+    pub(in crate::domain) fn resolves<T>(
+        &mut self,
+        name: &'static str,
+        resolve: impl FnOnce() -> T,
+    ) {
+        if panic::catch_unwind(AssertUnwindSafe(resolve)).is_err() {
+            self.failed.push(name);
+        }
+    }
+
+    fn capability(&mut self, name: &'static str, resolve: impl FnOnce()) {
+        self.capabilities.push(name);
+        self.resolves(name, resolve);
+    }
+}
+
+/** The failures from flushing everything in a `Resolver`. */
+#[derive(Default)]
+pub(in crate::domain) struct Shutdown {
+    failed: Vec<String>,
+}
+
+impl Shutdown {
+    /** Flush a store, keeping track of whether it failed. */
+    pub(in crate::domain) fn flushes(&mut self, name: &'static str, store: impl Flush) {
+        if let Err(err) = store.flush() {
+            self.failed.push(format!("{} ({})", name, err));
+        }
+    }
+}
+
 /**
 A registration in the resolver.
 
@@ -83,6 +384,9 @@ pub struct Register<T>(Arc<dyn Fn(&Resolver) -> T + Send + Sync>);
 impl<T> Register<T> {
     /**
     Create a register that returns the same instance of a value.
+
+    The value isn't created until the first time it's resolved. Resolvers that are cloned from
+    each other share the same instance.
     */
     pub fn once(f: impl Fn(&Resolver) -> T + Send + Sync + 'static) -> Self
     where
@@ -100,4 +404,158 @@ impl<T> Register<T> {
     pub fn factory(f: impl Fn(&Resolver) -> T + Send + Sync + 'static) -> Self {
         Register(Arc::new(f))
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::{
+            CreateCustomer,
+            CustomerId,
+        },
+        infra::{
+            Command,
+            Currency,
+            Query,
+        },
+        orders::{
+            AddOrUpdateProduct,
+            CreateOrder,
+            GetOrder,
+            OrderId,
+        },
+        products::{
+            CreateProduct,
+            ProductId,
+        },
+        ErrorKind,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    fn resolver_is_send_and_sync() {
+        assert_send_sync::<App>();
+        assert_send_sync::<Resolver>();
+
+        let resolver = Resolver::for_tests().resolver();
+
+        assert_send(&resolver.add_or_update_product_command());
+        assert_send(&resolver.get_order_query());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn clones_share_stores_across_threads() {
+        let resolver = Resolver::for_tests().resolver();
+
+        let customer_id = CustomerId::new();
+        let order_id = OrderId::new();
+
+        resolver
+            .create_customer_command()
+            .execute(CreateCustomer { id: customer_id })
+            .await
+            .unwrap();
+
+        resolver
+            .create_order_command()
+            .execute(CreateOrder {
+                id: order_id,
+                customer_id,
+                client_token: None,
+                guest: false,
+            })
+            .await
+            .unwrap();
+
+        let mut product_ids = vec![];
+        for _ in 0..8 {
+            let id = ProductId::new();
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+                .unwrap();
+
+            product_ids.push(id);
+        }
+
+        let tasks: Vec<_> = product_ids
+            .iter()
+            .map(|&product_id| {
+                let resolver = resolver.clone();
+
+                tokio::spawn(async move {
+                    // Writers racing on the same order may conflict, so retry until they don't
+                    loop {
+                        let r = resolver
+                            .add_or_update_product_command()
+                            .execute(AddOrUpdateProduct {
+                                id: order_id,
+                                product_id,
+                                quantity: 1,
+                            })
+                            .await;
+
+                        match r {
+                            Ok(_) => break,
+                            Err(err) if err.kind() == ErrorKind::Conflict => continue,
+                            Err(err) => panic!("{}", err),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order = resolver
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (_, line_items) = order.to_data();
+
+        assert_eq!(product_ids.len(), line_items.len());
+    }
+
+    #[test]
+    fn self_check_succeeds_for_default_resolver() {
+        App::new().self_check().unwrap();
+    }
+
+    #[test]
+    fn shutdown_succeeds_for_default_resolver() {
+        App::new().shutdown().unwrap();
+    }
+
+    #[test]
+    fn self_check_covers_capabilities() {
+        let check = App::new().root_resolver.run_self_check();
+
+        assert_eq!(Resolver::capabilities(), &check.capabilities[..]);
+    }
+
+    #[test]
+    fn capabilities_include_commands_and_queries() {
+        let capabilities = Resolver::capabilities();
+
+        assert!(capabilities.contains(&"create_order_command"));
+        assert!(capabilities.contains(&"create_product_command"));
+        assert!(capabilities.contains(&"get_order_query"));
+        assert!(capabilities.contains(&"get_product_query"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,412 @@
+/*! Contains the `Retry` type for retrying commands that fail with transient errors. */
+
+use std::time::Duration;
+
+use crate::domain::{
+    infra::*,
+    Error,
+    ErrorKind,
+};
+
+/**
+When to retry a failed command.
+
+By default a command is attempted up to 3 times if it fails with a conflict, without waiting
+between attempts. Bad input and missing entities won't go away by trying again, so commands that
+fail with them are never retried, even if the policy says they should be.
+*/
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    retryable: Vec<ErrorKind>,
+    backoff: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            retryable: vec![ErrorKind::Conflict],
+            backoff: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** The most times to attempt a command, including the first. */
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /** Also retry commands that fail with the given kind of error. */
+    pub fn retry_on(mut self, kind: ErrorKind) -> Self {
+        if !self.retryable.contains(&kind) {
+            self.retryable.push(kind);
+        }
+        self
+    }
+
+    /**
+    Wait before retrying a command.
+
+    The wait doubles after each failed attempt.
+    */
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    fn is_retryable(&self, kind: ErrorKind) -> bool {
+        match kind {
+            ErrorKind::BadInput | ErrorKind::NotFound => false,
+            kind => self.retryable.contains(&kind),
+        }
+    }
+
+    fn backoff_after(&self, attempt: u32) -> Option<Duration> {
+        self.backoff
+            .map(|backoff| backoff.saturating_mul(2u32.saturating_pow(attempt - 1)))
+    }
+}
+
+/**
+A command that's retried if it fails with a transient error.
+
+Commands can only be executed once, so `Retry` wraps a function that produces a new command for
+each attempt rather than a command itself. The input is cloned for each attempt.
+
+If all attempts fail then the returned error says how many attempts were made, and has the error
+from the last one as its source.
+*/
+pub struct Retry<TCommand> {
+    command: TCommand,
+    policy: RetryPolicy,
+    clock: DynClock,
+}
+
+impl<TCommand> Retry<TCommand> {
+    pub(in crate::domain) fn new(command: TCommand, policy: RetryPolicy, clock: DynClock) -> Self {
+        Retry {
+            command,
+            policy,
+            clock,
+        }
+    }
+}
+
+#[async_trait]
+impl<TArgs, TResolve, TCommand, T> Command<TArgs> for Retry<TResolve>
+where
+    TArgs: CommandArgs<Output = Result<T, Error>> + Clone + Send + 'static,
+    TResolve: Fn() -> TCommand + Send,
+    TCommand: Command<TArgs> + Send,
+    T: Send,
+{
+    async fn execute(self, input: TArgs) -> TArgs::Output {
+        let mut attempt = 1;
+
+        loop {
+            let command = (self.command)();
+
+            let err = match command.execute(input.clone()).await {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+
+            if !self.policy.is_retryable(err.kind()) {
+                return Err(err);
+            }
+
+            if attempt >= self.policy.max_attempts {
+                return Err(Error::with_source(
+                    err.kind(),
+                    format!("command failed after {} attempts", attempt),
+                    err,
+                ));
+            }
+
+            debug!("retrying command after attempt {} failed: {}", attempt, err);
+
+            if let Some(backoff) = self.policy.backoff_after(attempt) {
+                self.clock.sleep(backoff).await;
+            }
+
+            attempt += 1;
+        }
+    }
+}
+
+impl Resolver {
+    /**
+    Retry a command if it fails with a transient error.
+
+    The command is resolved again for each attempt. Commands resolved outside of `App::transaction`
+    get a new transaction for each attempt. Commands resolved within one all share it, so they can
+    only be retried if a failed attempt doesn't leave any changes behind.
+    */
+    pub fn retry_command<TCommand>(
+        &self,
+        policy: RetryPolicy,
+        command: impl Fn(&Resolver) -> TCommand + Send,
+    ) -> Retry<impl Fn() -> TCommand + Send> {
+        let resolver = self.by_ref();
+
+        Retry::new(move || command(&resolver), policy, self.clock())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        error::Error as StdError,
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::SystemTime,
+    };
+
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        error,
+        orders::{
+            model::store::{
+                in_memory_store_with_clock,
+                FailingStore,
+            },
+            *,
+        },
+    };
+
+    #[derive(Clone)]
+    struct Scripted;
+
+    impl CommandArgs for Scripted {
+        type Output = Result<u32, Error>;
+    }
+
+    /** A command that fails with each error in turn and then succeeds. */
+    struct Script {
+        errors: Mutex<VecDeque<Error>>,
+        attempts: Mutex<u32>,
+    }
+
+    impl Script {
+        fn new(errors: impl IntoIterator<Item = Error>) -> Arc<Self> {
+            Arc::new(Script {
+                errors: Mutex::new(errors.into_iter().collect()),
+                attempts: Mutex::new(0),
+            })
+        }
+
+        fn command(self: &Arc<Self>) -> impl Fn() -> ScriptedCommand {
+            let script = self.clone();
+
+            move || ScriptedCommand(script.clone())
+        }
+
+        fn attempts(&self) -> u32 {
+            *self.attempts.lock().unwrap()
+        }
+    }
+
+    struct ScriptedCommand(Arc<Script>);
+
+    #[async_trait]
+    impl Command<Scripted> for ScriptedCommand {
+        async fn execute(self, _: Scripted) -> Result<u32, Error> {
+            let mut attempts = self.0.attempts.lock().unwrap();
+            *attempts += 1;
+
+            match self.0.errors.lock().unwrap().pop_front() {
+                Some(err) => Err(err),
+                None => Ok(*attempts),
+            }
+        }
+    }
+
+    fn retry<TCommand>(
+        command: TCommand,
+        policy: RetryPolicy,
+    ) -> (Retry<TCommand>, Arc<FixedClock>) {
+        let clock = Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH));
+
+        (Retry::new(command, policy, clock.clone()), clock)
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let script = Script::new([error::conflict("conflict"), error::conflict("conflict")]);
+
+        let (retry, clock) = retry(
+            script.command(),
+            RetryPolicy::new()
+                .max_attempts(3)
+                .backoff(Duration::from_secs(1)),
+        );
+
+        assert_eq!(3, retry.execute(Scripted).await.unwrap());
+
+        // Waited 1s after the first attempt and 2s after the second
+        assert_eq!(SystemTime::UNIX_EPOCH + Duration::from_secs(3), clock.now());
+    }
+
+    #[tokio::test]
+    async fn bad_input_and_not_found_fail_immediately() {
+        for err in [
+            error::bad_input("bad input"),
+            error::not_found("order", "1"),
+        ] {
+            let kind = err.kind();
+            let script = Script::new([err]);
+
+            // Even asking for them to be retried doesn't retry them
+            let (retry, _) = retry(script.command(), RetryPolicy::new().retry_on(kind));
+
+            assert_eq!(kind, retry.execute(Scripted).await.unwrap_err().kind());
+            assert_eq!(1, script.attempts());
+        }
+    }
+
+    #[tokio::test]
+    async fn last_error_is_returned_when_attempts_are_exhausted() {
+        let script = Script::new([
+            error::conflict("first conflict"),
+            error::conflict("second conflict"),
+            error::conflict("third conflict"),
+        ]);
+
+        let (retry, _) = retry(script.command(), RetryPolicy::new().max_attempts(2));
+
+        let err = retry.execute(Scripted).await.unwrap_err();
+
+        assert_eq!(2, script.attempts());
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+        assert_eq!("command failed after 2 attempts", err.to_string());
+        assert_eq!("second conflict", err.source().unwrap().to_string());
+    }
+
+    #[tokio::test]
+    async fn retry_resolved_command() {
+        let test = Resolver::for_tests();
+        let resolver = test.resolver();
+
+        let id = CustomerId::new();
+
+        resolver
+            .create_customer_command()
+            .execute(CreateCustomer { id })
+            .await
+            .unwrap();
+
+        let start = test.clock().now();
+
+        // Creating the customer again keeps failing
+        let err = resolver
+            .retry_command(
+                RetryPolicy::new()
+                    .retry_on(ErrorKind::Internal)
+                    .backoff(Duration::from_millis(100)),
+                |resolver| resolver.create_customer_command(),
+            )
+            .execute(CreateCustomer { id })
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("3 attempts"));
+
+        // The backoff waited on the resolver's clock
+        assert_eq!(start + Duration::from_millis(300), test.clock().now());
+    }
+
+    /** An app with a customer, where the first write to the order store conflicts. */
+    async fn app_with_conflicting_order_store() -> (App, CustomerId) {
+        let app = App::new().with_order_store(|resolver| {
+            FailingStore::with_error(
+                in_memory_store_with_clock(resolver.transaction_store(), resolver.clock()),
+                1,
+                |_| error::conflict("the order was changed concurrently"),
+            )
+        });
+
+        let customer_id = CustomerId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await
+        })
+        .await
+        .unwrap();
+
+        (app, customer_id)
+    }
+
+    #[tokio::test]
+    async fn store_conflict_is_returned_without_retrying() {
+        let (app, customer_id) = app_with_conflicting_order_store().await;
+
+        let err = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id: OrderId::new(),
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
+
+    #[tokio::test]
+    async fn store_conflict_is_retried() {
+        let (app, customer_id) = app_with_conflicting_order_store().await;
+
+        let order_id = OrderId::new();
+
+        // The first attempt conflicts and the second succeeds
+        app.transaction(|resolver| async move {
+            resolver
+                .retry_command(RetryPolicy::new(), |resolver| {
+                    resolver.create_order_command()
+                })
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        let order = app
+            .transaction(|resolver| async move {
+                resolver
+                    .get_order_query()
+                    .execute(GetOrder { id: order_id })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert!(order.is_some());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,146 @@
+/*! Contains the `SequenceProvider` type for allocating sequential numbers. */
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::domain::{
+    error,
+    Error,
+};
+
+/**
+A place to persist counters for sequences of numbers.
+
+Allocating a number isn't transactional. Once a number has been allocated it's never handed out
+again, even if the transaction that asked for it is cancelled. That means sequences can have gaps,
+but two callers will never get the same number.
+*/
+#[auto_impl(&, Arc)]
+pub trait SequenceStore {
+    /** Allocate the next number in a sequence, starting from `1`. */
+    fn next_value(&self, sequence: &str) -> Result<u64, Error>;
+}
+
+/** A shared `SequenceStore` that can be stored in a resolver. */
+pub(in crate::domain) type DynSequenceStore = Arc<dyn SequenceStore + Send + Sync>;
+
+/** An in-memory `SequenceStore`. */
+#[derive(Default)]
+pub struct InMemorySequenceStore {
+    sequences: Mutex<HashMap<String, u64>>,
+}
+
+impl InMemorySequenceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SequenceStore for InMemorySequenceStore {
+    fn next_value(&self, sequence: &str) -> Result<u64, Error> {
+        let mut sequences = self.sequences.lock().unwrap();
+
+        let value = sequences.entry(sequence.to_owned()).or_insert(0);
+
+        *value = value
+            .checked_add(1)
+            .ok_or_else(|| error::msg(format!("sequence `{}` is exhausted", sequence)))?;
+
+        Ok(*value)
+    }
+}
+
+/**
+A provider for sequential numbers of type `T`.
+
+Numbers are human-friendly alternatives to ids. They aren't a replacement for them though, so
+items are still identified by their id.
+*/
+pub struct SequenceProvider<T> {
+    store: DynSequenceStore,
+    sequence: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for SequenceProvider<T> {
+    fn clone(&self) -> Self {
+        SequenceProvider {
+            store: self.store.clone(),
+            sequence: self.sequence,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> SequenceProvider<T> {
+    pub(in crate::domain) fn new(store: DynSequenceStore, sequence: &'static str) -> Self {
+        SequenceProvider {
+            store,
+            sequence,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: From<u64>> SequenceProvider<T> {
+    /** Allocate the next number in the sequence. */
+    pub fn next(&self) -> Result<T, Error> {
+        self.store.next_value(self.sequence).map(T::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn sequences_are_independent() {
+        let store = InMemorySequenceStore::new();
+
+        assert_eq!(1, store.next_value("a").unwrap());
+        assert_eq!(2, store.next_value("a").unwrap());
+        assert_eq!(1, store.next_value("b").unwrap());
+    }
+
+    #[test]
+    fn concurrent_allocation_is_unique_and_dense() {
+        let provider: SequenceProvider<u64> =
+            SequenceProvider::new(Arc::new(InMemorySequenceStore::new()), "test");
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let provider = provider.clone();
+
+                thread::spawn(move || {
+                    (0..125)
+                        .map(|_| provider.next().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let numbers: Vec<_> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+
+        let unique: HashSet<_> = numbers.iter().copied().collect();
+
+        assert_eq!(1000, numbers.len());
+        assert_eq!(1000, unique.len());
+
+        // There are no gaps in the numbers handed out
+        assert_eq!((1..=1000).collect::<HashSet<_>>(), unique);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,151 @@
+/*!
+Contains helpers for tracing commands.
+
+Each command runs in a span named after it, with fields for the ids and values it was given.
+Events logged while the command runs are nested in its span, so they can be searched by field
+rather than by matching text in their messages.
+*/
+
+use std::future::Future;
+
+use tracing::{
+    field,
+    Instrument,
+    Span,
+};
+
+use crate::domain::Error;
+
+/**
+Run a command in a span, recording its outcome when it finishes.
+
+The span needs to declare an empty `outcome` field. It's recorded as `ok` if the command
+succeeds, or the kind of error if it fails.
+*/
+pub(in crate::domain) async fn in_command_span<T>(
+    span: Span,
+    command: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let output = command.instrument(span.clone()).await;
+
+    match output {
+        Ok(_) => span.record("outcome", &"ok"),
+        Err(ref err) => span.record("outcome", &field::debug(err.kind())),
+    };
+
+    output
+}
+
+#[cfg(test)]
+pub(in crate::domain) mod test_support {
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{
+            Arc,
+            Mutex,
+        },
+    };
+
+    use tracing::{
+        field::{
+            Field,
+            Visit,
+        },
+        span::{
+            Attributes,
+            Id,
+            Record,
+        },
+        subscriber::DefaultGuard,
+        Event,
+        Metadata,
+        Subscriber,
+    };
+
+    /** A span and the latest values of its fields. */
+    #[derive(Debug, Clone)]
+    pub(in crate::domain) struct RecordedSpan {
+        pub(in crate::domain) name: &'static str,
+        pub(in crate::domain) fields: HashMap<&'static str, String>,
+    }
+
+    /**
+    A subscriber that keeps every span created while it's the default.
+
+    Subscribers are set per-thread, so tests using this need to run on a single thread.
+    */
+    #[derive(Clone, Default)]
+    pub(in crate::domain) struct SpanRecorder {
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    }
+
+    impl SpanRecorder {
+        /** Record spans until the returned guard is dropped. */
+        pub(in crate::domain) fn set_default() -> (Self, DefaultGuard) {
+            let recorder = SpanRecorder::default();
+            let guard = tracing::subscriber::set_default(recorder.clone());
+
+            (recorder, guard)
+        }
+
+        /** Get the recorded span with the given name. */
+        pub(in crate::domain) fn span(&self, name: &str) -> Option<RecordedSpan> {
+            self.spans
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|span| span.name == name)
+                .cloned()
+        }
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<&'static str, String>);
+
+    impl<'a> Visit for FieldVisitor<'a> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name(), value.to_owned());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for SpanRecorder {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+
+            let mut recorded = RecordedSpan {
+                name: span.metadata().name(),
+                fields: HashMap::new(),
+            };
+            span.record(&mut FieldVisitor(&mut recorded.fields));
+
+            spans.push(recorded);
+
+            // Span ids can't be zero
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record) {
+            let mut spans = self.spans.lock().unwrap();
+
+            if let Some(recorded) = spans.get_mut(span.into_u64() as usize - 1) {
+                values.record(&mut FieldVisitor(&mut recorded.fields));
+            }
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+}
\ No newline at end of file
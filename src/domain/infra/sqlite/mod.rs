@@ -0,0 +1,457 @@
+/*!
+Shared access to a SQLite database.
+
+The SQLite stores keep their entities in memory for reads, the same way the in-memory stores do,
+so a change made in a transaction is only visible to other callers once it commits. The changes
+a transaction made in every store sharing a database are written as part of committing it, in a
+single SQLite transaction, so the database never holds part of a transaction. If they can't be
+written then the transaction fails to commit. Changes made in a transaction that's cancelled are
+never written.
+*/
+
+pub(in crate::domain) mod schema;
+
+use std::{
+    mem,
+    path::Path,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use rusqlite::Connection;
+
+use crate::{
+    domain::{
+        infra::*,
+        orders::{
+            sqlite_store::SqliteOrderStore,
+            OrderId,
+        },
+        products::{
+            sqlite_store::SqliteProductStore,
+            ProductId,
+        },
+        Error,
+        ErrorKind,
+    },
+    store::{
+        lock,
+        Transaction,
+        TransactionId,
+        TransactionStore,
+    },
+};
+
+/**
+A connection to a SQLite database that's shared by the stores using it.
+*/
+#[derive(Clone)]
+pub(in crate::domain) struct Database {
+    connection: Arc<Mutex<Connection>>,
+    /** Writes waiting on the transactions that made them to commit, in the order they were made. */
+    pending: Arc<Mutex<Vec<PendingWrite>>>,
+}
+
+/** The entity a write is for, so it's only written once per transaction. */
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(in crate::domain) enum Row {
+    Product(ProductId),
+    Order(OrderId),
+}
+
+struct PendingWrite {
+    transaction_id: TransactionId,
+    row: Row,
+    write: Write,
+}
+
+type Write = Box<dyn FnOnce(&Transaction, &rusqlite::Transaction) -> Result<(), Error> + Send>;
+
+impl Database {
+    /**
+    Open the database at `path`, creating its tables if they don't exist yet.
+
+    A path of `":memory:"` opens a new in-memory database that's only seen by this `Database`
+    and its clones.
+    */
+    pub(in crate::domain) fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let connection =
+            Connection::open(path).map_err(sqlite_error("failed to open the database"))?;
+
+        schema::create(&connection)
+            .map_err(sqlite_error("failed to create the database tables"))?;
+
+        Ok(Database {
+            connection: Arc::new(Mutex::new(connection)),
+            pending: Default::default(),
+        })
+    }
+
+    /** Read from the database. */
+    pub(in crate::domain) fn read<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, Error> {
+        let connection = lock::lock(&self.connection);
+
+        f(&connection).map_err(sqlite_error("failed to read from the database"))
+    }
+
+    /**
+    Write a row changed in a transaction to the database as part of committing it.
+
+    `f` is called with the transaction being committed, so it can read the row as the transaction
+    sees it, and the SQLite transaction to write it in. It's called while the connection is locked,
+    just before the transaction commits, along with the writes for every other row the transaction
+    changed. Only the first write for each row is kept, because it reads the row's latest state.
+    If any write fails then the transaction is cancelled instead of committing.
+
+    A transaction that isn't tracked, like an empty one, has already committed, so the row is
+    written before this returns along with any error.
+    */
+    pub(in crate::domain) fn write(
+        &self,
+        transactions: &TransactionStore,
+        transaction: &Transaction,
+        row: Row,
+        f: impl FnOnce(&Transaction, &rusqlite::Transaction) -> Result<(), Error> + Send + 'static,
+    ) -> Result<(), Error> {
+        let transaction_id = transaction.id();
+
+        if transactions.is_committed(transaction_id) {
+            return self.write_all(transaction, vec![Box::new(f)]);
+        }
+
+        let mut pending = lock::lock(&self.pending);
+
+        let first = !pending
+            .iter()
+            .any(|write| write.transaction_id == transaction_id);
+
+        pending.push(PendingWrite {
+            transaction_id,
+            row,
+            write: Box::new(f),
+        });
+
+        if first {
+            let database = self.clone();
+            let prepared = transactions.clone();
+
+            transactions.on_prepare(transaction, move |transaction| {
+                database
+                    .write_pending(&prepared, transaction)
+                    .map_err(Into::into)
+            })?;
+
+            // Writes left behind by a transaction that's cancelled are dropped
+            let database = self.clone();
+            transactions.on_complete(transaction, move |_| {
+                lock::lock(&database.pending).retain(|write| write.transaction_id != transaction_id)
+            });
+        }
+
+        Ok(())
+    }
+
+    /** Write the rows changed in a transaction that's committing, including its savepoints. */
+    fn write_pending(
+        &self,
+        transactions: &TransactionStore,
+        transaction: &Transaction,
+    ) -> Result<(), Error> {
+        // The connection is locked first so other commits can't write in between
+        let mut connection = lock::lock(&self.connection);
+
+        let committing = {
+            let mut pending = lock::lock(&self.pending);
+
+            let (committing, waiting): (Vec<_>, Vec<_>) = mem::take(&mut *pending)
+                .into_iter()
+                .partition(|write| transactions.sees(transaction.id(), write.transaction_id));
+            *pending = waiting;
+
+            committing
+        };
+
+        let mut rows = vec![];
+        let writes = committing
+            .into_iter()
+            .filter(|write| {
+                if rows.contains(&write.row) {
+                    return false;
+                }
+
+                rows.push(write.row);
+                true
+            })
+            .map(|write| write.write)
+            .collect::<Vec<_>>();
+
+        // Savepoints that made writes register their own functions, but the first one to run
+        // writes everything, so the rest find nothing left
+        if writes.is_empty() {
+            return Ok(());
+        }
+
+        write_in(&mut connection, transaction, writes)
+    }
+
+    fn write_all(&self, transaction: &Transaction, writes: Vec<Write>) -> Result<(), Error> {
+        let mut connection = lock::lock(&self.connection);
+
+        write_in(&mut connection, transaction, writes)
+    }
+}
+
+/** Call each write in a single SQLite transaction that's only committed if they all succeed. */
+fn write_in(
+    connection: &mut Connection,
+    transaction: &Transaction,
+    writes: Vec<Write>,
+) -> Result<(), Error> {
+    let sqlite_transaction = connection
+        .transaction()
+        .map_err(sqlite_error("failed to write to the database"))?;
+
+    for write in writes {
+        write(transaction, &sqlite_transaction)?;
+    }
+
+    sqlite_transaction
+        .commit()
+        .map_err(sqlite_error("failed to write to the database"))
+}
+
+pub(in crate::domain) fn sqlite_error<E>(msg: &'static str) -> impl FnOnce(E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    move |err| Error::with_source(ErrorKind::Internal, msg, err)
+}
+
+impl App {
+    /**
+    Keep products and orders in a SQLite database at `path`, so they survive restarts.
+
+    Existing products and orders are read from the database straight away, so a database that
+    can't be opened is reported here rather than on the first request. Changes are written as
+    part of committing the transactions that make them, so a transaction whose changes can't be
+    written fails to commit.
+    */
+    pub fn with_sqlite(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let database = Database::open(path)?;
+        let transaction_store = self.root_resolver.transaction_store();
+
+        let products = Arc::new(SqliteProductStore::open(
+            database.clone(),
+            transaction_store.clone(),
+        )?);
+        let orders = Arc::new(SqliteOrderStore::open(
+            database,
+            transaction_store,
+            self.root_resolver.clock(),
+        )?);
+
+        Ok(App {
+            root_resolver: Resolver {
+                products_resolver: self
+                    .root_resolver
+                    .products_resolver
+                    .with_product_store(move |_| products.clone()),
+                orders_resolver: self
+                    .root_resolver
+                    .orders_resolver
+                    .with_order_store(move |_| orders.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        orders::{
+            model::test_data::OrderBuilder,
+            *,
+        },
+        products::{
+            model::test_data::ProductBuilder,
+            *,
+        },
+    };
+
+    #[tokio::test]
+    async fn products_and_orders_survive_restarting_with_sqlite() {
+        let path = std::env::temp_dir().join(format!("shop-{}.sqlite", OrderId::new()));
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let order_id = OrderId::new();
+
+        let app = App::new().with_sqlite(&path).unwrap();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id: product_id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await?;
+
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await?;
+
+            resolver
+                .add_or_update_product_command()
+                .execute(AddOrUpdateProduct {
+                    id: order_id,
+                    product_id,
+                    quantity: 2,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        // Committed changes are already in the database, so nothing needs to be flushed
+        drop(app);
+
+        let app = App::new().with_sqlite(&path).unwrap();
+        let resolver = app.root_resolver.by_ref();
+
+        let product = resolver
+            .get_product_query()
+            .execute(GetProduct {
+                id: product_id,
+                include_inactive: false,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("A title", product.into_data().title);
+
+        let order = resolver
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (_, line_items) = order.into_data();
+
+        assert_eq!(1, line_items.len());
+        assert_eq!(product_id, line_items[0].product_id);
+        assert_eq!(2, line_items[0].quantity);
+
+        drop(resolver);
+        drop(app);
+        std::fs::remove_file(path).unwrap();
+    }
+    fn count(database: &Database, table: &str) -> usize {
+        database
+            .read(|connection| {
+                connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+            })
+            .unwrap()
+    }
+
+    fn set_product_and_order(
+        products: &SqliteProductStore,
+        orders: &SqliteOrderStore,
+        transaction: &Transaction,
+    ) -> (ProductId, OrderId) {
+        let product = ProductBuilder::new().build();
+        let product_id = product.to_data().id;
+
+        let order = OrderBuilder::new()
+            .add_product(product.clone(), |l| l)
+            .build();
+        let order_id = order.to_data().0.id;
+
+        products.set_product(transaction, product).unwrap();
+        orders.set_order(transaction, order).unwrap();
+
+        (product_id, order_id)
+    }
+
+    #[test]
+    fn changes_in_every_store_are_written_when_their_transaction_commits() {
+        let database = Database::open(":memory:").unwrap();
+        let transactions = TransactionStore::new();
+
+        let products = SqliteProductStore::open(database.clone(), transactions.clone()).unwrap();
+        let orders =
+            SqliteOrderStore::open(database.clone(), transactions.clone(), SystemClock).unwrap();
+
+        let transaction = transactions.begin();
+        set_product_and_order(&products, &orders, &transaction);
+
+        // A savepoint's changes are written along with its parent's
+        let savepoint = transactions.begin_child(&transaction);
+        set_product_and_order(&products, &orders, &savepoint);
+        transactions.commit(savepoint).unwrap();
+
+        assert_eq!(0, count(&database, "products"));
+        assert_eq!(0, count(&database, "orders"));
+
+        transactions.commit(transaction).unwrap();
+
+        assert_eq!(2, count(&database, "products"));
+        assert_eq!(2, count(&database, "orders"));
+        assert_eq!(2, count(&database, "line_items"));
+    }
+
+    #[test]
+    fn transaction_fails_to_commit_if_its_changes_cant_be_written() {
+        let database = Database::open(":memory:").unwrap();
+        let transactions = TransactionStore::new();
+
+        let products = SqliteProductStore::open(database.clone(), transactions.clone()).unwrap();
+        let orders =
+            SqliteOrderStore::open(database.clone(), transactions.clone(), SystemClock).unwrap();
+
+        let transaction = transactions.begin();
+        let (product_id, order_id) = set_product_and_order(&products, &orders, &transaction);
+
+        database
+            .read(|connection| connection.execute("DROP TABLE line_items", []))
+            .unwrap();
+
+        assert!(transactions.commit(transaction).is_err());
+
+        // The product was written in the same SQLite transaction as the order, so neither is kept
+        assert_eq!(0, count(&database, "products"));
+        assert!(products
+            .get_product(&Transaction::none(), product_id)
+            .unwrap()
+            .is_none());
+        assert!(orders
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+    }
+}
\ No newline at end of file
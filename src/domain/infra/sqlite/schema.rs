@@ -0,0 +1,62 @@
+/*!
+The tables used by the SQLite stores.
+
+Ids and versions are stored as hyphenated UUIDs in `TEXT` columns. Each row also keeps the
+entity's full data as JSON in its `data` column, so fields can be added to entities without
+changing the schema. The other columns are there so rows can be looked up and joined.
+*/
+
+use rusqlite::Connection;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS products (
+    id TEXT PRIMARY KEY NOT NULL,
+    version TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS orders (
+    id TEXT PRIMARY KEY NOT NULL,
+    version TEXT NOT NULL,
+    customer_id TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS line_items (
+    id TEXT PRIMARY KEY NOT NULL,
+    order_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    product_id TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS line_items_by_order ON line_items (order_id);
+";
+
+/** Create any tables that don't exist yet. */
+pub(in crate::domain) fn create(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(SCHEMA)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_is_idempotent() {
+        let connection = Connection::open_in_memory().unwrap();
+
+        create(&connection).unwrap();
+        create(&connection).unwrap();
+
+        let tables: Vec<String> = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(vec!["line_items", "orders", "products"], tables);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,229 @@
+/*! Contains the `TestResolver` type. */
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{
+        Duration,
+        SystemTime,
+    },
+};
+
+use crate::domain::{
+    infra::{
+        transaction::test_support::TestTransactionProvider,
+        *,
+    },
+    Error,
+};
+
+/** The seed used for ids generated by a `TestResolver`. */
+pub(in crate::domain) const TEST_SEED: u64 = 42;
+
+/**
+A resolver preconfigured for tests.
+
+Each `TestResolver` has its own in-memory stores, generates ids from `TEST_SEED`, and has a clock
+that's frozen until it's changed through `clock`. Commands run through `transaction` use real
+transactions that are begun and completed by a `TestTransactionProvider`, so tests can assert
+on how they completed.
+*/
+pub(in crate::domain) struct TestResolver {
+    resolver: Resolver,
+    clock: Arc<FixedClock>,
+    transactions: TestTransactionProvider,
+}
+
+impl Resolver {
+    /**
+    Get a resolver for tests.
+    */
+    pub(in crate::domain) fn for_tests() -> TestResolver {
+        let clock = Arc::new(FixedClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        let transactions = TestTransactionProvider::new();
+
+        let app = App::new().with_seeded_ids(TEST_SEED);
+
+        // Run tests that go through the resolver against the sharded stores when they're enabled
+        #[cfg(feature = "concurrent")]
+        let app = app.with_concurrent_stores();
+
+        // Run tests that go through the resolver against the sled store when it's enabled
+        #[cfg(feature = "sled-store")]
+        let app = app.with_order_store(|resolver| {
+            crate::domain::orders::sled_store::SledStore::temporary(
+                resolver.transaction_store(),
+                resolver.clock(),
+            )
+            .expect("failed to open a temporary order database")
+        });
+
+        // Run tests that go through the resolver against an in-memory SQLite database when enabled
+        #[cfg(feature = "sqlite")]
+        let app = {
+            let database = crate::domain::infra::sqlite::Database::open(":memory:")
+                .expect("failed to open an in-memory database");
+
+            app.with_product_store({
+                let database = database.clone();
+
+                move |resolver| {
+                    crate::domain::products::sqlite_store::SqliteProductStore::open(
+                        database.clone(),
+                        resolver.transaction_store(),
+                    )
+                    .expect("failed to open the product store")
+                }
+            })
+            .with_order_store(move |resolver| {
+                crate::domain::orders::sqlite_store::SqliteOrderStore::open(
+                    database.clone(),
+                    resolver.transaction_store(),
+                    resolver.clock(),
+                )
+                .expect("failed to open the order store")
+            })
+        };
+
+        let root_resolver = app.root_resolver;
+
+        let resolver = {
+            let clock = clock.clone();
+
+            Resolver {
+                transactions_resolver: root_resolver
+                    .transactions_resolver
+                    .with_transaction_store(transactions.transaction_store()),
+                clock: Register::once(move |_| clock.clone() as DynClock),
+                ..root_resolver.by_ref()
+            }
+        };
+
+        TestResolver {
+            resolver,
+            clock,
+            transactions,
+        }
+    }
+}
+
+impl TestResolver {
+    /**
+    Get a resolver outside of any transaction.
+
+    This is useful for reading state to assert on. Any writes made through it are auto-committed.
+    */
+    pub(in crate::domain) fn resolver(&self) -> Resolver {
+        self.resolver.by_ref()
+    }
+
+    /**
+    The frozen clock used by the resolver.
+    */
+    pub(in crate::domain) fn clock(&self) -> &FixedClock {
+        &self.clock
+    }
+
+    /**
+    The provider used to begin transactions, for asserting on how they completed.
+    */
+    pub(in crate::domain) fn transactions(&self) -> &TestTransactionProvider {
+        &self.transactions
+    }
+
+    /**
+    Run a function in a new transaction with a resolver that uses it.
+
+    The transaction is committed if the function succeeds and cancelled if it fails.
+    */
+    pub(in crate::domain) async fn transaction<F, O, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Resolver) -> O,
+        O: Future<Output = Result<T, Error>>,
+    {
+        self.transactions
+            .transaction(|transaction| {
+                f(self
+                    .resolver
+                    .with_active_transaction(Register::once(move |_| transaction.clone())))
+            })
+            .await
+    }
+}
+
+/**
+Check that a command for `TArgs` has been resolved.
+
+This doesn't do anything at runtime. Calling it with a resolver method only compiles if that
+method returns a command for the expected input type, so it catches commands that haven't been
+wired into the `Resolver`.
+*/
+pub(in crate::domain) fn assert_command<TArgs>(_: impl Command<TArgs>)
+where
+    TArgs: CommandArgs,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        error,
+    };
+
+    #[tokio::test]
+    async fn ids_are_deterministic() {
+        let first = Resolver::for_tests();
+        let second = Resolver::for_tests();
+
+        assert_eq!(
+            first.resolver().customer_id().get().unwrap(),
+            second.resolver().customer_id().get().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn clock_is_frozen_until_set() {
+        let test = Resolver::for_tests();
+
+        let now = test.resolver().clock().now();
+
+        assert_eq!(now, test.resolver().clock().now());
+
+        test.clock().set(now + Duration::from_secs(1));
+
+        assert_eq!(now + Duration::from_secs(1), test.resolver().clock().now());
+    }
+
+    #[tokio::test]
+    async fn transactions_are_real() {
+        let test = Resolver::for_tests();
+
+        let id = CustomerId::new();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id })
+                .await?;
+
+            Err::<(), _>(error::msg("failed after creating the customer"))
+        })
+        .await
+        .unwrap_err();
+
+        test.transactions().assert_rolled_back();
+
+        assert!(test
+            .resolver()
+            .get_customer_query()
+            .execute(GetCustomer { id })
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
\ No newline at end of file
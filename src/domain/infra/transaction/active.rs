@@ -1,9 +1,19 @@
-use std::sync::Arc;
+use std::{
+    mem,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
 
 use crate::{
-    domain::error::Error,
+    domain::error::{
+        self,
+        Error,
+    },
     store::{
         Transaction,
+        TransactionId,
         TransactionStore,
     },
 };
@@ -18,8 +28,12 @@ manage the transaction themselves.
 pub struct ActiveTransaction {
     transaction: Arc<Transaction>,
     store: Option<TransactionStore>,
+    on_commit: Arc<Mutex<Vec<OnCommit>>>,
+    parent: Option<Box<ActiveTransaction>>,
 }
 
+type OnCommit = Box<dyn FnOnce() + Send>;
+
 impl ActiveTransaction {
     pub(in crate::domain::infra::transaction) fn begin(store: TransactionStore) -> Self {
         let transaction = Arc::new(store.begin());
@@ -27,50 +41,327 @@ impl ActiveTransaction {
         ActiveTransaction {
             transaction,
             store: Some(store),
+            on_commit: Default::default(),
+            parent: None,
+        }
+    }
+
+    /**
+    Begin a savepoint nested in this transaction.
+
+    The savepoint sees the changes already made in this transaction. Committing it doesn't make
+    its changes observable, it only hands them to this transaction, so they're committed or
+    cancelled along with it. Cancelling it reverts only the changes made since it began, and this
+    transaction can carry on afterwards. Savepoints can be nested in other savepoints.
+
+    This transaction can't be committed or cancelled while the savepoint is still active.
+    If this transaction isn't transactional at all then neither is the savepoint.
+    */
+    pub fn savepoint(&self) -> Self {
+        match self.store {
+            Some(ref store) => ActiveTransaction {
+                transaction: Arc::new(store.begin_child(&self.transaction)),
+                store: Some(store.clone()),
+                on_commit: Default::default(),
+                parent: Some(Box::new(self.clone())),
+            },
+            None => ActiveTransaction::auto_commit(),
         }
     }
 
+    /**
+    Get the id of the transaction.
+
+    Every active transaction has a unique id, even if it isn't transactional at all,
+    so the id can be used to correlate log lines for a single command.
+    */
+    pub fn id(&self) -> TransactionId {
+        self.transaction.id()
+    }
+
     pub(in crate::domain) fn get(&self) -> &Transaction {
+        if self.store.is_none() {
+            debug!(
+                "auto-committing write outside of a transaction (transaction `{}`)",
+                self.id()
+            );
+        }
+
         &self.transaction
     }
 
+    /**
+    Get the transaction to read entities in.
+
+    Reads see the changes made in the transaction before it's committed, along with everything
+    that's already been committed.
+    */
+    pub(in crate::domain) fn read(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    /** Whether the transaction's changes wait until it's committed to be observable. */
+    pub(in crate::domain) fn is_transactional(&self) -> bool {
+        self.store.is_some()
+    }
+
+    /**
+    Register a function to run after the transaction commits.
+
+    Functions run once, in the order they were registered, after the transaction's changes
+    are observable. They never run if the transaction is cancelled.
+    If the transaction isn't transactional at all then the function runs immediately.
+    Functions registered in a savepoint run when its outermost transaction commits.
+    */
+    pub fn on_commit(&self, f: impl FnOnce() + Send + 'static) {
+        if self.store.is_some() {
+            self.on_commit.lock().unwrap().push(Box::new(f));
+        } else {
+            f()
+        }
+    }
+
     /**
     Commit the transaction, making its changes observable.
 
+    Committing a savepoint hands its changes to its parent instead, so they only become
+    observable when the outermost transaction is committed.
+
     There must be no other callers holding on to this transaction when it's committed.
     If there are it will return an error instead of committing.
+    If a store fails to prepare the transaction's changes, like writing them to a database,
+    then the transaction is cancelled instead and the store's error is returned.
     */
     pub fn commit(mut self) -> Result<(), Error> {
         match Arc::try_unwrap(self.transaction) {
             Ok(transaction) => {
                 if let Some(store) = self.store.take() {
-                    store.commit(transaction);
+                    store
+                        .commit(transaction)
+                        .map_err(|err| match err.downcast::<Error>() {
+                            Ok(err) => *err,
+                            Err(err) => Error::from(err),
+                        })?;
+                }
+
+                // Take the functions before running them so a panic can't leave them behind
+                let on_commit = mem::take(&mut *self.on_commit.lock().unwrap());
+
+                if let Some(parent) = self.parent.take() {
+                    parent.on_commit.lock().unwrap().extend(on_commit);
+
+                    return Ok(());
+                }
+
+                for f in on_commit {
+                    f();
                 }
 
                 Ok(())
             }
-            Err(_) => Err(Error::from("transaction is still in use")),
+            Err(_) => Err(error::msg("transaction is still in use")),
         }
     }
 
     /**
     Cancel the transaction, reverting its changes.
 
+    Cancelling a savepoint only reverts the changes made since it began.
+
     There must be no other callers holding on to this transaction when it's cancelled.
     If there are it will return an error instead of cancelling.
+    Changes made outside of a transaction have already been committed, so they can't be
+    cancelled either.
     */
-    pub fn cancel(mut self) {
-        if let Ok(transaction) = Arc::try_unwrap(self.transaction) {
-            if let Some(store) = self.store.take() {
-                store.cancel(transaction);
+    pub fn cancel(mut self) -> Result<(), Error> {
+        match Arc::try_unwrap(self.transaction) {
+            Ok(transaction) => {
+                if let Some(store) = self.store.take() {
+                    store.cancel(transaction);
+
+                    Ok(())
+                } else {
+                    Err(error::msg("cannot roll back auto-committed writes"))
+                }
             }
+            Err(_) => Err(error::msg("transaction is still in use")),
         }
     }
 
-    pub(in crate::domain) fn none() -> Self {
+    /**
+    A transaction that isn't transactional at all.
+
+    Changes are observable as soon as they're made, so the transaction can't be cancelled.
+    */
+    pub(in crate::domain) fn auto_commit() -> Self {
         ActiveTransaction {
             transaction: Arc::new(Transaction::none()),
             store: None,
+            on_commit: Default::default(),
+            parent: None,
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{
+        self,
+        AssertUnwindSafe,
+    };
+
+    use super::*;
+
+    use crate::{
+        domain::ErrorKind,
+        store::{
+            Id,
+            TransactionValueStore,
+            Version,
+        },
+    };
+
+    fn record(transaction: &ActiveTransaction, calls: &Arc<Mutex<Vec<i32>>>, call: i32) {
+        let calls = calls.clone();
+
+        transaction.on_commit(move || calls.lock().unwrap().push(call));
+    }
+
+    #[test]
+    fn committed_transaction_runs_hooks_in_order() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+        record(&transaction, &calls, 2);
+        record(&transaction, &calls, 3);
+
+        assert!(calls.lock().unwrap().is_empty());
+
+        transaction.commit().unwrap();
+
+        assert_eq!(vec![1, 2, 3], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn committed_savepoint_runs_hooks_with_outermost_transaction() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+
+        let committed = transaction.savepoint();
+        record(&committed, &calls, 2);
+        committed.commit().unwrap();
+
+        let cancelled = transaction.savepoint();
+        record(&cancelled, &calls, 3);
+        cancelled.cancel().unwrap();
+
+        assert!(calls.lock().unwrap().is_empty());
+
+        transaction.commit().unwrap();
+
+        assert_eq!(vec![1, 2], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn transaction_with_active_savepoint_can_not_be_committed() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let _savepoint = transaction.savepoint();
+
+        assert!(transaction.commit().is_err());
+    }
+
+    #[test]
+    fn transaction_that_fails_to_prepare_runs_no_hooks() {
+        let store = TransactionStore::new();
+        let transaction = ActiveTransaction::begin(store.clone());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+
+        store
+            .on_prepare(transaction.get(), |_| {
+                Err(Box::new(error::conflict("failed to prepare")))
+            })
+            .unwrap();
+
+        let err = transaction.commit().unwrap_err();
+
+        // Domain errors keep their kind when they fail a commit
+        assert_eq!(ErrorKind::Conflict, err.kind());
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cancelled_transaction_runs_no_hooks() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+
+        transaction.cancel().unwrap();
+
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dropped_transaction_runs_no_hooks() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+
+        drop(transaction);
+
+        assert!(calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn auto_commit_transaction_runs_hooks_immediately() {
+        let transaction = ActiveTransaction::auto_commit();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        record(&transaction, &calls, 1);
+
+        assert_eq!(vec![1], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn panicking_hook_leaves_changes_committed() {
+        let transactions = TransactionStore::new();
+        let store = TransactionValueStore::new(transactions.clone());
+
+        let transaction = ActiveTransaction::begin(transactions);
+        let id = Id::new();
+
+        store
+            .set(transaction.get(), id, None::<Version>, Version::new(), 1)
+            .unwrap();
+
+        transaction.on_commit(|| panic!("failed to run hook"));
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(move || transaction.commit())).is_err());
+
+        assert_eq!(
+            Some(1),
+            store.get(&Transaction::none(), id).map(|(_, value)| value)
+        );
+    }
+
+    #[test]
+    fn auto_commit_transaction_can_not_be_cancelled() {
+        let err = ActiveTransaction::auto_commit().cancel().unwrap_err();
+
+        assert!(err.to_string().contains("cannot roll back"));
+    }
+
+    #[test]
+    fn transaction_in_use_can_not_be_cancelled() {
+        let transaction = ActiveTransaction::begin(TransactionStore::new());
+        let _in_use = transaction.clone();
+
+        assert!(transaction.cancel().is_err());
+    }
 }
\ No newline at end of file
@@ -1,4 +1,10 @@
 mod active;
 pub(in crate::domain) mod resolver;
+#[cfg(test)]
+pub(in crate::domain) mod test_support;
+mod unit_of_work;
 
-pub use self::active::*;
\ No newline at end of file
+pub use self::{
+    active::*,
+    unit_of_work::*,
+};
\ No newline at end of file
@@ -19,18 +19,57 @@ impl Default for TransactionsResolver {
             active_transaction: Register::factory(|_| {
                 // By default, each call to get an active transaction will receive a fresh one
                 // that isn't transactional at all
-                ActiveTransaction::none()
+                ActiveTransaction::auto_commit()
             }),
         }
     }
 }
 
+impl TransactionsResolver {
+    #[cfg(test)]
+    pub(in crate::domain) fn with_transaction_store(&self, store: TransactionStore) -> Self {
+        TransactionsResolver {
+            transaction_store: Register::once(move |_| store.clone()),
+            ..self.clone()
+        }
+    }
+}
+
 impl App {
     /**
     Begin a transaction and return a resolver that uses it.
 
     Any commands that are resolved from the returned resolver will participate in the returned transaction.
     The transaction will need to be completed before it will commit.
+    If it fails then the transaction is cancelled, so none of its changes are observable.
+
+    Commands never commit or cancel transactions themselves, so commands that are composed of
+    other commands all share the same transaction. A part of the work that might fail without
+    failing the whole transaction can use `Resolver::transaction` to run in a savepoint.
+
+    This is a `UnitOfWork` that's committed if `f` succeeds and cancelled if it fails.
+    */
+    pub async fn transaction<F, O, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(Resolver) -> O,
+        O: ::std::future::Future<Output = Result<T, E>>,
+        E: From<Error>,
+    {
+        complete(self.unit_of_work(), f).await
+    }
+}
+
+impl Resolver {
+    /**
+    Begin a savepoint in this resolver's transaction and return a resolver that uses it.
+
+    The savepoint is committed if `f` succeeds, which hands its changes to the enclosing
+    transaction. They're only observable once the outermost transaction commits.
+    If `f` fails then only the changes made in the savepoint are rolled back, and the enclosing
+    transaction can carry on. Savepoints can be nested.
+
+    If this resolver isn't part of a transaction then this begins a new one, just like
+    `App::transaction`.
     */
     pub async fn transaction<F, O, T, E>(&self, f: F) -> Result<T, E>
     where
@@ -38,17 +77,29 @@ impl App {
         O: ::std::future::Future<Output = Result<T, E>>,
         E: From<Error>,
     {
-        let resolver = self
-            .root_resolver
-            .with_active_transaction(Register::once(|resolver| {
-                ActiveTransaction::begin(resolver.transaction_store())
-            }));
+        complete(self.savepoint(), f).await
+    }
+}
+
+async fn complete<F, O, T, E>(unit_of_work: UnitOfWork, f: F) -> Result<T, E>
+where
+    F: FnOnce(Resolver) -> O,
+    O: ::std::future::Future<Output = Result<T, E>>,
+    E: From<Error>,
+{
+    match f(unit_of_work.resolver().by_ref()).await {
+        Ok(r) => {
+            unit_of_work.commit()?;
 
-        let transaction = resolver.active_transaction();
-        let r = f(resolver).await?;
-        transaction.commit()?;
+            Ok(r)
+        }
+        Err(err) => {
+            // Cancel the transaction so its changes don't block other transactions
+            // If it's still in use then it'll be cancelled when it's dropped instead
+            let _ = unit_of_work.cancel();
 
-        Ok(r)
+            Err(err)
+        }
     }
 }
 
@@ -73,4 +124,362 @@ impl Resolver {
             ..self.by_ref()
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        error,
+        orders::*,
+        products::*,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn failed_transaction_is_cancelled() {
+        let app = App::new();
+
+        let id = ProductId::new();
+        let create = move || CreateProduct {
+            id,
+            title: "A title".into(),
+            price: Currency::usd(100),
+        };
+
+        // Create a product, but fail before the transaction completes
+        let r: Result<(), Error> = app
+            .transaction(|resolver| async move {
+                resolver.create_product_command().execute(create()).await?;
+
+                Err(error::msg("failed after creating the product"))
+            })
+            .await;
+
+        assert!(r.is_err());
+
+        // The product was never created, so creating it again succeeds
+        app.transaction(|resolver| async move {
+            assert!(resolver
+                .get_product_query()
+                .execute(GetProduct {
+                    id,
+                    include_inactive: false,
+                })
+                .await?
+                .is_none());
+
+            resolver.create_product_command().execute(create()).await
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failure_partway_leaves_every_store_untouched() {
+        let app = App::new();
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let order_id = OrderId::new();
+
+        // Create a product and an order for it, then fail to add a product that doesn't exist
+        let err = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_customer_command()
+                    .execute(CreateCustomer { id: customer_id })
+                    .await?;
+
+                resolver
+                    .create_product_command()
+                    .execute(CreateProduct {
+                        id: product_id,
+                        title: "A title".into(),
+                        price: Currency::usd(100),
+                    })
+                    .await?;
+
+                // Later steps see the changes made by earlier ones before they're committed
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id: order_id,
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await?;
+
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 1,
+                    })
+                    .await?;
+
+                let order = resolver
+                    .get_order_query()
+                    .execute(GetOrder { id: order_id })
+                    .await?
+                    .expect("missing order");
+                assert!(order.contains_product(product_id));
+
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id: order_id,
+                        product_id: ProductId::new(),
+                        quantity: 1,
+                    })
+                    .await
+                    .map(|_| ())
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        // None of the stores kept any of the changes
+        app.transaction(|resolver| async move {
+            assert!(resolver
+                .get_customer_query()
+                .execute(GetCustomer { id: customer_id })
+                .await?
+                .is_none());
+
+            assert!(resolver
+                .get_product_query()
+                .execute(GetProduct {
+                    id: product_id,
+                    include_inactive: true,
+                })
+                .await?
+                .is_none());
+
+            assert!(resolver
+                .get_order_query()
+                .execute(GetOrder { id: order_id })
+                .await?
+                .is_none());
+
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_savepoint_rolls_back_only_its_changes() {
+        let app = App::new();
+
+        let create = |id| CreateProduct {
+            id,
+            title: "A title".into(),
+            price: Currency::usd(100),
+        };
+
+        let outer = ProductId::new();
+        let middle = ProductId::new();
+        let inner = ProductId::new();
+        let after = ProductId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(create(outer))
+                .await?;
+
+            // The middle level fails after the inner level succeeds
+            let r: Result<(), Error> = resolver
+                .transaction(|resolver| async move {
+                    resolver
+                        .create_product_command()
+                        .execute(create(middle))
+                        .await?;
+
+                    resolver
+                        .transaction(|resolver| async move {
+                            resolver
+                                .create_product_command()
+                                .execute(create(inner))
+                                .await
+                        })
+                        .await?;
+
+                    // The inner level's product is visible to the middle level
+                    assert!(resolver
+                        .get_product_query()
+                        .execute(GetProduct {
+                            id: inner,
+                            include_inactive: false,
+                        })
+                        .await?
+                        .is_some());
+
+                    Err(error::msg("failed in the middle"))
+                })
+                .await;
+
+            assert!(r.is_err());
+
+            // The outer level carries on after the middle level is rolled back
+            for id in [middle, inner] {
+                assert!(resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await?
+                    .is_none());
+            }
+
+            resolver
+                .create_product_command()
+                .execute(create(after))
+                .await
+        })
+        .await
+        .unwrap();
+
+        // Only the outer level's products were committed
+        app.transaction(|resolver| async move {
+            for (id, committed) in [
+                (outer, true),
+                (middle, false),
+                (inner, false),
+                (after, true),
+            ] {
+                let product = resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await?;
+
+                assert_eq!(committed, product.is_some());
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_in_transaction_are_atomic() {
+        let app = App::new();
+
+        let id = ProductId::new();
+
+        // Create a product, then fail to update a different one that doesn't exist
+        let r: Result<(), Error> = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_product_command()
+                    .execute(CreateProduct {
+                        id,
+                        title: "A title".into(),
+                        price: Currency::usd(100),
+                    })
+                    .await?;
+
+                resolver
+                    .set_product_title_command()
+                    .execute(SetProductTitle {
+                        id: ProductId::new(),
+                        title: "Another title".into(),
+                    })
+                    .await
+            })
+            .await;
+
+        assert!(r.is_err());
+
+        let product = app
+            .transaction(|resolver| async move {
+                resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert!(product.is_none());
+    }
+
+    #[tokio::test]
+    async fn nested_failure_cancels_whole_transaction() {
+        let app = App::new();
+
+        let create = |id| CreateProduct {
+            id,
+            title: "A title".into(),
+            price: Currency::usd(100),
+        };
+
+        let outer = ProductId::new();
+        let middle = ProductId::new();
+        let inner = ProductId::new();
+
+        let r: Result<(), Error> = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_product_command()
+                    .execute(create(outer))
+                    .await?;
+
+                // The middle level fails after the inner level succeeds
+                let middle_level = |resolver: Resolver| async move {
+                    resolver
+                        .create_product_command()
+                        .execute(create(middle))
+                        .await?;
+
+                    let inner_level = |resolver: Resolver| async move {
+                        resolver
+                            .create_product_command()
+                            .execute(create(inner))
+                            .await
+                    };
+
+                    inner_level(resolver).await?;
+
+                    Err::<(), _>(error::msg("failed in the middle"))
+                };
+
+                middle_level(resolver.by_ref()).await
+            })
+            .await;
+
+        assert!(r.is_err());
+
+        // None of the products were created, including those from the outer and inner levels
+        app.transaction(|resolver| async move {
+            for id in [outer, middle, inner] {
+                assert!(resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await?
+                    .is_none());
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,143 @@
+/*! Helpers for asserting how transactions completed in tests. */
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::{
+    domain::{
+        infra::*,
+        Error,
+    },
+    store::{
+        TransactionId,
+        TransactionStore,
+    },
+};
+
+/**
+A transaction provider for tests.
+
+Transactions are begun and completed the same way `App::transaction` does, committing on success
+and cancelling on failure. The provider records each transaction it begins so tests can assert
+on how the most recent one completed.
+*/
+#[derive(Clone, Default)]
+pub(in crate::domain) struct TestTransactionProvider {
+    store: TransactionStore,
+    transactions: Arc<Mutex<Vec<TransactionId>>>,
+}
+
+impl TestTransactionProvider {
+    pub(in crate::domain) fn new() -> Self {
+        TestTransactionProvider::default()
+    }
+
+    /**
+    The transaction store used by this provider.
+
+    Value stores need to share this store to observe transactions begun by the provider.
+    */
+    pub(in crate::domain) fn transaction_store(&self) -> TransactionStore {
+        self.store.clone()
+    }
+
+    /**
+    Run a function in a new transaction.
+    */
+    pub(in crate::domain) async fn transaction<F, O, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(ActiveTransaction) -> O,
+        O: Future<Output = Result<T, Error>>,
+    {
+        let transaction = ActiveTransaction::begin(self.store.clone());
+
+        self.transactions
+            .lock()
+            .unwrap()
+            .push(transaction.get().id());
+
+        match f(transaction.clone()).await {
+            Ok(r) => {
+                transaction.commit()?;
+
+                Ok(r)
+            }
+            Err(err) => {
+                transaction.cancel()?;
+
+                Err(err)
+            }
+        }
+    }
+
+    /**
+    Assert that the most recent transaction was committed.
+    */
+    #[track_caller]
+    pub(in crate::domain) fn assert_committed(&self) {
+        let id = self.last();
+
+        assert!(
+            self.store.is_committed(id),
+            "expected transaction `{:?}` to be committed",
+            id
+        );
+    }
+
+    /**
+    Assert that the most recent transaction was cancelled.
+    */
+    #[track_caller]
+    pub(in crate::domain) fn assert_rolled_back(&self) {
+        let id = self.last();
+
+        assert!(
+            self.store.is_cancelled(id),
+            "expected transaction `{:?}` to be rolled back",
+            id
+        );
+    }
+
+    #[track_caller]
+    fn last(&self) -> TransactionId {
+        *self
+            .transactions
+            .lock()
+            .unwrap()
+            .last()
+            .expect("no transactions have been begun")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::error;
+
+    #[tokio::test]
+    async fn ok_is_committed() {
+        let provider = TestTransactionProvider::new();
+
+        provider.transaction(|_| async { Ok(()) }).await.unwrap();
+
+        provider.assert_committed();
+    }
+
+    #[tokio::test]
+    async fn err_is_rolled_back() {
+        let provider = TestTransactionProvider::new();
+
+        provider
+            .transaction(|_| async { Err::<(), _>(error::msg("failed")) })
+            .await
+            .unwrap_err();
+
+        provider.assert_rolled_back();
+    }
+}
\ No newline at end of file
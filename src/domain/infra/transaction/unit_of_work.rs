@@ -0,0 +1,309 @@
+use crate::domain::{
+    infra::*,
+    Error,
+};
+
+/**
+A set of commands that either all commit together or not at all.
+
+Commands resolved from a unit of work's resolver all share its transaction, no matter which
+stores they write to. Nothing they change is observable until the unit of work is committed.
+If it's cancelled or dropped before then, every change is rolled back.
+
+`App::transaction` is a unit of work that's committed when its closure succeeds. A `UnitOfWork`
+can be used directly when the commands don't fit neatly into a single closure.
+*/
+pub struct UnitOfWork {
+    resolver: Resolver,
+    transaction: ActiveTransaction,
+}
+
+impl UnitOfWork {
+    /** Get the resolver for commands and queries that participate in the unit of work. */
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    /**
+    Commit every change made in the unit of work.
+
+    Any resolvers cloned from the unit of work, and any commands resolved from it but not executed,
+    need to be dropped first. If they aren't then this will return an error without committing.
+    */
+    pub fn commit(self) -> Result<(), Error> {
+        let UnitOfWork {
+            resolver,
+            transaction,
+        } = self;
+
+        // The resolver holds on to the transaction, so it needs to go before committing
+        drop(resolver);
+
+        transaction.commit()
+    }
+
+    /**
+    Roll back every change made in the unit of work.
+
+    Dropping a unit of work also rolls it back, but cancelling it releases its changes right away
+    rather than whenever the last clone of its resolver is dropped.
+    */
+    pub fn cancel(self) -> Result<(), Error> {
+        let UnitOfWork {
+            resolver,
+            transaction,
+        } = self;
+
+        drop(resolver);
+
+        transaction.cancel()
+    }
+}
+
+impl App {
+    /** Begin a unit of work that needs to be committed before any of its changes are observable. */
+    pub fn unit_of_work(&self) -> UnitOfWork {
+        self.root_resolver.unit_of_work()
+    }
+}
+
+impl Resolver {
+    /**
+    Begin a unit of work that resolves the same stores as this resolver.
+
+    The unit of work has its own transaction, even if this resolver is already part of one.
+    */
+    pub(in crate::domain) fn unit_of_work(&self) -> UnitOfWork {
+        let resolver = self.with_active_transaction(Register::once(|resolver| {
+            ActiveTransaction::begin(resolver.transaction_store())
+        }));
+
+        let transaction = resolver.active_transaction();
+
+        UnitOfWork {
+            resolver,
+            transaction,
+        }
+    }
+
+    /**
+    Begin a unit of work nested in this resolver's transaction.
+
+    Committing the unit of work hands its changes to the enclosing transaction, and cancelling it
+    only rolls back the changes made in it. If this resolver isn't part of a transaction then the
+    unit of work has its own.
+    */
+    pub fn savepoint(&self) -> UnitOfWork {
+        let parent = self.active_transaction();
+
+        let resolver = self.with_active_transaction(Register::once(move |resolver| {
+            if parent.is_transactional() {
+                parent.savepoint()
+            } else {
+                ActiveTransaction::begin(resolver.transaction_store())
+            }
+        }));
+
+        let transaction = resolver.active_transaction();
+
+        UnitOfWork {
+            resolver,
+            transaction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        orders::*,
+        products::*,
+        ErrorKind,
+    };
+
+    struct Created {
+        customer_id: CustomerId,
+        order_id: OrderId,
+    }
+
+    impl Created {
+        fn new() -> Self {
+            Created {
+                customer_id: CustomerId::new(),
+                order_id: OrderId::new(),
+            }
+        }
+    }
+
+    async fn create_products(app: &App) -> Vec<ProductId> {
+        let ids = vec![ProductId::new(), ProductId::new(), ProductId::new()];
+
+        for id in ids.clone() {
+            app.transaction(|resolver| async move {
+                resolver
+                    .create_product_command()
+                    .execute(CreateProduct {
+                        id,
+                        title: "A title".into(),
+                        price: Currency::usd(100),
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+        }
+
+        ids
+    }
+
+    /** Create a customer, then an order for that customer, then add the given products to it. */
+    async fn create_all(
+        resolver: &Resolver,
+        created: &Created,
+        product_ids: &[ProductId],
+    ) -> Result<(), Error> {
+        resolver
+            .create_customer_command()
+            .execute(CreateCustomer {
+                id: created.customer_id,
+            })
+            .await?;
+
+        resolver
+            .create_order_command()
+            .execute(CreateOrder {
+                id: created.order_id,
+                customer_id: created.customer_id,
+                client_token: None,
+                guest: false,
+            })
+            .await?;
+
+        for &product_id in product_ids {
+            resolver
+                .add_or_update_product_command()
+                .execute(AddOrUpdateProduct {
+                    id: created.order_id,
+                    product_id,
+                    quantity: 1,
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /** Check which of the created entities are observable outside of a unit of work. */
+    async fn observable(app: &App, created: &Created) -> [bool; 2] {
+        let unit_of_work = app.unit_of_work();
+        let resolver = unit_of_work.resolver();
+
+        let customer = resolver
+            .get_customer_query()
+            .execute(GetCustomer {
+                id: created.customer_id,
+            })
+            .await
+            .unwrap();
+        let order = resolver
+            .get_order_query()
+            .execute(GetOrder {
+                id: created.order_id,
+            })
+            .await
+            .unwrap();
+
+        [customer.is_some(), order.is_some()]
+    }
+
+    #[tokio::test]
+    async fn committed_changes_are_observable() {
+        let app = App::new();
+        let product_ids = create_products(&app).await;
+
+        let created = Created::new();
+
+        let unit_of_work = app.unit_of_work();
+        create_all(unit_of_work.resolver(), &created, &product_ids)
+            .await
+            .unwrap();
+
+        // Nothing is observable until the unit of work commits
+        assert_eq!([false; 2], observable(&app, &created).await);
+
+        unit_of_work.commit().unwrap();
+
+        assert_eq!([true; 2], observable(&app, &created).await);
+
+        let order = app
+            .transaction(|resolver| async move {
+                resolver
+                    .get_order_query()
+                    .execute(GetOrder {
+                        id: created.order_id,
+                    })
+                    .await
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        for product_id in product_ids {
+            assert!(order.contains_product(product_id));
+        }
+    }
+
+    #[tokio::test]
+    async fn failure_rolls_back_every_store() {
+        let app = App::new();
+        let mut product_ids = create_products(&app).await;
+
+        // The third product doesn't exist, so adding it fails
+        product_ids[2] = ProductId::new();
+
+        let created = Created::new();
+
+        let unit_of_work = app.unit_of_work();
+        let err = create_all(unit_of_work.resolver(), &created, &product_ids)
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        unit_of_work.cancel().unwrap();
+
+        // The customer and order from the earlier steps were rolled back too
+        assert_eq!([false; 2], observable(&app, &created).await);
+    }
+
+    #[tokio::test]
+    async fn dropped_unit_of_work_is_rolled_back() {
+        let app = App::new();
+        let product_ids = create_products(&app).await;
+
+        let created = Created::new();
+
+        {
+            let unit_of_work = app.unit_of_work();
+
+            create_all(unit_of_work.resolver(), &created, &product_ids)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!([false; 2], observable(&app, &created).await);
+    }
+
+    #[tokio::test]
+    async fn unit_of_work_in_use_can_not_be_committed() {
+        let app = App::new();
+
+        let unit_of_work = app.unit_of_work();
+        let _in_use = unit_of_work.resolver().by_ref();
+
+        assert!(unit_of_work.commit().is_err());
+    }
+}
\ No newline at end of file
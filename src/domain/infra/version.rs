@@ -25,25 +25,38 @@ use std::{
 };
 use uuid::Uuid;
 
-use crate::store;
+use crate::{
+    domain::{
+        error,
+        infra::{
+            Id,
+            IdTag,
+        },
+        Error,
+        ErrorKind,
+        VersionConflict,
+    },
+    store,
+};
 
 /**
 A version.
 
 The version provides optimistic concurrency.
+Versions start at zero and increase each time their entity is stored.
 Versions have a phantom generic type so you can't compare `Version<T>` to `Version<U>`.
 */
-pub struct Version<T>(Uuid, PhantomData<T>);
+pub struct Version<T>(u64, PhantomData<T>);
 
 impl<T> From<Version<T>> for store::Version {
-    fn from(id: Version<T>) -> store::Version {
-        store::Version::from_raw(id.0)
+    fn from(version: Version<T>) -> store::Version {
+        store::Version::from_raw(Uuid::from_u64_pair(0, version.0))
     }
 }
 
 impl<T> From<store::Version> for Version<T> {
-    fn from(id: store::Version) -> Version<T> {
-        Version(id.into_raw(), PhantomData)
+    fn from(version: store::Version) -> Version<T> {
+        Version(version.into_raw().as_u64_pair().1, PhantomData)
     }
 }
 
@@ -69,7 +82,7 @@ impl<T> Copy for Version<T> {}
 
 impl<T> Default for Version<T> {
     fn default() -> Self {
-        Version(Uuid::default(), PhantomData)
+        Version(0, PhantomData)
     }
 }
 
@@ -113,20 +126,222 @@ impl<'de, T> Deserialize<'de> for Version<T> {
     where
         D: Deserializer<'de>,
     {
-        let id = Uuid::deserialize(deserializer)?;
-        Ok(Version(id, PhantomData))
+        let version = u64::deserialize(deserializer)?;
+        Ok(Version(version, PhantomData))
     }
 }
 
 impl<T> Version<T> {
+    /** Create the initial version. */
     pub fn new() -> Self {
-        Version(Uuid::new_v4(), PhantomData)
+        Version::default()
+    }
+
+    /** Get the raw value of the version. */
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    /**
+    Get the version that follows this one.
+
+    The largest version has nothing after it, so it's an error to go past it rather than
+    wrapping back around to a version that's already been used.
+    */
+    pub fn next(&self) -> Result<Version<T>, Error> {
+        self.0
+            .checked_add(1)
+            .map(|version| Version(version, PhantomData))
+            .ok_or_else(|| error::msg(format!("version {} is the last version", self.0)))
+    }
+
+    /**
+    Get the version that comes before this one.
+
+    Storing data with the previous version brings it back to this one. The first version has
+    nothing before it, so it's its own previous version.
+    */
+    pub(in crate::domain) fn previous(&self) -> Version<T> {
+        Version(self.0.saturating_sub(1), PhantomData)
     }
 }
 
-impl<T> Version<T> {
-    pub(in crate::domain) fn next(&mut self) -> Version<T> {
-        *self = Version(Uuid::new_v4(), PhantomData);
-        *self
+impl<T: IdTag> Version<T> {
+    /**
+    Check that an actual version of an entity matches the one that was expected.
+
+    If the versions don't match then the error will carry a `VersionConflict` with both of them.
+    */
+    pub fn assert_matches(id: Id<T>, expected: Self, actual: Self) -> Result<(), Error> {
+        if expected != actual {
+            return Err(error::version_conflict(
+                T::TAG,
+                id,
+                expected.value(),
+                actual.value(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+    Convert an error from setting an entity in a store into a version conflict if the write was stale.
+
+    The error from the store is kept as the source of the returned error.
+    */
+    pub(in crate::domain) fn stale_write(id: Id<T>) -> impl FnOnce(store::Error) -> Error {
+        move |err| match err.downcast::<store::VersionMismatch>() {
+            Ok(mismatch) => {
+                let value = |version: Option<store::Version>| {
+                    version
+                        .map(|version| Version::<T>::from(version).value())
+                        .unwrap_or_default()
+                };
+
+                Error::with_source(
+                    ErrorKind::Conflict,
+                    VersionConflict {
+                        entity: T::TAG,
+                        id: id.to_string(),
+                        expected: value(mismatch.expected),
+                        actual: value(mismatch.actual),
+                    },
+                    *mismatch,
+                )
+            }
+            Err(err) => {
+                Error::with_source(ErrorKind::Internal, "failed to write to the store", err)
+            }
+        }
+    }
+}
+
+/**
+A strategy that generates versions for property tests.
+
+Versions are kept well below the largest value, so tests can still call `next` on them.
+*/
+#[cfg(any(test, feature = "proptest"))]
+pub fn version_strategy<T>() -> impl proptest::strategy::Strategy<Value = Version<T>> {
+    <Version<T> as proptest::arbitrary::Arbitrary>::arbitrary()
+}
+
+#[cfg(any(test, feature = "proptest"))]
+impl<T> proptest::arbitrary::Arbitrary for Version<T> {
+    type Parameters = ();
+    type Strategy = proptest::strategy::Map<std::ops::RangeInclusive<u64>, fn(u64) -> Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        proptest::strategy::Strategy::prop_map(0..=u64::from(u32::MAX), |version| {
+            Version(version, PhantomData)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Tagged;
+
+    impl IdTag for Tagged {
+        const TAG: &'static str = "tagged";
+    }
+
+    #[test]
+    fn versions_are_ordered() {
+        let initial = Version::<()>::default();
+        let next = initial.next().unwrap();
+
+        assert_eq!(0, initial.value());
+        assert_eq!(1, next.value());
+        assert!(initial < next);
+        assert!(next < next.next().unwrap());
+    }
+
+    #[test]
+    fn err_next_of_last_version() {
+        let last = Version::<()>(u64::MAX, PhantomData);
+
+        assert!(last.next().is_err());
+    }
+
+    #[test]
+    fn version_round_trips_through_store() {
+        let version = Version::<()>::new().next().unwrap().next().unwrap();
+
+        let stored: store::Version = version.into();
+
+        assert_eq!(version, Version::from(stored));
+    }
+
+    #[test]
+    fn version_serializes_as_integer() {
+        let version = Version::<()>::new().next().unwrap();
+
+        let json = serde_json::to_string(&version).unwrap();
+
+        assert_eq!("1", json);
+        assert_eq!(version, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn err_version_conflict() {
+        let id = Id::<Tagged>::new();
+        let expected = Version::new();
+        let actual = expected.next().unwrap();
+
+        assert!(Version::assert_matches(id, expected, expected).is_ok());
+
+        let err = Version::assert_matches(id, expected, actual).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "version conflict on tagged `{}`: expected version 0 but found version 1",
+                id
+            ),
+            err.to_string()
+        );
+
+        let conflict = err.version_conflict().unwrap();
+
+        assert_eq!("tagged", conflict.entity);
+        assert_eq!(id.to_string(), conflict.id);
+        assert_eq!(0, conflict.expected);
+        assert_eq!(1, conflict.actual);
+    }
+
+    #[test]
+    fn stale_write_is_version_conflict() {
+        let id = Id::<Tagged>::new();
+
+        let err = Version::stale_write(id)(Box::new(store::VersionMismatch {
+            expected: Some(Version::<Tagged>::new().into()),
+            actual: Some(
+                Version::<Tagged>::new()
+                    .next()
+                    .unwrap()
+                    .next()
+                    .unwrap()
+                    .into(),
+            ),
+        }));
+
+        let conflict = err.version_conflict().unwrap();
+
+        assert_eq!(0, conflict.expected);
+        assert_eq!(2, conflict.actual);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn arbitrary_versions_round_trip_through_json(version in version_strategy::<Tagged>()) {
+            let json = serde_json::to_string(&version).unwrap();
+            let parsed: Version<Tagged> = serde_json::from_str(&json).unwrap();
+
+            proptest::prop_assert_eq!(version, parsed);
+            proptest::prop_assert!(version.next().unwrap() > version);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,535 @@
+/*!
+Contains the `WalStore` type.
+
+A `WalStore` wraps another store and appends each change it's given to a log before passing it
+on. The wrapped store keeps everything in memory, and the log is replayed into a fresh store
+when the app starts, so changes survive restarts without a database.
+
+# Transactions
+
+Changes are logged as they're made, along with the id of their transaction. Whether each
+transaction committed is logged as soon as it completes. Only changes in transactions that are
+logged as committed are replayed, in the order they were made, so a crash can lose a transaction
+that was committing at the time, but it can't resurrect ones that were cancelled.
+*/
+
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    marker::PhantomData,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use serde::{
+    de::{
+        DeserializeOwned,
+        IgnoredAny,
+    },
+    Serialize,
+};
+
+use crate::{
+    domain::{
+        infra::Flush,
+        Error,
+        ErrorKind,
+    },
+    store::{
+        lock,
+        Transaction,
+        TransactionId,
+        TransactionStore,
+        Wal,
+    },
+};
+
+/** A change that a `WalStore` logs before making it to a store of type `S`. */
+pub(in crate::domain) trait WalChange<S>: Serialize + DeserializeOwned {
+    /** Make the change to a store again, the way it was made when it was logged. */
+    fn apply(self, store: &S) -> Result<(), Error>;
+
+    /** Get the changes that bring an empty store up to the committed state of `store`. */
+    fn snapshot(store: &S) -> Result<Vec<Self>, Error>
+    where
+        Self: Sized;
+}
+
+/** A record in the log. */
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record<C> {
+    Change {
+        transaction_id: TransactionId,
+        change: C,
+    },
+    Completed {
+        transaction_id: TransactionId,
+        committed: bool,
+    },
+}
+
+/** A store that logs each change before passing it on to the store it wraps. */
+pub(in crate::domain) struct WalStore<S, C> {
+    store: S,
+    log: Arc<Log>,
+    transactions: TransactionStore,
+    _change: PhantomData<fn(C)>,
+}
+
+/** The log that a `WalStore` appends to, shared with the transactions that complete in it. */
+struct Log {
+    path: PathBuf,
+    wal: Wal,
+    /**
+    Held while a change is logged and made, and while the log is compacted.
+
+    Changes are made in the same order they're logged, so replaying them gives the same result.
+    */
+    changing: Mutex<()>,
+    /** Transactions that have changes in the log but haven't been logged as completed yet. */
+    pending: Mutex<HashSet<TransactionId>>,
+}
+
+impl<S, C> WalStore<S, C> {
+    /** The wrapped store, for reads that don't need logging. */
+    pub(in crate::domain) fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S, C> WalStore<S, C>
+where
+    C: WalChange<S>,
+{
+    /**
+    Wrap a store, appending its changes to the log at `path`.
+
+    The log is created if it doesn't exist. Replay the log into the store before wrapping it,
+    or the changes already in the log will be missing from it.
+    */
+    pub(in crate::domain) fn open(
+        path: impl Into<PathBuf>,
+        store: S,
+        transaction_store: TransactionStore,
+    ) -> Result<Self, Error> {
+        let path = path.into();
+
+        let wal = Wal::open(&path).map_err(log_error("failed to open the log", &path))?;
+
+        Ok(WalStore {
+            store,
+            log: Arc::new(Log {
+                path,
+                wal,
+                changing: Mutex::new(()),
+                pending: Mutex::new(HashSet::new()),
+            }),
+            transactions: transaction_store,
+            _change: PhantomData,
+        })
+    }
+
+    /**
+    Log a change, then make it by calling `f` with the wrapped store.
+
+    The change is logged even if `f` fails. It'll fail the same way when it's replayed.
+    Whether the change's transaction committed is logged as soon as it completes.
+    */
+    pub(in crate::domain) fn change<T>(
+        &self,
+        transaction: &Transaction,
+        change: C,
+        f: impl FnOnce(&S) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        // Concurrent changes could otherwise be made in a different order than they're logged,
+        // and a change made while the log is compacted could miss the snapshot and be dropped
+        let _changing = lock::lock(&self.log.changing);
+
+        let transaction_id = transaction.id();
+
+        self.log.append(&Record::Change {
+            transaction_id,
+            change,
+        })?;
+
+        let changed = f(&self.store);
+
+        // Auto-committed changes complete straight away, so they're logged before this returns
+        if self.transactions.is_committed(transaction_id) {
+            self.log.append(&Record::<()>::Completed {
+                transaction_id,
+                committed: true,
+            })?;
+        } else if lock::lock(&self.log.pending).insert(transaction_id) {
+            let log = self.log.clone();
+
+            self.transactions
+                .on_complete(transaction, move |committed| {
+                    log.completed(transaction_id, committed)
+                });
+        }
+
+        changed
+    }
+
+    /**
+    Replace the log with a snapshot of the wrapped store.
+
+    Changes in transactions that are still active aren't in the snapshot yet, so they're kept
+    after it.
+    */
+    pub(in crate::domain) fn compact(&self) -> Result<(), Error> {
+        let _changing = lock::lock(&self.log.changing);
+
+        let snapshot = C::snapshot(&self.store)?;
+
+        let active = self
+            .log
+            .wal
+            .records()
+            .map_err(log_error("failed to read the log", &self.log.path))?
+            .into_iter()
+            .filter(
+                |record| match serde_json::from_slice::<Record<IgnoredAny>>(record) {
+                    Ok(Record::Change { transaction_id, .. }) => {
+                        !self.transactions.is_committed(transaction_id)
+                            && !self.transactions.is_cancelled(transaction_id)
+                    }
+                    _ => false,
+                },
+            );
+
+        // The snapshot is logged as a single transaction that's already committed
+        let transaction_id = Transaction::none().id();
+
+        let mut records = snapshot
+            .into_iter()
+            .map(|change| {
+                serialize(&Record::Change {
+                    transaction_id,
+                    change,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        records.push(serialize(&Record::<()>::Completed {
+            transaction_id,
+            committed: true,
+        })?);
+
+        records.extend(active);
+
+        self.log
+            .wal
+            .replace(records)
+            .map_err(log_error("failed to compact the log", &self.log.path))
+    }
+}
+
+impl Log {
+    fn append<R>(&self, record: &Record<R>) -> Result<(), Error>
+    where
+        R: Serialize,
+    {
+        self.wal
+            .append(&serialize(record)?)
+            .map_err(log_error("failed to append to the log", &self.path))
+    }
+
+    /**
+    Log whether a transaction committed, once it's completed.
+
+    The transaction has already completed, so there's nobody to return an error to. If the record
+    can't be appended then the transaction's changes won't be replayed.
+    */
+    fn completed(&self, transaction_id: TransactionId, committed: bool) {
+        // The record could be lost if the log was compacted while it's appended
+        let _changing = lock::lock(&self.changing);
+
+        lock::lock(&self.pending).remove(&transaction_id);
+
+        if let Err(err) = self.append(&Record::<()>::Completed {
+            transaction_id,
+            committed,
+        }) {
+            error!(
+                "failed to log that transaction `{}` completed: {}",
+                transaction_id, err
+            );
+        }
+    }
+}
+
+impl<S, C> Flush for WalStore<S, C>
+where
+    S: Flush,
+    C: WalChange<S>,
+{
+    /** Compact the log so it's quick to replay. */
+    fn flush(&self) -> Result<(), Error> {
+        self.store.flush()?;
+
+        self.compact()
+    }
+}
+
+/**
+Replay the changes in the log at `path` into a store, in the order they were made.
+
+Only changes in transactions that were logged as committed are replayed. A change that can't be
+applied is skipped with a warning, since it failed the same way when it was first made.
+Returns the number of changes that were replayed.
+*/
+pub(in crate::domain) fn replay<S, C>(path: impl AsRef<Path>, store: &S) -> Result<usize, Error>
+where
+    C: WalChange<S>,
+{
+    let path = path.as_ref();
+
+    let records = Wal::read(path)
+        .map_err(log_error("failed to read the log", path))?
+        .into_iter()
+        .map(|record| {
+            serde_json::from_slice(&record).map_err(|err| {
+                Error::with_source(
+                    ErrorKind::Internal,
+                    format!("a record in the log `{}` is corrupted", path.display()),
+                    err,
+                )
+            })
+        })
+        .collect::<Result<Vec<Record<C>>, Error>>()?;
+
+    // Transactions complete after their changes are logged, so find out which ones committed
+    // before replaying any changes
+    let completed: HashMap<TransactionId, bool> = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Completed {
+                transaction_id,
+                committed,
+            } => Some((*transaction_id, *committed)),
+            Record::Change { .. } => None,
+        })
+        .collect();
+
+    let mut incomplete = HashSet::new();
+    let mut replayed = 0;
+
+    for record in records {
+        let (transaction_id, change) = match record {
+            Record::Change {
+                transaction_id,
+                change,
+            } => (transaction_id, change),
+            Record::Completed { .. } => continue,
+        };
+
+        match completed.get(&transaction_id) {
+            Some(true) => match change.apply(store) {
+                Ok(()) => replayed += 1,
+                Err(err) => warn!(
+                    "skipping a change in `{}` that can't be replayed: {}",
+                    path.display(),
+                    err
+                ),
+            },
+            Some(false) => (),
+            None => {
+                incomplete.insert(transaction_id);
+            }
+        }
+    }
+
+    if !incomplete.is_empty() {
+        warn!(
+            "skipping changes from {} transactions that weren't completed in `{}`",
+            incomplete.len(),
+            path.display()
+        );
+    }
+
+    Ok(replayed)
+}
+
+fn serialize<R>(record: &Record<R>) -> Result<Vec<u8>, Error>
+where
+    R: Serialize,
+{
+    serde_json::to_vec(record).map_err(|err| {
+        Error::with_source(ErrorKind::Internal, "failed to serialize a log record", err)
+    })
+}
+
+fn log_error<'a, E>(msg: &'static str, path: &'a Path) -> impl FnOnce(E) -> Error + 'a
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    move |err| {
+        Error::with_source(
+            ErrorKind::Internal,
+            format!("{} `{}`", msg, path.display()),
+            err,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    type Changes = Mutex<Vec<String>>;
+
+    impl WalChange<Changes> for String {
+        fn apply(self, store: &Changes) -> Result<(), Error> {
+            lock::lock(store).push(self);
+
+            Ok(())
+        }
+
+        fn snapshot(store: &Changes) -> Result<Vec<Self>, Error> {
+            Ok(lock::lock(store).clone())
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("shop-wal-{}", Uuid::new_v4()))
+    }
+
+    fn replayed(path: &Path) -> Vec<String> {
+        let store = Changes::default();
+
+        replay::<_, String>(path, &store).unwrap();
+
+        store.into_inner().unwrap()
+    }
+
+    #[test]
+    fn only_committed_changes_are_replayed() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store: WalStore<Changes, String> =
+            WalStore::open(&path, Changes::default(), transactions.clone()).unwrap();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+        let active = transactions.begin();
+
+        for (transaction, change) in [
+            (&committed, "committed"),
+            (&cancelled, "cancelled"),
+            (&active, "active"),
+            (&Transaction::none(), "auto-committed"),
+        ] {
+            store
+                .change(transaction, change.to_owned(), |_| Ok(()))
+                .unwrap();
+        }
+
+        transactions.commit(committed).unwrap();
+        transactions.cancel(cancelled);
+
+        // Changes are replayed in the order they were made, not the order they committed in
+        assert_eq!(vec!["committed", "auto-committed"], replayed(&path));
+
+        // Compacting keeps the changes in transactions that are still active
+        lock::lock(store.store()).push("snapshot".to_owned());
+        store.compact().unwrap();
+
+        transactions.commit(active).unwrap();
+
+        assert_eq!(vec!["snapshot", "active"], replayed(&path));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn committed_savepoints_are_replayed_in_order() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store: WalStore<Changes, String> =
+            WalStore::open(&path, Changes::default(), transactions.clone()).unwrap();
+
+        let change = |transaction: &Transaction, change: &str| {
+            store
+                .change(transaction, change.to_owned(), |store| {
+                    lock::lock(store).push(change.to_owned());
+
+                    Ok(())
+                })
+                .unwrap()
+        };
+
+        let parent = transactions.begin();
+        change(&parent, "parent");
+
+        let committed = transactions.begin_child(&parent);
+        change(&committed, "committed savepoint");
+        transactions.commit(committed).unwrap();
+
+        let cancelled = transactions.begin_child(&parent);
+        change(&cancelled, "cancelled savepoint");
+        transactions.cancel(cancelled);
+
+        change(&parent, "parent again");
+
+        // Nothing is replayed until the outermost transaction commits
+        assert!(replayed(&path).is_empty());
+
+        transactions.commit(parent).unwrap();
+
+        assert_eq!(
+            vec!["parent", "committed savepoint", "parent again"],
+            replayed(&path)
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_changes_are_logged_in_the_order_they_are_made() {
+        let path = temp_path();
+
+        let store: WalStore<Changes, String> =
+            WalStore::open(&path, Changes::default(), TransactionStore::new()).unwrap();
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let store = &store;
+
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        let change = format!("{}-{}", thread, i);
+
+                        store
+                            .change(&Transaction::none(), change.clone(), |store| {
+                                lock::lock(store).push(change);
+
+                                Ok(())
+                            })
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock::lock(store.store()), replayed(&path));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
\ No newline at end of file
@@ -4,6 +4,8 @@ Domain modules.
 The domain contains modules for entities like products and customers as well as some shared infrastructure.
 Entity submodules are organized around a single entity, or group of closely related entities, and their storage.
 The public API contains entities, queries and commands that can depend on private storage.
+The store traits are public so applications can plug in their own stores, but the stores that
+implement them stay private.
 
 Organizing entities this way means we don't need a leaky public API for the sake of storage.
 Cross-cutting concerns should either live in the most specific entity submodule, or go in a new one.
@@ -11,7 +13,9 @@ Cross-cutting concerns should either live in the most specific entity submodule,
 
 #[macro_use]
 mod error;
+pub mod events;
 pub mod infra;
+mod snapshot;
 
 pub mod customers;
 pub mod orders;
@@ -19,8 +23,11 @@ pub mod products;
 
 pub use self::{
     error::{
+        EntityNotFound,
         Error,
         ErrorKind,
+        StatusHint,
+        VersionConflict,
     },
     infra::App,
 };
\ No newline at end of file
@@ -1,11 +1,22 @@
 /*! Contains the `AddOrUpdateProductCommand` type. */
 
+use std::convert::TryFrom;
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
 use crate::domain::{
     error,
+    events::*,
     infra::*,
     orders::*,
     products::*,
     Error,
+    ErrorKind,
 };
 
 /** Input for an `AddOrUpdateProductCommand`. */
@@ -16,6 +27,23 @@ pub struct AddOrUpdateProduct {
     pub quantity: u32,
 }
 
+impl AddOrUpdateProduct {
+    /**
+    Parse input from JSON and check it before it reaches the command.
+
+    Malformed JSON and a quantity the command would reject are both bad input.
+    */
+    pub fn parse(json: &str) -> Result<Self, Error> {
+        let input: AddOrUpdateProduct = serde_json::from_str(json).map_err(|err| {
+            Error::with_source(ErrorKind::BadInput, format!("invalid input: {}", err), err)
+        })?;
+
+        Quantity::try_from(input.quantity)?;
+
+        Ok(input)
+    }
+}
+
 impl CommandArgs for AddOrUpdateProduct {
     type Output = Result<LineItemId, Error>;
 }
@@ -26,56 +54,76 @@ async fn execute(
     store: impl OrderStore,
     id: impl IdProvider<LineItemData>,
     product_query: impl Query<GetProduct>,
+    events: Events,
 ) -> Result<LineItemId, Error> {
     debug!(
-        "updating product `{}` in order `{}`",
-        command.product_id, command.id
+        product_id = %command.product_id.tagged(),
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "updating product in order"
     );
 
-    if let Some(order) = store.get_order(command.id)? {
-        let id = match order.into_line_item_for_product(command.product_id) {
+    if let Some(order) = store.get_order(transaction.read(), command.id, ReadConsistency::Strong)? {
+        let (id, price) = match order.into_line_item_for_product(command.product_id) {
             IntoLineItem::InOrder(mut line_item) => {
                 debug!(
-                    "updating existing product `{}` in order `{}`",
-                    command.product_id, command.id
+                    product_id = %command.product_id.tagged(),
+                    order_id = %command.id.tagged(),
+                    transaction_id = %transaction.id(),
+                    "updating existing product in order"
                 );
 
-                let (_, &LineItemData { id, .. }) = line_item.to_data();
+                let (id, price) = (line_item.id(), line_item.to_data().1.price);
 
                 line_item.set_quantity(command.quantity)?;
                 store.set_line_item(transaction.get(), line_item)?;
 
-                id
+                (id, price)
             }
             IntoLineItem::NotInOrder(mut order) => {
                 debug!(
-                    "adding new product `{}` to order `{}`",
-                    command.product_id, command.id
+                    product_id = %command.product_id.tagged(),
+                    order_id = %command.id.tagged(),
+                    transaction_id = %transaction.id(),
+                    "adding new product to order"
                 );
 
                 let id = id.get()?;
                 let product = product_query
                     .execute(GetProduct {
                         id: command.product_id,
+                        include_inactive: false,
                     })
                     .await?
-                    .ok_or_else(|| error::bad_input("product not found"))?;
+                    .ok_or_else(|| error::not_found("product", command.product_id))?;
 
                 order.add_product(id, &product, command.quantity)?;
                 store.set_order(transaction.get(), order)?;
 
-                id
+                (id, product.to_data().price)
             }
         };
 
+        let (order_id, product_id, quantity) = (command.id, command.product_id, command.quantity);
+        transaction.on_commit(move || {
+            events.publish(DomainEvent::OrderProductSet {
+                id: order_id,
+                product_id,
+                price,
+                quantity,
+            })
+        });
+
         info!(
-            "updated product `{}` in order `{}`",
-            command.product_id, command.id
+            product_id = %command.product_id.tagged(),
+            order_id = %command.id.tagged(),
+            transaction_id = %transaction.id(),
+            "updated product in order"
         );
 
         Ok(id)
     } else {
-        Err(error::bad_input("not found"))
+        Err(error::not_found("order", command.id))
     }
 }
 
@@ -89,22 +137,50 @@ impl Resolver {
             let id = resolver.line_item_id();
 
             let get_product = resolver.get_product_query();
-
-            execute(command, active_transaction, store, id, get_product).await
+            let events = resolver.events();
+
+            let context = format!("add_or_update_product order={}", command.id);
+
+            let span = info_span!(
+                "add_or_update_product",
+                order_id = %command.id.tagged(),
+                product_id = %command.product_id.tagged(),
+                quantity = command.quantity,
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, id, get_product, events),
+            )
+            .await
+            .map_err(|err| err.context(context))
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
     use crate::domain::{
+        customers::*,
+        infra::{
+            span::test_support::SpanRecorder,
+            transaction::test_support::TestTransactionProvider,
+        },
         orders::model::{
-            store::in_memory_store,
+            store::{
+                in_memory_store,
+                FailingStore,
+            },
             test_data::OrderBuilder,
         },
         products::model::test_data::ProductBuilder,
+        ErrorKind,
     };
 
     #[tokio::test]
@@ -117,7 +193,7 @@ mod tests {
 
         store
             .set_order(
-                ActiveTransaction::none().get(),
+                ActiveTransaction::auto_commit().get(),
                 OrderBuilder::new().id(order_id).build(),
             )
             .unwrap();
@@ -128,16 +204,21 @@ mod tests {
                 product_id,
                 quantity,
             },
-            ActiveTransaction::none(),
+            ActiveTransaction::auto_commit(),
             &store,
             NextLineItemId::new(),
             |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+            Events::new(),
         )
         .await
         .unwrap();
 
         let (_, line_item) = store
-            .get_line_item(order_id, line_item_id)
+            .get_line_item(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                line_item_id,
+            )
             .unwrap()
             .unwrap()
             .into_data();
@@ -163,7 +244,7 @@ mod tests {
             .build();
 
         store
-            .set_order(ActiveTransaction::none().get(), order)
+            .set_order(ActiveTransaction::auto_commit().get(), order)
             .unwrap();
 
         let updated_line_item_id = execute(
@@ -172,16 +253,21 @@ mod tests {
                 product_id,
                 quantity,
             },
-            ActiveTransaction::none(),
+            ActiveTransaction::auto_commit(),
             &store,
             NextLineItemId::new(),
             |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+            Events::new(),
         )
         .await
         .unwrap();
 
         let (_, line_item) = store
-            .get_line_item(order_id, line_item_id)
+            .get_line_item(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                line_item_id,
+            )
             .unwrap()
             .unwrap()
             .into_data();
@@ -189,4 +275,344 @@ mod tests {
         assert_eq!(line_item_id, updated_line_item_id);
         assert_eq!(quantity, line_item.quantity);
     }
+
+    #[tokio::test]
+    async fn add_and_update_through_resolver() {
+        let (spans, _guard) = SpanRecorder::set_default();
+
+        let test = Resolver::for_tests();
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let order_id = OrderId::new();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id: product_id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await?;
+
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        let add = |quantity| {
+            test.transaction(move |resolver| async move {
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity,
+                    })
+                    .await
+            })
+        };
+
+        let added = add(1).await.unwrap();
+        let updated = add(3).await.unwrap();
+
+        assert_eq!(added, updated);
+
+        let order = test
+            .resolver()
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (_, line_items) = order.to_data();
+
+        assert_eq!(1, line_items.len());
+        assert_eq!(3, line_items[0].quantity);
+
+        let span = spans.span("add_or_update_product").unwrap();
+
+        assert_eq!("ok", span.fields["outcome"]);
+    }
+
+    #[tokio::test]
+    async fn span_records_fields_and_error_kind() {
+        let (spans, _guard) = SpanRecorder::set_default();
+
+        let test = Resolver::for_tests();
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        let err = test
+            .transaction(|resolver| async move {
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 2,
+                    })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        let span = spans.span("add_or_update_product").unwrap();
+
+        assert_eq!(order_id.tagged().to_string(), span.fields["order_id"]);
+        assert_eq!(product_id.tagged().to_string(), span.fields["product_id"]);
+        assert_eq!("2", span.fields["quantity"]);
+        assert!(span.fields.contains_key("transaction_id"));
+        assert_eq!("NotFound", span.fields["outcome"]);
+    }
+
+    #[tokio::test]
+    async fn err_if_order_not_found() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 1,
+                    },
+                    transaction,
+                    &store,
+                    NextLineItemId::new(),
+                    |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&order_id.to_string()));
+
+        assert!(store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn err_if_product_not_found() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 1,
+                    },
+                    transaction,
+                    &store,
+                    NextLineItemId::new(),
+                    |_| async { Ok(None) },
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&product_id.to_string()));
+
+        let (_, line_items) = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(0, line_items.len());
+    }
+
+    #[tokio::test]
+    async fn failed_write_rolls_back_earlier_writes() {
+        let transactions = TestTransactionProvider::new();
+        // The first write creates the order and the second updates its line item
+        let store = FailingStore::new(in_memory_store(transactions.transaction_store()), 3);
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+        let line_item_id = LineItemId::new();
+
+        let order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(
+                ProductBuilder::new().id(product_id).build(),
+                move |line_item| line_item.id(line_item_id).quantity(1),
+            )
+            .build();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        transactions
+            .transaction(|transaction| async {
+                // Update the existing product, which succeeds
+                execute(
+                    AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 5,
+                    },
+                    transaction.clone(),
+                    &store,
+                    NextLineItemId::new(),
+                    |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+                    Events::new(),
+                )
+                .await?;
+
+                // Add a new product, which fails to write
+                let new_product_id = ProductId::new();
+
+                execute(
+                    AddOrUpdateProduct {
+                        id: order_id,
+                        product_id: new_product_id,
+                        quantity: 1,
+                    },
+                    transaction,
+                    &store,
+                    NextLineItemId::new(),
+                    |_| async { Ok(Some(ProductBuilder::new().id(new_product_id).build())) },
+                    Events::new(),
+                )
+                .await
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        let (_, line_items) = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(1, line_items.len());
+        assert_eq!(1, line_items[0].quantity);
+    }
+
+    #[test]
+    fn parse_valid_input() {
+        let id = OrderId::new();
+        let product_id = ProductId::new();
+
+        let input = AddOrUpdateProduct::parse(&format!(
+            r#"{{"id":"{}","product_id":"{}","quantity":2}}"#,
+            id, product_id
+        ))
+        .unwrap();
+
+        assert_eq!(id, input.id);
+        assert_eq!(product_id, input.product_id);
+        assert_eq!(2, input.quantity);
+    }
+
+    #[test]
+    fn parse_err_if_malformed() {
+        for json in [
+            "",
+            r#"{"id":"#,
+            r#"{"id":"not an id","product_id":"not an id","quantity":1}"#,
+            r#"{"id":"00000000-0000-0000-0000-000000000001","quantity":1}"#,
+        ] {
+            let err = AddOrUpdateProduct::parse(json).err().unwrap();
+
+            assert_eq!(ErrorKind::BadInput, err.kind(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn parse_err_if_quantity_is_zero() {
+        let err = AddOrUpdateProduct::parse(&format!(
+            r#"{{"id":"{}","product_id":"{}","quantity":0}}"#,
+            OrderId::new(),
+            ProductId::new()
+        ))
+        .err()
+        .unwrap();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(err.to_string().contains("quantity"));
+    }
+
+    proptest! {
+        #[test]
+        fn input_round_trips_through_json(
+            id in id_strategy::<OrderData>(),
+            product_id in id_strategy::<ProductData>(),
+            quantity: u32,
+        ) {
+            let json = serde_json::json!({
+                "id": id,
+                "product_id": product_id,
+                "quantity": quantity,
+            });
+
+            let command: AddOrUpdateProduct = serde_json::from_value(json).unwrap();
+
+            prop_assert_eq!(id, command.id);
+            prop_assert_eq!(product_id, command.product_id);
+            prop_assert_eq!(quantity, command.quantity);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,220 @@
+/*! Contains the `ConfirmPaymentCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `ConfirmPaymentCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct ConfirmPayment {
+    pub order_id: OrderId,
+    /** The payment provider's reference for the payment. */
+    pub transaction_ref: String,
+}
+
+impl CommandArgs for ConfirmPayment {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `ConfirmPaymentCommand`. */
+async fn execute(
+    command: ConfirmPayment,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    clock: impl Clock,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        order_id = %command.order_id.tagged(),
+        transaction_id = %transaction.id(),
+        "confirming payment for order"
+    );
+
+    let mut order = store
+        .get_order(
+            transaction.read(),
+            command.order_id,
+            ReadConsistency::Strong,
+        )?
+        .ok_or_else(|| error::not_found("order", command.order_id))?;
+
+    order.confirm_payment(clock, command.transaction_ref)?;
+
+    store.set_order(transaction.get(), order)?;
+
+    let id = command.order_id;
+    transaction.on_commit(move || events.publish(DomainEvent::OrderPaid { id }));
+
+    info!(
+        order_id = %command.order_id.tagged(),
+        transaction_id = %transaction.id(),
+        "confirmed payment for order"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Record that a placed order has been paid for by an external payment provider. */
+    pub fn confirm_payment_command(&self) -> impl Command<ConfirmPayment> {
+        self.command(|resolver, command: ConfirmPayment| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+            let clock = resolver.clock();
+            let events = resolver.events();
+
+            let context = format!("confirm_payment order={}", command.order_id);
+
+            let span = info_span!(
+                "confirm_payment",
+                order_id = %command.order_id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, clock, events),
+            )
+            .await
+            .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        ErrorKind,
+    };
+
+    fn confirm_payment(order_id: OrderId) -> ConfirmPayment {
+        ConfirmPayment {
+            order_id,
+            transaction_ref: "pay_123".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn placed_order_is_paid() {
+        let store = in_memory_store(Default::default());
+        let events = Events::new();
+
+        let published = Arc::new(Mutex::new(vec![]));
+        events.subscribe({
+            let published = published.clone();
+            move |event| published.lock().unwrap().push(event)
+        });
+
+        let order_id = OrderId::new();
+
+        let mut order = OrderBuilder::new().id(order_id).build();
+        order.place(SystemClock).unwrap();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        execute(
+            confirm_payment(order_id),
+            ActiveTransaction::auto_commit(),
+            &store,
+            SystemClock,
+            events,
+        )
+        .await
+        .unwrap();
+
+        let order = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(OrderStatus::Paid, order.status());
+        assert_eq!(Some("pay_123"), order.to_data().0.payment_ref.as_deref());
+
+        assert_eq!(
+            vec![DomainEvent::OrderPaid { id: order_id }],
+            *published.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn err_if_not_placed_or_already_paid() {
+        let test = Resolver::for_tests();
+
+        let open_id = OrderId::new();
+        let paid_id = OrderId::new();
+
+        let store = test.resolver().order_store();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(open_id).build(),
+            )
+            .unwrap();
+
+        let mut paid = OrderBuilder::new().id(paid_id).build();
+        paid.place(SystemClock).unwrap();
+        paid.confirm_payment(SystemClock, "pay_000").unwrap();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), paid)
+            .unwrap();
+
+        for order_id in [open_id, paid_id] {
+            let err = test
+                .transaction(|resolver| async move {
+                    resolver
+                        .confirm_payment_command()
+                        .execute(confirm_payment(order_id))
+                        .await
+                })
+                .await
+                .unwrap_err();
+
+            test.transactions().assert_rolled_back();
+
+            assert_eq!(ErrorKind::Conflict, err.kind());
+        }
+
+        let (paid, _) = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                paid_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(Some("pay_000"), paid.payment_ref.as_deref());
+    }
+}
\ No newline at end of file
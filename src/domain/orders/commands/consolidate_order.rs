@@ -0,0 +1,164 @@
+/*! Contains the `ConsolidateOrderCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `ConsolidateOrderCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct ConsolidateOrder {
+    pub order_id: OrderId,
+}
+
+impl CommandArgs for ConsolidateOrder {
+    type Output = Result<usize, Error>;
+}
+
+/** Default implementation for a `ConsolidateOrderCommand`. */
+async fn execute(
+    command: ConsolidateOrder,
+    transaction: ActiveTransaction,
+    store: impl Repository<Order>,
+) -> Result<usize, Error> {
+    debug!(
+        order_id = %command.order_id.tagged(),
+        transaction_id = %transaction.id(),
+        "consolidating order"
+    );
+
+    let mut order = store
+        .get(transaction.read(), command.order_id)?
+        .ok_or_else(|| error::not_found("order", command.order_id))?;
+
+    let merged = order.consolidate();
+
+    // Only write the order if something changed so its version isn't bumped needlessly
+    if merged > 0 {
+        store.save(transaction.get(), order)?;
+    }
+
+    info!(
+        order_id = %command.order_id.tagged(),
+        merged,
+        transaction_id = %transaction.id(),
+        "consolidated order"
+    );
+
+    Ok(merged)
+}
+
+impl Resolver {
+    /** Merge line items in an order that are for the same product. */
+    pub fn consolidate_order_command(&self) -> impl Command<ConsolidateOrder> {
+        self.command(|resolver, command: ConsolidateOrder| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+
+            let context = format!("consolidate_order order={}", command.order_id);
+
+            let span = info_span!(
+                "consolidate_order",
+                order_id = %command.order_id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store))
+                .await
+                .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::{
+                duplicate_product,
+                OrderBuilder,
+            },
+        },
+        products::{
+            model::test_data::ProductBuilder,
+            ProductId,
+        },
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn duplicate_line_items_are_merged() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        let mut order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                line_item.quantity(2)
+            })
+            .build();
+
+        duplicate_product(&mut order, LineItemId::new(), product_id, 3);
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        let merged = execute(
+            ConsolidateOrder { order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(1, merged);
+
+        let (_, line_items) = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(1, line_items.len());
+        assert_eq!(product_id, line_items[0].product_id);
+        assert_eq!(5, line_items[0].quantity);
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        let err = execute(
+            ConsolidateOrder { order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&order_id.to_string()));
+    }
+}
\ No newline at end of file
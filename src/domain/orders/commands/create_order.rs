@@ -1,8 +1,16 @@
 /*! Contains the `CreateOrderCommand` type. */
 
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
 use crate::domain::{
     customers::*,
     error,
+    events::*,
     infra::*,
     orders::*,
     Error,
@@ -13,40 +21,111 @@ use crate::domain::{
 pub struct CreateOrder {
     pub id: OrderId,
     pub customer_id: CustomerId,
+    /**
+    A token chosen by the client that makes creating the order idempotent.
+
+    If the customer already has an order created with the same token then that order's id is
+    returned instead of creating another one, so a client that sends the same request twice
+    only ends up with a single order.
+    */
+    #[serde(default)]
+    pub client_token: Option<String>,
+    /**
+    Whether the customer is a guest, like the owner of an anonymous cart.
+
+    Guests aren't stored, so the customer isn't checked to exist.
+    */
+    #[serde(default)]
+    pub guest: bool,
 }
 
 impl CommandArgs for CreateOrder {
-    type Output = Result<(), Error>;
+    /** The id of the created order, or of the order previously created with the same token. */
+    type Output = Result<OrderId, Error>;
 }
 
 async fn execute(
     command: CreateOrder,
     transaction: ActiveTransaction,
     store: impl OrderStore,
+    store_filter: impl OrderStoreFilter,
     customer_query: impl Query<GetCustomer>,
-) -> Result<(), Error> {
-    debug!("creating order `{}`", command.id);
+    order_numbers: Option<SequenceProvider<OrderNumber>>,
+    events: Events,
+) -> Result<OrderId, Error> {
+    debug!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "creating order"
+    );
+
+    if let Some(ref client_token) = command.client_token {
+        let filter = OrderFilter {
+            customer_id: Some(command.customer_id),
+            ..Default::default()
+        };
+
+        let existing = store_filter
+            .query(transaction.read(), &filter)?
+            .find(|order| order.client_token.as_ref() == Some(client_token));
+
+        if let Some(existing) = existing {
+            info!(
+                order_id = %existing.id.tagged(),
+                transaction_id = %transaction.id(),
+                "order was already created with the same client token"
+            );
+
+            return Ok(existing.id);
+        }
+    }
 
-    let order = {
-        if store.get_order(command.id)?.is_some() {
-            err!("order `{}` already exists", command.id)?
+    let mut order = {
+        if store
+            .get_order_header(transaction.read(), command.id)?
+            .is_some()
+        {
+            return Err(error::conflict(format!(
+                "order `{}` already exists",
+                command.id
+            )));
         } else {
-            let customer = customer_query
-                .execute(GetCustomer {
-                    id: command.customer_id,
-                })
-                .await?
-                .ok_or_else(|| error::bad_input("customer not found"))?;
+            let customer = if command.guest {
+                Customer::guest(command.customer_id)?
+            } else {
+                customer_query
+                    .execute(GetCustomer {
+                        id: command.customer_id,
+                    })
+                    .await?
+                    .ok_or_else(|| error::not_found("customer", command.customer_id))?
+            };
 
             Order::new(command.id, &customer)?
         }
     };
 
+    // Numbers are only allocated once the order is known to be valid, to avoid wasting them
+    if let Some(order_numbers) = order_numbers {
+        order.set_number(order_numbers.next()?)?;
+    }
+
+    if let Some(client_token) = command.client_token {
+        order.set_client_token(client_token);
+    }
+
     store.set_order(transaction.get(), order)?;
 
-    info!("created order `{}`", command.id);
+    let (id, customer_id) = (command.id, command.customer_id);
+    transaction.on_commit(move || events.publish(DomainEvent::OrderCreated { id, customer_id }));
+
+    info!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "created order"
+    );
 
-    Ok(())
+    Ok(id)
 }
 
 impl Resolver {
@@ -54,11 +133,38 @@ impl Resolver {
     pub fn create_order_command(&self) -> impl Command<CreateOrder> {
         self.command(|resolver, command: CreateOrder| async move {
             let store = resolver.order_store();
+            let store_filter = resolver.order_store_filter();
             let active_transaction = resolver.active_transaction();
 
             let customer_query = resolver.get_customer_query();
+            let order_numbers = resolver.order_numbers();
+            let events = resolver.events();
 
-            execute(command, active_transaction, store, customer_query).await
+            let context = format!("create_order order={}", command.id);
+
+            let span = info_span!(
+                "create_order",
+                order_id = %command.id.tagged(),
+                customer_id = %command.customer_id.tagged(),
+                guest = command.guest,
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(
+                    command,
+                    active_transaction,
+                    store,
+                    store_filter,
+                    customer_query,
+                    order_numbers,
+                    events,
+                ),
+            )
+            .await
+            .map_err(|err| err.context(context))
         })
     }
 }
@@ -69,12 +175,15 @@ mod tests {
 
     use crate::domain::{
         customers::model::test_data::CustomerBuilder,
+        infra::transaction::test_support::TestTransactionProvider,
         orders::model::store::in_memory_store,
+        ErrorKind,
     };
 
     #[tokio::test]
     async fn err_if_already_exists() {
-        let store = in_memory_store(Default::default());
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
 
         let customer_id = CustomerId::new();
 
@@ -83,24 +192,223 @@ mod tests {
         let create = CreateOrder {
             id: OrderId::new(),
             customer_id,
+            client_token: None,
+            guest: false,
         };
 
         execute(
             create.clone(),
-            ActiveTransaction::none(),
+            ActiveTransaction::auto_commit(),
+            &store,
             &store,
             &customer_query,
+            None,
+            Events::new(),
         )
         .await
         .unwrap();
 
-        assert!(execute(
-            create.clone(),
-            ActiveTransaction::none(),
-            &store,
-            &customer_query
-        )
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    create.clone(),
+                    transaction,
+                    &store,
+                    &store,
+                    &customer_query,
+                    None,
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
+
+    #[tokio::test]
+    async fn create_with_same_client_token_is_idempotent() {
+        let store = in_memory_store(Default::default());
+
+        let customer_id = CustomerId::new();
+        let other_customer_id = CustomerId::new();
+
+        let customer_query = |query: GetCustomer| async move {
+            Ok(Some(CustomerBuilder::new().id(query.id).build()))
+        };
+
+        let create = |id, customer_id| {
+            execute(
+                CreateOrder {
+                    id,
+                    customer_id,
+                    client_token: Some("checkout-1".into()),
+                    guest: false,
+                },
+                ActiveTransaction::auto_commit(),
+                &store,
+                &store,
+                &customer_query,
+                None,
+                Events::new(),
+            )
+        };
+
+        let first_id = OrderId::new();
+        let second_id = OrderId::new();
+
+        assert_eq!(first_id, create(first_id, customer_id).await.unwrap());
+        assert_eq!(first_id, create(second_id, customer_id).await.unwrap());
+
+        assert!(store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                second_id,
+                ReadConsistency::Strong
+            )
+            .unwrap()
+            .is_none());
+
+        // Tokens only need to be unique for each customer
+        let other_id = OrderId::new();
+        assert_eq!(other_id, create(other_id, other_customer_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn err_if_customer_not_found() {
+        let test = Resolver::for_tests();
+
+        let customer_id = CustomerId::new();
+
+        let err = test
+            .transaction(|resolver| async move {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id: OrderId::new(),
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        test.transactions().assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&customer_id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn guest_does_not_need_a_stored_customer() {
+        let test = Resolver::for_tests();
+
+        let order_id = OrderId::new();
+        let customer_id = CustomerId::new();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: true,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        let customer = test
+            .resolver()
+            .get_customer_query()
+            .execute(GetCustomer { id: customer_id })
+            .await
+            .unwrap();
+
+        assert!(customer.is_none());
+
+        let order = test
+            .resolver()
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(customer_id, order.to_data().0.customer_id);
+    }
+
+    async fn create_orders(app: &App, n: usize) -> Vec<Option<OrderNumber>> {
+        let customer_id = CustomerId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await
+        })
         .await
-        .is_err());
+        .unwrap();
+
+        let mut numbers = vec![];
+
+        for _ in 0..n {
+            let id = OrderId::new();
+
+            app.transaction(|resolver| async move {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id,
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+            let order = app
+                .transaction(|resolver| async move {
+                    resolver.get_order_query().execute(GetOrder { id }).await
+                })
+                .await
+                .unwrap()
+                .unwrap();
+
+            numbers.push(order.to_data().0.number);
+        }
+
+        numbers
+    }
+
+    #[tokio::test]
+    async fn orders_are_numbered_in_sequence() {
+        let app = App::new().with_order_numbers(InMemorySequenceStore::new());
+
+        let numbers = create_orders(&app, 3).await;
+
+        assert_eq!(
+            vec![
+                Some(OrderNumber::from(1)),
+                Some(OrderNumber::from(2)),
+                Some(OrderNumber::from(3)),
+            ],
+            numbers
+        );
+    }
+
+    #[tokio::test]
+    async fn orders_are_not_numbered_by_default() {
+        let numbers = create_orders(&App::new(), 1).await;
+
+        assert_eq!(vec![None], numbers);
     }
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+/*! Contains the `DeleteOrderCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `DeleteOrderCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct DeleteOrder {
+    pub id: OrderId,
+}
+
+impl CommandArgs for DeleteOrder {
+    type Output = Result<(), Error>;
+}
+
+async fn execute(
+    command: DeleteOrder,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "deleting order"
+    );
+
+    store
+        .get_order(transaction.read(), command.id, ReadConsistency::Strong)?
+        .ok_or_else(|| error::not_found("order", command.id))?
+        .check_can_delete()?;
+
+    if !store.delete_order(transaction.get(), command.id)? {
+        return Err(error::not_found("order", command.id));
+    }
+
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::OrderDeleted { id }));
+
+    info!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "deleted order"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /**
+    Delete an order along with its line items.
+
+    Orders that have been placed can't be deleted.
+    */
+    pub fn delete_order_command(&self) -> impl Command<DeleteOrder> {
+        self.command(|resolver, command: DeleteOrder| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
+
+            let context = format!("delete_order order={}", command.id);
+
+            let span = info_span!(
+                "delete_order",
+                order_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store, events))
+                .await
+                .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn delete_existing_order() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        execute(
+            DeleteOrder { id: order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        assert!(store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn err_if_placed() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        let mut order = OrderBuilder::new().id(order_id).build();
+        order.place(SystemClock).unwrap();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        let err = execute(
+            DeleteOrder { id: order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+
+        let order = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(OrderStatus::Placed, order.status());
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let test = Resolver::for_tests();
+
+        let order_id = OrderId::new();
+
+        let err = test
+            .transaction(|resolver| async move {
+                resolver
+                    .delete_order_command()
+                    .execute(DeleteOrder { id: order_id })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        test.transactions().assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&order_id.to_string()));
+    }
+}
\ No newline at end of file
@@ -1,9 +1,48 @@
 /*! Commands for modifying order state. */
 
 mod add_or_update_product;
+mod confirm_payment;
+mod consolidate_order;
 mod create_order;
+mod delete_order;
+mod move_line_item;
+mod place_order;
+mod set_discount_eligible;
+mod set_recipient;
 
 pub use self::{
     add_or_update_product::*,
+    confirm_payment::*,
+    consolidate_order::*,
     create_order::*,
-};
\ No newline at end of file
+    delete_order::*,
+    move_line_item::*,
+    place_order::*,
+    set_discount_eligible::*,
+    set_recipient::*,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::infra::{
+        test_support::assert_command,
+        Resolver,
+    };
+
+    #[test]
+    fn commands_are_resolved() {
+        let resolver = Resolver::for_tests().resolver();
+
+        assert_command::<AddOrUpdateProduct>(resolver.add_or_update_product_command());
+        assert_command::<ConfirmPayment>(resolver.confirm_payment_command());
+        assert_command::<ConsolidateOrder>(resolver.consolidate_order_command());
+        assert_command::<CreateOrder>(resolver.create_order_command());
+        assert_command::<DeleteOrder>(resolver.delete_order_command());
+        assert_command::<MoveLineItem>(resolver.move_line_item_command());
+        assert_command::<PlaceOrder>(resolver.place_order_command());
+        assert_command::<SetDiscountEligible>(resolver.set_discount_eligible_command());
+        assert_command::<SetRecipient>(resolver.set_recipient_command());
+    }
+}
\ No newline at end of file
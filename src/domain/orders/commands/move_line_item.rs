@@ -0,0 +1,373 @@
+/*! Contains the `MoveLineItemCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `MoveLineItemCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct MoveLineItem {
+    pub from_order: OrderId,
+    pub to_order: OrderId,
+    pub product_id: ProductId,
+    pub quantity: u32,
+}
+
+impl CommandArgs for MoveLineItem {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `MoveLineItemCommand`. */
+async fn execute(
+    command: MoveLineItem,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    id: impl IdProvider<LineItemData>,
+    product_query: impl Query<GetProduct>,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        product_id = %command.product_id.tagged(),
+        from_order_id = %command.from_order.tagged(),
+        to_order_id = %command.to_order.tagged(),
+        transaction_id = %transaction.id(),
+        "moving product between orders"
+    );
+
+    if command.from_order == command.to_order {
+        return Err(error::bad_input(
+            "can't move a product to the order it's already in",
+        ));
+    }
+
+    let mut from_order = store
+        .get_order(
+            transaction.read(),
+            command.from_order,
+            ReadConsistency::Strong,
+        )?
+        .ok_or_else(|| error::not_found("order", command.from_order))?;
+
+    let to_order = store
+        .get_order(
+            transaction.read(),
+            command.to_order,
+            ReadConsistency::Strong,
+        )?
+        .ok_or_else(|| error::not_found("order", command.to_order))?;
+
+    if from_order.to_data().0.customer_id != to_order.to_data().0.customer_id {
+        return Err(error::bad_input(
+            "products can only be moved between orders for the same customer",
+        ));
+    }
+
+    from_order.take_product(command.product_id, command.quantity)?;
+
+    let (product_id, from_id, to_id) = (command.product_id, command.from_order, command.to_order);
+
+    let taken = match from_order
+        .to_data()
+        .1
+        .iter()
+        .find(|line_item| line_item.product_id == product_id)
+    {
+        Some(line_item) => DomainEvent::OrderProductSet {
+            id: from_id,
+            product_id,
+            price: line_item.price,
+            quantity: line_item.quantity,
+        },
+        None => DomainEvent::OrderProductRemoved {
+            id: from_id,
+            product_id,
+        },
+    };
+
+    let moved = match to_order.into_line_item_for_product(product_id) {
+        IntoLineItem::InOrder(mut line_item) => {
+            let quantity = line_item.quantity() + command.quantity;
+            let price = line_item.to_data().1.price;
+
+            line_item.set_quantity(quantity)?;
+            store.set_line_item(transaction.get(), line_item)?;
+
+            DomainEvent::OrderProductSet {
+                id: to_id,
+                product_id,
+                price,
+                quantity,
+            }
+        }
+        IntoLineItem::NotInOrder(mut to_order) => {
+            let product = product_query
+                .execute(GetProduct {
+                    id: command.product_id,
+                    include_inactive: false,
+                })
+                .await?
+                .ok_or_else(|| error::not_found("product", command.product_id))?;
+
+            to_order.add_product(id, &product, command.quantity)?;
+            store.set_order(transaction.get(), to_order)?;
+
+            DomainEvent::OrderProductSet {
+                id: to_id,
+                product_id,
+                price: product.to_data().price,
+                quantity: command.quantity,
+            }
+        }
+    };
+
+    store.set_order(transaction.get(), from_order)?;
+
+    transaction.on_commit(move || {
+        events.publish(taken);
+        events.publish(moved);
+    });
+
+    info!(
+        product_id = %command.product_id.tagged(),
+        from_order_id = %command.from_order.tagged(),
+        to_order_id = %command.to_order.tagged(),
+        transaction_id = %transaction.id(),
+        "moved product between orders"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Move some quantity of a product from one order to another for the same customer. */
+    pub fn move_line_item_command(&self) -> impl Command<MoveLineItem> {
+        self.command(|resolver, command: MoveLineItem| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+
+            let id = resolver.line_item_id();
+
+            let get_product = resolver.get_product_query();
+            let events = resolver.events();
+
+            let context = format!("move_line_item order={}", command.from_order);
+
+            let span = info_span!(
+                "move_line_item",
+                product_id = %command.product_id.tagged(),
+                from_order_id = %command.from_order.tagged(),
+                to_order_id = %command.to_order.tagged(),
+                quantity = command.quantity,
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, id, get_product, events),
+            )
+            .await
+            .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::CustomerId,
+        infra::transaction::test_support::TestTransactionProvider,
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+        ErrorKind,
+    };
+
+    fn quantity_in(
+        store: impl OrderStore,
+        order_id: OrderId,
+        product_id: ProductId,
+    ) -> Option<u32> {
+        let (_, line_items) = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        line_items
+            .into_iter()
+            .find(|line_item| line_item.product_id == product_id)
+            .map(|line_item| line_item.quantity)
+    }
+
+    fn set_orders(
+        store: impl OrderStore,
+        customer_id: CustomerId,
+        product_id: ProductId,
+    ) -> (OrderId, OrderId) {
+        let from_order = OrderId::new();
+        let to_order = OrderId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(from_order)
+                    .customer_id(customer_id)
+                    .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                        line_item.quantity(3)
+                    })
+                    .build(),
+            )
+            .unwrap();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(to_order)
+                    .customer_id(customer_id)
+                    .build(),
+            )
+            .unwrap();
+
+        (from_order, to_order)
+    }
+
+    #[tokio::test]
+    async fn full_move_removes_from_source() {
+        let store = in_memory_store(Default::default());
+
+        let product_id = ProductId::new();
+        let (from_order, to_order) = set_orders(&store, CustomerId::new(), product_id);
+
+        execute(
+            MoveLineItem {
+                from_order,
+                to_order,
+                product_id,
+                quantity: 3,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            NextLineItemId::new(),
+            |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(None, quantity_in(&store, from_order, product_id));
+        assert_eq!(Some(3), quantity_in(&store, to_order, product_id));
+    }
+
+    #[tokio::test]
+    async fn partial_move_updates_both_orders() {
+        let store = in_memory_store(Default::default());
+
+        let product_id = ProductId::new();
+        let (from_order, to_order) = set_orders(&store, CustomerId::new(), product_id);
+
+        for _ in 0..2 {
+            execute(
+                MoveLineItem {
+                    from_order,
+                    to_order,
+                    product_id,
+                    quantity: 1,
+                },
+                ActiveTransaction::auto_commit(),
+                &store,
+                NextLineItemId::new(),
+                |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+                Events::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(Some(1), quantity_in(&store, from_order, product_id));
+        assert_eq!(Some(2), quantity_in(&store, to_order, product_id));
+    }
+
+    #[tokio::test]
+    async fn err_if_not_enough_quantity() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let product_id = ProductId::new();
+        let (from_order, to_order) = set_orders(&store, CustomerId::new(), product_id);
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    MoveLineItem {
+                        from_order,
+                        to_order,
+                        product_id,
+                        quantity: 4,
+                    },
+                    transaction,
+                    &store,
+                    NextLineItemId::new(),
+                    |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert_eq!(Some(3), quantity_in(&store, from_order, product_id));
+        assert_eq!(None, quantity_in(&store, to_order, product_id));
+    }
+
+    #[tokio::test]
+    async fn err_if_orders_are_for_different_customers() {
+        let store = in_memory_store(Default::default());
+
+        let product_id = ProductId::new();
+        let (from_order, _) = set_orders(&store, CustomerId::new(), product_id);
+        let (_, to_order) = set_orders(&store, CustomerId::new(), product_id);
+
+        let err = execute(
+            MoveLineItem {
+                from_order,
+                to_order,
+                product_id,
+                quantity: 1,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            NextLineItemId::new(),
+            |_| async { Ok(Some(ProductBuilder::new().id(product_id).build())) },
+            Events::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,215 @@
+/*! Contains the `PlaceOrderCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `PlaceOrderCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct PlaceOrder {
+    pub id: OrderId,
+}
+
+impl CommandArgs for PlaceOrder {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `PlaceOrderCommand`. */
+async fn execute(
+    command: PlaceOrder,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    clock: impl Clock,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "placing order"
+    );
+
+    let order = {
+        if let Some(mut order) =
+            store.get_order(transaction.read(), command.id, ReadConsistency::Strong)?
+        {
+            order.validate().map_err(|errors| {
+                error::bad_input(
+                    errors
+                        .iter()
+                        .map(|err| err.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+            })?;
+
+            order.place(clock)?;
+
+            order
+        } else {
+            return Err(error::not_found("order", command.id));
+        }
+    };
+
+    store.set_order(transaction.get(), order)?;
+
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::OrderPlaced { id }));
+
+    info!(
+        order_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "placed order"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Place an order, freezing the prices of its line items. */
+    pub fn place_order_command(&self) -> impl Command<PlaceOrder> {
+        self.command(|resolver, command: PlaceOrder| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+            let clock = resolver.clock();
+            let events = resolver.events();
+
+            let context = format!("place_order order={}", command.id);
+
+            let span = info_span!(
+                "place_order",
+                order_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, clock, events),
+            )
+            .await
+            .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::default_product,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn placed_order_can_not_be_repriced() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(default_product(), |line_item| line_item)
+                    .build(),
+            )
+            .unwrap();
+
+        execute(
+            PlaceOrder { id: order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            SystemClock,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut order = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap();
+
+        let err = order
+            .map_line_items(|line_item| line_item.price = Currency::usd(1))
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
+
+    #[tokio::test]
+    async fn err_if_invalid() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    PlaceOrder { id: order_id },
+                    transaction,
+                    &store,
+                    SystemClock,
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(err.to_string().contains("order has no line items"));
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let test = Resolver::for_tests();
+
+        let order_id = OrderId::new();
+
+        let err = test
+            .transaction(|resolver| async move {
+                resolver
+                    .place_order_command()
+                    .execute(PlaceOrder { id: order_id })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        test.transactions().assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+    }
+}
\ No newline at end of file
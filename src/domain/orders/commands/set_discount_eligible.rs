@@ -0,0 +1,204 @@
+/*! Contains the `SetDiscountEligibleCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `SetDiscountEligibleCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct SetDiscountEligible {
+    pub id: OrderId,
+    pub product_id: ProductId,
+    pub discount_eligible: bool,
+}
+
+impl CommandArgs for SetDiscountEligible {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `SetDiscountEligibleCommand`. */
+async fn execute(
+    command: SetDiscountEligible,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+) -> Result<(), Error> {
+    debug!(
+        order_id = %command.id.tagged(),
+        product_id = %command.product_id.tagged(),
+        discount_eligible = command.discount_eligible,
+        transaction_id = %transaction.id(),
+        "setting discount eligibility of product in order"
+    );
+
+    let order = store
+        .get_order(transaction.read(), command.id, ReadConsistency::Strong)?
+        .ok_or_else(|| error::not_found("order", command.id))?;
+
+    let mut line_item = match order.into_line_item_for_product(command.product_id) {
+        IntoLineItem::InOrder(line_item) => line_item,
+        IntoLineItem::NotInOrder(_) => {
+            return Err(error::not_found("product", command.product_id));
+        }
+    };
+
+    line_item.set_discount_eligible(command.discount_eligible);
+    store.set_line_item(transaction.get(), line_item)?;
+
+    info!(
+        order_id = %command.id.tagged(),
+        product_id = %command.product_id.tagged(),
+        discount_eligible = command.discount_eligible,
+        transaction_id = %transaction.id(),
+        "set discount eligibility of product in order"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Set whether discounts on an order apply to one of its products. */
+    pub fn set_discount_eligible_command(&self) -> impl Command<SetDiscountEligible> {
+        self.command(|resolver, command: SetDiscountEligible| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+
+            let context = format!("set_discount_eligible order={}", command.id);
+
+            let span = info_span!(
+                "set_discount_eligible",
+                order_id = %command.id.tagged(),
+                product_id = %command.product_id.tagged(),
+                discount_eligible = command.discount_eligible,
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store))
+                .await
+                .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn discount_applies_only_to_eligible_line_items() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+        let eligible_id = ProductId::new();
+        let ineligible_id = ProductId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(
+                        ProductBuilder::new()
+                            .id(eligible_id)
+                            .price(Currency::usd(1000))
+                            .build(),
+                        |line_item| line_item,
+                    )
+                    .add_product(
+                        ProductBuilder::new()
+                            .id(ineligible_id)
+                            .price(Currency::usd(1000))
+                            .build(),
+                        |line_item| line_item,
+                    )
+                    .build(),
+            )
+            .unwrap();
+
+        transactions
+            .transaction(|transaction| {
+                execute(
+                    SetDiscountEligible {
+                        id: order_id,
+                        product_id: ineligible_id,
+                        discount_eligible: false,
+                    },
+                    transaction,
+                    &store,
+                )
+            })
+            .await
+            .unwrap();
+
+        transactions.assert_committed();
+
+        let order = store
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(Currency::usd(1500), order.total(50).unwrap());
+    }
+
+    #[tokio::test]
+    async fn err_if_product_not_in_order() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    SetDiscountEligible {
+                        id: order_id,
+                        product_id,
+                        discount_eligible: false,
+                    },
+                    transaction,
+                    &store,
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&product_id.to_string()));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,216 @@
+/*! Contains the `SetRecipientCommand` type. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    customers::*,
+    error,
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `SetRecipientCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct SetRecipient {
+    pub id: OrderId,
+    pub recipient_id: CustomerId,
+}
+
+impl CommandArgs for SetRecipient {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `SetRecipientCommand`. */
+async fn execute(
+    command: SetRecipient,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    customer_query: impl Query<GetCustomer>,
+) -> Result<(), Error> {
+    debug!(
+        order_id = %command.id.tagged(),
+        recipient_id = %command.recipient_id.tagged(),
+        transaction_id = %transaction.id(),
+        "setting recipient of order"
+    );
+
+    let order = {
+        if let Some(mut order) =
+            store.get_order(transaction.read(), command.id, ReadConsistency::Strong)?
+        {
+            let recipient = customer_query
+                .execute(GetCustomer {
+                    id: command.recipient_id,
+                })
+                .await?
+                .ok_or_else(|| error::not_found("customer", command.recipient_id))?;
+
+            order.set_recipient(&recipient);
+
+            order
+        } else {
+            return Err(error::not_found("order", command.id));
+        }
+    };
+
+    store.set_order(transaction.get(), order)?;
+
+    info!(
+        order_id = %command.id.tagged(),
+        recipient_id = %command.recipient_id.tagged(),
+        transaction_id = %transaction.id(),
+        "set recipient of order"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Ship an order to a customer other than the one who purchased it. */
+    pub fn set_recipient_command(&self) -> impl Command<SetRecipient> {
+        self.command(|resolver, command: SetRecipient| async move {
+            let store = resolver.order_store();
+            let active_transaction = resolver.active_transaction();
+
+            let customer_query = resolver.get_customer_query();
+
+            let context = format!("set_recipient order={}", command.id);
+
+            let span = info_span!(
+                "set_recipient",
+                order_id = %command.id.tagged(),
+                recipient_id = %command.recipient_id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, customer_query),
+            )
+            .await
+            .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn set_recipient() {
+        let test = Resolver::for_tests();
+
+        let customer_id = CustomerId::new();
+        let recipient_id = CustomerId::new();
+        let order_id = OrderId::new();
+
+        test.transaction(|resolver| async move {
+            for id in [customer_id, recipient_id] {
+                resolver
+                    .create_customer_command()
+                    .execute(CreateCustomer { id })
+                    .await?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .set_recipient_command()
+                .execute(SetRecipient {
+                    id: order_id,
+                    recipient_id,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        test.transactions().assert_committed();
+
+        let order = test
+            .resolver()
+            .order_store()
+            .get_order(
+                ActiveTransaction::auto_commit().read(),
+                order_id,
+                ReadConsistency::Strong,
+            )
+            .unwrap()
+            .unwrap();
+
+        let (order, _) = order.to_data();
+
+        assert_eq!(Some(recipient_id), order.recipient);
+    }
+
+    #[tokio::test]
+    async fn err_if_recipient_not_found() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let order_id = OrderId::new();
+        let recipient_id = CustomerId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    SetRecipient {
+                        id: order_id,
+                        recipient_id,
+                    },
+                    transaction,
+                    &store,
+                    |_| async { Ok(None) },
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&recipient_id.to_string()));
+    }
+}
\ No newline at end of file
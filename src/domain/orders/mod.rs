@@ -2,16 +2,21 @@
 
 pub mod commands;
 pub mod model;
+pub mod projections;
 pub mod queries;
 pub(in crate::domain) mod resolver;
 
-pub(self) use self::model::store::{
+pub use self::model::store::{
+    OrderFilter,
     OrderStore,
     OrderStoreFilter,
+    OrderStoreWithFilter,
+    ReadConsistency,
 };
 
 pub use self::{
     commands::*,
     model::*,
+    projections::*,
     queries::*,
 };
\ No newline at end of file
@@ -9,12 +9,23 @@ This kind of suggests it shouldn't have an id of its own, and instead should be
 We'll probably need to come back here one day to work this out properly.
 */
 
-use std::convert::{
-    TryFrom,
-    TryInto,
+use std::{
+    collections::HashSet,
+    convert::{
+        TryFrom,
+        TryInto,
+    },
+    fmt,
+    sync::Arc,
+    time::SystemTime,
 };
 
+#[cfg(feature = "sled-store")]
+pub mod sled_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
 pub mod store;
+pub mod wal_store;
 
 #[cfg(test)]
 pub mod test_data;
@@ -46,22 +57,104 @@ impl TryFrom<u32> for Quantity {
 
     fn try_from(quantity: u32) -> Result<Self, Self::Error> {
         if quantity < 1 {
-            return Err(error::msg("quantity must be greater than 0"));
+            return Err(error::bad_input("quantity must be greater than 0"));
         }
 
         Ok(Quantity(quantity))
     }
 }
 
+/**
+A human-friendly number for an order.
+
+Order numbers increase as orders are created, but can have gaps. Orders are still identified by
+their id, and not every order has a number.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OrderNumber(u64);
+
+impl From<u64> for OrderNumber {
+    fn from(number: u64) -> Self {
+        OrderNumber(number)
+    }
+}
+
+impl fmt::Display for OrderNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl OrderNumber {
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Quantity {
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+}
+
+impl From<Quantity> for u32 {
+    fn from(quantity: Quantity) -> u32 {
+        quantity.0
+    }
+}
+
 /** Data for an order. */
 #[derive(Clone, Serialize, Deserialize)]
 pub struct OrderData {
     pub id: OrderId,
     pub version: OrderVersion,
     pub customer_id: CustomerId,
+    /** When the order or any of its line items were last stored. */
+    pub updated_at: Option<SystemTime>,
+    /** Whether the order has been placed, locking the prices of its line items. */
+    #[serde(default)]
+    pub placed_prices_frozen: bool,
+    /** Every change to the status of the order, oldest first. */
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>,
+    /** The customer the order ships to, if it's a gift for someone other than the purchaser. */
+    #[serde(default)]
+    pub recipient: Option<CustomerId>,
+    /** The order's number, if orders were being numbered when it was created. */
+    #[serde(default)]
+    pub number: Option<OrderNumber>,
+    /** The token the client created the order with, so creating it again doesn't duplicate it. */
+    #[serde(default)]
+    pub client_token: Option<String>,
+    /** The payment provider's reference for the payment that paid for the order. */
+    #[serde(default)]
+    pub payment_ref: Option<String>,
     _private: (),
 }
 
+/**
+The status of an order.
+
+Orders start out `Open`. Open orders can be placed, and placed orders are `Paid` once their
+payment is confirmed. Orders can be cancelled in any status except `Cancelled`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Open,
+    Placed,
+    Paid,
+    Cancelled,
+}
+
+/** A change to the status of an order. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub from: OrderStatus,
+    pub to: OrderStatus,
+    pub at: SystemTime,
+    pub reason: Option<String>,
+}
+
 /** Data for a single order line item. */
 #[derive(Clone, Serialize, Deserialize)]
 pub struct LineItemData {
@@ -70,9 +163,115 @@ pub struct LineItemData {
     pub product_id: ProductId,
     pub price: Currency,
     pub quantity: u32,
+    /** Whether discounts on the order apply to the line item. */
+    #[serde(default = "discount_eligible_by_default")]
+    pub discount_eligible: bool,
+    /** How much the product weighed when it was added to the order, if it was known. */
+    #[serde(default)]
+    pub weight_grams: Option<u32>,
     _private: (),
 }
 
+fn discount_eligible_by_default() -> bool {
+    true
+}
+
+#[cfg(feature = "storage-adapter")]
+impl OrderData {
+    /**
+    Restore the data for an order that was read from an external store.
+
+    The rest of the order starts out empty, like a new order, and can be restored through its fields.
+    This is only for storage adapters. Application code should create orders with `Order::new`.
+    */
+    pub fn restore(
+        id: OrderId,
+        version: OrderVersion,
+        customer_id: CustomerId,
+    ) -> Result<Self, Error> {
+        Ok(OrderData {
+            id,
+            version,
+            customer_id,
+            updated_at: None,
+            placed_prices_frozen: false,
+            status_history: vec![],
+            recipient: None,
+            number: None,
+            client_token: None,
+            payment_ref: None,
+            _private: (),
+        })
+    }
+}
+
+#[cfg(feature = "storage-adapter")]
+impl LineItemData {
+    /**
+    Restore the data for a line item that was read from an external store.
+
+    The price and quantity are checked the same way they are when a product is added to an order.
+    This is only for storage adapters. Application code should add products with `Order::add_product`.
+    */
+    pub fn restore(
+        id: LineItemId,
+        version: LineItemVersion,
+        product_id: ProductId,
+        price: Currency,
+        quantity: impl TryInto<Quantity, Error = Error>,
+    ) -> Result<Self, Error> {
+        Price::try_from(price)?;
+
+        Ok(LineItemData {
+            id,
+            version,
+            product_id,
+            price,
+            quantity: quantity.try_into()?.0,
+            discount_eligible: discount_eligible_by_default(),
+            weight_grams: None,
+            _private: (),
+        })
+    }
+}
+
+/**
+A rule that an order breaks.
+
+Violations are collected by `Order::validate` so they can all be shown at once.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    /** The order has no line items. */
+    Empty,
+    /** A product appears in more than one line item. */
+    DuplicateProduct(ProductId),
+    /** A line item has a quantity that isn't greater than zero. */
+    InvalidQuantity {
+        line_item_id: LineItemId,
+        quantity: u32,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::Empty => write!(f, "order has no line items"),
+            OrderError::DuplicateProduct(product_id) => {
+                write!(f, "product `{}` is in more than one line item", product_id)
+            }
+            OrderError::InvalidQuantity {
+                line_item_id,
+                quantity,
+            } => write!(
+                f,
+                "line item `{}` has a quantity of {}, which isn't greater than 0",
+                line_item_id, quantity
+            ),
+        }
+    }
+}
+
 /**
 An order and its line items.
 
@@ -80,7 +279,13 @@ Products can be added to an order as a line item.
 */
 pub struct Order {
     order: OrderData,
-    line_items: Vec<LineItemData>,
+    /**
+    The line items in the order.
+
+    Line items are shared with the store the order was read from, so reading an order doesn't
+    copy them. A line item is only copied when it's changed.
+    */
+    line_items: Vec<Arc<LineItemData>>,
 }
 
 /**
@@ -105,8 +310,22 @@ pub enum IntoLineItem {
 }
 
 impl OrderLineItem {
-    pub(self) fn from_data(order: OrderData, line_item: LineItemData) -> Self {
-        OrderLineItem { order, line_item }
+    /**
+    Create a line item from the data for it and its order.
+
+    The line item must be one of the order's, given by `line_item_ids`. If it isn't then changing
+    it wouldn't change the order, so it's rejected.
+    */
+    pub(self) fn from_data<'a>(
+        order: OrderData,
+        line_item_ids: impl IntoIterator<Item = &'a LineItemId>,
+        line_item: LineItemData,
+    ) -> Result<Self, Error> {
+        if !line_item_ids.into_iter().any(|id| *id == line_item.id) {
+            return Err(error::not_found("line item", line_item.id));
+        }
+
+        Ok(OrderLineItem { order, line_item })
     }
 
     pub fn into_data(self) -> (OrderId, LineItemData) {
@@ -117,6 +336,18 @@ impl OrderLineItem {
         (self.order.id, &self.line_item)
     }
 
+    pub fn id(&self) -> LineItemId {
+        self.line_item.id
+    }
+
+    pub fn product_id(&self) -> ProductId {
+        self.line_item.product_id
+    }
+
+    pub fn quantity(&self) -> u32 {
+        self.line_item.quantity
+    }
+
     pub fn set_quantity<TQuantity>(&mut self, quantity: TQuantity) -> Result<(), Error>
     where
         TQuantity: TryInto<Quantity, Error = Error>,
@@ -125,6 +356,15 @@ impl OrderLineItem {
 
         Ok(())
     }
+
+    pub fn discount_eligible(&self) -> bool {
+        self.line_item.discount_eligible
+    }
+
+    /** Set whether discounts on the order apply to the line item. */
+    pub fn set_discount_eligible(&mut self, eligible: bool) {
+        self.line_item.discount_eligible = eligible;
+    }
 }
 
 impl Order {
@@ -132,19 +372,70 @@ impl Order {
     where
         TItems: IntoIterator<Item = LineItemData>,
     {
-        let line_items = line_items.into_iter().collect();
+        let line_items = line_items.into_iter().map(Arc::new).collect();
 
+        Order::from_shared_data(order, line_items)
+    }
+
+    pub(self) fn from_shared_data(order: OrderData, line_items: Vec<Arc<LineItemData>>) -> Self {
         Order { order, line_items }
     }
 
+    /** Take the data for the order. Line items that are still shared with a store are copied. */
     pub fn into_data(self) -> (OrderData, Vec<LineItemData>) {
+        let (order, line_items) = self.into_shared_data();
+
+        (order, line_items.into_iter().map(unshare).collect())
+    }
+
+    pub(self) fn into_shared_data(self) -> (OrderData, Vec<Arc<LineItemData>>) {
         (self.order, self.line_items)
     }
 
-    pub fn to_data(&self) -> (&OrderData, &[LineItemData]) {
+    pub fn to_data(&self) -> (&OrderData, &[Arc<LineItemData>]) {
         (&self.order, &self.line_items)
     }
 
+    /**
+    Restore an order from its data and the data for its line items.
+
+    Line items are checked again, because their fields may have been changed since they were restored.
+    A product can only appear in an order once.
+    */
+    #[cfg(feature = "storage-adapter")]
+    pub fn restore<TItems>(order: OrderData, line_items: TItems) -> Result<Self, Error>
+    where
+        TItems: IntoIterator<Item = LineItemData>,
+    {
+        Order::checked(order, line_items)
+    }
+
+    /** Build an order from data that came from outside the domain, checking it like `restore`. */
+    pub(in crate::domain) fn checked<TItems>(
+        order: OrderData,
+        line_items: TItems,
+    ) -> Result<Self, Error>
+    where
+        TItems: IntoIterator<Item = LineItemData>,
+    {
+        let order = Order::from_data(order, line_items);
+
+        let mut product_ids = HashSet::new();
+        for line_item in &order.line_items {
+            Price::try_from(line_item.price)?;
+            Quantity::try_from(line_item.quantity)?;
+
+            if !product_ids.insert(line_item.product_id) {
+                return Err(error::bad_input(format!(
+                    "product `{}` is in order `{}` more than once",
+                    line_item.product_id, order.order.id
+                )));
+            }
+        }
+
+        Ok(order)
+    }
+
     pub fn into_line_item_for_product(self, product_id: ProductId) -> IntoLineItem {
         if !self.contains_product(product_id) {
             IntoLineItem::NotInOrder(self)
@@ -153,12 +444,17 @@ impl Order {
                 order, line_items, ..
             } = self;
 
+            let line_item_ids: Vec<_> = line_items.iter().map(|item| item.id).collect();
+
             let item = line_items
                 .into_iter()
                 .find(|item| item.product_id == product_id)
                 .unwrap();
 
-            IntoLineItem::InOrder(OrderLineItem::from_data(order, item))
+            // The line item was taken from the order, so it's always part of it
+            IntoLineItem::InOrder(
+                OrderLineItem::from_data(order, &line_item_ids, unshare(item)).unwrap(),
+            )
         }
     }
 
@@ -172,18 +468,82 @@ impl Order {
             id,
             version: OrderVersion::default(),
             customer_id,
+            updated_at: None,
+            placed_prices_frozen: false,
+            status_history: vec![],
+            recipient: None,
+            number: None,
+            client_token: None,
+            payment_ref: None,
             _private: (),
         };
 
         Ok(Order::from_data(order_data, vec![]))
     }
 
+    /**
+    Get the total price of the order, with a percentage discount taken off eligible line items.
+
+    Line items that aren't eligible for discounts are always charged in full. Discounted prices
+    are rounded to the nearest smallest unit before they're multiplied by the quantity.
+    The total is capped at the largest value that can be represented.
+    */
+    pub fn total(&self, discount_percent: u32) -> Result<Currency, Error> {
+        if discount_percent > 100 {
+            return Err(error::bad_input(format!(
+                "a {}% discount would make prices negative",
+                discount_percent
+            )));
+        }
+
+        let adjust_by = -(discount_percent as i32);
+
+        let total = self
+            .line_items
+            .iter()
+            .fold(Currency::usd(0), |total, line_item| {
+                // A discount of at most 100% can only lower a price, so it's always in range
+                let price = if line_item.discount_eligible {
+                    line_item.price.adjust_by_percent(adjust_by).unwrap()
+                } else {
+                    line_item.price
+                };
+
+                total.saturating_add(price.saturating_mul(line_item.quantity))
+            });
+
+        Ok(total)
+    }
+
+    /**
+    Get the total weight of the order in grams, for estimating shipping.
+
+    Line items for products whose weight isn't known don't add anything to the total.
+    The total saturates at `u32::MAX` instead of overflowing, which is far heavier than
+    anything that could be shipped.
+    */
+    pub fn total_weight(&self) -> u32 {
+        self.line_items.iter().fold(0u32, |total, line_item| {
+            let weight = line_item
+                .weight_grams
+                .unwrap_or(0)
+                .saturating_mul(line_item.quantity);
+
+            total.saturating_add(weight)
+        })
+    }
+
     pub fn contains_product(&self, product_id: ProductId) -> bool {
         self.line_items
             .iter()
             .any(|item| item.product_id == product_id)
     }
 
+    /** Get the ids of the order's line items, in the order they were added. */
+    pub fn line_item_ids(&self) -> Vec<LineItemId> {
+        self.line_items.iter().map(|item| item.id).collect()
+    }
+
     pub fn add_product(
         &mut self,
         id: impl IdProvider<LineItemData>,
@@ -193,11 +553,12 @@ impl Order {
         let &ProductData {
             id: product_id,
             price,
+            weight_grams,
             ..
         } = product.to_data();
 
         if self.contains_product(product_id) {
-            return Err(error::msg("product is already in order"));
+            return Err(error::bad_input("product is already in order"));
         }
 
         let id = id.get()?;
@@ -207,20 +568,371 @@ impl Order {
             product_id,
             price,
             quantity: quantity.try_into()?.0,
+            discount_eligible: discount_eligible_by_default(),
+            weight_grams,
             _private: (),
         };
 
-        self.line_items.push(line_item);
+        self.line_items.push(Arc::new(line_item));
+
+        Ok(())
+    }
+
+    /**
+    Add a product to the order, returning the order so calls can be chained.
+
+    This is the same as `add_product`, but takes ownership of the order.
+
+    ```
+    # use shop::domain::{customers::*, infra::*, orders::*, products::*};
+    let customer = Customer::new(NextCustomerId::new())?;
+
+    let apple = Product::new(NextProductId::new(), "Apple", Currency::usd(100))?;
+    let pear = Product::new(NextProductId::new(), "Pear", Currency::usd(150))?;
+
+    let order = Order::new(NextOrderId::new(), &customer)?
+        .with_product(NextLineItemId::new(), &apple, 2)?
+        .with_product(NextLineItemId::new(), &pear, 1)?;
+
+    let (_, line_items) = order.to_data();
+    assert_eq!(2, line_items.len());
+    # Ok::<(), shop::domain::Error>(())
+    ```
+    */
+    pub fn with_product(
+        mut self,
+        id: impl IdProvider<LineItemData>,
+        product: &Product,
+        quantity: impl TryInto<Quantity, Error = Error>,
+    ) -> Result<Self, Error> {
+        self.add_product(id, product, quantity)?;
+
+        Ok(self)
+    }
+
+    /**
+    Take some quantity of a product out of the order.
+
+    The order must have at least that quantity of the product.
+    If all of it is taken then the product's line item is removed from the order.
+    */
+    pub fn take_product(
+        &mut self,
+        product_id: ProductId,
+        quantity: impl TryInto<Quantity, Error = Error>,
+    ) -> Result<(), Error> {
+        let quantity = quantity.try_into()?.0;
+
+        let index = self
+            .line_items
+            .iter()
+            .position(|item| item.product_id == product_id)
+            .ok_or_else(|| error::bad_input("product is not in order"))?;
+
+        let available = self.line_items[index].quantity;
+
+        if available < quantity {
+            return Err(error::bad_input(format!(
+                "can't take {} of product `{}` from an order that only has {}",
+                quantity, product_id, available
+            )));
+        }
+
+        if available == quantity {
+            self.line_items.remove(index);
+        } else {
+            Arc::make_mut(&mut self.line_items[index]).quantity -= quantity;
+        }
+
+        Ok(())
+    }
+
+    /**
+    Merge line items for the same product into a single line item.
+
+    Orders don't normally have more than one line item for a product, but bad data might.
+    The earliest line item for a product is kept, and the quantities of any later ones are added
+    to it, capped at the largest quantity a line item can hold.
+    Returns the number of line items that were merged away.
+    */
+    pub fn consolidate(&mut self) -> usize {
+        let mut line_items: Vec<Arc<LineItemData>> = Vec::with_capacity(self.line_items.len());
+        let mut merged = 0;
+
+        for line_item in self.line_items.drain(..) {
+            match line_items
+                .iter_mut()
+                .find(|existing| existing.product_id == line_item.product_id)
+            {
+                Some(existing) => {
+                    let existing = Arc::make_mut(existing);
+
+                    existing.quantity = existing.quantity.saturating_add(line_item.quantity);
+                    merged += 1;
+                }
+                None => line_items.push(line_item),
+            }
+        }
+
+        self.line_items = line_items;
+
+        merged
+    }
+
+    /**
+    Ship the order to a customer other than the one who purchased it.
+    */
+    pub fn set_recipient(&mut self, recipient: &Customer) {
+        let &CustomerData { id, .. } = recipient.to_data();
+
+        self.order.recipient = Some(id);
+    }
+
+    /**
+    Give the order a number.
+
+    Orders are numbered when they're created, so an order that already has a number can't be
+    given another one.
+    */
+    pub fn set_number(&mut self, number: OrderNumber) -> Result<(), Error> {
+        if let Some(existing) = self.order.number {
+            return Err(error::conflict(format!(
+                "order `{}` already has number `{}`",
+                self.order.id, existing
+            )));
+        }
+
+        self.order.number = Some(number);
+
+        Ok(())
+    }
+
+    /** Remember the token the client created the order with. */
+    pub fn set_client_token(&mut self, token: impl Into<String>) {
+        self.order.client_token = Some(token.into());
+    }
+
+    /**
+    The current status of the order.
+    */
+    pub fn status(&self) -> OrderStatus {
+        self.order
+            .status_history
+            .last()
+            .map(|change| change.to)
+            .unwrap_or(OrderStatus::Open)
+    }
+
+    /**
+    Place the order, freezing the prices of its line items.
+
+    Later changes to the catalog, or attempts to reprice the order, won't change what the
+    customer pays for line items already in the order.
+    */
+    pub fn place(&mut self, clock: impl Clock) -> Result<(), Error> {
+        self.check_can_place()?;
+
+        self.order.placed_prices_frozen = true;
+        self.change_status(OrderStatus::Placed, clock, None);
+
+        Ok(())
+    }
+
+    /**
+    Check whether the order can be placed without placing it.
+    */
+    pub fn check_can_place(&self) -> Result<(), Error> {
+        match self.status() {
+            OrderStatus::Open => Ok(()),
+            OrderStatus::Placed | OrderStatus::Paid => Err(error::conflict(format!(
+                "order `{}` has already been placed",
+                self.order.id
+            ))),
+            OrderStatus::Cancelled => Err(error::conflict(format!(
+                "order `{}` has been cancelled",
+                self.order.id
+            ))),
+        }
+    }
+
+    /**
+    Check whether the order can be deleted.
+
+    Orders that have been placed are kept, whether or not they've been paid for.
+    */
+    pub fn check_can_delete(&self) -> Result<(), Error> {
+        match self.status() {
+            OrderStatus::Open | OrderStatus::Cancelled => Ok(()),
+            OrderStatus::Placed | OrderStatus::Paid => Err(error::conflict(format!(
+                "order `{}` has been placed so it can't be deleted",
+                self.order.id
+            ))),
+        }
+    }
+
+    /**
+    Check the order's line items, returning every rule that's broken rather than just the first.
+
+    A product is only reported as a duplicate once, however many line items it's in.
+    */
+    pub fn validate(&self) -> Result<(), Vec<OrderError>> {
+        let mut errors = vec![];
+
+        if self.line_items.is_empty() {
+            errors.push(OrderError::Empty);
+        }
+
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+
+        for line_item in &self.line_items {
+            if !seen.insert(line_item.product_id) && duplicates.insert(line_item.product_id) {
+                errors.push(OrderError::DuplicateProduct(line_item.product_id));
+            }
+
+            if line_item.quantity < 1 {
+                errors.push(OrderError::InvalidQuantity {
+                    line_item_id: line_item.id,
+                    quantity: line_item.quantity,
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /**
+    Record that the order has been paid for, with the payment provider's reference for it.
+
+    Only placed orders can be paid for, and only once.
+    */
+    pub fn confirm_payment(
+        &mut self,
+        clock: impl Clock,
+        payment_ref: impl Into<String>,
+    ) -> Result<(), Error> {
+        let payment_ref = payment_ref.into();
+
+        if payment_ref.trim().is_empty() {
+            return Err(error::bad_input("payment reference must not be empty"));
+        }
+
+        match self.status() {
+            OrderStatus::Placed => (),
+            OrderStatus::Open => {
+                return Err(error::conflict(format!(
+                    "order `{}` hasn't been placed",
+                    self.order.id
+                )))
+            }
+            OrderStatus::Paid => {
+                return Err(error::conflict(format!(
+                    "order `{}` has already been paid",
+                    self.order.id
+                )))
+            }
+            OrderStatus::Cancelled => {
+                return Err(error::conflict(format!(
+                    "order `{}` has been cancelled",
+                    self.order.id
+                )))
+            }
+        }
+
+        self.order.payment_ref = Some(payment_ref);
+        self.change_status(OrderStatus::Paid, clock, None);
+
+        Ok(())
+    }
+
+    /**
+    Cancel the order, optionally recording why.
+
+    Both open and placed orders can be cancelled.
+    */
+    pub fn cancel(&mut self, clock: impl Clock, reason: Option<String>) -> Result<(), Error> {
+        if self.status() == OrderStatus::Cancelled {
+            return Err(error::conflict(format!(
+                "order `{}` has already been cancelled",
+                self.order.id
+            )));
+        }
+
+        self.change_status(OrderStatus::Cancelled, clock, reason);
+
+        Ok(())
+    }
+
+    fn change_status(&mut self, to: OrderStatus, clock: impl Clock, reason: Option<String>) {
+        let change = StatusChange {
+            from: self.status(),
+            to,
+            at: clock.now(),
+            reason,
+        };
+
+        self.order.status_history.push(change);
+    }
+
+    /**
+    Change every line item in the order.
+
+    Either all of the changes are applied or none of them are.
+    If any line item ends up invalid, like having a quantity of zero, then the order is left unchanged.
+    The id and version of a line item can't be changed, and neither can its price once the order
+    has been placed.
+    */
+    pub fn map_line_items(&mut self, mut f: impl FnMut(&mut LineItemData)) -> Result<(), Error> {
+        let mut line_items = self.line_items.clone();
+
+        for line_item in &mut line_items {
+            let line_item = Arc::make_mut(line_item);
+            let (id, version, price) = (line_item.id, line_item.version, line_item.price);
+
+            f(line_item);
+
+            if line_item.id != id || line_item.version != version {
+                return Err(error::msg("line item ids and versions can't be changed"));
+            }
+
+            if self.order.placed_prices_frozen && line_item.price != price {
+                return Err(error::conflict(format!(
+                    "prices on order `{}` are frozen because it has been placed",
+                    self.order.id
+                )));
+            }
+
+            Quantity::try_from(line_item.quantity)?;
+        }
+
+        self.line_items = line_items;
 
         Ok(())
     }
 }
 
+/** Take a line item that may be shared, copying it if it is. */
+fn unshare(line_item: Arc<LineItemData>) -> LineItemData {
+    Arc::try_unwrap(line_item).unwrap_or_else(|line_item| (*line_item).clone())
+}
+
 impl Entity for Order {
     type Id = OrderId;
     type Version = OrderVersion;
     type Data = OrderData;
     type Error = Error;
+
+    fn id(&self) -> OrderId {
+        self.order.id
+    }
+
+    fn version(&self) -> OrderVersion {
+        self.order.version
+    }
 }
 
 impl Entity for OrderLineItem {
@@ -228,29 +940,52 @@ impl Entity for OrderLineItem {
     type Version = LineItemVersion;
     type Data = LineItemData;
     type Error = Error;
+
+    fn id(&self) -> LineItemId {
+        self.line_item.id
+    }
+
+    fn version(&self) -> LineItemVersion {
+        self.line_item.version
+    }
+}
+
+impl IdTag for OrderData {
+    const TAG: &'static str = "order";
+}
+
+impl IdTag for LineItemData {
+    const TAG: &'static str = "line_item";
 }
 
 impl Resolver {
     pub fn order_id(&self) -> impl IdProvider<OrderData> {
-        NextId::<OrderData>::new()
+        self.resolve(&self.orders_resolver.order_id)
     }
 
     pub fn line_item_id(&self) -> impl IdProvider<LineItemData> {
-        NextId::<LineItemData>::new()
+        self.resolve(&self.orders_resolver.line_item_id)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     use crate::domain::{
         customers::model::test_data::default_customer,
-        orders::model::test_data::default_order,
+        orders::model::test_data::{
+            default_order,
+            duplicate_product,
+            OrderBuilder,
+        },
         products::model::test_data::{
             default_product,
             ProductBuilder,
         },
+        ErrorKind,
     };
 
     #[test]
@@ -270,6 +1005,25 @@ mod tests {
         assert!(order.contains_product(product_id));
     }
 
+    #[test]
+    fn add_items_with_chosen_ids() {
+        let line_item_ids = vec![LineItemId::new(), LineItemId::new(), LineItemId::new()];
+        let id = IterIdProvider::new(line_item_ids.clone());
+
+        let mut order = default_order();
+
+        for _ in 0..3 {
+            order.add_product(&id, &default_product(), 1).unwrap();
+        }
+
+        let ids: Vec<_> = order.line_items.iter().map(|l| l.id).collect();
+
+        assert_eq!(line_item_ids, ids);
+
+        // There are no ids left for another line item
+        assert!(order.add_product(&id, &default_product(), 1).is_err());
+    }
+
     #[test]
     fn quantity_must_be_greater_than_0() {
         let mut order = default_order();
@@ -279,12 +1033,352 @@ mod tests {
 
         order.add_product(LineItemId::new(), &product, 1).unwrap();
 
-        let (order_data, mut line_item_data) = order.into_data();
-        let mut order = OrderLineItem::from_data(order_data, line_item_data.pop().unwrap());
+        let mut order = match order.into_line_item_for_product(product.to_data().id) {
+            IntoLineItem::InOrder(line_item) => line_item,
+            IntoLineItem::NotInOrder(_) => panic!("product should be in the order"),
+        };
 
         assert!(order.set_quantity(0).is_err());
     }
 
+    #[test]
+    fn line_item_must_be_in_its_order() {
+        let mut order = default_order();
+        let line_item_id = LineItemId::new();
+
+        order
+            .add_product(line_item_id, &default_product(), 1)
+            .unwrap();
+
+        let (order_data, mut line_items) = order.into_data();
+        let line_item = line_items.pop().unwrap();
+
+        let (other_order_data, _) = default_order().into_data();
+
+        let err = OrderLineItem::from_data(other_order_data, &[], line_item.clone())
+            .err()
+            .unwrap();
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        assert!(OrderLineItem::from_data(order_data, &[line_item_id], line_item).is_ok());
+    }
+
+    #[test]
+    fn quantity_round_trip() {
+        let quantity = Quantity::try_from(5u32).unwrap();
+
+        assert_eq!(5, quantity.get());
+        assert_eq!(5u32, u32::from(quantity));
+    }
+
+    #[test]
+    fn map_line_items() {
+        let mut order = default_order();
+
+        order
+            .add_product(LineItemId::new(), &default_product(), 2)
+            .unwrap();
+        order
+            .add_product(LineItemId::new(), &default_product(), 3)
+            .unwrap();
+
+        order
+            .map_line_items(|line_item| line_item.quantity *= 2)
+            .unwrap();
+
+        let quantities: Vec<_> = order.line_items.iter().map(|l| l.quantity).collect();
+
+        assert_eq!(vec![4, 6], quantities);
+    }
+
+    #[test]
+    fn map_line_items_is_validated() {
+        let mut order = default_order();
+
+        order
+            .add_product(LineItemId::new(), &default_product(), 2)
+            .unwrap();
+        order
+            .add_product(LineItemId::new(), &default_product(), 3)
+            .unwrap();
+
+        // Only one line item becomes invalid, but none of them are changed
+        assert!(order
+            .map_line_items(|line_item| line_item.quantity -= 2)
+            .is_err());
+
+        let quantities: Vec<_> = order.line_items.iter().map(|l| l.quantity).collect();
+
+        assert_eq!(vec![2, 3], quantities);
+    }
+
+    #[test]
+    fn open_order_can_be_repriced() {
+        let mut order = default_order();
+
+        order
+            .add_product(LineItemId::new(), &default_product(), 1)
+            .unwrap();
+
+        order
+            .map_line_items(|line_item| line_item.price = Currency::usd(50))
+            .unwrap();
+
+        assert_eq!(Currency::usd(50), order.line_items[0].price);
+    }
+
+    #[test]
+    fn placed_order_can_not_be_repriced() {
+        let mut order = default_order();
+
+        order
+            .add_product(LineItemId::new(), &default_product(), 1)
+            .unwrap();
+
+        let price = order.line_items[0].price;
+
+        order.place(SystemClock).unwrap();
+
+        let err = order
+            .map_line_items(|line_item| line_item.price = Currency::usd(50))
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+        assert_eq!(price, order.line_items[0].price);
+
+        // Other changes are still allowed
+        order
+            .map_line_items(|line_item| line_item.quantity = 2)
+            .unwrap();
+
+        assert_eq!(2, order.line_items[0].quantity);
+    }
+
+    #[test]
+    fn order_can_only_be_placed_once() {
+        let mut order = default_order();
+
+        order.place(SystemClock).unwrap();
+
+        assert_eq!(
+            ErrorKind::Conflict,
+            order.place(SystemClock).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn only_placed_orders_can_be_paid() {
+        let mut order = default_order();
+
+        // Open orders haven't been placed yet
+        let err = order.confirm_payment(SystemClock, "pay_123").unwrap_err();
+        assert_eq!(ErrorKind::Conflict, err.kind());
+
+        order.place(SystemClock).unwrap();
+
+        let err = order.confirm_payment(SystemClock, " ").unwrap_err();
+        assert_eq!(ErrorKind::BadInput, err.kind());
+
+        order.confirm_payment(SystemClock, "pay_123").unwrap();
+
+        assert_eq!(OrderStatus::Paid, order.status());
+        assert_eq!(Some("pay_123"), order.order.payment_ref.as_deref());
+
+        // Paying twice keeps the first reference
+        let err = order.confirm_payment(SystemClock, "pay_456").unwrap_err();
+        assert_eq!(ErrorKind::Conflict, err.kind());
+        assert_eq!(Some("pay_123"), order.order.payment_ref.as_deref());
+
+        // Paid orders can't be placed again
+        assert_eq!(
+            ErrorKind::Conflict,
+            order.place(SystemClock).unwrap_err().kind()
+        );
+
+        let mut order = default_order();
+        order.cancel(SystemClock, None).unwrap();
+
+        let err = order.confirm_payment(SystemClock, "pay_123").unwrap_err();
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
+
+    #[test]
+    fn set_recipient() {
+        let mut order = default_order();
+        let recipient = default_customer();
+
+        assert!(order.order.recipient.is_none());
+
+        order.set_recipient(&recipient);
+
+        assert_eq!(Some(recipient.to_data().id), order.order.recipient);
+    }
+
+    #[test]
+    fn status_history_is_recorded() {
+        let mut order = default_order();
+
+        let placed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let cancelled_at = placed_at + Duration::from_secs(5);
+
+        let clock = FixedClock::new(placed_at);
+
+        assert_eq!(OrderStatus::Open, order.status());
+
+        order.place(&clock).unwrap();
+
+        clock.set(cancelled_at);
+
+        order
+            .cancel(&clock, Some("changed my mind".to_owned()))
+            .unwrap();
+
+        assert_eq!(OrderStatus::Cancelled, order.status());
+        assert_eq!(
+            vec![
+                StatusChange {
+                    from: OrderStatus::Open,
+                    to: OrderStatus::Placed,
+                    at: placed_at,
+                    reason: None,
+                },
+                StatusChange {
+                    from: OrderStatus::Placed,
+                    to: OrderStatus::Cancelled,
+                    at: cancelled_at,
+                    reason: Some("changed my mind".to_owned()),
+                },
+            ],
+            order.order.status_history
+        );
+    }
+
+    #[test]
+    fn cancelled_order_can_not_be_placed_or_cancelled() {
+        let mut order = default_order();
+
+        order.cancel(SystemClock, None).unwrap();
+
+        assert_eq!(
+            ErrorKind::Conflict,
+            order.place(SystemClock).unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::Conflict,
+            order.cancel(SystemClock, None).unwrap_err().kind()
+        );
+        assert_eq!(1, order.order.status_history.len());
+    }
+
+    #[test]
+    fn take_product() {
+        let mut order = default_order();
+        let product_id = ProductId::new();
+
+        order
+            .add_product(
+                LineItemId::new(),
+                &ProductBuilder::new().id(product_id).build(),
+                3,
+            )
+            .unwrap();
+
+        order.take_product(product_id, 2).unwrap();
+
+        assert_eq!(1, order.line_items[0].quantity);
+
+        // Taking more than the order has leaves it unchanged
+        assert_eq!(
+            ErrorKind::BadInput,
+            order.take_product(product_id, 2).unwrap_err().kind()
+        );
+        assert_eq!(1, order.line_items[0].quantity);
+
+        // Taking everything removes the line item
+        order.take_product(product_id, 1).unwrap();
+
+        assert!(!order.contains_product(product_id));
+    }
+
+    #[test]
+    fn validate_returns_every_violation() {
+        let mut order = default_order();
+
+        let product = default_product();
+        let product_id = product.to_data().id;
+
+        order.add_product(LineItemId::new(), &product, 1).unwrap();
+        duplicate_product(&mut order, LineItemId::new(), product_id, 2);
+
+        let line_item_id = LineItemId::new();
+        order
+            .add_product(line_item_id, &ProductBuilder::new().build(), 1)
+            .unwrap();
+        Arc::make_mut(order.line_items.last_mut().unwrap()).quantity = 0;
+
+        assert_eq!(
+            Err(vec![
+                OrderError::DuplicateProduct(product_id),
+                OrderError::InvalidQuantity {
+                    line_item_id,
+                    quantity: 0,
+                },
+            ]),
+            order.validate()
+        );
+    }
+
+    #[test]
+    fn validate_empty_order() {
+        let order = default_order();
+
+        assert_eq!(Err(vec![OrderError::Empty]), order.validate());
+    }
+
+    #[test]
+    fn validate_ok() {
+        let mut order = default_order();
+
+        order
+            .add_product(LineItemId::new(), &default_product(), 1)
+            .unwrap();
+
+        assert_eq!(Ok(()), order.validate());
+    }
+
+    #[test]
+    fn consolidate() {
+        let mut order = default_order();
+
+        let first = ProductBuilder::new().id(ProductId::new()).build();
+        let second = ProductBuilder::new().id(ProductId::new()).build();
+
+        let first_id = LineItemId::new();
+
+        order.add_product(first_id, &first, 2).unwrap();
+        order.add_product(LineItemId::new(), &second, 1).unwrap();
+
+        duplicate_product(&mut order, LineItemId::new(), first.to_data().id, 3);
+        duplicate_product(&mut order, LineItemId::new(), first.to_data().id, u32::MAX);
+
+        assert_eq!(2, order.consolidate());
+
+        let line_items: Vec<_> = order
+            .line_items
+            .iter()
+            .map(|l| (l.product_id, l.quantity))
+            .collect();
+
+        assert_eq!(
+            vec![(first.to_data().id, u32::MAX), (second.to_data().id, 1)],
+            line_items
+        );
+        assert_eq!(first_id, order.line_items[0].id);
+
+        // Consolidating again has nothing to merge
+        assert_eq!(0, order.consolidate());
+    }
+
     #[test]
     fn product_must_not_be_in_order_when_adding() {
         let mut order = default_order();
@@ -294,4 +1388,284 @@ mod tests {
 
         assert!(order.add_product(LineItemId::new(), &product, 1).is_err());
     }
+
+    #[test]
+    fn line_item_accessors() {
+        let product_id = ProductId::new();
+        let line_item_id = LineItemId::new();
+
+        let mut order = default_order();
+        order
+            .add_product(
+                line_item_id,
+                &ProductBuilder::new().id(product_id).build(),
+                3,
+            )
+            .unwrap();
+
+        let line_item = match order.into_line_item_for_product(product_id) {
+            IntoLineItem::InOrder(line_item) => line_item,
+            IntoLineItem::NotInOrder(_) => panic!("expected the product to be in the order"),
+        };
+
+        assert_eq!(line_item_id, line_item.id());
+        assert_eq!(product_id, line_item.product_id());
+        assert_eq!(3, line_item.quantity());
+    }
+
+    #[test]
+    fn total_only_discounts_eligible_line_items() {
+        let order = OrderBuilder::new()
+            .add_product(
+                ProductBuilder::new().price(Currency::usd(1000)).build(),
+                |line_item| line_item.quantity(2),
+            )
+            .add_product(
+                ProductBuilder::new().price(Currency::usd(999)).build(),
+                |line_item| line_item,
+            )
+            .add_product(
+                ProductBuilder::new().price(Currency::usd(500)).build(),
+                |line_item| line_item.quantity(3).discount_eligible(false),
+            )
+            .build();
+
+        assert_eq!(Currency::usd(4499), order.total(0).unwrap());
+
+        // 2 * 900 + 899 (rounded from 899.1) + 3 * 500
+        assert_eq!(Currency::usd(4199), order.total(10).unwrap());
+
+        // Ineligible items are still charged in full
+        assert_eq!(Currency::usd(1500), order.total(100).unwrap());
+
+        assert!(order.total(101).is_err());
+    }
+
+    #[test]
+    fn total_weight_sums_line_items() {
+        let order = OrderBuilder::new()
+            .add_product(
+                ProductBuilder::new().weight_grams(250).build(),
+                |line_item| line_item.quantity(4),
+            )
+            .add_product(
+                ProductBuilder::new().weight_grams(1200).build(),
+                |line_item| line_item,
+            )
+            // Products without a known weight don't count
+            .add_product(default_product(), |line_item| line_item.quantity(3))
+            .build();
+
+        assert_eq!(2200, order.total_weight());
+        assert_eq!(0, OrderBuilder::new().build().total_weight());
+    }
+
+    #[test]
+    fn total_weight_saturates() {
+        let order = OrderBuilder::new()
+            .add_product(
+                ProductBuilder::new().weight_grams(u32::MAX / 2).build(),
+                |line_item| line_item.quantity(3),
+            )
+            .build();
+
+        assert_eq!(u32::MAX, order.total_weight());
+
+        let order = OrderBuilder::new()
+            .add_product(
+                ProductBuilder::new().weight_grams(u32::MAX - 1).build(),
+                |line_item| line_item,
+            )
+            .add_product(ProductBuilder::new().weight_grams(2).build(), |line_item| {
+                line_item
+            })
+            .build();
+
+        assert_eq!(u32::MAX, order.total_weight());
+    }
+
+    #[test]
+    fn line_item_ids_match_line_items() {
+        let first = LineItemId::new();
+        let second = LineItemId::new();
+
+        let order = OrderBuilder::new()
+            .add_product(default_product(), move |line_item| line_item.id(first))
+            .add_product(default_product(), move |line_item| line_item.id(second))
+            .build();
+
+        assert_eq!(vec![first, second], order.line_item_ids());
+    }
+
+    #[test]
+    fn line_items_are_discount_eligible_by_default() {
+        let product_id = ProductId::new();
+
+        let order = OrderBuilder::new()
+            .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                line_item
+            })
+            .build();
+
+        let mut line_item = match order.into_line_item_for_product(product_id) {
+            IntoLineItem::InOrder(line_item) => line_item,
+            IntoLineItem::NotInOrder(_) => panic!("expected the product to be in the order"),
+        };
+
+        assert!(line_item.discount_eligible());
+
+        line_item.set_discount_eligible(false);
+
+        assert!(!line_item.discount_eligible());
+    }
+
+    #[test]
+    fn entity_accessors_match_data() {
+        let product = default_product();
+        let product_id = product.to_data().id;
+
+        let mut order = default_order();
+        order.add_product(LineItemId::new(), &product, 1).unwrap();
+
+        let (order_id, order_version) = {
+            let (order_data, _) = order.to_data();
+
+            (order_data.id, order_data.version)
+        };
+
+        assert_eq!(order_id, Entity::id(&order));
+        assert_eq!(order_version, Entity::version(&order));
+
+        let line_item = match order.into_line_item_for_product(product_id) {
+            IntoLineItem::InOrder(line_item) => line_item,
+            IntoLineItem::NotInOrder(_) => panic!("expected the product to be in the order"),
+        };
+
+        let (_, line_item_data) = line_item.to_data();
+
+        assert_eq!(line_item_data.id, Entity::id(&line_item));
+        assert_eq!(line_item_data.version, Entity::version(&line_item));
+    }
+    /** A sample storage adapter that rehydrates orders from rows, like the ones read from SQL. */
+    #[cfg(feature = "storage-adapter")]
+    mod storage_adapter {
+        use super::*;
+
+        type OrderRow = (OrderId, OrderVersion, CustomerId);
+        type LineItemRow = (LineItemId, LineItemVersion, ProductId, Currency, u32);
+
+        fn rehydrate(order: OrderRow, line_items: Vec<LineItemRow>) -> Result<Order, Error> {
+            let (id, version, customer_id) = order;
+
+            let line_items = line_items
+                .into_iter()
+                .map(|(id, version, product_id, price, quantity)| {
+                    LineItemData::restore(id, version, product_id, price, quantity)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Order::restore(OrderData::restore(id, version, customer_id)?, line_items)
+        }
+
+        fn dehydrate(order: Order) -> (OrderRow, Vec<LineItemRow>) {
+            let (order, line_items) = order.into_data();
+
+            (
+                (order.id, order.version, order.customer_id),
+                line_items
+                    .into_iter()
+                    .map(|item| {
+                        (
+                            item.id,
+                            item.version,
+                            item.product_id,
+                            item.price,
+                            item.quantity,
+                        )
+                    })
+                    .collect(),
+            )
+        }
+
+        fn order_row() -> OrderRow {
+            (
+                OrderId::new(),
+                OrderVersion::new().next().unwrap(),
+                CustomerId::new(),
+            )
+        }
+
+        fn line_item_row(product_id: ProductId, quantity: u32) -> LineItemRow {
+            (
+                LineItemId::new(),
+                LineItemVersion::new().next().unwrap(),
+                product_id,
+                Currency::usd(100),
+                quantity,
+            )
+        }
+
+        #[test]
+        fn round_trip_through_domain() {
+            let order_row = order_row();
+            let line_item_rows = vec![
+                line_item_row(ProductId::new(), 1),
+                line_item_row(ProductId::new(), 3),
+            ];
+
+            let mut order = rehydrate(order_row, line_item_rows.clone()).unwrap();
+
+            let product = default_product();
+            let line_item_id = LineItemId::new();
+            order.add_product(line_item_id, &product, 2).unwrap();
+
+            let (restored_order_row, restored_line_item_rows) = dehydrate(order);
+
+            assert_eq!(order_row, restored_order_row);
+            assert_eq!(&line_item_rows[..], &restored_line_item_rows[..2]);
+            assert_eq!(
+                (
+                    line_item_id,
+                    LineItemVersion::default(),
+                    product.to_data().id,
+                    product.to_data().price,
+                    2
+                ),
+                restored_line_item_rows[2]
+            );
+        }
+
+        #[test]
+        fn invalid_rows_are_rejected() {
+            let product_id = ProductId::new();
+
+            let zero_quantity = rehydrate(order_row(), vec![line_item_row(product_id, 0)]);
+            assert!(zero_quantity.is_err());
+
+            let duplicate_product = rehydrate(
+                order_row(),
+                vec![line_item_row(product_id, 1), line_item_row(product_id, 2)],
+            );
+            assert!(matches!(duplicate_product, Err(err) if err.kind() == ErrorKind::BadInput));
+        }
+
+        #[test]
+        fn changed_data_is_checked_again() {
+            let (id, version, customer_id) = order_row();
+            let (line_item_id, line_item_version, product_id, price, _) =
+                line_item_row(ProductId::new(), 1);
+
+            let mut line_item =
+                LineItemData::restore(line_item_id, line_item_version, product_id, price, 1)
+                    .unwrap();
+            line_item.quantity = 0;
+
+            let r = Order::restore(
+                OrderData::restore(id, version, customer_id).unwrap(),
+                vec![line_item],
+            );
+
+            assert!(r.is_err());
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,554 @@
+/*!
+Order storage on top of a sled database.
+
+Orders are kept in two trees. The `orders` tree holds each order's data keyed by its id, and the
+`line_items` tree holds each line item keyed by its order's id followed by its own id, so all of
+an order's line items can be found with a prefix scan.
+
+The database is only a durable copy of the orders, not where they're read from. Orders are read
+from it once, when the store is opened, and kept in memory. Every read after that is answered
+from memory, and changes are written through to the database as they're committed.
+
+# Crash safety
+
+Writes are staged in memory first, the same way the in-memory store stages them, so changes in a
+transaction that's cancelled never reach the database. As part of committing a transaction, the
+orders it changed are written and the database is flushed, so they're durable before the
+transaction's changes are observable. If they can't be written then the transaction fails to
+commit. Each order is written along with its line items in a single sled transaction across both
+trees, so a crash can't leave an order with only some of its line items.
+
+Orders are written one transaction at a time, so two transactions changing the same order can't
+interleave their writes. A crash part way through writing a transaction's orders can still leave
+only some of them in the database.
+*/
+
+use std::{
+    convert::Infallible,
+    path::Path,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use sled::{
+    transaction::{
+        TransactionResult,
+        Transactional,
+    },
+    Db,
+    Tree,
+};
+
+use crate::{
+    domain::{
+        infra::*,
+        orders::{
+            model::store::*,
+            *,
+        },
+        Error,
+        ErrorKind,
+    },
+    store::{
+        self,
+        lock,
+        PendingWrites,
+        Transaction,
+        TransactionStore,
+    },
+};
+
+/** An order store that keeps orders in memory and writes them through to a sled database. */
+pub(in crate::domain) struct SledStore {
+    store: Arc<InMemoryStore>,
+    trees: Trees,
+    /** Orders that have been changed in transactions but not written to the database yet. */
+    pending: PendingWrites<OrderId>,
+}
+
+/** The database that orders are written to. */
+#[derive(Clone)]
+struct Trees {
+    db: Db,
+    orders: Tree,
+    line_items: Tree,
+    writing: Arc<Mutex<()>>,
+}
+
+impl SledStore {
+    /** Open a store backed by the database at `path`, reading any orders that are already in it. */
+    pub(in crate::domain) fn open(
+        path: impl AsRef<Path>,
+        transaction_store: TransactionStore,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let db = sled::open(path).map_err(storage_error("failed to open the order database"))?;
+
+        Self::from_db(db, transaction_store, clock)
+    }
+
+    /** Open a store backed by a temporary database that's removed when the store is dropped. */
+    #[cfg(test)]
+    pub(in crate::domain) fn temporary(
+        transaction_store: TransactionStore,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(storage_error("failed to open the order database"))?;
+
+        Self::from_db(db, transaction_store, clock)
+    }
+
+    fn from_db(
+        db: Db,
+        transaction_store: TransactionStore,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let open_tree = |name| {
+            db.open_tree(name)
+                .map_err(storage_error("failed to open the order database"))
+        };
+
+        let store = SledStore {
+            store: Arc::new(in_memory_store_with_clock(transaction_store.clone(), clock)),
+            trees: Trees {
+                orders: open_tree("orders")?,
+                line_items: open_tree("line_items")?,
+                db,
+                writing: Default::default(),
+            },
+            pending: PendingWrites::new(transaction_store),
+        };
+
+        for order in store.trees.orders.iter() {
+            let (key, order) = order.map_err(storage_error("failed to read orders"))?;
+
+            let order_data: OrderData = serde_json::from_slice(&order)
+                .map_err(storage_error("an order in the database is corrupted"))?;
+
+            let line_items_data = store
+                .trees
+                .line_items
+                .scan_prefix(&key)
+                .values()
+                .map(|line_item| {
+                    let line_item =
+                        line_item.map_err(storage_error("failed to read line items"))?;
+
+                    serde_json::from_slice(&line_item)
+                        .map_err(storage_error("a line item in the database is corrupted"))
+                })
+                .collect::<Result<Vec<LineItemData>, Error>>()?;
+
+            store.store.restore(order_data, line_items_data)?;
+        }
+
+        Ok(store)
+    }
+
+    /**
+    Note that an order was changed in a transaction, so it's written as part of committing it.
+
+    Auto-committed writes complete straight away, so they're written before this returns.
+    */
+    fn changed(&self, transaction: &Transaction, id: OrderId) -> Result<(), Error> {
+        let store = self.store.clone();
+        let trees = self.trees.clone();
+
+        self.pending.push(transaction, id, move |transaction, ids| {
+            trees.write(&store, transaction, ids)
+        })
+    }
+}
+
+impl Trees {
+    /** Write orders as a committing transaction sees them, then wait for them to be durable. */
+    fn write(
+        &self,
+        store: &InMemoryStore,
+        transaction: &Transaction,
+        mut ids: Vec<OrderId>,
+    ) -> Result<(), Error> {
+        // Orders are read and written while locked, so a concurrent write can't interleave
+        let _writing = lock::lock(&self.writing);
+
+        ids.sort();
+        ids.dedup();
+
+        for id in ids {
+            self.write_order(store, transaction, id)?;
+        }
+
+        self.db
+            .flush()
+            .map_err(storage_error("failed to flush the order database"))?;
+
+        Ok(())
+    }
+
+    /** Write an order as it's seen by a transaction, or remove it if it's been deleted. */
+    fn write_order(
+        &self,
+        store: &InMemoryStore,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<(), Error> {
+        let order_key = key(id);
+
+        let order = store
+            .get_order(transaction, id, ReadConsistency::Strong)?
+            .map(|order| {
+                let (order_data, line_items_data) = order.into_data();
+
+                let order = serde_json::to_vec(&order_data)
+                    .map_err(storage_error("failed to serialize an order"))?;
+                let line_items = line_items_data
+                    .iter()
+                    .map(|line_item| {
+                        serde_json::to_vec(line_item)
+                            .map(|line_item_json| (line_item_key(id, line_item.id), line_item_json))
+                            .map_err(storage_error("failed to serialize a line item"))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+
+                Ok::<_, Error>((order, line_items))
+            })
+            .transpose()?;
+
+        // Line items that are already stored are removed, unless they're still in the order
+        let stored_line_items = self
+            .line_items
+            .scan_prefix(order_key)
+            .keys()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error("failed to read line items"))?;
+
+        let written: TransactionResult<(), Infallible> = (&self.orders, &self.line_items)
+            .transaction(|(orders, line_items)| {
+                for stored in &stored_line_items {
+                    line_items.remove(stored)?;
+                }
+
+                match &order {
+                    Some((order, order_line_items)) => {
+                        orders.insert(&order_key[..], &order[..])?;
+
+                        for (key, line_item) in order_line_items {
+                            line_items.insert(&key[..], &line_item[..])?;
+                        }
+                    }
+                    None => {
+                        orders.remove(&order_key[..])?;
+                    }
+                }
+
+                Ok(())
+            });
+
+        written.map_err(storage_error("failed to write an order"))
+    }
+}
+
+fn key(id: OrderId) -> [u8; 16] {
+    store::Id::from(id).into_raw().into_bytes()
+}
+
+fn line_item_key(id: OrderId, line_item_id: LineItemId) -> [u8; 32] {
+    let mut key = [0; 32];
+
+    key[..16].copy_from_slice(&self::key(id));
+    key[16..].copy_from_slice(&store::Id::from(line_item_id).into_raw().into_bytes());
+
+    key
+}
+
+fn storage_error<E>(msg: &'static str) -> impl FnOnce(E) -> Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    move |err| Error::with_source(ErrorKind::Internal, msg, err)
+}
+
+impl OrderStore for SledStore {
+    fn get_line_item(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        line_item_id: LineItemId,
+    ) -> Result<Option<OrderLineItem>, Error> {
+        self.store.get_line_item(transaction, id, line_item_id)
+    }
+
+    fn set_line_item(&self, transaction: &Transaction, order: OrderLineItem) -> Result<(), Error> {
+        let (id, _) = order.to_data();
+
+        self.store.set_line_item(transaction, order)?;
+
+        self.changed(transaction, id)
+    }
+
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error> {
+        self.store.get_order(transaction, id, consistency)
+    }
+
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        self.store.get_order_header(transaction, id)
+    }
+
+    fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
+        let id = order.to_data().0.id;
+
+        self.store.set_order(transaction, order)?;
+
+        self.changed(transaction, id)
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error> {
+        let deleted = self.store.delete_order(transaction, id)?;
+
+        if deleted {
+            self.changed(transaction, id)?;
+        }
+
+        Ok(deleted)
+    }
+}
+
+impl OrderStoreFilter for SledStore {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error> {
+        self.store.filter(transaction, predicate)
+    }
+
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error> {
+        self.store.query(transaction, filter)
+    }
+}
+
+impl Flush for SledStore {
+    fn flush(&self) -> Result<(), Error> {
+        self.trees
+            .db
+            .flush()
+            .map_err(storage_error("failed to flush the order database"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::test_data::OrderBuilder,
+        products::{
+            model::test_data::ProductBuilder,
+            ProductId,
+        },
+    };
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("shop-orders-{}", OrderId::new()))
+    }
+
+    #[test]
+    fn orders_survive_reopening() {
+        let path = temp_path();
+
+        let order_id = OrderId::new();
+        let deleted_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        {
+            let store = SledStore::open(&path, Default::default(), SystemClock).unwrap();
+
+            for id in [order_id, deleted_id] {
+                store
+                    .set_order(
+                        ActiveTransaction::auto_commit().get(),
+                        OrderBuilder::new()
+                            .id(id)
+                            .add_product(ProductBuilder::new().id(product_id).build(), |l| {
+                                l.quantity(2)
+                            })
+                            .add_product(ProductBuilder::new().build(), |l| l)
+                            .build(),
+                    )
+                    .unwrap();
+            }
+
+            store
+                .delete_order(ActiveTransaction::auto_commit().get(), deleted_id)
+                .unwrap();
+
+            store.flush().unwrap();
+        }
+
+        let store = SledStore::open(&path, Default::default(), SystemClock).unwrap();
+
+        let (order_data, line_items) = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(order_id, order_data.id);
+        assert_eq!(2, line_items.len());
+
+        let line_item = line_items
+            .iter()
+            .find(|line_item| line_item.product_id == product_id)
+            .unwrap();
+
+        let (_, found) = store
+            .get_line_item(&Transaction::none(), order_id, line_item.id)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(2, found.quantity);
+
+        assert!(store
+            .get_order(&Transaction::none(), deleted_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+
+        // Versions are kept, so stale writes are still caught after reopening
+        assert!(store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .is_err());
+
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn committed_orders_survive_reopening_without_flushing() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let order_id = OrderId::new();
+
+        {
+            let store = SledStore::open(&path, transactions.clone(), SystemClock).unwrap();
+
+            let transaction = transactions.begin();
+            store
+                .set_order(&transaction, OrderBuilder::new().id(order_id).build())
+                .unwrap();
+            transactions.commit(transaction).unwrap();
+        }
+
+        let store = SledStore::open(&path, Default::default(), SystemClock).unwrap();
+
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_some());
+
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn only_completed_transactions_are_written() {
+        let transactions = TransactionStore::new();
+        let store = SledStore::temporary(transactions.clone(), SystemClock).unwrap();
+
+        let committed_id = OrderId::new();
+        let cancelled_id = OrderId::new();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+
+        for (transaction, id) in [(&committed, committed_id), (&cancelled, cancelled_id)] {
+            store
+                .set_order(transaction, OrderBuilder::new().id(id).build())
+                .unwrap();
+        }
+
+        assert!(store.trees.orders.is_empty());
+
+        transactions.commit(committed).unwrap();
+        transactions.cancel(cancelled);
+
+        // Committing writes the transaction's orders without waiting for another write
+        assert!(store.trees.orders.contains_key(key(committed_id)).unwrap());
+        assert!(!store.trees.orders.contains_key(key(cancelled_id)).unwrap());
+    }
+
+    #[test]
+    fn transaction_fails_to_commit_if_orders_cant_be_written() {
+        let transactions = TransactionStore::new();
+        let store = SledStore::temporary(transactions.clone(), SystemClock).unwrap();
+
+        let order_id = OrderId::new();
+
+        // Trees that have been dropped can't be written to any more
+        store.trees.db.drop_tree("orders").unwrap();
+
+        let transaction = transactions.begin();
+        store
+            .set_order(&transaction, OrderBuilder::new().id(order_id).build())
+            .unwrap();
+
+        assert!(transactions.commit(transaction).is_err());
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn line_items_taken_out_of_an_order_are_removed() {
+        let store = SledStore::temporary(Default::default(), SystemClock).unwrap();
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(ProductBuilder::new().id(product_id).build(), |l| l)
+                    .add_product(ProductBuilder::new().build(), |l| l)
+                    .build(),
+            )
+            .unwrap();
+
+        assert_eq!(2, store.trees.line_items.scan_prefix(key(order_id)).count());
+
+        let mut order = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap();
+        order.take_product(product_id, 1).unwrap();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        assert_eq!(1, store.trees.line_items.scan_prefix(key(order_id)).count());
+    }
+}
\ No newline at end of file
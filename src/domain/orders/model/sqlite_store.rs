@@ -0,0 +1,402 @@
+/*!
+Order storage on top of a SQLite database.
+
+Orders are kept in the `orders` table and their line items in the `line_items` table.
+*/
+
+use std::sync::Arc;
+
+use rusqlite::params;
+
+use crate::{
+    domain::{
+        customers::CustomerId,
+        infra::{
+            sqlite::{
+                sqlite_error,
+                Database,
+                Row,
+            },
+            *,
+        },
+        orders::{
+            model::store::*,
+            *,
+        },
+        products::ProductId,
+        Error,
+    },
+    store::{
+        Transaction,
+        TransactionStore,
+    },
+};
+
+/**
+An order store that persists orders to a SQLite database.
+
+Orders are read from the database when the store is opened and kept in memory for reads.
+See the `sqlite` module for when changes are written.
+*/
+pub(in crate::domain) struct SqliteOrderStore {
+    store: Arc<InMemoryStore>,
+    database: Database,
+    transactions: TransactionStore,
+}
+
+/** The columns written for an order and its line items. */
+struct OrderRow {
+    id: OrderId,
+    version: String,
+    customer_id: CustomerId,
+    data: String,
+    line_items: Vec<LineItemRow>,
+}
+
+struct LineItemRow {
+    id: LineItemId,
+    version: String,
+    product_id: ProductId,
+    data: String,
+}
+
+impl SqliteOrderStore {
+    /** Open a store backed by `database`, reading any orders that are already in it. */
+    pub(in crate::domain) fn open(
+        database: Database,
+        transaction_store: TransactionStore,
+        clock: impl Clock + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let store = SqliteOrderStore {
+            store: Arc::new(in_memory_store_with_clock(transaction_store.clone(), clock)),
+            database,
+            transactions: transaction_store,
+        };
+
+        let rows = store.database.read(|connection| {
+            let mut line_items =
+                connection.prepare("SELECT data FROM line_items WHERE order_id = ?1")?;
+
+            connection
+                .prepare("SELECT id, data FROM orders")?
+                .query_map([], |row| {
+                    Ok((row.get::<_, OrderId>(0)?, row.get::<_, String>(1)?))
+                })?
+                .map(|order| {
+                    let (id, order) = order?;
+
+                    let line_items = line_items
+                        .query_map([id], |row| row.get::<_, String>(0))?
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    Ok((order, line_items))
+                })
+                .collect::<Result<Vec<_>, rusqlite::Error>>()
+        })?;
+
+        for (order, line_items) in rows {
+            let order_data: OrderData = serde_json::from_str(&order)
+                .map_err(sqlite_error("an order in the database is corrupted"))?;
+
+            let line_items_data = line_items
+                .iter()
+                .map(|line_item| serde_json::from_str(line_item))
+                .collect::<Result<Vec<LineItemData>, _>>()
+                .map_err(sqlite_error("a line item in the database is corrupted"))?;
+
+            store.store.restore(order_data, line_items_data)?;
+        }
+
+        Ok(store)
+    }
+
+    /**
+    Note that an order was changed in a transaction, so it's written as part of committing it.
+
+    Auto-committed writes complete straight away, so they're written before this returns.
+    */
+    fn changed(&self, transaction: &Transaction, id: OrderId) -> Result<(), Error> {
+        let store = self.store.clone();
+
+        self.database.write(
+            &self.transactions,
+            transaction,
+            Row::Order(id),
+            move |transaction, sqlite_transaction| {
+                let order = order_row(&store, transaction, id)?;
+
+                write(sqlite_transaction, id, order.as_ref())
+                    .map_err(sqlite_error("failed to write an order to the database"))
+            },
+        )
+    }
+}
+
+/**
+Write the state of an order as it's seen by the transaction that's committing.
+
+An order that's been deleted is removed along with its line items.
+*/
+fn write(
+    transaction: &rusqlite::Transaction,
+    id: OrderId,
+    order: Option<&OrderRow>,
+) -> rusqlite::Result<()> {
+    transaction
+        .prepare_cached("DELETE FROM line_items WHERE order_id = ?1")?
+        .execute([id])?;
+
+    let order = match order {
+        Some(order) => order,
+        None => {
+            transaction
+                .prepare_cached("DELETE FROM orders WHERE id = ?1")?
+                .execute([id])?;
+
+            return Ok(());
+        }
+    };
+
+    transaction
+        .prepare_cached(
+            "INSERT INTO orders (id, version, customer_id, data) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (id) DO UPDATE SET
+                version = excluded.version,
+                customer_id = excluded.customer_id,
+                data = excluded.data",
+        )?
+        .execute(params![
+            order.id,
+            order.version,
+            order.customer_id,
+            order.data
+        ])?;
+
+    let mut insert_line_item = transaction.prepare_cached(
+        "INSERT INTO line_items (id, order_id, version, product_id, data)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+
+    for line_item in &order.line_items {
+        insert_line_item.execute(params![
+            line_item.id,
+            order.id,
+            line_item.version,
+            line_item.product_id,
+            line_item.data
+        ])?;
+    }
+
+    Ok(())
+}
+
+fn order_row(
+    store: &InMemoryStore,
+    transaction: &Transaction,
+    id: OrderId,
+) -> Result<Option<OrderRow>, Error> {
+    let order = match store.get_order(transaction, id, ReadConsistency::Strong)? {
+        Some(order) => order,
+        None => return Ok(None),
+    };
+
+    let (order_data, line_items_data) = order.into_data();
+
+    let line_items = line_items_data
+        .iter()
+        .map(|line_item| {
+            Ok(LineItemRow {
+                id: line_item.id,
+                version: line_item.version.to_string(),
+                product_id: line_item.product_id,
+                data: serde_json::to_string(line_item)
+                    .map_err(sqlite_error("failed to serialize a line item"))?,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(Some(OrderRow {
+        id: order_data.id,
+        version: order_data.version.to_string(),
+        customer_id: order_data.customer_id,
+        data: serde_json::to_string(&order_data)
+            .map_err(sqlite_error("failed to serialize an order"))?,
+        line_items,
+    }))
+}
+
+impl OrderStore for SqliteOrderStore {
+    fn get_line_item(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        line_item_id: LineItemId,
+    ) -> Result<Option<OrderLineItem>, Error> {
+        self.store.get_line_item(transaction, id, line_item_id)
+    }
+
+    fn set_line_item(&self, transaction: &Transaction, order: OrderLineItem) -> Result<(), Error> {
+        let (id, _) = order.to_data();
+
+        self.store.set_line_item(transaction, order)?;
+
+        self.changed(transaction, id)
+    }
+
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error> {
+        self.store.get_order(transaction, id, consistency)
+    }
+
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        self.store.get_order_header(transaction, id)
+    }
+
+    fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
+        let id = order.to_data().0.id;
+
+        self.store.set_order(transaction, order)?;
+
+        self.changed(transaction, id)
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error> {
+        let deleted = self.store.delete_order(transaction, id)?;
+
+        if deleted {
+            self.changed(transaction, id)?;
+        }
+
+        Ok(deleted)
+    }
+}
+
+impl OrderStoreFilter for SqliteOrderStore {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error> {
+        self.store.filter(transaction, predicate)
+    }
+
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error> {
+        self.store.query(transaction, filter)
+    }
+}
+
+impl Flush for SqliteOrderStore {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::test_data::OrderBuilder,
+        products::model::test_data::ProductBuilder,
+    };
+
+    fn count(database: &Database, sql: &str) -> usize {
+        database
+            .read(|connection| connection.query_row(sql, [], |row| row.get(0)))
+            .unwrap()
+    }
+
+    #[test]
+    fn orders_are_read_back_when_reopened() {
+        let database = Database::open(":memory:").unwrap();
+
+        let order_id = OrderId::new();
+        let deleted_id = OrderId::new();
+        let mut product_ids = [ProductId::new(), ProductId::new(), ProductId::new()];
+        product_ids.sort();
+
+        let store =
+            SqliteOrderStore::open(database.clone(), Default::default(), SystemClock).unwrap();
+
+        for id in [order_id, deleted_id] {
+            store
+                .set_order(
+                    ActiveTransaction::auto_commit().get(),
+                    product_ids
+                        .iter()
+                        .fold(OrderBuilder::new().id(id), |order, &product_id| {
+                            order.add_product(ProductBuilder::new().id(product_id).build(), |l| {
+                                l.quantity(2)
+                            })
+                        })
+                        .build(),
+                )
+                .unwrap();
+        }
+
+        store
+            .delete_order(ActiveTransaction::auto_commit().get(), deleted_id)
+            .unwrap();
+        drop(store);
+
+        assert_eq!(1, count(&database, "SELECT COUNT(*) FROM orders"));
+        assert_eq!(3, count(&database, "SELECT COUNT(*) FROM line_items"));
+
+        let store = SqliteOrderStore::open(database, Default::default(), SystemClock).unwrap();
+
+        let (_, line_items) = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        let mut found: Vec<_> = line_items
+            .iter()
+            .map(|line_item| line_item.product_id)
+            .collect();
+        found.sort();
+
+        assert_eq!(product_ids.to_vec(), found);
+
+        assert!(store
+            .get_order(&Transaction::none(), deleted_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+
+        // Versions are kept, so stale writes are still caught after reopening
+        assert!(store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn orders_are_written_when_their_transaction_commits() {
+        let database = Database::open(":memory:").unwrap();
+        let transactions = TransactionStore::new();
+        let store =
+            SqliteOrderStore::open(database.clone(), transactions.clone(), SystemClock).unwrap();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+
+        for transaction in [&committed, &cancelled] {
+            store
+                .set_order(transaction, OrderBuilder::new().build())
+                .unwrap();
+        }
+
+        assert_eq!(0, count(&database, "SELECT COUNT(*) FROM orders"));
+
+        transactions.commit(committed).unwrap();
+        transactions.cancel(cancelled);
+
+        assert_eq!(1, count(&database, "SELECT COUNT(*) FROM orders"));
+    }
+}
\ No newline at end of file
@@ -1,14 +1,28 @@
 /*! Persistent order storage. */
 
 use std::{
-    collections::HashSet,
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    sync::{
+        Arc,
+        RwLock,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
     vec::IntoIter,
 };
 
 use crate::{
     domain::{
+        customers::CustomerId,
         error,
+        infra::*,
         orders::*,
+        products::ProductId,
         Error,
     },
     store::*,
@@ -16,64 +30,301 @@ use crate::{
 
 /** A place to persist and fetch order entities. */
 #[auto_impl(&, Arc)]
-pub(in crate::domain) trait OrderStore {
+pub trait OrderStore: Flush {
     fn get_line_item(
         &self,
+        transaction: &Transaction,
         id: OrderId,
         line_item_id: LineItemId,
     ) -> Result<Option<OrderLineItem>, Error>;
     fn set_line_item(&self, transaction: &Transaction, order: OrderLineItem) -> Result<(), Error>;
 
-    fn get_order(&self, id: OrderId) -> Result<Option<Order>, Error>;
+    /**
+    Get an order as it's seen by a transaction.
+
+    Strong reads include the transaction's own uncommitted changes. Snapshot reads only ever
+    see committed orders.
+    */
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error>;
     fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error>;
+
+    /**
+    Get the data for an order as it's seen by a transaction, without its line items.
+
+    This is cheaper than getting the whole order when only its own fields are needed.
+    */
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        Ok(self
+            .get_order(transaction, id, ReadConsistency::Strong)?
+            .map(|order| order.into_data().0))
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error>;
+}
+
+impl<T> Repository<Order> for T
+where
+    T: OrderStore + ?Sized,
+{
+    fn get(&self, transaction: &Transaction, id: OrderId) -> Result<Option<Order>, Error> {
+        self.get_order(transaction, id, ReadConsistency::Strong)
+    }
+
+    fn save(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
+        self.set_order(transaction, order)
+    }
+}
+
+/**
+How up-to-date a read from a store needs to be.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReadConsistency {
+    /** Read the latest state, including changes made in the reading transaction. */
+    #[default]
+    Strong,
+    /**
+    Read from a recent snapshot of committed orders that may be stale.
+
+    Snapshot reads avoid contending with writers, so they suit callers that read a lot
+    but can tolerate not seeing the latest changes.
+    */
+    Snapshot,
 }
 
 /**
 An additional store for fetching multiple order records at a time.
 
-This trait is an implementation detail that lets us fetch more than one order.
-It will probably need to be refactored or just removed when we add a proper database.
-The fact that it's internal to `domain::orders` though means the scope of breakage is a bit smaller.
+This trait lets us fetch more than one order.
+It will probably need to be refactored when we add a proper database.
+Stores given to `App::with_order_store` implement it too, so changing it will break them.
 Commands and queries that depend on `OrderStoreFilter` won't need to break their public API.
 */
 #[auto_impl(&, Arc)]
-pub(in crate::domain) trait OrderStoreFilter {
-    fn filter<F>(&self, predicate: F) -> Result<Iter, Error>
-    where
-        F: Fn(&OrderData) -> bool;
+pub trait OrderStoreFilter {
+    /** Get the orders that match a predicate, as they're seen by a transaction. */
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error>;
+
+    /** Get the orders that match a filter, as they're seen by a transaction. */
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error>;
+}
+
+pub type Iter = IntoIter<OrderData>;
+
+/** A store that can both persist and filter orders. */
+pub trait OrderStoreWithFilter: OrderStore + OrderStoreFilter {}
+
+impl<T> OrderStoreWithFilter for T where T: OrderStore + OrderStoreFilter + ?Sized {}
+
+/** A shared order store that can be stored in a resolver. */
+pub(in crate::domain) type DynOrderStore = Arc<dyn OrderStoreWithFilter + Send + Sync>;
+
+/**
+A filter for orders.
+
+Orders must match all of the fields that are set on the filter.
+An empty filter matches all orders.
+*/
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OrderFilter {
+    pub customer_id: Option<CustomerId>,
+    pub contains_product: Option<ProductId>,
 }
 
-pub(in crate::domain) type Iter = IntoIter<OrderData>;
+/**
+A test in-memory order store.
 
-/** A test in-memory order store. */
+The store keeps track of when orders were last stored using its clock.
+Changing a line item stamps the line item rather than its order, so it doesn't conflict with
+other changes to the order. The order's `updated_at` is the latest of those stamps.
+Snapshot reads use a copy of all orders that's refreshed when it's older than `SNAPSHOT_MAX_AGE`.
+Line items are kept behind an `Arc`, so orders that are read share them with the store.
+*/
 pub(in crate::domain) struct InMemoryStore {
     orders: TransactionValueStore<(OrderData, HashSet<LineItemId>)>,
-    line_items: TransactionValueStore<LineItemData>,
+    line_items: TransactionValueStore<Arc<LineItemData>>,
+    line_items_updated_at: TransactionValueStore<SystemTime>,
+    snapshot: RwLock<Option<Snapshot>>,
+    clock: DynClock,
+}
+
+const SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(1);
+
+struct Snapshot {
+    taken_at: SystemTime,
+    orders: Arc<HashMap<OrderId, (OrderData, Vec<Arc<LineItemData>>)>>,
+}
+
+impl InMemoryStore {
+    /**
+    Put back an order that was persisted somewhere else, along with its line items.
+
+    Unlike setting an order, it keeps the versions it was persisted with and is visible straight away.
+    */
+    #[cfg(any(feature = "sled-store", feature = "sqlite"))]
+    pub(in crate::domain) fn restore(
+        &self,
+        order_data: OrderData,
+        line_items_data: Vec<LineItemData>,
+    ) -> Result<(), Error> {
+        let (id, version) = (order_data.id, order_data.version);
+        let item_ids = line_items_data.iter().map(|item| item.id).collect();
+
+        for line_item_data in line_items_data {
+            let (id, version) = (line_item_data.id, line_item_data.version);
+
+            self.line_items
+                .set(
+                    &Transaction::none(),
+                    id,
+                    None::<LineItemVersion>,
+                    version,
+                    Arc::new(line_item_data),
+                )
+                .map_err(LineItemVersion::stale_write(id))?;
+        }
+
+        self.orders
+            .set(
+                &Transaction::none(),
+                id,
+                None::<OrderVersion>,
+                version,
+                (order_data, item_ids),
+            )
+            .map_err(OrderVersion::stale_write(id))?;
+
+        Ok(())
+    }
+
+    fn get_order_data(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Option<(OrderData, Vec<Arc<LineItemData>>)> {
+        let (version, (mut order_data, line_item_ids)) = self.orders.get(transaction, id)?;
+
+        assert_eq!(version, order_data.version.into());
+
+        order_data.updated_at = self.updated_at(transaction, &order_data, &line_item_ids);
+
+        let items_data = line_item_ids
+            .iter()
+            .filter_map(|line_item_id| self.line_items.get(transaction, *line_item_id))
+            .map(|(version, line_item_data)| {
+                assert_eq!(version, line_item_data.version.into());
+
+                line_item_data
+            })
+            .collect();
+
+        Some((order_data, items_data))
+    }
+
+    /** The last time an order or any of its line items were stored. */
+    fn updated_at(
+        &self,
+        transaction: &Transaction,
+        order_data: &OrderData,
+        line_item_ids: &HashSet<LineItemId>,
+    ) -> Option<SystemTime> {
+        line_item_ids
+            .iter()
+            .filter_map(|line_item_id| self.line_items_updated_at.get(transaction, *line_item_id))
+            .map(|(_, updated_at)| updated_at)
+            .chain(order_data.updated_at)
+            .max()
+    }
+
+    fn remove_line_item(&self, transaction: &Transaction, id: LineItemId) -> Result<(), Error> {
+        if let Some((version, _)) = self.line_items.get(transaction, id) {
+            self.line_items
+                .remove(transaction, id, version)
+                .map_err(LineItemVersion::stale_write(id))?;
+        }
+
+        if let Some((version, _)) = self.line_items_updated_at.get(transaction, id) {
+            self.line_items_updated_at
+                .remove(transaction, id, version)
+                .map_err(LineItemVersion::stale_write(id))?;
+        }
+
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Arc<HashMap<OrderId, (OrderData, Vec<Arc<LineItemData>>)>> {
+        let now = self.clock.now();
+
+        if let Some(snapshot) = &*lock::read(&self.snapshot) {
+            let fresh = now
+                .duration_since(snapshot.taken_at)
+                .map(|age| age < SNAPSHOT_MAX_AGE)
+                .unwrap_or(false);
+
+            if fresh {
+                return snapshot.orders.clone();
+            }
+        }
+
+        // The snapshot is built without holding its lock, so writers and other readers
+        // aren't blocked while it's taken
+        let committed = Transaction::none();
+        let orders: Arc<HashMap<_, _>> = Arc::new(
+            self.orders
+                .get_all(&committed, |_| true)
+                .filter_map(|(_, (order_data, _))| self.get_order_data(&committed, order_data.id))
+                .map(|(order_data, items_data)| (order_data.id, (order_data, items_data)))
+                .collect(),
+        );
+
+        *lock::write(&self.snapshot) = Some(Snapshot {
+            taken_at: now,
+            orders: orders.clone(),
+        });
+
+        orders
+    }
 }
 
+impl Flush for InMemoryStore {}
+
 impl OrderStore for InMemoryStore {
     fn get_line_item(
         &self,
+        transaction: &Transaction,
         id: OrderId,
         line_item_id: LineItemId,
     ) -> Result<Option<OrderLineItem>, Error> {
-        if let Some((version, (order_data, item_ids))) = self.orders.get(id) {
+        if let Some((version, (order_data, item_ids))) = self.orders.get(transaction, id) {
             assert_eq!(version, order_data.version.into());
 
-            // Check that the line item is part of the order
-            if !item_ids.contains(&line_item_id) {
-                return Err(error::msg("line item not found"));
-            }
-
             // Find the line item
             let (version, line_item_data) = self
                 .line_items
-                .get(line_item_id)
-                .ok_or_else(|| error::msg("line item not found"))?;
+                .get(transaction, line_item_id)
+                .ok_or_else(|| error::not_found("line item", line_item_id))?;
 
             assert_eq!(version, line_item_data.version.into());
 
-            Ok(Some(OrderLineItem::from_data(order_data, line_item_data)))
+            // The line item is rejected if it's not part of the order
+            Ok(Some(OrderLineItem::from_data(
+                order_data,
+                &item_ids,
+                super::unshare(line_item_data),
+            )?))
         } else {
             Ok(None)
         }
@@ -84,98 +335,366 @@ impl OrderStore for InMemoryStore {
         let line_item_id = order_item_data.id;
 
         // Check that the line item is part of the order
-        {
-            let (_, (_, item_ids)) = self
-                .orders
-                .get(order_id)
-                .ok_or_else(|| error::msg("order not found"))?;
-
-            if !item_ids.contains(&line_item_id) {
-                return Err(error::msg("line item not found"));
-            }
+        let (_, (_, item_ids)) = self
+            .orders
+            .get(transaction, order_id)
+            .ok_or_else(|| error::not_found("order", order_id))?;
+
+        if !item_ids.contains(&line_item_id) {
+            return Err(error::not_found("line item", line_item_id));
         }
 
-        self.line_items.set(
-            transaction,
-            line_item_id,
-            Some(order_item_data.version),
-            order_item_data.version.next(),
-            order_item_data,
-        )?;
+        let line_item_version = order_item_data.version;
+        order_item_data.version = line_item_version.next()?;
+        let new_version = order_item_data.version;
 
-        Ok(())
-    }
+        self.line_items
+            .set(
+                transaction,
+                line_item_id,
+                Some(line_item_version),
+                new_version,
+                Arc::new(order_item_data),
+            )
+            .map_err(LineItemVersion::stale_write(line_item_id))?;
 
-    fn get_order(&self, id: OrderId) -> Result<Option<Order>, Error> {
-        if let Some((version, (order_data, line_items))) = self.orders.get(id) {
-            assert_eq!(version, order_data.version.into());
+        // Changing a line item also changes when its order was updated, but not the order's version
+        // The stamp is only ever set alongside its line item, so it can't conflict on its own
+        let updated_at_version = self
+            .line_items_updated_at
+            .get(transaction, line_item_id)
+            .map(|(version, _)| version);
 
-            let items_data = self
-                .line_items
-                .get_all(|line_item| line_items.contains(&line_item.id))
-                .map(|(version, line_item_data)| {
-                    assert_eq!(version, line_item_data.version.into());
+        self.line_items_updated_at
+            .set(
+                transaction,
+                line_item_id,
+                updated_at_version,
+                new_version,
+                self.clock.now(),
+            )
+            .map_err(LineItemVersion::stale_write(line_item_id))?;
 
-                    line_item_data
-                });
+        Ok(())
+    }
 
-            Ok(Some(Order::from_data(order_data, items_data)))
-        } else {
-            Ok(None)
-        }
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error> {
+        let order = match consistency {
+            ReadConsistency::Strong => self.get_order_data(transaction, id),
+            ReadConsistency::Snapshot => self.snapshot().get(&id).cloned(),
+        };
+
+        Ok(order.map(|(order_data, items_data)| Order::from_shared_data(order_data, items_data)))
     }
 
     fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
-        let (mut order_data, line_items_data) = order.into_data();
+        let (mut order_data, line_items_data) = order.into_shared_data();
         let id = order_data.id;
-        let order_item_ids = line_items_data.iter().map(|item| item.id).collect();
+        let order_item_ids: HashSet<_> = line_items_data.iter().map(|item| item.id).collect();
+
+        let version = order_data.version;
+        order_data.version = version.next()?;
+        order_data.updated_at = Some(self.clock.now());
+
+        // Find any line items that have been taken out of the order
+        let removed_item_ids: Vec<_> = self
+            .orders
+            .get(transaction, id)
+            .map(|(_, (_, existing_item_ids))| {
+                existing_item_ids
+                    .difference(&order_item_ids)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default();
 
         // Update the order
-        self.orders.set(
-            transaction,
-            id,
-            Some(order_data.version),
-            order_data.version.next(),
-            (order_data, order_item_ids),
-        )?;
+        self.orders
+            .set(
+                transaction,
+                id,
+                Some(version),
+                order_data.version,
+                (order_data, order_item_ids),
+            )
+            .map_err(OrderVersion::stale_write(id))?;
 
         // Update each of its line items
+        // Line items that are still shared with the store are copied to change their version
         for mut line_item_data in line_items_data {
             let id = line_item_data.id;
+            let version = line_item_data.version;
 
-            self.line_items.set(
-                transaction,
-                id,
-                Some(line_item_data.version),
-                line_item_data.version.next(),
-                line_item_data,
-            )?;
+            Arc::make_mut(&mut line_item_data).version = version.next()?;
+
+            self.line_items
+                .set(
+                    transaction,
+                    id,
+                    Some(version),
+                    line_item_data.version,
+                    line_item_data,
+                )
+                .map_err(LineItemVersion::stale_write(id))?;
+        }
+
+        // Remove the line items that are no longer in the order
+        for line_item_id in removed_item_ids {
+            self.remove_line_item(transaction, line_item_id)?;
         }
 
         Ok(())
     }
+
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        Ok(self
+            .orders
+            .get(transaction, id)
+            .map(|(version, (mut order_data, line_item_ids))| {
+                assert_eq!(version, order_data.version.into());
+
+                order_data.updated_at = self.updated_at(transaction, &order_data, &line_item_ids);
+
+                order_data
+            }))
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error> {
+        if let Some((version, (_, line_item_ids))) = self.orders.get(transaction, id) {
+            // Remove the order
+            self.orders
+                .remove(transaction, id, version)
+                .map_err(OrderVersion::stale_write(id))?;
+
+            // Remove each of its line items
+            for line_item_id in line_item_ids {
+                self.remove_line_item(transaction, line_item_id)?;
+            }
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
 }
 
 impl OrderStoreFilter for InMemoryStore {
     #[allow(clippy::needless_collect)]
-    fn filter<F>(&self, predicate: F) -> Result<Iter, Error>
-    where
-        F: Fn(&OrderData) -> bool,
-    {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error> {
+        let orders: Vec<_> = self
+            .orders
+            .get_all(transaction, |_| true)
+            .map(|(_, (mut data, line_item_ids))| {
+                data.updated_at = self.updated_at(transaction, &data, &line_item_ids);
+
+                data
+            })
+            .filter(|data| predicate(data))
+            .collect();
+
+        Ok(orders.into_iter())
+    }
+
+    #[allow(clippy::needless_collect)]
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error> {
+        let contains_product = |line_item_ids: &HashSet<LineItemId>, product_id| {
+            line_item_ids.iter().any(|line_item_id| {
+                self.line_items
+                    .get(transaction, *line_item_id)
+                    .map(|(_, line_item)| line_item.product_id == product_id)
+                    .unwrap_or(false)
+            })
+        };
+
         let orders: Vec<_> = self
             .orders
-            .get_all(|(data, _)| predicate(data))
-            .map(|(_, (data, _))| data)
+            .get_all(transaction, |(data, line_item_ids)| {
+                filter
+                    .customer_id
+                    .map(|customer_id| data.customer_id == customer_id)
+                    .unwrap_or(true)
+                    && filter
+                        .contains_product
+                        .map(|product_id| contains_product(line_item_ids, product_id))
+                        .unwrap_or(true)
+            })
+            .map(|(_, (mut data, line_item_ids))| {
+                data.updated_at = self.updated_at(transaction, &data, &line_item_ids);
+
+                data
+            })
             .collect();
 
         Ok(orders.into_iter())
     }
 }
 
+#[cfg(test)]
 pub(in crate::domain) fn in_memory_store(transaction_store: TransactionStore) -> InMemoryStore {
+    in_memory_store_with_clock(transaction_store, SystemClock)
+}
+
+pub(in crate::domain) fn in_memory_store_with_clock(
+    transaction_store: TransactionStore,
+    clock: impl Clock + Send + Sync + 'static,
+) -> InMemoryStore {
     InMemoryStore {
         orders: TransactionValueStore::new(transaction_store.clone()),
-        line_items: TransactionValueStore::new(transaction_store),
+        line_items: TransactionValueStore::new(transaction_store.clone()),
+        line_items_updated_at: TransactionValueStore::new(transaction_store),
+        snapshot: RwLock::new(None),
+        clock: Arc::new(clock),
+    }
+}
+
+/**
+An in-memory order store where writers to different orders don't block each other.
+
+It keeps the same rules as `in_memory_store_with_clock`, but its orders and line items are
+locked in shards. Snapshot reads still share a single copy of all orders.
+*/
+#[cfg(feature = "concurrent")]
+pub(in crate::domain) fn concurrent_store_with_clock(
+    transaction_store: TransactionStore,
+    clock: impl Clock + Send + Sync + 'static,
+) -> InMemoryStore {
+    InMemoryStore {
+        orders: TransactionValueStore::concurrent(transaction_store.clone()),
+        line_items: TransactionValueStore::concurrent(transaction_store.clone()),
+        line_items_updated_at: TransactionValueStore::concurrent(transaction_store),
+        snapshot: RwLock::new(None),
+        clock: Arc::new(clock),
+    }
+}
+
+/**
+A test order store that fails on a given write.
+
+Reads are passed through to the wrapped store.
+Writes are counted from 1 and the one matching `fail_on` returns an error instead of reaching
+the wrapped store. Writes after it succeed again, so the store can stand in for transient
+failures like conflicts.
+*/
+#[cfg(test)]
+pub(in crate::domain) struct FailingStore<S> {
+    store: S,
+    fail_on: usize,
+    err: Box<dyn Fn(usize) -> Error + Send + Sync>,
+    writes: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(test)]
+impl<S> FailingStore<S> {
+    pub(in crate::domain) fn new(store: S, fail_on: usize) -> Self {
+        Self::with_error(store, fail_on, |write| {
+            error::msg(format!("failed on write {}", write))
+        })
+    }
+
+    /** Fail with a specific error, given the number of the write that failed. */
+    pub(in crate::domain) fn with_error(
+        store: S,
+        fail_on: usize,
+        err: impl Fn(usize) -> Error + Send + Sync + 'static,
+    ) -> Self {
+        FailingStore {
+            store,
+            fail_on,
+            err: Box::new(err),
+            writes: Default::default(),
+        }
+    }
+
+    fn write(&self) -> Result<(), Error> {
+        let write = self
+            .writes
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            + 1;
+
+        if write == self.fail_on {
+            Err((self.err)(write))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+impl<S: OrderStore> OrderStore for FailingStore<S> {
+    fn get_line_item(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        line_item_id: LineItemId,
+    ) -> Result<Option<OrderLineItem>, Error> {
+        self.store.get_line_item(transaction, id, line_item_id)
+    }
+
+    fn set_line_item(&self, transaction: &Transaction, order: OrderLineItem) -> Result<(), Error> {
+        self.write()?;
+        self.store.set_line_item(transaction, order)
+    }
+
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error> {
+        self.store.get_order(transaction, id, consistency)
+    }
+
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        self.store.get_order_header(transaction, id)
+    }
+
+    fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
+        self.write()?;
+        self.store.set_order(transaction, order)
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error> {
+        self.write()?;
+        self.store.delete_order(transaction, id)
+    }
+}
+
+#[cfg(test)]
+impl<S: Flush> Flush for FailingStore<S> {
+    fn flush(&self) -> Result<(), Error> {
+        self.store.flush()
+    }
+}
+
+#[cfg(test)]
+impl<S: OrderStoreFilter> OrderStoreFilter for FailingStore<S> {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error> {
+        self.store.filter(transaction, predicate)
+    }
+
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error> {
+        self.store.query(transaction, filter)
     }
 }
 
@@ -184,8 +703,14 @@ mod tests {
     use super::*;
 
     use crate::domain::{
-        orders::model::test_data::OrderBuilder,
-        products::model::test_data::default_product,
+        orders::model::test_data::{
+            order_data,
+            OrderBuilder,
+        },
+        products::model::test_data::{
+            default_product,
+            ProductBuilder,
+        },
     };
 
     #[test]
@@ -204,7 +729,10 @@ mod tests {
             .unwrap();
 
         // Add a product to the order
-        let mut order = store.get_order(order_id).unwrap().unwrap();
+        let mut order = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap();
         order
             .add_product(line_item_id, &default_product(), 1)
             .unwrap();
@@ -212,7 +740,7 @@ mod tests {
 
         // Update the product in the order
         let mut line_item = store
-            .get_line_item(order_id, line_item_id)
+            .get_line_item(&Transaction::none(), order_id, line_item_id)
             .unwrap()
             .unwrap();
         line_item.set_quantity(5).unwrap();
@@ -221,12 +749,41 @@ mod tests {
             .unwrap();
 
         // Get the product with the order
-        let (_, line_items) = store.get_order(order_id).unwrap().unwrap().into_data();
+        let (_, line_items) = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap()
+            .into_data();
 
         assert_eq!(1, line_items.len());
         assert_eq!(5, line_items[0].quantity);
     }
 
+    #[test]
+    fn order_data_round_trip() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let customer_id = CustomerId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                Order::from_data(order_data(order_id, customer_id), vec![]),
+            )
+            .unwrap();
+
+        let (order, line_items) = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(order_id, order.id);
+        assert_eq!(customer_id, order.customer_id);
+        assert!(line_items.is_empty());
+    }
+
     #[test]
     fn add_order_twice_fails_concurrency_check() {
         let store = in_memory_store(Default::default());
@@ -270,7 +827,7 @@ mod tests {
         // Attempting to update a line item twice fails optimistic concurrency check
         let get_item = || {
             store
-                .get_line_item(order_id, line_item_id)
+                .get_line_item(&Transaction::none(), order_id, line_item_id)
                 .unwrap()
                 .unwrap()
         };
@@ -288,4 +845,637 @@ mod tests {
             .set_line_item(&Transaction::none(), line_item_b)
             .is_err());
     }
+
+    #[test]
+    fn reads_share_line_items_until_they_change() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(default_product(), |line_item| line_item.quantity(2))
+                    .add_product(default_product(), |line_item| line_item)
+                    .build(),
+            )
+            .unwrap();
+
+        let read = |consistency| {
+            store
+                .get_order(&Transaction::none(), order_id, consistency)
+                .unwrap()
+                .unwrap()
+        };
+
+        let first = read(ReadConsistency::Strong);
+
+        // Every read hands out the same line items instead of copying them
+        for order in [
+            read(ReadConsistency::Strong),
+            read(ReadConsistency::Snapshot),
+        ] {
+            assert_eq!(2, order.line_items.len());
+
+            for line_item in &order.line_items {
+                assert!(first
+                    .line_items
+                    .iter()
+                    .any(|shared| Arc::ptr_eq(shared, line_item)));
+            }
+        }
+
+        // Changing an order copies its line items, so other readers don't see the change
+        let mut changed = read(ReadConsistency::Strong);
+        changed
+            .map_line_items(|line_item| line_item.quantity += 1)
+            .unwrap();
+
+        for line_item in &changed.line_items {
+            assert!(!first
+                .line_items
+                .iter()
+                .any(|shared| Arc::ptr_eq(shared, line_item)));
+        }
+
+        let mut quantities: Vec<_> = first.line_items.iter().map(|l| l.quantity).collect();
+        quantities.sort();
+        assert_eq!(vec![1, 2], quantities);
+
+        // The header doesn't need the line items at all
+        let header = store
+            .get_order_header(&Transaction::none(), order_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(order_id, header.id);
+        assert!(store
+            .get_order_header(&Transaction::none(), OrderId::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn delete_order_removes_line_items() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let line_item_id = LineItemId::new();
+
+        let order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(default_product(), move |line_item| {
+                line_item.id(line_item_id)
+            })
+            .build();
+
+        store.set_order(&Transaction::none(), order).unwrap();
+
+        assert!(store.delete_order(&Transaction::none(), order_id).unwrap());
+
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+        assert!(store
+            .line_items
+            .get(&Transaction::none(), line_item_id)
+            .is_none());
+
+        // Deleting the order a second time finds nothing to delete
+        assert!(!store.delete_order(&Transaction::none(), order_id).unwrap());
+    }
+
+    #[test]
+    fn cancelled_delete_order_restores_order_and_line_items() {
+        let transactions = TransactionStore::new();
+        let store = in_memory_store(transactions.clone());
+
+        let order_id = OrderId::new();
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let line_item_id = LineItemId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .customer_id(customer_id)
+                    .add_product(ProductBuilder::new().id(product_id).build(), move |l| {
+                        l.id(line_item_id)
+                    })
+                    .build(),
+            )
+            .unwrap();
+
+        let by_customer = OrderFilter {
+            customer_id: Some(customer_id),
+            ..Default::default()
+        };
+        let by_product = OrderFilter {
+            contains_product: Some(product_id),
+            ..Default::default()
+        };
+
+        let is_visible = || {
+            let found = [
+                store
+                    .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+                    .unwrap()
+                    .is_some(),
+                store
+                    .get_line_item(&Transaction::none(), order_id, line_item_id)
+                    .unwrap()
+                    .is_some(),
+                store
+                    .query(&Transaction::none(), &by_customer)
+                    .unwrap()
+                    .len()
+                    == 1,
+                store
+                    .query(&Transaction::none(), &by_product)
+                    .unwrap()
+                    .len()
+                    == 1,
+                store
+                    .filter(&Transaction::none(), &|order| order.id == order_id)
+                    .unwrap()
+                    .len()
+                    == 1,
+            ];
+
+            // The order and its line items are always seen together
+            assert!(found.iter().all(|&f| f == found[0]), "{:?}", found);
+
+            found[0]
+        };
+
+        // The delete isn't seen until it commits
+        let cancelled = transactions.begin();
+        assert!(store.delete_order(&cancelled, order_id).unwrap());
+        assert!(is_visible());
+
+        // Cancelling it leaves the order as it was
+        transactions.cancel(cancelled);
+        assert!(is_visible());
+
+        let (_, line_items) = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap()
+            .into_data();
+        assert_eq!(line_item_id, line_items[0].id);
+
+        let committed = transactions.begin();
+        assert!(store.delete_order(&committed, order_id).unwrap());
+        transactions.commit(committed).unwrap();
+        assert!(!is_visible());
+    }
+
+    #[test]
+    fn queries_see_orders_set_in_their_own_transaction() {
+        let transactions = TransactionStore::new();
+        let store = in_memory_store(transactions.clone());
+
+        let order_id = OrderId::new();
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+
+        let transaction = transactions.begin();
+
+        store
+            .set_order(
+                &transaction,
+                OrderBuilder::new()
+                    .id(order_id)
+                    .customer_id(customer_id)
+                    .add_product(ProductBuilder::new().id(product_id).build(), |l| l)
+                    .build(),
+            )
+            .unwrap();
+
+        let by_customer = OrderFilter {
+            customer_id: Some(customer_id),
+            ..Default::default()
+        };
+        let by_product = OrderFilter {
+            contains_product: Some(product_id),
+            ..Default::default()
+        };
+
+        let found = |transaction: &Transaction| {
+            [
+                store.query(transaction, &by_customer).unwrap().len(),
+                store.query(transaction, &by_product).unwrap().len(),
+                store
+                    .filter(transaction, &|order| order.id == order_id)
+                    .unwrap()
+                    .len(),
+            ]
+        };
+
+        // The transaction sees its own order, but nothing else does until it commits
+        assert_eq!([1, 1, 1], found(&transaction));
+        assert_eq!([0, 0, 0], found(&Transaction::none()));
+
+        transactions.commit(transaction).unwrap();
+        assert_eq!([1, 1, 1], found(&Transaction::none()));
+    }
+
+    #[test]
+    fn concurrency_check_carries_versions() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap_err();
+
+        let conflict = err.version_conflict().unwrap();
+
+        assert_eq!("order", conflict.entity);
+        assert_eq!(order_id.to_string(), conflict.id);
+        assert_eq!(0, conflict.expected);
+        assert_eq!(1, conflict.actual);
+    }
+
+    #[test]
+    fn concurrency_check_keeps_store_error_as_source() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap_err();
+
+        // Walk the chain of errors down to the one from the value store
+        let chain: Vec<_> =
+            std::iter::successors(Some(&err as &(dyn std::error::Error + 'static)), |err| {
+                err.source()
+            })
+            .collect();
+
+        assert_eq!(2, chain.len());
+        assert!(chain[1].downcast_ref::<VersionMismatch>().is_some());
+
+        // The store error isn't part of the message
+        assert!(!err.to_string().contains(&chain[1].to_string()));
+    }
+
+    #[test]
+    fn query_combines_filter_fields() {
+        let store = in_memory_store(Default::default());
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let product = || ProductBuilder::new().id(product_id).build();
+
+        let set_order = |order: Order| {
+            let (data, _) = order.to_data();
+            let id = data.id;
+
+            store.set_order(&Transaction::none(), order).unwrap();
+
+            id
+        };
+
+        let matching = set_order(
+            OrderBuilder::new()
+                .customer_id(customer_id)
+                .add_product(product(), |line_item| line_item)
+                .build(),
+        );
+        let without_product = set_order(OrderBuilder::new().customer_id(customer_id).build());
+        let other_customer = set_order(
+            OrderBuilder::new()
+                .add_product(product(), |line_item| line_item)
+                .build(),
+        );
+
+        let query = |filter| {
+            let mut ids: Vec<_> = store
+                .query(&Transaction::none(), &filter)
+                .unwrap()
+                .map(|o| o.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            vec![matching],
+            query(OrderFilter {
+                customer_id: Some(customer_id),
+                contains_product: Some(product_id),
+            })
+        );
+
+        let mut by_customer = vec![matching, without_product];
+        by_customer.sort();
+
+        assert_eq!(
+            by_customer,
+            query(OrderFilter {
+                customer_id: Some(customer_id),
+                ..Default::default()
+            })
+        );
+
+        let mut by_product = vec![matching, other_customer];
+        by_product.sort();
+
+        assert_eq!(
+            by_product,
+            query(OrderFilter {
+                contains_product: Some(product_id),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(3, query(OrderFilter::default()).len());
+    }
+
+    #[test]
+    fn set_order_removes_line_items_taken_out_of_order() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+        let line_item_id = LineItemId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(
+                        ProductBuilder::new().id(product_id).build(),
+                        move |line_item| line_item.id(line_item_id),
+                    )
+                    .build(),
+            )
+            .unwrap();
+
+        let mut order = store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap();
+        order.take_product(product_id, 1).unwrap();
+        store.set_order(&Transaction::none(), order).unwrap();
+
+        assert!(store
+            .line_items
+            .get(&Transaction::none(), line_item_id)
+            .is_none());
+    }
+
+    #[test]
+    fn failing_store_fails_on_nth_write() {
+        let store = FailingStore::new(in_memory_store(Default::default()), 2);
+
+        let first = OrderId::new();
+        let second = OrderId::new();
+
+        store
+            .set_order(&Transaction::none(), OrderBuilder::new().id(first).build())
+            .unwrap();
+
+        assert!(store
+            .set_order(&Transaction::none(), OrderBuilder::new().id(second).build(),)
+            .is_err());
+
+        assert!(store
+            .get_order(&Transaction::none(), first, ReadConsistency::Strong)
+            .unwrap()
+            .is_some());
+        assert!(store
+            .get_order(&Transaction::none(), second, ReadConsistency::Strong)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn snapshot_reads_are_refreshed_after_max_age() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Arc::new(FixedClock::new(start));
+
+        let store = in_memory_store_with_clock(Default::default(), clock.clone());
+
+        let order_id = OrderId::new();
+
+        // Take a snapshot before the order exists
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Snapshot)
+            .unwrap()
+            .is_none());
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        // Strong reads see the latest write, but snapshot reads don't yet
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+            .unwrap()
+            .is_some());
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Snapshot)
+            .unwrap()
+            .is_none());
+
+        clock.set(start + SNAPSHOT_MAX_AGE);
+
+        assert!(store
+            .get_order(&Transaction::none(), order_id, ReadConsistency::Snapshot)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn set_line_item_stamps_order_without_changing_its_version() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Arc::new(FixedClock::new(start));
+
+        let store = in_memory_store_with_clock(Default::default(), clock.clone());
+
+        let order_id = OrderId::new();
+        let line_item_id = LineItemId::new();
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(default_product(), move |line_item| {
+                        line_item.id(line_item_id)
+                    })
+                    .build(),
+            )
+            .unwrap();
+
+        let before = store
+            .get_order_header(&Transaction::none(), order_id)
+            .unwrap()
+            .unwrap();
+
+        let later = start + Duration::from_secs(10);
+        clock.set(later);
+
+        let mut line_item = store
+            .get_line_item(&Transaction::none(), order_id, line_item_id)
+            .unwrap()
+            .unwrap();
+        line_item.set_quantity(3).unwrap();
+        store
+            .set_line_item(&Transaction::none(), line_item)
+            .unwrap();
+
+        let after = store
+            .get_order_header(&Transaction::none(), order_id)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(before.version, after.version);
+        assert_eq!(Some(start), before.updated_at);
+        assert_eq!(Some(later), after.updated_at);
+
+        assert_eq!(
+            1,
+            store
+                .filter(&Transaction::none(), &|order| order.updated_at
+                    == Some(later))
+                .unwrap()
+                .count()
+        );
+    }
+
+    #[test]
+    fn snapshot_reads_do_not_block_writers() {
+        let store = Arc::new(in_memory_store(Default::default()));
+
+        let order_ids: Vec<_> = (0..100).map(|_| OrderId::new()).collect();
+
+        let writer = {
+            let store = store.clone();
+            let order_ids = order_ids.clone();
+
+            std::thread::spawn(move || {
+                for order_id in order_ids {
+                    store
+                        .set_order(
+                            &Transaction::none(),
+                            OrderBuilder::new().id(order_id).build(),
+                        )
+                        .unwrap();
+                }
+            })
+        };
+
+        for order_id in &order_ids {
+            store
+                .get_order(&Transaction::none(), *order_id, ReadConsistency::Snapshot)
+                .unwrap();
+        }
+
+        writer.join().unwrap();
+
+        for order_id in order_ids {
+            assert!(store
+                .get_order(&Transaction::none(), order_id, ReadConsistency::Strong)
+                .unwrap()
+                .is_some());
+        }
+    }
+
+    #[cfg(feature = "concurrent")]
+    #[test]
+    fn concurrent_store_keeps_orders_written_from_many_threads() {
+        const THREADS: usize = 8;
+        const ORDERS: usize = 50;
+
+        let store = concurrent_store_with_clock(Default::default(), SystemClock);
+
+        let ids: Vec<Vec<OrderId>> = (0..THREADS)
+            .map(|_| (0..ORDERS).map(|_| OrderId::new()).collect())
+            .collect();
+
+        std::thread::scope(|scope| {
+            for ids in &ids {
+                let store = &store;
+
+                scope.spawn(move || {
+                    for id in ids {
+                        store
+                            .set_order(
+                                &Transaction::none(),
+                                OrderBuilder::new()
+                                    .id(*id)
+                                    .add_product(default_product(), |l| l.quantity(1))
+                                    .build(),
+                            )
+                            .unwrap();
+
+                        // Update the order from the version that was just stored
+                        let mut order = store
+                            .get_order(&Transaction::none(), *id, ReadConsistency::Strong)
+                            .unwrap()
+                            .unwrap();
+                        order
+                            .map_line_items(|line_item| line_item.quantity = 2)
+                            .unwrap();
+
+                        store.set_order(&Transaction::none(), order).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            THREADS * ORDERS,
+            store
+                .filter(&Transaction::none(), &|_| true)
+                .unwrap()
+                .count()
+        );
+
+        for id in ids.iter().flatten() {
+            let (_, line_items) = store
+                .get_order(&Transaction::none(), *id, ReadConsistency::Strong)
+                .unwrap()
+                .unwrap()
+                .into_data();
+
+            assert_eq!(
+                vec![2],
+                line_items.iter().map(|l| l.quantity).collect::<Vec<_>>()
+            );
+        }
+    }
 }
\ No newline at end of file
@@ -1,5 +1,10 @@
+use std::sync::Arc;
+
 use crate::domain::{
-    customers::model::test_data::default_customer,
+    customers::{
+        model::test_data::default_customer,
+        CustomerId,
+    },
     orders::*,
     products::*,
 };
@@ -8,6 +13,49 @@ pub fn default_order() -> Order {
     Order::new(NextOrderId::new(), &default_customer()).unwrap()
 }
 
+/**
+Data for an order with a known id and customer.
+
+This doesn't go through an `IdProvider`, so it's useful for checking how stores
+handle data without needing a customer.
+*/
+pub fn order_data(id: OrderId, customer_id: CustomerId) -> OrderData {
+    OrderData {
+        id,
+        version: OrderVersion::default(),
+        customer_id,
+        updated_at: None,
+        placed_prices_frozen: false,
+        status_history: vec![],
+        recipient: None,
+        number: None,
+        client_token: None,
+        payment_ref: None,
+        _private: (),
+    }
+}
+
+/**
+Add another line item for a product that's already in an order.
+
+Orders don't allow this themselves, so it's useful for checking how bad data is handled.
+*/
+pub fn duplicate_product(order: &mut Order, id: LineItemId, product_id: ProductId, quantity: u32) {
+    let mut line_item = LineItemData::clone(
+        order
+            .line_items
+            .iter()
+            .find(|line_item| line_item.product_id == product_id)
+            .expect("product is not in order"),
+    );
+
+    line_item.id = id;
+    line_item.version = LineItemVersion::default();
+    line_item.quantity = quantity;
+
+    order.line_items.push(Arc::new(line_item));
+}
+
 pub struct OrderBuilder {
     order: Order,
     line_items: Vec<(
@@ -35,6 +83,16 @@ impl OrderBuilder {
         self
     }
 
+    pub fn customer_id(mut self, id: CustomerId) -> Self {
+        self.order.order.customer_id = id;
+        self
+    }
+
+    pub fn recipient(mut self, id: CustomerId) -> Self {
+        self.order.order.recipient = Some(id);
+        self
+    }
+
     pub fn add_product<F>(mut self, product: Product, builder: F) -> Self
     where
         F: Fn(OrderLineItemBuilder) -> OrderLineItemBuilder + 'static,
@@ -48,13 +106,16 @@ impl OrderBuilder {
             self.order
                 .add_product(NextLineItemId::new(), &product, 1)
                 .unwrap();
-            let line_item = self.order.line_items.pop().unwrap();
+            let line_item = super::unshare(self.order.line_items.pop().unwrap());
 
             let line_item = builder(OrderLineItemBuilder {
-                line_item: OrderLineItem::from_data(self.order.order.clone(), line_item),
+                line_item: OrderLineItem {
+                    order: self.order.order.clone(),
+                    line_item,
+                },
             });
 
-            self.order.line_items.push(line_item.build());
+            self.order.line_items.push(Arc::new(line_item.build()));
         }
         self.order
     }
@@ -75,6 +136,11 @@ impl OrderLineItemBuilder {
         self
     }
 
+    pub fn discount_eligible(mut self, eligible: bool) -> Self {
+        self.line_item.set_discount_eligible(eligible);
+        self
+    }
+
     fn build(self) -> LineItemData {
         self.line_item.into_data().1
     }
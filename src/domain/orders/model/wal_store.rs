@@ -0,0 +1,334 @@
+/*!
+Order storage that logs changes, so orders survive restarts without a database.
+
+Orders are kept in another store, usually an in-memory one, and each change is logged before
+it's made there. See `infra::wal` for how the log is written and replayed.
+
+Replayed orders keep their versions, but their `updated_at` is when they were replayed.
+*/
+
+use std::path::Path;
+
+use crate::{
+    domain::{
+        error,
+        infra::wal::{
+            self,
+            WalChange,
+            WalStore,
+        },
+        orders::{
+            model::store::*,
+            *,
+        },
+        Error,
+    },
+    store::Transaction,
+};
+
+/** A change to orders that's written to the log. */
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", content = "payload", rename_all = "snake_case")]
+pub(in crate::domain) enum OrderChange {
+    SetOrder {
+        order: OrderData,
+        line_items: Vec<LineItemData>,
+    },
+    SetLineItem {
+        order_id: OrderId,
+        line_item: LineItemData,
+    },
+    DeleteOrder {
+        id: OrderId,
+    },
+}
+
+/** An order store that logs each change before making it. */
+pub(in crate::domain) type OrderWalStore<S> = WalStore<S, OrderChange>;
+
+impl<S> WalChange<S> for OrderChange
+where
+    S: OrderStore + OrderStoreFilter,
+{
+    fn apply(self, store: &S) -> Result<(), Error> {
+        let transaction = Transaction::none();
+
+        match self {
+            OrderChange::SetOrder { order, line_items } => {
+                store.set_order(&transaction, Order::checked(order, line_items)?)
+            }
+            OrderChange::SetLineItem {
+                order_id,
+                line_item,
+            } => {
+                let order = store
+                    .get_order_header(&transaction, order_id)?
+                    .ok_or_else(|| error::not_found("order", order_id))?;
+
+                store.set_line_item(&transaction, OrderLineItem { order, line_item })
+            }
+            OrderChange::DeleteOrder { id } => store.delete_order(&transaction, id).map(|_| ()),
+        }
+    }
+
+    fn snapshot(store: &S) -> Result<Vec<Self>, Error> {
+        let mut orders = vec![];
+
+        for order in store.filter(&Transaction::none(), &|_| true)? {
+            let order =
+                match store.get_order(&Transaction::none(), order.id, ReadConsistency::Strong)? {
+                    Some(order) => order,
+                    None => continue,
+                };
+
+            // Setting an order from its previous versions brings it back to its current ones
+            let (mut order, mut line_items) = order.into_data();
+
+            order.version = order.version.previous();
+            for line_item in &mut line_items {
+                line_item.version = line_item.version.previous();
+            }
+
+            orders.push(OrderChange::SetOrder { order, line_items });
+        }
+
+        Ok(orders)
+    }
+}
+
+/** Replay the order changes logged at `path` into a store, returning how many there were. */
+pub(in crate::domain) fn replay<S>(path: impl AsRef<Path>, store: &S) -> Result<usize, Error>
+where
+    S: OrderStore + OrderStoreFilter,
+{
+    wal::replay::<_, OrderChange>(path, store)
+}
+
+impl<S> OrderStore for OrderWalStore<S>
+where
+    S: OrderStore + OrderStoreFilter,
+{
+    fn get_line_item(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        line_item_id: LineItemId,
+    ) -> Result<Option<OrderLineItem>, Error> {
+        self.store().get_line_item(transaction, id, line_item_id)
+    }
+
+    fn set_line_item(&self, transaction: &Transaction, order: OrderLineItem) -> Result<(), Error> {
+        let change = OrderChange::SetLineItem {
+            order_id: order.order.id,
+            line_item: order.line_item.clone(),
+        };
+
+        self.change(transaction, change, |store| {
+            store.set_line_item(transaction, order)
+        })
+    }
+
+    fn get_order(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+        consistency: ReadConsistency,
+    ) -> Result<Option<Order>, Error> {
+        self.store().get_order(transaction, id, consistency)
+    }
+
+    fn get_order_header(
+        &self,
+        transaction: &Transaction,
+        id: OrderId,
+    ) -> Result<Option<OrderData>, Error> {
+        self.store().get_order_header(transaction, id)
+    }
+
+    fn set_order(&self, transaction: &Transaction, order: Order) -> Result<(), Error> {
+        let (order_data, line_items_data) = order.to_data();
+
+        let change = OrderChange::SetOrder {
+            order: order_data.clone(),
+            line_items: line_items_data
+                .iter()
+                .map(|line_item| LineItemData::clone(line_item))
+                .collect(),
+        };
+
+        self.change(transaction, change, |store| {
+            store.set_order(transaction, order)
+        })
+    }
+
+    fn delete_order(&self, transaction: &Transaction, id: OrderId) -> Result<bool, Error> {
+        self.change(transaction, OrderChange::DeleteOrder { id }, |store| {
+            store.delete_order(transaction, id)
+        })
+    }
+}
+
+impl<S> OrderStoreFilter for OrderWalStore<S>
+where
+    S: OrderStoreFilter,
+{
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&OrderData) -> bool,
+    ) -> Result<Iter, Error> {
+        self.store().filter(transaction, predicate)
+    }
+
+    fn query(&self, transaction: &Transaction, filter: &OrderFilter) -> Result<Iter, Error> {
+        self.store().query(transaction, filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{
+            self,
+            OpenOptions,
+        },
+        path::PathBuf,
+    };
+
+    use super::*;
+
+    use crate::{
+        domain::{
+            infra::Flush,
+            orders::model::test_data::OrderBuilder,
+            products::{
+                model::test_data::ProductBuilder,
+                ProductId,
+            },
+        },
+        store::TransactionStore,
+    };
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("shop-orders-{}.wal", OrderId::new()))
+    }
+
+    /** The parts of an order that are kept when it's replayed. */
+    fn summary(store: &impl OrderStore, id: OrderId) -> Option<(u64, Vec<(ProductId, u32, u64)>)> {
+        store
+            .get_order(&Transaction::none(), id, ReadConsistency::Strong)
+            .unwrap()
+            .map(|order| {
+                let (order, mut line_items) = order.into_data();
+
+                line_items.sort_by_key(|line_item| line_item.product_id);
+
+                (
+                    order.version.value(),
+                    line_items
+                        .into_iter()
+                        .map(|line_item| {
+                            (
+                                line_item.product_id,
+                                line_item.quantity,
+                                line_item.version.value(),
+                            )
+                        })
+                        .collect(),
+                )
+            })
+    }
+
+    #[test]
+    fn replay_restores_committed_orders() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store = OrderWalStore::open(
+            &path,
+            in_memory_store(transactions.clone()),
+            transactions.clone(),
+        )
+        .unwrap();
+
+        let kept_id = OrderId::new();
+        let deleted_id = OrderId::new();
+        let cancelled_id = OrderId::new();
+        let torn_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        for id in [kept_id, deleted_id] {
+            store
+                .set_order(
+                    &Transaction::none(),
+                    OrderBuilder::new()
+                        .id(id)
+                        .add_product(ProductBuilder::new().id(product_id).build(), |l| l)
+                        .add_product(ProductBuilder::new().build(), |l| l)
+                        .build(),
+                )
+                .unwrap();
+        }
+
+        let order = store
+            .get_order(&Transaction::none(), kept_id, ReadConsistency::Strong)
+            .unwrap()
+            .unwrap();
+        let mut line_item = match order.into_line_item_for_product(product_id) {
+            IntoLineItem::InOrder(line_item) => line_item,
+            IntoLineItem::NotInOrder(_) => panic!("expected the product to be in the order"),
+        };
+        line_item.set_quantity(3).unwrap();
+        store
+            .set_line_item(&Transaction::none(), line_item)
+            .unwrap();
+
+        store
+            .delete_order(&Transaction::none(), deleted_id)
+            .unwrap();
+
+        let transaction = transactions.begin();
+        store
+            .set_order(&transaction, OrderBuilder::new().id(cancelled_id).build())
+            .unwrap();
+        transactions.cancel(transaction);
+
+        store
+            .set_order(
+                &Transaction::none(),
+                OrderBuilder::new().id(torn_id).build(),
+            )
+            .unwrap();
+
+        // Simulate a crash part way through logging that the last order's change committed
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let replayed = in_memory_store(Default::default());
+
+        assert_eq!(4, replay(&path, &replayed).unwrap());
+
+        assert!(summary(&store, kept_id).is_some());
+        assert_eq!(summary(&store, kept_id), summary(&replayed, kept_id));
+
+        assert_eq!(None, summary(&replayed, deleted_id));
+        assert_eq!(None, summary(&replayed, cancelled_id));
+        assert_eq!(None, summary(&replayed, torn_id));
+
+        // Replaying a compacted log gives the same orders
+        store.flush().unwrap();
+
+        let compacted = in_memory_store(Default::default());
+        replay(&path, &compacted).unwrap();
+
+        assert_eq!(summary(&store, kept_id), summary(&compacted, kept_id));
+        assert_eq!(summary(&store, torn_id), summary(&compacted, torn_id));
+
+        fs::remove_file(path).unwrap();
+    }
+}
\ No newline at end of file
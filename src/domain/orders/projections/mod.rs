@@ -0,0 +1,5 @@
+/*! Projections of orders into read models. */
+
+mod order_summary;
+
+pub use self::order_summary::*;
\ No newline at end of file
@@ -0,0 +1,314 @@
+/*! Contains the `OrderSummaryProjection` type. */
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        RwLock,
+    },
+};
+
+use crate::domain::{
+    events::*,
+    infra::*,
+    orders::*,
+    products::*,
+};
+
+/** A summary of an order, projected from the events published about it. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProjectedOrderSummary {
+    /** The total quantity of products in the order. */
+    pub item_count: u64,
+    /** The total price of the order, capped at the largest value that can be represented. */
+    pub total: Currency,
+    pub status: OrderStatus,
+}
+
+struct ProjectedOrder {
+    status: OrderStatus,
+    line_items: HashMap<ProductId, (Currency, u32)>,
+}
+
+impl ProjectedOrder {
+    fn summary(&self) -> ProjectedOrderSummary {
+        let (item_count, total) = self.line_items.values().fold(
+            (0, Currency::usd(0)),
+            |(item_count, total), &(price, quantity)| {
+                (
+                    item_count + u64::from(quantity),
+                    total.saturating_add(price.saturating_mul(quantity)),
+                )
+            },
+        );
+
+        ProjectedOrderSummary {
+            item_count,
+            total,
+            status: self.status,
+        }
+    }
+}
+
+/**
+A read model of order summaries that's kept up to date by domain events.
+
+Summaries can be read without touching the order store, so they're cheap enough for dashboards.
+Only orders created after the projection subscribed to the bus are tracked, so it should be
+subscribed before any commands are executed. Clones of the projection share the same summaries.
+*/
+#[derive(Clone, Default)]
+pub struct OrderSummaryProjection {
+    orders: Arc<RwLock<HashMap<OrderId, ProjectedOrder>>>,
+}
+
+impl OrderSummaryProjection {
+    /** Create a projection that's updated by events published to the given bus. */
+    pub fn subscribe(events: &Events) -> Self {
+        let projection = OrderSummaryProjection::default();
+
+        // Hold a weak reference so subscribing doesn't keep the projection alive
+        let orders = Arc::downgrade(&projection.orders);
+
+        events.subscribe(move |event| {
+            if let Some(orders) = orders.upgrade() {
+                OrderSummaryProjection { orders }.apply(&event);
+            }
+        });
+
+        projection
+    }
+
+    /** Get the summary of an order, if it's been created and not deleted. */
+    pub fn get(&self, id: OrderId) -> Option<ProjectedOrderSummary> {
+        self.orders
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(ProjectedOrder::summary)
+    }
+
+    fn apply(&self, event: &DomainEvent) {
+        let mut orders = self.orders.write().unwrap();
+
+        match *event {
+            DomainEvent::OrderCreated { id, .. } => {
+                orders.insert(
+                    id,
+                    ProjectedOrder {
+                        status: OrderStatus::Open,
+                        line_items: HashMap::new(),
+                    },
+                );
+            }
+            DomainEvent::OrderProductSet {
+                id,
+                product_id,
+                price,
+                quantity,
+            } => {
+                if let Some(order) = orders.get_mut(&id) {
+                    order.line_items.insert(product_id, (price, quantity));
+                }
+            }
+            DomainEvent::OrderProductRemoved { id, product_id } => {
+                if let Some(order) = orders.get_mut(&id) {
+                    order.line_items.remove(&product_id);
+                }
+            }
+            DomainEvent::OrderPlaced { id } => {
+                if let Some(order) = orders.get_mut(&id) {
+                    order.status = OrderStatus::Placed;
+                }
+            }
+            DomainEvent::OrderPaid { id } => {
+                if let Some(order) = orders.get_mut(&id) {
+                    order.status = OrderStatus::Paid;
+                }
+            }
+            DomainEvent::OrderDeleted { id } => {
+                orders.remove(&id);
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        Error,
+    };
+
+    fn summary(item_count: u64, cents: u64, status: OrderStatus) -> Option<ProjectedOrderSummary> {
+        Some(ProjectedOrderSummary {
+            item_count,
+            total: Currency::usd(cents),
+            status,
+        })
+    }
+
+    #[test]
+    fn summary_follows_events() {
+        let projection = OrderSummaryProjection::default();
+
+        let id = OrderId::new();
+        let apple = ProductId::new();
+        let pear = ProductId::new();
+
+        let product_set = |product_id, cents, quantity| DomainEvent::OrderProductSet {
+            id,
+            product_id,
+            price: Currency::usd(cents),
+            quantity,
+        };
+
+        assert_eq!(None, projection.get(id));
+
+        projection.apply(&DomainEvent::OrderCreated {
+            id,
+            customer_id: CustomerId::new(),
+        });
+        assert_eq!(summary(0, 0, OrderStatus::Open), projection.get(id));
+
+        projection.apply(&product_set(apple, 100, 2));
+        projection.apply(&product_set(pear, 150, 1));
+        assert_eq!(summary(3, 350, OrderStatus::Open), projection.get(id));
+
+        // Setting a product that's already in the order replaces it rather than adding to it
+        projection.apply(&product_set(apple, 100, 5));
+        assert_eq!(summary(6, 650, OrderStatus::Open), projection.get(id));
+
+        projection.apply(&DomainEvent::OrderProductRemoved {
+            id,
+            product_id: pear,
+        });
+        assert_eq!(summary(5, 500, OrderStatus::Open), projection.get(id));
+
+        projection.apply(&DomainEvent::OrderPlaced { id });
+        assert_eq!(summary(5, 500, OrderStatus::Placed), projection.get(id));
+
+        projection.apply(&DomainEvent::OrderPaid { id });
+        assert_eq!(summary(5, 500, OrderStatus::Paid), projection.get(id));
+
+        projection.apply(&DomainEvent::OrderDeleted { id });
+        assert_eq!(None, projection.get(id));
+    }
+
+    #[test]
+    fn events_for_unknown_orders_are_ignored() {
+        let projection = OrderSummaryProjection::default();
+
+        let id = OrderId::new();
+
+        projection.apply(&DomainEvent::OrderProductSet {
+            id,
+            product_id: ProductId::new(),
+            price: Currency::usd(100),
+            quantity: 1,
+        });
+        projection.apply(&DomainEvent::OrderPlaced { id });
+
+        assert_eq!(None, projection.get(id));
+    }
+
+    #[tokio::test]
+    async fn projection_follows_commands() {
+        let app = App::new();
+        let projection = OrderSummaryProjection::subscribe(&app.events());
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let from_order = OrderId::new();
+        let to_order = OrderId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id: product_id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        // Orders are created separately because they need to read the committed customer
+        app.transaction(|resolver| async move {
+            for id in [from_order, to_order] {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id,
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await?;
+            }
+
+            Ok::<_, Error>(())
+        })
+        .await
+        .unwrap();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .add_or_update_product_command()
+                .execute(AddOrUpdateProduct {
+                    id: from_order,
+                    product_id,
+                    quantity: 3,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary(3, 300, OrderStatus::Open),
+            projection.get(from_order)
+        );
+
+        app.transaction(|resolver| async move {
+            resolver
+                .move_line_item_command()
+                .execute(MoveLineItem {
+                    from_order,
+                    to_order,
+                    product_id,
+                    quantity: 2,
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary(1, 100, OrderStatus::Open),
+            projection.get(from_order)
+        );
+        assert_eq!(summary(2, 200, OrderStatus::Open), projection.get(to_order));
+
+        app.transaction(|resolver| async move {
+            resolver
+                .delete_order_command()
+                .execute(DeleteOrder { id: from_order })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(None, projection.get(from_order));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,220 @@
+/*! Contains the `CanPlaceOrderQuery` type. */
+
+use crate::domain::{
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `CanPlaceOrderQuery`. */
+#[derive(Deserialize)]
+pub struct CanPlaceOrder {
+    pub order_id: OrderId,
+}
+
+/**
+Whether an order can be placed.
+
+If it can't then `reasons` describes everything that's stopping it.
+*/
+#[derive(Serialize)]
+pub struct CanPlace {
+    pub ok: bool,
+    pub reasons: Vec<String>,
+}
+
+impl QueryArgs for CanPlaceOrder {
+    type Output = Result<Option<CanPlace>, Error>;
+}
+
+/** Default implementation for a `CanPlaceOrderQuery`. */
+async fn execute(
+    query: CanPlaceOrder,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    products_query: impl Query<GetProductSummaries>,
+) -> Result<Option<CanPlace>, Error> {
+    let order =
+        match store.get_order(transaction.read(), query.order_id, ReadConsistency::Strong)? {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+
+    let mut reasons = vec![];
+
+    // Use the same rules as placing the order
+    if let Err(err) = order.check_can_place() {
+        reasons.push(err.to_string());
+    }
+
+    if let Err(errors) = order.validate() {
+        reasons.extend(errors.iter().map(|err| err.to_string()));
+    }
+
+    let (_, line_items) = order.to_data();
+
+    let products = {
+        let product_ids = line_items.iter().map(|l| l.product_id).collect();
+        products_query.execute(GetProductSummaries {
+            ids: product_ids,
+            include_inactive: false,
+        })
+    }
+    .await?;
+
+    for line_item in line_items {
+        if !products.iter().any(|p| p.id == line_item.product_id) {
+            reasons.push(format!(
+                "product `{}` is no longer available",
+                line_item.product_id
+            ));
+        }
+    }
+
+    Ok(Some(CanPlace {
+        ok: reasons.is_empty(),
+        reasons,
+    }))
+}
+
+impl Resolver {
+    /** Check whether an order can be placed, without placing it. */
+    pub fn can_place_order_query(&self) -> impl Query<CanPlaceOrder> {
+        self.query(|resolver, query: CanPlaceOrder| async move {
+            let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
+            let products_query = resolver.get_product_summaries_query();
+
+            execute(query, transaction, store, products_query).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn order_can_be_placed() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new()
+                    .id(order_id)
+                    .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                        line_item
+                    })
+                    .build(),
+            )
+            .unwrap();
+
+        let products_query = move |_| async move {
+            Ok(vec![ProductSummary {
+                id: product_id,
+                title: "A test product".to_owned(),
+                price: Currency::usd(100),
+            }])
+        };
+
+        let can_place = execute(
+            CanPlaceOrder { order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            products_query,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(can_place.ok);
+        assert!(can_place.reasons.is_empty());
+    }
+
+    #[tokio::test]
+    async fn order_can_not_be_placed() {
+        let store = in_memory_store(Default::default());
+
+        let empty_id = OrderId::new();
+        let missing_product_id = OrderId::new();
+        let placed_id = OrderId::new();
+
+        let product_id = ProductId::new();
+
+        let mut placed = OrderBuilder::new().id(placed_id).build();
+        placed.place(SystemClock).unwrap();
+
+        for order in [
+            OrderBuilder::new().id(empty_id).build(),
+            OrderBuilder::new()
+                .id(missing_product_id)
+                .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                    line_item
+                })
+                .build(),
+            placed,
+        ] {
+            store
+                .set_order(ActiveTransaction::auto_commit().get(), order)
+                .unwrap();
+        }
+
+        let can_place = |order_id| {
+            execute(
+                CanPlaceOrder { order_id },
+                ActiveTransaction::auto_commit(),
+                &store,
+                |_| async { Ok(vec![]) },
+            )
+        };
+
+        let empty = can_place(empty_id).await.unwrap().unwrap();
+
+        assert!(!empty.ok);
+        assert_eq!(vec!["order has no line items".to_owned()], empty.reasons);
+
+        let missing_product = can_place(missing_product_id).await.unwrap().unwrap();
+
+        assert!(!missing_product.ok);
+        assert_eq!(1, missing_product.reasons.len());
+        assert!(missing_product.reasons[0].contains(&product_id.to_string()));
+
+        // An order can have more than one reason it can't be placed
+        let placed = can_place(placed_id).await.unwrap().unwrap();
+
+        assert!(!placed.ok);
+        assert_eq!(2, placed.reasons.len());
+        assert!(placed.reasons[0].contains("already been placed"));
+    }
+
+    #[tokio::test]
+    async fn none_if_not_found() {
+        let store = in_memory_store(Default::default());
+
+        let can_place = execute(
+            CanPlaceOrder {
+                order_id: OrderId::new(),
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            |_| async { Ok(vec![]) },
+        )
+        .await
+        .unwrap();
+
+        assert!(can_place.is_none());
+    }
+}
\ No newline at end of file
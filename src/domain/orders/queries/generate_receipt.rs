@@ -0,0 +1,255 @@
+/*! Contains the `GenerateReceiptQuery` type. */
+
+use std::time::SystemTime;
+
+use crate::domain::{
+    customers::*,
+    error,
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `GenerateReceiptQuery`. */
+#[derive(Deserialize)]
+pub struct GenerateReceipt {
+    pub order_id: OrderId,
+}
+
+/** A printable receipt for a placed order. */
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub order_id: OrderId,
+    pub number: Option<OrderNumber>,
+    pub customer_id: CustomerId,
+    /** The customer the order ships to, if it's a gift for someone other than the purchaser. */
+    pub recipient_id: Option<CustomerId>,
+    pub placed_at: SystemTime,
+    /** The line items in the order, sorted by product. */
+    pub line_items: Vec<ReceiptLineItem>,
+    pub total: Currency,
+}
+
+/**
+A line item on a receipt.
+
+The price is the one the customer paid, which was frozen when the order was placed. If the product
+can't be found anymore then its title will be `None`.
+*/
+#[derive(Debug, Serialize)]
+pub struct ReceiptLineItem {
+    pub product_id: ProductId,
+    pub title: Option<String>,
+    pub price: Currency,
+    pub quantity: u32,
+    pub subtotal: Currency,
+}
+
+impl QueryArgs for GenerateReceipt {
+    type Output = Result<Receipt, Error>;
+}
+
+/** Default implementation for a `GenerateReceiptQuery`. */
+async fn execute(
+    query: GenerateReceipt,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    products_query: impl Query<GetProductSummaries>,
+) -> Result<Receipt, Error> {
+    let order = store
+        .get_order(transaction.read(), query.order_id, ReadConsistency::Strong)?
+        .ok_or_else(|| error::not_found("order", query.order_id))?;
+
+    if !matches!(order.status(), OrderStatus::Placed | OrderStatus::Paid) {
+        return Err(error::bad_input(format!(
+            "order `{}` hasn't been placed, so there's no receipt for it",
+            query.order_id
+        )));
+    }
+
+    let total = order.total(0)?;
+    let (order, line_items) = order.into_data();
+
+    let placed_at = order
+        .status_history
+        .iter()
+        .rev()
+        .find(|change| change.to == OrderStatus::Placed)
+        .map(|change| change.at)
+        .ok_or_else(|| error::msg(format!("order `{}` has no placed status", order.id)))?;
+
+    let products = products_query
+        .execute(GetProductSummaries {
+            ids: line_items.iter().map(|l| l.product_id).collect(),
+            include_inactive: true,
+        })
+        .await?;
+
+    let mut line_items: Vec<_> = line_items
+        .into_iter()
+        .map(|line_item| ReceiptLineItem {
+            product_id: line_item.product_id,
+            title: products
+                .iter()
+                .find(|p| p.id == line_item.product_id)
+                .map(|p| p.title.to_owned()),
+            price: line_item.price,
+            quantity: line_item.quantity,
+            subtotal: line_item.price.saturating_mul(line_item.quantity),
+        })
+        .collect();
+
+    line_items.sort_by_key(|line_item| line_item.product_id);
+
+    Ok(Receipt {
+        order_id: order.id,
+        number: order.number,
+        customer_id: order.customer_id,
+        recipient_id: order.recipient,
+        placed_at,
+        line_items,
+        total,
+    })
+}
+
+impl Resolver {
+    /** Generate a printable receipt for a placed order. */
+    pub fn generate_receipt_query(&self) -> impl Query<GenerateReceipt> {
+        self.query(|resolver, query: GenerateReceipt| async move {
+            let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
+            let products_query = resolver.get_product_summaries_query();
+
+            execute(query, transaction, store, products_query).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn receipt_for_placed_order() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let hat = ProductBuilder::new()
+            .title("Hat")
+            .price(Currency::usd(1500))
+            .build();
+        let scarf = ProductBuilder::new()
+            .title("Scarf")
+            .price(Currency::usd(999))
+            .build();
+
+        let mut order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(hat.clone(), |line_item| line_item.quantity(2))
+            .add_product(scarf.clone(), |line_item| line_item.quantity(3))
+            .build();
+
+        let placed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        order.place(FixedClock::new(placed_at)).unwrap();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        let products: Vec<_> = [&hat, &scarf]
+            .into_iter()
+            .map(|product| product.to_data().clone())
+            .collect();
+
+        let products_query = |_| async {
+            Ok(products
+                .iter()
+                .map(|product| ProductSummary {
+                    id: product.id,
+                    title: product.title.clone(),
+                    price: product.price,
+                })
+                .collect())
+        };
+
+        let receipt = execute(
+            GenerateReceipt { order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            products_query,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(order_id, receipt.order_id);
+        assert_eq!(placed_at, receipt.placed_at);
+        assert_eq!(2, receipt.line_items.len());
+
+        let hat_line = receipt
+            .line_items
+            .iter()
+            .find(|l| l.product_id == hat.to_data().id)
+            .unwrap();
+
+        assert_eq!(Some("Hat"), hat_line.title.as_deref());
+        assert_eq!(Currency::usd(3000), hat_line.subtotal);
+
+        // 2 * 1500 + 3 * 999
+        assert_eq!(Currency::usd(5997), receipt.total);
+    }
+
+    #[tokio::test]
+    async fn err_if_not_placed() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+
+        store
+            .set_order(
+                ActiveTransaction::auto_commit().get(),
+                OrderBuilder::new().id(order_id).build(),
+            )
+            .unwrap();
+
+        let err = execute(
+            GenerateReceipt { order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            |_| async { Ok(vec![]) },
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let store = in_memory_store(Default::default());
+
+        let err = execute(
+            GenerateReceipt {
+                order_id: OrderId::new(),
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            |_| async { Ok(vec![]) },
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+    }
+}
\ No newline at end of file
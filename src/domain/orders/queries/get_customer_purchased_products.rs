@@ -0,0 +1,145 @@
+/*! Contains the `GetCustomerPurchasedProductsQuery` type. */
+
+use std::collections::BTreeSet;
+
+use crate::domain::{
+    customers::*,
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `GetCustomerPurchasedProductsQuery`. */
+#[derive(Deserialize)]
+pub struct GetCustomerPurchasedProducts {
+    pub customer_id: CustomerId,
+}
+
+impl QueryArgs for GetCustomerPurchasedProducts {
+    type Output = Result<Vec<ProductId>, Error>;
+}
+
+/** Default implementation for a `GetCustomerPurchasedProductsQuery`. */
+async fn execute(
+    query: GetCustomerPurchasedProducts,
+    transaction: ActiveTransaction,
+    filter_store: impl OrderStoreFilter,
+    store: impl OrderStore,
+) -> Result<Vec<ProductId>, Error> {
+    let filter = OrderFilter {
+        customer_id: Some(query.customer_id),
+        ..Default::default()
+    };
+
+    let mut product_ids = BTreeSet::new();
+
+    for order in filter_store.query(transaction.read(), &filter)? {
+        // Purchase history doesn't need to reflect the very latest changes to an order,
+        // so orders are read from a snapshot. Orders that aren't in the snapshot yet
+        // don't contribute any products
+        if let Some(order) =
+            store.get_order(transaction.read(), order.id, ReadConsistency::Snapshot)?
+        {
+            let (_, line_items) = order.to_data();
+
+            product_ids.extend(line_items.iter().map(|line_item| line_item.product_id));
+        }
+    }
+
+    Ok(product_ids.into_iter().collect())
+}
+
+impl Resolver {
+    /** Get the distinct set of products a customer has ever had on an order. */
+    pub fn get_customer_purchased_products_query(
+        &self,
+    ) -> impl Query<GetCustomerPurchasedProducts> {
+        self.query(|resolver, query: GetCustomerPurchasedProducts| async move {
+            let filter_store = resolver.order_store_filter();
+            let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, filter_store, store).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn shared_products_appear_once() {
+        let store = in_memory_store(Default::default());
+
+        let customer_id = CustomerId::new();
+
+        let shared = ProductId::new();
+        let first_only = ProductId::new();
+        let second_only = ProductId::new();
+        let other_customer = ProductId::new();
+
+        let product = |id| ProductBuilder::new().id(id).build();
+
+        for order in [
+            OrderBuilder::new()
+                .customer_id(customer_id)
+                .add_product(product(shared), |line_item| line_item)
+                .add_product(product(first_only), |line_item| line_item)
+                .build(),
+            OrderBuilder::new()
+                .customer_id(customer_id)
+                .add_product(product(shared), |line_item| line_item)
+                .add_product(product(second_only), |line_item| line_item)
+                .build(),
+            OrderBuilder::new()
+                .add_product(product(other_customer), |line_item| line_item)
+                .build(),
+        ] {
+            store
+                .set_order(ActiveTransaction::auto_commit().get(), order)
+                .unwrap();
+        }
+
+        let product_ids = execute(
+            GetCustomerPurchasedProducts { customer_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            &store,
+        )
+        .await
+        .unwrap();
+
+        let mut expected = vec![shared, first_only, second_only];
+        expected.sort();
+
+        assert_eq!(expected, product_ids);
+    }
+
+    #[tokio::test]
+    async fn empty_if_no_orders() {
+        let store = in_memory_store(Default::default());
+
+        let product_ids = execute(
+            GetCustomerPurchasedProducts {
+                customer_id: CustomerId::new(),
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert!(product_ids.is_empty());
+    }
+}
\ No newline at end of file
@@ -17,8 +17,12 @@ impl QueryArgs for GetOrder {
 }
 
 /** Default implementation for a `GetOrderQuery`. */
-async fn execute(query: GetOrder, store: impl OrderStore) -> Result<Option<Order>, Error> {
-    Ok(store.get_order(query.id)?)
+async fn execute(
+    query: GetOrder,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+) -> Result<Option<Order>, Error> {
+    Ok(store.get_order(transaction.read(), query.id, ReadConsistency::Strong)?)
 }
 
 impl Resolver {
@@ -26,8 +30,34 @@ impl Resolver {
     pub fn get_order_query(&self) -> impl Query<GetOrder> {
         self.query(|resolver, query: GetOrder| async move {
             let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
 
-            execute(query, store).await
+            execute(query, transaction, store).await
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_get_order() {
+        let id = OrderId::new();
+
+        let query: GetOrder = serde_json::from_str(&format!(r#"{{"id":"{}"}}"#, id)).unwrap();
+
+        assert_eq!(id, query.id);
+    }
+
+    #[test]
+    fn err_deserialize_get_order_nil_id() {
+        let r =
+            serde_json::from_str::<GetOrder>(r#"{"id":"00000000-0000-0000-0000-000000000000"}"#);
+
+        match r {
+            Err(e) => assert!(e.to_string().contains("id must not be nil")),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
 }
\ No newline at end of file
@@ -11,6 +11,9 @@ use crate::domain::{
 #[derive(Deserialize)]
 pub struct GetOrderSummariesForCustomer {
     pub id: CustomerId,
+    /** Whether to also include gift orders where the customer is the recipient. */
+    #[serde(default)]
+    pub include_gifts: bool,
 }
 
 /** An individual order summary. */
@@ -26,12 +29,23 @@ impl QueryArgs for GetOrderSummariesForCustomer {
 /** Default implementation for a `GetOrderSummariesForCustomerQuery`. */
 async fn execute(
     query: GetOrderSummariesForCustomer,
+    transaction: ActiveTransaction,
     store: impl OrderStoreFilter,
 ) -> Result<Vec<OrderSummary>, Error> {
-    store
-        .filter(|o| o.customer_id == query.id)?
-        .map(|o| Ok(OrderSummary { id: o.id }))
-        .collect()
+    let orders = if query.include_gifts {
+        store.filter(transaction.read(), &|order| {
+            order.customer_id == query.id || order.recipient == Some(query.id)
+        })?
+    } else {
+        let filter = OrderFilter {
+            customer_id: Some(query.id),
+            ..Default::default()
+        };
+
+        store.query(transaction.read(), &filter)?
+    };
+
+    orders.map(|o| Ok(OrderSummary { id: o.id })).collect()
 }
 
 impl Resolver {
@@ -42,7 +56,78 @@ impl Resolver {
         self.query(|resolver, query: GetOrderSummariesForCustomer| async move {
             let store = resolver.order_store_filter();
 
-            execute(query, store).await
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, store).await
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::orders::model::{
+        store::in_memory_store,
+        test_data::OrderBuilder,
+    };
+
+    #[tokio::test]
+    async fn gifts_are_only_included_if_requested() {
+        let store = in_memory_store(Default::default());
+
+        let customer_id = CustomerId::new();
+
+        let purchased_id = OrderId::new();
+        let gift_id = OrderId::new();
+
+        for order in [
+            OrderBuilder::new()
+                .id(purchased_id)
+                .customer_id(customer_id)
+                .build(),
+            OrderBuilder::new()
+                .id(gift_id)
+                .recipient(customer_id)
+                .build(),
+            OrderBuilder::new().build(),
+        ] {
+            store
+                .set_order(ActiveTransaction::auto_commit().get(), order)
+                .unwrap();
+        }
+
+        let summaries = |include_gifts| {
+            execute(
+                GetOrderSummariesForCustomer {
+                    id: customer_id,
+                    include_gifts,
+                },
+                ActiveTransaction::auto_commit(),
+                &store,
+            )
+        };
+
+        let ids: Vec<_> = summaries(false)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|order| order.id)
+            .collect();
+
+        assert_eq!(vec![purchased_id], ids);
+
+        let mut ids: Vec<_> = summaries(true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|order| order.id)
+            .collect();
+        ids.sort();
+
+        let mut expected = vec![purchased_id, gift_id];
+        expected.sort();
+
+        assert_eq!(expected, ids);
+    }
 }
\ No newline at end of file
@@ -1,7 +1,6 @@
 /*! Contains the `GetOrderWithProductsQuery` type. */
 
 use crate::domain::{
-    error,
     infra::*,
     orders::*,
     products::*,
@@ -18,15 +17,26 @@ pub struct GetOrderWithProducts {
 #[derive(Serialize)]
 pub struct OrderWithProducts {
     pub id: OrderId,
+    /**
+    The line items in the order, sorted by product.
+
+    Stores don't return line items in any particular order, so they're sorted to make the same
+    order look the same no matter where it's stored.
+    */
     pub line_items: Vec<ProductLineItem>,
 }
 
-/** An individual line item with a product summary. */
+/**
+An individual line item with a product summary.
+
+If the product for the line item can't be found then its title will be `None`
+and its price will be the one captured on the line item.
+*/
 #[derive(Serialize)]
 pub struct ProductLineItem {
     pub line_item_id: LineItemId,
     pub product_id: ProductId,
-    pub title: String,
+    pub title: Option<String>,
     pub price: Currency,
     pub quantity: u32,
 }
@@ -38,36 +48,60 @@ impl QueryArgs for GetOrderWithProducts {
 /** Default implementation for a `GetOrderWithProductsQuery`. */
 async fn execute(
     query: GetOrderWithProducts,
+    transaction: ActiveTransaction,
     store: impl OrderStore,
     products_query: impl Query<GetProductSummaries>,
 ) -> Result<Option<OrderWithProducts>, Error> {
-    let (order, line_items) = match store.get_order(query.id)? {
-        Some(order) => order.into_data(),
-        None => return Ok(None),
-    };
+    let (order, line_items) =
+        match store.get_order(transaction.read(), query.id, ReadConsistency::Strong)? {
+            Some(order) => order.into_data(),
+            None => return Ok(None),
+        };
 
     let products = {
         let product_ids = line_items.iter().map(|l| l.product_id).collect();
-        products_query.execute(GetProductSummaries { ids: product_ids })
+
+        // Products that have been deactivated since they were ordered are still part of the order
+        products_query.execute(GetProductSummaries {
+            ids: product_ids,
+            include_inactive: true,
+        })
     }
     .await?;
 
-    let line_items = line_items
+    // Products referenced by line items might not exist anymore if they were removed
+    // behind our back. Rather than failing the whole order we surface those line items
+    // without their product details
+    let mut line_items: Vec<_> = line_items
         .into_iter()
-        .map(|line_item| {
-            products
-                .iter()
-                .find(|p| p.id == line_item.product_id)
-                .map(|product| ProductLineItem {
+        .map(
+            |line_item| match products.iter().find(|p| p.id == line_item.product_id) {
+                Some(product) => ProductLineItem {
                     line_item_id: line_item.id,
                     product_id: product.id,
-                    title: product.title.to_owned(),
+                    title: Some(product.title.to_owned()),
                     price: product.price,
                     quantity: line_item.quantity,
-                })
-                .ok_or_else(|| error::bad_input("missing product for line item"))
-        })
-        .collect::<std::result::Result<Vec<_>, _>>()?;
+                },
+                None => {
+                    warn!(
+                        "missing product `{}` for line item `{}` in order `{}`",
+                        line_item.product_id, line_item.id, order.id
+                    );
+
+                    ProductLineItem {
+                        line_item_id: line_item.id,
+                        product_id: line_item.product_id,
+                        title: None,
+                        price: line_item.price,
+                        quantity: line_item.quantity,
+                    }
+                }
+            },
+        )
+        .collect();
+
+    line_items.sort_by_key(|line_item| (line_item.product_id, line_item.line_item_id));
 
     Ok(Some(OrderWithProducts {
         id: order.id,
@@ -80,9 +114,144 @@ impl Resolver {
     pub fn get_order_with_products_query(&self) -> impl Query<GetOrderWithProducts> {
         self.query(|resolver, query: GetOrderWithProducts| async move {
             let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
             let products_query = resolver.get_product_summaries_query();
 
-            execute(query, store, products_query).await
+            execute(query, transaction, store, products_query).await
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn missing_product_is_unknown() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+        let orphaned_product_id = ProductId::new();
+
+        let order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                line_item
+            })
+            .add_product(
+                ProductBuilder::new().id(orphaned_product_id).build(),
+                |line_item| line_item,
+            )
+            .build();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        // Only one of the products in the order can be found
+        let products_query = move |_| async move {
+            Ok(vec![ProductSummary {
+                id: product_id,
+                title: "A test product".to_owned(),
+                price: Currency::usd(100),
+            }])
+        };
+
+        let order = execute(
+            GetOrderWithProducts { id: order_id },
+            ActiveTransaction::auto_commit(),
+            &store,
+            products_query,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(2, order.line_items.len());
+
+        let found = order
+            .line_items
+            .iter()
+            .find(|l| l.product_id == product_id)
+            .unwrap();
+        let orphaned = order
+            .line_items
+            .iter()
+            .find(|l| l.product_id == orphaned_product_id)
+            .unwrap();
+
+        assert_eq!(Some("A test product"), found.title.as_deref());
+        assert_eq!(None, orphaned.title);
+    }
+
+    #[tokio::test]
+    async fn line_items_are_sorted_by_product() {
+        let order_id = OrderId::new();
+
+        let first = (LineItemId::new(), ProductBuilder::new().build());
+        let second = (LineItemId::new(), ProductBuilder::new().build());
+
+        let products: Vec<_> = [&first, &second]
+            .into_iter()
+            .map(|(_, product)| product.to_data().clone())
+            .collect();
+
+        let mut serialized = vec![];
+
+        // Store the same order in different stores, adding its line items in a different order
+        for items in [[&first, &second], [&second, &first]] {
+            let store = in_memory_store(Default::default());
+
+            let order = items
+                .into_iter()
+                .fold(OrderBuilder::new().id(order_id), |order, (id, product)| {
+                    let id = *id;
+                    order.add_product(product.clone(), move |line_item| line_item.id(id))
+                })
+                .build();
+
+            store
+                .set_order(ActiveTransaction::auto_commit().get(), order)
+                .unwrap();
+
+            let products_query = |_| async {
+                Ok(products
+                    .iter()
+                    .map(|product| ProductSummary {
+                        id: product.id,
+                        title: product.title.clone(),
+                        price: product.price,
+                    })
+                    .collect())
+            };
+
+            let order = execute(
+                GetOrderWithProducts { id: order_id },
+                ActiveTransaction::auto_commit(),
+                &store,
+                products_query,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+            assert!(order
+                .line_items
+                .windows(2)
+                .all(|pair| pair[0].product_id <= pair[1].product_id));
+
+            serialized.push(serde_json::to_string(&order).unwrap());
+        }
+
+        assert_eq!(serialized[0], serialized[1]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+/*! Contains the `GetOrdersModifiedBetweenQuery` type. */
+
+use std::time::SystemTime;
+
+use crate::domain::{
+    infra::*,
+    orders::*,
+    Error,
+};
+
+/** Input for a `GetOrdersModifiedBetweenQuery`. */
+#[derive(Deserialize)]
+pub struct GetOrdersModifiedBetween {
+    pub from: SystemTime,
+    pub to: SystemTime,
+}
+
+/** An order that was modified. */
+#[derive(Serialize)]
+pub struct ModifiedOrder {
+    pub id: OrderId,
+    pub updated_at: SystemTime,
+}
+
+impl QueryArgs for GetOrdersModifiedBetween {
+    type Output = Result<Vec<ModifiedOrder>, Error>;
+}
+
+/** Default implementation for a `GetOrdersModifiedBetweenQuery`. */
+async fn execute(
+    query: GetOrdersModifiedBetween,
+    transaction: ActiveTransaction,
+    store: impl OrderStoreFilter,
+) -> Result<Vec<ModifiedOrder>, Error> {
+    let in_range = |updated_at: Option<SystemTime>| {
+        updated_at
+            .map(|updated_at| query.from <= updated_at && updated_at <= query.to)
+            .unwrap_or(false)
+    };
+
+    let mut orders = store
+        .filter(transaction.read(), &|o| in_range(o.updated_at))?
+        .filter_map(|o| {
+            o.updated_at.map(|updated_at| ModifiedOrder {
+                id: o.id,
+                updated_at,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    orders.sort_by_key(|o| o.updated_at);
+
+    Ok(orders)
+}
+
+impl Resolver {
+    /** Get all orders that were modified within an inclusive range of time. */
+    pub fn get_orders_modified_between_query(&self) -> impl Query<GetOrdersModifiedBetween> {
+        self.query(|resolver, query: GetOrdersModifiedBetween| async move {
+            let store = resolver.order_store_filter();
+
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, store).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::Duration,
+    };
+
+    use super::*;
+
+    use crate::domain::orders::model::{
+        store::in_memory_store_with_clock,
+        test_data::OrderBuilder,
+    };
+
+    #[tokio::test]
+    async fn get_orders_in_range() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = Arc::new(FixedClock::new(start));
+
+        let store = in_memory_store_with_clock(Default::default(), clock.clone());
+
+        let set_order_at = |secs| {
+            let id = OrderId::new();
+
+            clock.set(start + Duration::from_secs(secs));
+            store
+                .set_order(
+                    ActiveTransaction::auto_commit().get(),
+                    OrderBuilder::new().id(id).build(),
+                )
+                .unwrap();
+
+            id
+        };
+
+        let before = set_order_at(0);
+        let on_start = set_order_at(10);
+        let inside = set_order_at(15);
+        let on_end = set_order_at(20);
+        let after = set_order_at(30);
+
+        let orders = execute(
+            GetOrdersModifiedBetween {
+                from: start + Duration::from_secs(10),
+                to: start + Duration::from_secs(20),
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+        )
+        .await
+        .unwrap();
+
+        let ids: Vec<_> = orders.iter().map(|o| o.id).collect();
+
+        assert_eq!(vec![on_start, inside, on_end], ids);
+        assert!(!ids.contains(&before));
+        assert!(!ids.contains(&after));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,170 @@
+/*! Contains the `GetTopProductsQuery` type. */
+
+use std::collections::{
+    BTreeSet,
+    HashMap,
+};
+
+use crate::domain::{
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `GetTopProductsQuery`. */
+#[derive(Deserialize)]
+pub struct GetTopProducts {
+    /** The most products to return. */
+    pub limit: usize,
+}
+
+/** A product and the number of orders it appears in. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProductOrderCount {
+    pub product_id: ProductId,
+    pub orders: usize,
+}
+
+impl QueryArgs for GetTopProducts {
+    type Output = Result<Vec<ProductOrderCount>, Error>;
+}
+
+/** Default implementation for a `GetTopProductsQuery`. */
+async fn execute(
+    query: GetTopProducts,
+    transaction: ActiveTransaction,
+    filter_store: impl OrderStoreFilter,
+    store: impl OrderStore,
+) -> Result<Vec<ProductOrderCount>, Error> {
+    let mut counts = HashMap::new();
+
+    for order in filter_store.query(transaction.read(), &OrderFilter::default())? {
+        // Bestsellers don't need to reflect the very latest changes to an order,
+        // so orders are read from a snapshot
+        if let Some(order) =
+            store.get_order(transaction.read(), order.id, ReadConsistency::Snapshot)?
+        {
+            let (_, line_items) = order.to_data();
+
+            // Only count each product once per order, even if it has more than one line item
+            let product_ids: BTreeSet<_> = line_items
+                .iter()
+                .map(|line_item| line_item.product_id)
+                .collect();
+
+            for product_id in product_ids {
+                *counts.entry(product_id).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut top: Vec<_> = counts
+        .into_iter()
+        .map(|(product_id, orders)| ProductOrderCount { product_id, orders })
+        .collect();
+
+    // Ties are broken by product id so the results are stable
+    top.sort_by(|a, b| {
+        b.orders
+            .cmp(&a.orders)
+            .then_with(|| a.product_id.cmp(&b.product_id))
+    });
+    top.truncate(query.limit);
+
+    Ok(top)
+}
+
+impl Resolver {
+    /** Get the products that appear in the most orders, along with how many orders they're in. */
+    pub fn get_top_products_query(&self) -> impl Query<GetTopProducts> {
+        self.query(|resolver, query: GetTopProducts| async move {
+            let filter_store = resolver.order_store_filter();
+            let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, filter_store, store).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn products_in_the_most_orders_come_first() {
+        let store = in_memory_store(Default::default());
+
+        let in_three = ProductId::new();
+        let in_two = ProductId::new();
+        let in_one = ProductId::new();
+
+        let order = |product_ids: &[ProductId]| {
+            product_ids
+                .iter()
+                .fold(OrderBuilder::new(), |order, &id| {
+                    order.add_product(ProductBuilder::new().id(id).build(), |line_item| line_item)
+                })
+                .build()
+        };
+
+        for order in [
+            order(&[in_three, in_two, in_one]),
+            order(&[in_three, in_two]),
+            order(&[in_three]),
+            order(&[]),
+        ] {
+            store
+                .set_order(ActiveTransaction::auto_commit().get(), order)
+                .unwrap();
+        }
+
+        let top = execute(
+            GetTopProducts { limit: 2 },
+            ActiveTransaction::auto_commit(),
+            &store,
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ProductOrderCount {
+                    product_id: in_three,
+                    orders: 3,
+                },
+                ProductOrderCount {
+                    product_id: in_two,
+                    orders: 2,
+                },
+            ],
+            top
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_if_no_orders() {
+        let store = in_memory_store(Default::default());
+
+        let top = execute(
+            GetTopProducts { limit: 10 },
+            ActiveTransaction::auto_commit(),
+            &store,
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert!(top.is_empty());
+    }
+}
\ No newline at end of file
@@ -1,11 +1,23 @@
 /*! Queries for fetching order state. */
 
+mod can_place_order;
+mod generate_receipt;
+mod get_customer_purchased_products;
 mod get_order;
 mod get_order_summaries_for_customer;
 mod get_order_with_products;
+mod get_orders_modified_between;
+mod get_top_products;
+mod preview_reorder;
 
 pub use self::{
+    can_place_order::*,
+    generate_receipt::*,
+    get_customer_purchased_products::*,
     get_order::*,
     get_order_summaries_for_customer::*,
     get_order_with_products::*,
+    get_orders_modified_between::*,
+    get_top_products::*,
+    preview_reorder::*,
 };
\ No newline at end of file
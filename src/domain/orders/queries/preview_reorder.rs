@@ -0,0 +1,230 @@
+/*! Contains the `PreviewReorderQuery` type. */
+
+use crate::domain::{
+    infra::*,
+    orders::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `PreviewReorderQuery`. */
+#[derive(Deserialize)]
+pub struct PreviewReorder {
+    pub source_order_id: OrderId,
+}
+
+/** What a new order placed from the products in an existing one would contain. */
+#[derive(Serialize)]
+pub struct ReorderPreview {
+    pub source_order_id: OrderId,
+    pub line_items: Vec<ReorderLineItem>,
+}
+
+/**
+An individual line item in a reorder preview.
+
+If the product for the line item can't be found anymore then it's unavailable,
+and it won't have a title or price.
+*/
+#[derive(Serialize)]
+pub struct ReorderLineItem {
+    pub product_id: ProductId,
+    pub available: bool,
+    pub title: Option<String>,
+    pub price: Option<Currency>,
+    pub quantity: u32,
+}
+
+impl QueryArgs for PreviewReorder {
+    type Output = Result<Option<ReorderPreview>, Error>;
+}
+
+/** Default implementation for a `PreviewReorderQuery`. */
+async fn execute(
+    query: PreviewReorder,
+    transaction: ActiveTransaction,
+    store: impl OrderStore,
+    products_query: impl Query<GetProductSummaries>,
+) -> Result<Option<ReorderPreview>, Error> {
+    let (order, line_items) = match store.get_order(
+        transaction.read(),
+        query.source_order_id,
+        ReadConsistency::Strong,
+    )? {
+        Some(order) => order.into_data(),
+        None => return Ok(None),
+    };
+
+    let products = {
+        let product_ids = line_items.iter().map(|l| l.product_id).collect();
+        products_query.execute(GetProductSummaries {
+            ids: product_ids,
+            include_inactive: false,
+        })
+    }
+    .await?;
+
+    // Prices come from the current products rather than the ones captured on the source order
+    let line_items = line_items
+        .into_iter()
+        .map(
+            |line_item| match products.iter().find(|p| p.id == line_item.product_id) {
+                Some(product) => ReorderLineItem {
+                    product_id: product.id,
+                    available: true,
+                    title: Some(product.title.to_owned()),
+                    price: Some(product.price),
+                    quantity: line_item.quantity,
+                },
+                None => ReorderLineItem {
+                    product_id: line_item.product_id,
+                    available: false,
+                    title: None,
+                    price: None,
+                    quantity: line_item.quantity,
+                },
+            },
+        )
+        .collect();
+
+    Ok(Some(ReorderPreview {
+        source_order_id: order.id,
+        line_items,
+    }))
+}
+
+impl Resolver {
+    /** Preview the order that would be created from the products in an existing one. */
+    pub fn preview_reorder_query(&self) -> impl Query<PreviewReorder> {
+        self.query(|resolver, query: PreviewReorder| async move {
+            let store = resolver.order_store();
+            let transaction = resolver.active_transaction();
+            let products_query = resolver.get_product_summaries_query();
+
+            execute(query, transaction, store, products_query).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        orders::model::{
+            store::in_memory_store,
+            test_data::OrderBuilder,
+        },
+        products::model::test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn preview_all_available() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+
+        let order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                line_item.quantity(2)
+            })
+            .build();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        // The product's price has changed since the order was created
+        let products_query = move |_| async move {
+            Ok(vec![ProductSummary {
+                id: product_id,
+                title: "A test product".to_owned(),
+                price: Currency::usd(250),
+            }])
+        };
+
+        let preview = execute(
+            PreviewReorder {
+                source_order_id: order_id,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            products_query,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(1, preview.line_items.len());
+
+        let line_item = &preview.line_items[0];
+
+        assert!(line_item.available);
+        assert_eq!(2, line_item.quantity);
+        assert!(matches!(line_item.price, Some(Currency::USD(usd)) if usd == USD::new(250)));
+    }
+
+    #[tokio::test]
+    async fn preview_partially_available() {
+        let store = in_memory_store(Default::default());
+
+        let order_id = OrderId::new();
+        let product_id = ProductId::new();
+        let unavailable_product_id = ProductId::new();
+
+        let order = OrderBuilder::new()
+            .id(order_id)
+            .add_product(ProductBuilder::new().id(product_id).build(), |line_item| {
+                line_item
+            })
+            .add_product(
+                ProductBuilder::new().id(unavailable_product_id).build(),
+                |line_item| line_item,
+            )
+            .build();
+
+        store
+            .set_order(ActiveTransaction::auto_commit().get(), order)
+            .unwrap();
+
+        // Only one of the products in the order can be found
+        let products_query = move |_| async move {
+            Ok(vec![ProductSummary {
+                id: product_id,
+                title: "A test product".to_owned(),
+                price: Currency::usd(100),
+            }])
+        };
+
+        let preview = execute(
+            PreviewReorder {
+                source_order_id: order_id,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            products_query,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(2, preview.line_items.len());
+
+        let available = preview
+            .line_items
+            .iter()
+            .find(|l| l.product_id == product_id)
+            .unwrap();
+        let unavailable = preview
+            .line_items
+            .iter()
+            .find(|l| l.product_id == unavailable_product_id)
+            .unwrap();
+
+        assert!(available.available);
+        assert!(!unavailable.available);
+        assert_eq!(None, unavailable.title);
+    }
+}
\ No newline at end of file
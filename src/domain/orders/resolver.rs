@@ -1,15 +1,37 @@
 /*! Contains the `OrdersResolver` type. */
 
-use std::sync::Arc;
-
-use crate::domain::{
-    infra::*,
-    orders::model::store::{
-        self,
-        InMemoryStore,
-        OrderStore,
-        OrderStoreFilter,
+use std::{
+    path::Path,
+    sync::Arc,
+};
+
+#[cfg(feature = "sled-store")]
+use crate::domain::orders::model::sled_store::SledStore;
+use crate::{
+    domain::{
+        infra::*,
+        orders::model::{
+            store::{
+                self,
+                DynOrderStore,
+                OrderStore,
+                OrderStoreFilter,
+                ReadConsistency,
+            },
+            wal_store::{
+                self,
+                OrderWalStore,
+            },
+            LineItemData,
+            NextLineItemId,
+            NextOrderId,
+            Order,
+            OrderData,
+            OrderNumber,
+        },
+        Error,
     },
+    store::Transaction,
 };
 
 /**
@@ -19,25 +41,435 @@ The `OrdersResolver` type wraps private implementation details and exposes them
 */
 #[derive(Clone)]
 pub(in crate::domain) struct OrdersResolver {
-    order_store: Register<Arc<InMemoryStore>>,
+    order_store: Register<DynOrderStore>,
+    pub(in crate::domain::orders) order_id: Register<DynIdProvider<OrderData>>,
+    pub(in crate::domain::orders) line_item_id: Register<DynIdProvider<LineItemData>>,
+    order_numbers: Register<Option<SequenceProvider<OrderNumber>>>,
 }
 
 impl Default for OrdersResolver {
     fn default() -> Self {
         OrdersResolver {
             order_store: Register::once(|resolver| {
-                Arc::new(store::in_memory_store(resolver.transaction_store()))
+                Arc::new(store::in_memory_store_with_clock(
+                    resolver.transaction_store(),
+                    resolver.clock(),
+                )) as DynOrderStore
+            }),
+            order_id: Register::factory(|_| {
+                Arc::new(NextOrderId::new()) as DynIdProvider<OrderData>
+            }),
+            line_item_id: Register::factory(|_| {
+                Arc::new(NextLineItemId::new()) as DynIdProvider<LineItemData>
+            }),
+            order_numbers: Register::once(|_| None),
+        }
+    }
+}
+
+impl OrdersResolver {
+    pub(in crate::domain) fn with_order_store<S>(
+        &self,
+        store: impl Fn(&Resolver) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: store::OrderStoreWithFilter + Send + Sync + 'static,
+    {
+        OrdersResolver {
+            order_store: Register::once(move |resolver| Arc::new(store(resolver)) as DynOrderStore),
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "concurrent")]
+    pub(in crate::domain) fn with_concurrent_stores(&self) -> Self {
+        self.with_order_store(|resolver| {
+            store::concurrent_store_with_clock(resolver.transaction_store(), resolver.clock())
+        })
+    }
+
+    pub(in crate::domain) fn with_id_strategy(&self, strategy: IdStrategy) -> Self {
+        OrdersResolver {
+            order_id: Register::factory(move |_| {
+                Arc::new(NextOrderId::with_strategy(strategy)) as DynIdProvider<OrderData>
+            }),
+            line_item_id: Register::factory(move |_| {
+                Arc::new(NextLineItemId::with_strategy(strategy)) as DynIdProvider<LineItemData>
             }),
+            ..self.clone()
+        }
+    }
+
+    pub(in crate::domain) fn with_seeded_ids(&self, seed: u64) -> Self {
+        OrdersResolver {
+            order_id: Register::once(move |_| {
+                Arc::new(SeededIdProvider::new(seed)) as DynIdProvider<OrderData>
+            }),
+            line_item_id: Register::once(move |_| {
+                Arc::new(SeededIdProvider::new(seed)) as DynIdProvider<LineItemData>
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+impl App {
+    /**
+    Give new orders a sequential number, allocated from the given store.
+
+    Orders aren't numbered by default.
+    */
+    pub fn with_order_numbers(self, store: impl SequenceStore + Send + Sync + 'static) -> Self {
+        let store = Arc::new(store) as DynSequenceStore;
+
+        App {
+            root_resolver: Resolver {
+                orders_resolver: OrdersResolver {
+                    order_numbers: Register::once(move |_| {
+                        Some(SequenceProvider::new(store.clone(), "order_number"))
+                    }),
+                    ..self.root_resolver.orders_resolver.clone()
+                },
+                ..self.root_resolver.by_ref()
+            },
+        }
+    }
+
+    /**
+    Keep orders in a sled database at `path`, so they survive restarts.
+
+    Existing orders are read from the database straight away, so a database that can't be opened
+    is reported here rather than on the first request. Orders are still read from memory after
+    that, and the database is only written to. Orders are written as part of committing the
+    transactions that change them, so a transaction whose orders can't be written fails to commit.
+    */
+    #[cfg(feature = "sled-store")]
+    pub fn with_order_database(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let store = Arc::new(SledStore::open(
+            path,
+            self.root_resolver.transaction_store(),
+            self.root_resolver.clock(),
+        )?);
+
+        Ok(App {
+            root_resolver: Resolver {
+                orders_resolver: self
+                    .root_resolver
+                    .orders_resolver
+                    .with_order_store(move |_| store.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        })
+    }
+
+    /**
+    Keep orders in memory and log each change to `path`, so they survive restarts.
+
+    The log is replayed straight away, so a log that can't be read is reported here rather than
+    on the first request. Transactions are logged as soon as they complete, and `App::shutdown`
+    compacts the log.
+    */
+    pub fn with_order_log(self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let transaction_store = self.root_resolver.transaction_store();
+
+        let orders = store::in_memory_store_with_clock(
+            transaction_store.clone(),
+            self.root_resolver.clock(),
+        );
+        wal_store::replay(path, &orders)?;
+
+        let store = Arc::new(OrderWalStore::open(path, orders, transaction_store)?);
+
+        Ok(App {
+            root_resolver: Resolver {
+                orders_resolver: self
+                    .root_resolver
+                    .orders_resolver
+                    .with_order_store(move |_| store.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        })
+    }
+
+    /**
+    Use a different store for orders.
+
+    The store is created the first time it's needed. An in-memory store is used by default.
+    Applications can keep orders somewhere else by implementing `OrderStore` and `OrderStoreFilter`.
+    */
+    pub fn with_order_store<S>(self, store: impl Fn(&Resolver) -> S + Send + Sync + 'static) -> Self
+    where
+        S: store::OrderStoreWithFilter + Send + Sync + 'static,
+    {
+        App {
+            root_resolver: Resolver {
+                orders_resolver: self.root_resolver.orders_resolver.with_order_store(store),
+                ..self.root_resolver.by_ref()
+            },
         }
     }
 }
 
 impl Resolver {
+    pub(in crate::domain) fn shutdown_orders(&self, shutdown: &mut Shutdown) {
+        shutdown.flushes("order_store", self.order_store());
+    }
+
+    pub(in crate::domain) fn self_check_orders(&self, check: &mut SelfCheck) {
+        check.resolves("order_store", || self.order_store());
+        check.resolves("order_id", || self.order_id());
+        check.resolves("line_item_id", || self.line_item_id());
+        check.resolves("order_numbers", || self.order_numbers());
+    }
+
+    /** Get the data for every committed order and its line items, ordered by id. */
+    pub(in crate::domain) fn export_orders(
+        &self,
+    ) -> Result<Vec<(OrderData, Vec<LineItemData>)>, Error> {
+        let store = self.order_store();
+
+        let mut orders = self
+            .order_store_filter()
+            .filter(&Transaction::none(), &|_| true)?
+            // Orders that were deleted after they were filtered are left out
+            .filter_map(|order| {
+                store
+                    .get_order(&Transaction::none(), order.id, ReadConsistency::Strong)
+                    .map(|order| order.map(Order::into_data))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        orders.sort_by_key(|(order, _)| order.id);
+
+        Ok(orders)
+    }
+
+    /** Save an order and its line items that were exported from another resolver, keeping their ids. */
+    pub(in crate::domain) fn import_order(
+        &self,
+        transaction: &Transaction,
+        order: OrderData,
+        line_items: Vec<LineItemData>,
+    ) -> Result<(), Error> {
+        self.order_store()
+            .set_order(transaction, Order::checked(order, line_items)?)
+    }
+
     pub(in crate::domain::orders) fn order_store(&self) -> impl OrderStore {
         self.resolve(&self.orders_resolver.order_store)
     }
 
+    pub(in crate::domain::orders) fn order_numbers(&self) -> Option<SequenceProvider<OrderNumber>> {
+        self.resolve(&self.orders_resolver.order_numbers)
+    }
+
     pub(in crate::domain::orders) fn order_store_filter(&self) -> impl OrderStoreFilter {
         self.resolve(&self.orders_resolver.order_store)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::{
+            CreateCustomer,
+            CustomerId,
+        },
+        orders::*,
+        products::{
+            CreateProduct,
+            ProductId,
+        },
+        Error,
+    };
+
+    #[tokio::test]
+    async fn commands_use_custom_order_store() {
+        // Fail on the first write to show the commands use the store
+        let app = App::new().with_order_store(|resolver| {
+            store::FailingStore::new(
+                store::in_memory_store_with_clock(resolver.transaction_store(), resolver.clock()),
+                1,
+            )
+        });
+
+        let customer_id = CustomerId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await
+        })
+        .await
+        .unwrap();
+
+        let r = app
+            .transaction(|resolver| async move {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id: OrderId::new(),
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await
+            })
+            .await;
+
+        assert!(r.unwrap_err().to_string().contains("failed on write 1"));
+    }
+
+    fn app_failing_on_write(write: usize) -> App {
+        App::new().with_order_store(move |resolver| {
+            store::FailingStore::new(
+                store::in_memory_store_with_clock(resolver.transaction_store(), resolver.clock()),
+                write,
+            )
+        })
+    }
+
+    async fn create_customer_and_product(app: &App) -> (CustomerId, ProductId) {
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id: product_id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        (customer_id, product_id)
+    }
+
+    async fn create_order(app: &App, customer_id: CustomerId) -> Result<OrderId, Error> {
+        let id = OrderId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn errors_name_the_command_that_failed() {
+        // The first write is creating the order
+        let app = app_failing_on_write(1);
+        let (customer_id, _) = create_customer_and_product(&app).await;
+
+        let err = create_order(&app, customer_id).await.unwrap_err();
+
+        assert!(err.to_string().starts_with("create_order order="));
+        assert!(err.to_string().ends_with(": failed on write 1"));
+
+        // The second write is adding the product to the order
+        let app = app_failing_on_write(2);
+        let (customer_id, product_id) = create_customer_and_product(&app).await;
+
+        let order_id = create_order(&app, customer_id).await.unwrap();
+
+        let err = app
+            .transaction(|resolver| async move {
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id: order_id,
+                        product_id,
+                        quantity: 1,
+                    })
+                    .await
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            format!(
+                "add_or_update_product order={}: failed on write 2",
+                order_id
+            ),
+            err.to_string()
+        );
+    }
+
+    #[cfg(feature = "sled-store")]
+    #[tokio::test]
+    async fn orders_survive_restarting_with_order_database() {
+        let path = std::env::temp_dir().join(format!("shop-orders-{}", OrderId::new()));
+
+        let app = App::new().with_order_database(&path).unwrap();
+
+        let (customer_id, _) = create_customer_and_product(&app).await;
+        let order_id = create_order(&app, customer_id).await.unwrap();
+
+        app.shutdown().unwrap();
+        drop(app);
+
+        let app = App::new().with_order_database(&path).unwrap();
+
+        let order = app
+            .root_resolver
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(customer_id, order.to_data().0.customer_id);
+
+        drop(app);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn orders_survive_restarting_with_order_log() {
+        let path = std::env::temp_dir().join(format!("shop-orders-{}.wal", OrderId::new()));
+
+        let app = App::new().with_order_log(&path).unwrap();
+
+        let (customer_id, _) = create_customer_and_product(&app).await;
+        let order_id = create_order(&app, customer_id).await.unwrap();
+
+        app.shutdown().unwrap();
+        drop(app);
+
+        let app = App::new().with_order_log(&path).unwrap();
+
+        let order = app
+            .root_resolver
+            .get_order_query()
+            .execute(GetOrder { id: order_id })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(customer_id, order.to_data().0.customer_id);
+
+        drop(app);
+        std::fs::remove_file(path).unwrap();
+    }
 }
\ No newline at end of file
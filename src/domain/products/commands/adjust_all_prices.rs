@@ -0,0 +1,256 @@
+/*! Contains the `AdjustAllPricesCommand`. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    products::*,
+    Error,
+};
+
+/**
+Input for an `AdjustAllPricesCommand`.
+
+Without a filter every active product is changed.
+*/
+#[derive(Clone, Deserialize)]
+pub struct AdjustAllPrices {
+    /** How much to change prices by, so `10` is a 10% increase and `-10` is a 10% discount. */
+    pub percent: i32,
+    #[serde(default)]
+    pub filter: Option<ProductFilter>,
+}
+
+impl CommandArgs for AdjustAllPrices {
+    /** The number of products that were changed. */
+    type Output = Result<usize, Error>;
+}
+
+/** Default implementation for an `AdjustAllPricesCommand`. */
+async fn execute(
+    command: AdjustAllPrices,
+    transaction: ActiveTransaction,
+    store: impl ProductStore,
+    store_filter: impl ProductStoreFilter,
+    events: Events,
+) -> Result<usize, Error> {
+    debug!(
+        percent = command.percent,
+        transaction_id = %transaction.id(),
+        "adjusting prices"
+    );
+
+    if command.percent < -100 {
+        return Err(error::bad_input(format!(
+            "a {}% change would make prices negative",
+            command.percent
+        )));
+    }
+
+    let filter = command.filter.unwrap_or_default();
+
+    // Work out every new price before saving any, so a price that can't be changed leaves them all
+    let mut products = vec![];
+    for data in store_filter.query(transaction.read(), &filter, Page::default())? {
+        let mut product = store
+            .get_product(transaction.read(), data.id)?
+            .ok_or_else(|| error::not_found("product", data.id))?;
+
+        let price = product
+            .to_data()
+            .price
+            .adjust_by_percent(command.percent)
+            .ok_or_else(|| {
+                error::bad_input(format!(
+                    "a {}% change to the price of product `{}` is out of range",
+                    command.percent, data.id
+                ))
+            })?;
+
+        product.set_price(price)?;
+
+        products.push(product);
+    }
+
+    let updated = products.len();
+
+    for product in products {
+        let id = product.to_data().id;
+
+        store.set_product(transaction.get(), product)?;
+
+        let events = events.clone();
+        transaction.on_commit(move || events.publish(DomainEvent::ProductUpdated { id }));
+    }
+
+    info!(
+        percent = command.percent,
+        updated,
+        transaction_id = %transaction.id(),
+        "adjusted prices"
+    );
+
+    Ok(updated)
+}
+
+impl Resolver {
+    /** Change the prices of many products at once by a percentage. */
+    pub fn adjust_all_prices_command(&self) -> impl Command<AdjustAllPrices> {
+        self.command(|resolver, command: AdjustAllPrices| async move {
+            let store = resolver.product_store();
+            let store_filter = resolver.product_store_filter();
+            let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
+
+            let context = format!("adjust_all_prices percent={}", command.percent);
+
+            let span = info_span!(
+                "adjust_all_prices",
+                percent = command.percent,
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(
+                span,
+                execute(command, active_transaction, store, store_filter, events),
+            )
+            .await
+            .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        products::model::{
+            store::in_memory_store,
+            test_data::ProductBuilder,
+        },
+        ErrorKind,
+    };
+
+    fn price(store: &impl ProductStore, id: ProductId) -> Currency {
+        store
+            .get_product(ActiveTransaction::auto_commit().read(), id)
+            .unwrap()
+            .unwrap()
+            .into_data()
+            .price
+    }
+
+    #[tokio::test]
+    async fn increase_matching_prices() {
+        let store = in_memory_store(Default::default());
+
+        let hat_id = ProductId::new();
+        let scarf_id = ProductId::new();
+        let other_id = ProductId::new();
+        let inactive_id = ProductId::new();
+
+        for product in [
+            ProductBuilder::new()
+                .id(hat_id)
+                .title("Winter hat")
+                .price(Currency::usd(1000))
+                .build(),
+            ProductBuilder::new()
+                .id(scarf_id)
+                .title("Winter scarf")
+                .price(Currency::usd(999))
+                .build(),
+            ProductBuilder::new()
+                .id(other_id)
+                .title("Summer hat")
+                .price(Currency::usd(1000))
+                .build(),
+            ProductBuilder::new()
+                .id(inactive_id)
+                .title("Winter boots")
+                .price(Currency::usd(1000))
+                .inactive()
+                .build(),
+        ] {
+            store
+                .set_product(ActiveTransaction::auto_commit().get(), product)
+                .unwrap();
+        }
+
+        let updated = execute(
+            AdjustAllPrices {
+                percent: 10,
+                filter: Some(ProductFilter {
+                    title_contains: Some("winter".into()),
+                    ..Default::default()
+                }),
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(2, updated);
+
+        assert_eq!(Currency::usd(1100), price(&store, hat_id));
+        // 1098.9 cents rounds to the nearest cent
+        assert_eq!(Currency::usd(1099), price(&store, scarf_id));
+
+        assert_eq!(Currency::usd(1000), price(&store, other_id));
+        assert_eq!(Currency::usd(1000), price(&store, inactive_id));
+    }
+
+    #[tokio::test]
+    async fn negative_prices_are_rejected() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let id = ProductId::new();
+
+        store
+            .set_product(
+                ActiveTransaction::auto_commit().get(),
+                ProductBuilder::new()
+                    .id(id)
+                    .price(Currency::usd(1000))
+                    .build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    AdjustAllPrices {
+                        percent: -200,
+                        filter: None,
+                    },
+                    transaction,
+                    &store,
+                    &store,
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(err.to_string().contains("negative"));
+
+        assert_eq!(Currency::usd(1000), price(&store, id));
+    }
+}
\ No newline at end of file
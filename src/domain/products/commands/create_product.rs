@@ -1,6 +1,15 @@
 /*! Contains the `CreateProductCommand` type. */
 
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
 use crate::domain::{
+    error,
+    events::*,
     infra::*,
     products::*,
     Error,
@@ -23,12 +32,23 @@ async fn execute(
     command: CreateProduct,
     transaction: ActiveTransaction,
     store: impl ProductStore,
+    events: Events,
 ) -> Result<(), Error> {
-    debug!("creating product `{}`", command.id);
+    debug!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "creating product"
+    );
+
+    // Validate the title before looking for an existing product
+    Title::try_from(command.title.as_str())?;
 
     let product = {
-        if store.get_product(command.id)?.is_some() {
-            err!("product `{}` already exists", command.id)?
+        if store.get_product(transaction.read(), command.id)?.is_some() {
+            return Err(error::conflict(format!(
+                "product `{}` already exists",
+                command.id
+            )));
         } else {
             Product::new(command.id, command.title, command.price)?
         }
@@ -36,7 +56,14 @@ async fn execute(
 
     store.set_product(transaction.get(), product)?;
 
-    info!("created product `{}`", command.id);
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::ProductCreated { id }));
+
+    info!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "created product"
+    );
 
     Ok(())
 }
@@ -47,8 +74,20 @@ impl Resolver {
         self.command(|resolver, command: CreateProduct| async move {
             let store = resolver.product_store();
             let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
 
-            execute(command, active_transaction, store).await
+            let context = format!("create_product product={}", command.id);
+
+            let span = info_span!(
+                "create_product",
+                product_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store, events))
+                .await
+                .map_err(|err| err.context(context))
         })
     }
 }
@@ -57,11 +96,18 @@ impl Resolver {
 mod tests {
     use super::*;
 
-    use crate::domain::products::model::store::in_memory_store;
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        products::model::store::in_memory_store,
+        ErrorKind,
+    };
+    #[cfg(feature = "log-compat")]
+    use crate::logger;
 
     #[tokio::test]
     async fn err_if_already_exists() {
-        let store = in_memory_store(Default::default());
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
 
         let create = CreateProduct {
             id: ProductId::new(),
@@ -69,12 +115,99 @@ mod tests {
             price: Currency::usd(100),
         };
 
-        execute(create.clone(), ActiveTransaction::none(), &store)
+        execute(
+            create.clone(),
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| execute(create, transaction, &store, Events::new()))
             .await
-            .unwrap();
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
 
-        assert!(execute(create, ActiveTransaction::none(), &store)
+    #[tokio::test]
+    async fn err_if_title_is_empty() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let id = ProductId::new();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    CreateProduct {
+                        id,
+                        title: "".into(),
+                        price: Currency::usd(100),
+                    },
+                    transaction,
+                    &store,
+                    Events::new(),
+                )
+            })
             .await
-            .is_err());
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(store
+            .get_product(ActiveTransaction::auto_commit().read(), id)
+            .unwrap()
+            .is_none());
+    }
+
+    // Commands log through `tracing`, so messages are only captured as `log` records with `log-compat`
+    #[cfg(feature = "log-compat")]
+    #[tokio::test]
+    async fn log_messages_include_transaction_id() {
+        logger::test_support::init();
+
+        let store = in_memory_store(Default::default());
+
+        let create = |id| CreateProduct {
+            id,
+            title: "Test Product".into(),
+            price: Currency::usd(100),
+        };
+
+        let (first, second) = (ProductId::new(), ProductId::new());
+        let (first_transaction, second_transaction) = (
+            ActiveTransaction::auto_commit(),
+            ActiveTransaction::auto_commit(),
+        );
+        let (first_transaction_id, second_transaction_id) =
+            (first_transaction.id(), second_transaction.id());
+
+        let (first_result, second_result) = tokio::join!(
+            execute(create(first), first_transaction, &store, Events::new()),
+            execute(create(second), second_transaction, &store, Events::new()),
+        );
+
+        first_result.unwrap();
+        second_result.unwrap();
+
+        for (transaction_id, product_id, other_product_id) in [
+            (first_transaction_id, first, second),
+            (second_transaction_id, second, first),
+        ] {
+            let messages = logger::test_support::messages_containing(&transaction_id.to_string());
+
+            assert!(messages
+                .iter()
+                .any(|message| message.contains(&product_id.tagged().to_string())));
+            assert!(messages
+                .iter()
+                .all(|message| !message.contains(&other_product_id.tagged().to_string())));
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+/*! Contains the `DeactivateProductCommand`. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    products::*,
+    Error,
+};
+
+/** Input for a `DeactivateProductCommand`. */
+#[derive(Clone, Deserialize)]
+pub struct DeactivateProduct {
+    pub id: ProductId,
+}
+
+impl CommandArgs for DeactivateProduct {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for a `DeactivateProductCommand`. */
+async fn execute(
+    command: DeactivateProduct,
+    transaction: ActiveTransaction,
+    store: impl Repository<Product>,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "deactivating product"
+    );
+
+    let mut product = store
+        .get(transaction.read(), command.id)?
+        .ok_or_else(|| error::not_found("product", command.id))?;
+
+    // Deactivating a product that's already inactive doesn't change anything
+    if !product.to_data().active {
+        return Ok(());
+    }
+
+    product.deactivate();
+
+    let id = product.id();
+
+    store.save(transaction.get(), product)?;
+
+    transaction.on_commit(move || events.publish(DomainEvent::ProductDeactivated { id }));
+
+    info!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "deactivated product"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /**
+    Remove a product from the catalog.
+
+    The product isn't deleted, so orders that already contain it are left intact, but it can't be
+    added to any more orders.
+    */
+    pub fn deactivate_product_command(&self) -> impl Command<DeactivateProduct> {
+        self.command(|resolver, command: DeactivateProduct| async move {
+            let store = resolver.product_store();
+            let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
+
+            let context = format!("deactivate_product product={}", command.id);
+
+            let span = info_span!(
+                "deactivate_product",
+                product_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store, events))
+                .await
+                .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        infra::transaction::test_support::TestTransactionProvider,
+        orders::*,
+        products::model::store::in_memory_store,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn deactivated_product_can_not_be_added_to_orders() {
+        let test = Resolver::for_tests();
+
+        let customer_id = CustomerId::new();
+        let product_id = ProductId::new();
+        let existing_order_id = OrderId::new();
+        let new_order_id = OrderId::new();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id: product_id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            for id in [existing_order_id, new_order_id] {
+                resolver
+                    .create_order_command()
+                    .execute(CreateOrder {
+                        id,
+                        customer_id,
+                        client_token: None,
+                        guest: false,
+                    })
+                    .await?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let add_to = |id| {
+            test.transaction(move |resolver| async move {
+                resolver
+                    .add_or_update_product_command()
+                    .execute(AddOrUpdateProduct {
+                        id,
+                        product_id,
+                        quantity: 1,
+                    })
+                    .await
+            })
+        };
+
+        add_to(existing_order_id).await.unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .deactivate_product_command()
+                .execute(DeactivateProduct { id: product_id })
+                .await
+        })
+        .await
+        .unwrap();
+
+        // New adds fail
+        let err = add_to(new_order_id).await.unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        // Existing line items keep their product details
+        let order = test
+            .resolver()
+            .get_order_with_products_query()
+            .execute(GetOrderWithProducts {
+                id: existing_order_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, order.line_items.len());
+        assert_eq!(Some("A title"), order.line_items[0].title.as_deref());
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let id = ProductId::new();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(DeactivateProduct { id }, transaction, &store, Events::new())
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&id.to_string()));
+    }
+}
\ No newline at end of file
@@ -1,9 +1,36 @@
 /*! Commands for modifying product state. */
 
+mod adjust_all_prices;
 mod create_product;
+mod deactivate_product;
 mod set_product_title;
+mod update_product;
 
 pub use self::{
+    adjust_all_prices::*,
     create_product::*,
+    deactivate_product::*,
     set_product_title::*,
-};
\ No newline at end of file
+    update_product::*,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::infra::{
+        test_support::assert_command,
+        Resolver,
+    };
+
+    #[test]
+    fn commands_are_resolved() {
+        let resolver = Resolver::for_tests().resolver();
+
+        assert_command::<AdjustAllPrices>(resolver.adjust_all_prices_command());
+        assert_command::<CreateProduct>(resolver.create_product_command());
+        assert_command::<DeactivateProduct>(resolver.deactivate_product_command());
+        assert_command::<SetProductTitle>(resolver.set_product_title_command());
+        assert_command::<UpdateProduct>(resolver.update_product_command());
+    }
+}
\ No newline at end of file
@@ -1,7 +1,15 @@
 /*! Contains the `SetProductTitleCommand`. */
 
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
 use crate::domain::{
     error,
+    events::*,
     infra::*,
     products::*,
     Error,
@@ -23,25 +31,35 @@ async fn execute(
     command: SetProductTitle,
     transaction: ActiveTransaction,
     store: impl ProductStore,
+    events: Events,
 ) -> Result<(), Error> {
     debug!(
-        "updating product `{}` title to {:?}",
-        command.id, command.title
+        product_id = %command.id.tagged(),
+        title = ?command.title,
+        transaction_id = %transaction.id(),
+        "updating product title"
     );
 
     let product = {
-        if let Some(mut product) = store.get_product(command.id)? {
+        if let Some(mut product) = store.get_product(transaction.read(), command.id)? {
             product.set_title(command.title)?;
 
             product
         } else {
-            return Err(error::msg("not found"));
+            return Err(error::not_found("product", command.id));
         }
     };
 
     store.set_product(transaction.get(), product)?;
 
-    info!("updated product `{}` title", command.id);
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::ProductTitleSet { id }));
+
+    info!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "updated product title"
+    );
 
     Ok(())
 }
@@ -52,8 +70,59 @@ impl Resolver {
         self.command(|resolver, command: SetProductTitle| async move {
             let store = resolver.product_store();
             let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
 
-            execute(command, active_transaction, store).await
+            let context = format!("set_product_title product={}", command.id);
+
+            let span = info_span!(
+                "set_product_title",
+                product_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store, events))
+                .await
+                .map_err(|err| err.context(context))
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        products::model::store::in_memory_store,
+        ErrorKind,
+    };
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let id = ProductId::new();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    SetProductTitle {
+                        id,
+                        title: "A title".into(),
+                    },
+                    transaction,
+                    &store,
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains(&id.to_string()));
+    }
 }
\ No newline at end of file
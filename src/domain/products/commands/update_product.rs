@@ -0,0 +1,243 @@
+/*! Contains the `UpdateProductCommand`. */
+
+use tracing::{
+    debug,
+    field,
+    info,
+    info_span,
+};
+
+use crate::domain::{
+    error,
+    events::*,
+    infra::*,
+    products::*,
+    Error,
+};
+
+/**
+Input for an `UpdateProductCommand`.
+
+Fields that are `None` are left unchanged, so callers only need to send the fields they're changing.
+*/
+#[derive(Clone, Deserialize)]
+pub struct UpdateProduct {
+    pub id: ProductId,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub price: Option<Currency>,
+}
+
+impl CommandArgs for UpdateProduct {
+    type Output = Result<(), Error>;
+}
+
+/** Default implementation for an `UpdateProductCommand`. */
+async fn execute(
+    command: UpdateProduct,
+    transaction: ActiveTransaction,
+    store: impl ProductStore,
+    events: Events,
+) -> Result<(), Error> {
+    debug!(
+        product_id = %command.id.tagged(),
+        title = ?command.title,
+        transaction_id = %transaction.id(),
+        "updating product"
+    );
+
+    if command.title.is_none() && command.price.is_none() {
+        return Ok(());
+    }
+
+    let mut product = store
+        .get_product(transaction.read(), command.id)?
+        .ok_or_else(|| error::not_found("product", command.id))?;
+
+    // All fields are validated before the product is saved, so an invalid field leaves it unchanged
+    if let Some(title) = command.title {
+        product.set_title(title)?;
+    }
+
+    if let Some(price) = command.price {
+        product.set_price(price)?;
+    }
+
+    store.set_product(transaction.get(), product)?;
+
+    let id = command.id;
+    transaction.on_commit(move || events.publish(DomainEvent::ProductUpdated { id }));
+
+    info!(
+        product_id = %command.id.tagged(),
+        transaction_id = %transaction.id(),
+        "updated product"
+    );
+
+    Ok(())
+}
+
+impl Resolver {
+    /** Update some of the fields of an existing product. */
+    pub fn update_product_command(&self) -> impl Command<UpdateProduct> {
+        self.command(|resolver, command: UpdateProduct| async move {
+            let store = resolver.product_store();
+            let active_transaction = resolver.active_transaction();
+            let events = resolver.events();
+
+            let context = format!("update_product product={}", command.id);
+
+            let span = info_span!(
+                "update_product",
+                product_id = %command.id.tagged(),
+                transaction_id = %active_transaction.id(),
+                outcome = field::Empty,
+            );
+
+            in_command_span(span, execute(command, active_transaction, store, events))
+                .await
+                .map_err(|err| err.context(context))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::transaction::test_support::TestTransactionProvider,
+        products::model::{
+            store::in_memory_store,
+            test_data::{
+                default_price,
+                default_title,
+                ProductBuilder,
+            },
+        },
+        ErrorKind,
+    };
+
+    async fn update(title: Option<&str>, price: Option<Currency>) -> ProductData {
+        let store = in_memory_store(Default::default());
+
+        let id = ProductId::new();
+
+        store
+            .set_product(
+                ActiveTransaction::auto_commit().get(),
+                ProductBuilder::new().id(id).build(),
+            )
+            .unwrap();
+
+        execute(
+            UpdateProduct {
+                id,
+                title: title.map(Into::into),
+                price,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap();
+
+        store
+            .get_product(ActiveTransaction::auto_commit().read(), id)
+            .unwrap()
+            .unwrap()
+            .into_data()
+    }
+
+    #[tokio::test]
+    async fn update_title_only() {
+        let product = update(Some("A new title"), None).await;
+
+        assert_eq!("A new title", product.title);
+        assert_eq!(default_price(), product.price);
+    }
+
+    #[tokio::test]
+    async fn update_price_only() {
+        let product = update(None, Some(Currency::usd(250))).await;
+
+        assert_eq!(default_title(), product.title);
+        assert_eq!(Currency::usd(250), product.price);
+    }
+
+    #[tokio::test]
+    async fn update_title_and_price() {
+        let product = update(Some("A new title"), Some(Currency::usd(250))).await;
+
+        assert_eq!("A new title", product.title);
+        assert_eq!(Currency::usd(250), product.price);
+    }
+
+    #[tokio::test]
+    async fn invalid_field_leaves_product_unchanged() {
+        let transactions = TestTransactionProvider::new();
+        let store = in_memory_store(transactions.transaction_store());
+
+        let id = ProductId::new();
+
+        store
+            .set_product(
+                ActiveTransaction::auto_commit().get(),
+                ProductBuilder::new().id(id).build(),
+            )
+            .unwrap();
+
+        let err = transactions
+            .transaction(|transaction| {
+                execute(
+                    UpdateProduct {
+                        id,
+                        title: Some("".into()),
+                        price: Some(Currency::usd(250)),
+                    },
+                    transaction,
+                    &store,
+                    Events::new(),
+                )
+            })
+            .await
+            .unwrap_err();
+
+        transactions.assert_rolled_back();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+
+        let product = store
+            .get_product(ActiveTransaction::auto_commit().read(), id)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!(default_title(), product.title);
+        assert_eq!(default_price(), product.price);
+    }
+
+    #[tokio::test]
+    async fn err_if_not_found() {
+        let store = in_memory_store(Default::default());
+
+        let id = ProductId::new();
+
+        let err = execute(
+            UpdateProduct {
+                id,
+                title: Some("A new title".into()),
+                price: None,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+            Events::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(ErrorKind::NotFound, err.kind());
+    }
+}
\ No newline at end of file
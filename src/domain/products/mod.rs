@@ -5,12 +5,16 @@ pub mod model;
 pub mod queries;
 pub(in crate::domain) mod resolver;
 
-pub(self) use self::model::store::{
-    ProductStore,
-    ProductStoreFilter,
-};
 pub use self::{
     commands::*,
-    model::*,
+    model::{
+        store::{
+            Page,
+            ProductStore,
+            ProductStoreFilter,
+            ProductStoreWithFilter,
+        },
+        *,
+    },
     queries::*,
 };
\ No newline at end of file
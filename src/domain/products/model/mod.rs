@@ -5,7 +5,12 @@ use std::convert::{
     TryInto,
 };
 
+use uuid::Uuid;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
 pub mod store;
+pub mod wal_store;
 
 #[cfg(test)]
 pub mod test_data;
@@ -20,6 +25,20 @@ pub type ProductId = Id<ProductData>;
 pub type NextProductId = NextId<ProductData>;
 pub type ProductVersion = Version<ProductData>;
 
+/** The namespace used to derive product ids from legacy integer ids. */
+const LEGACY_PRODUCT_ID_NAMESPACE: Uuid = Uuid::from_u128(0xec24f251_7562_4e5f_9584_192cbd5b68a5);
+
+impl ProductId {
+    /**
+    Map a legacy integer product id to a product id.
+
+    The same legacy id will always map to the same product id, so repeated migrations are stable.
+    */
+    pub fn from_legacy(id: i32) -> Self {
+        ProductId::from_name(&LEGACY_PRODUCT_ID_NAMESPACE, &id.to_be_bytes())
+    }
+}
+
 /**
 A product title.
 
@@ -32,7 +51,7 @@ impl TryFrom<String> for Title {
 
     fn try_from(title: String) -> Result<Self, Self::Error> {
         if title.is_empty() {
-            return Err(error::msg("title must not be empty"));
+            return Err(error::bad_input("title must not be empty"));
         }
 
         Ok(Title(title))
@@ -69,10 +88,46 @@ pub struct ProductData {
     pub version: ProductVersion,
     pub title: String,
     pub price: Currency,
+    #[serde(default = "active_by_default")]
+    pub active: bool,
+    /** How much the product weighs for shipping, if it's known. */
+    #[serde(default)]
+    pub weight_grams: Option<u32>,
     _private: (),
 }
 
+fn active_by_default() -> bool {
+    true
+}
+
+#[cfg(feature = "storage-adapter")]
+impl ProductData {
+    /**
+    Restore the data for a product that was read from an external store.
+
+    The title and price are checked the same way they are when a product is created.
+    This is only for storage adapters. Application code should create products with `Product::new`.
+    */
+    pub fn restore(
+        id: ProductId,
+        version: ProductVersion,
+        title: impl TryInto<Title, Error = Error>,
+        price: impl TryInto<Price, Error = Error>,
+    ) -> Result<Self, Error> {
+        Ok(ProductData {
+            id,
+            version,
+            title: title.try_into()?.0,
+            price: price.try_into()?.0,
+            active: true,
+            weight_grams: None,
+            _private: (),
+        })
+    }
+}
+
 /** A product with some simple metadata. */
+#[derive(Clone)]
 pub struct Product {
     data: ProductData,
 }
@@ -90,6 +145,24 @@ impl Product {
         &self.data
     }
 
+    /**
+    Restore a product from its data.
+
+    The data is checked again, because its fields may have been changed since it was restored.
+    */
+    #[cfg(feature = "storage-adapter")]
+    pub fn restore(data: ProductData) -> Result<Self, Error> {
+        Product::checked(data)
+    }
+
+    /** Build a product from data that came from outside the domain, checking it like `restore`. */
+    pub(in crate::domain) fn checked(data: ProductData) -> Result<Self, Error> {
+        Title::try_from(data.title.as_str())?;
+        Price::try_from(data.price)?;
+
+        Ok(Product::from_data(data))
+    }
+
     pub fn new(
         id: impl IdProvider<ProductData>,
         title: impl TryInto<Title, Error = Error>,
@@ -102,6 +175,8 @@ impl Product {
             version: ProductVersion::default(),
             title: title.try_into()?.0,
             price: price.try_into()?.0,
+            active: true,
+            weight_grams: None,
             _private: (),
         }))
     }
@@ -114,6 +189,26 @@ impl Product {
 
         Ok(())
     }
+
+    pub fn set_price(&mut self, price: impl TryInto<Price, Error = Error>) -> Result<(), Error> {
+        self.data.price = price.try_into()?.0;
+
+        Ok(())
+    }
+
+    pub fn set_weight_grams(&mut self, weight_grams: Option<u32>) {
+        self.data.weight_grams = weight_grams;
+    }
+
+    /**
+    Soft delete the product.
+
+    Inactive products are still stored, so orders that reference them can still be read, but
+    they're hidden from queries unless they're explicitly asked for.
+    */
+    pub fn deactivate(&mut self) {
+        self.data.active = false;
+    }
 }
 
 impl Entity for Product {
@@ -121,11 +216,81 @@ impl Entity for Product {
     type Version = ProductVersion;
     type Data = ProductData;
     type Error = Error;
+
+    fn id(&self) -> ProductId {
+        self.data.id
+    }
+
+    fn version(&self) -> ProductVersion {
+        self.data.version
+    }
+}
+
+impl InMemoryEntity for Product {
+    fn from_data(data: ProductData) -> Self {
+        Product::from_data(data)
+    }
+
+    fn into_data_with_version(self, version: ProductVersion) -> ProductData {
+        ProductData {
+            version,
+            ..self.data
+        }
+    }
+}
+
+/**
+Which products a query or bulk change applies to.
+
+Products must match all of the fields that are set on the filter. An empty filter matches every
+active product.
+*/
+#[derive(Clone, Default, Deserialize)]
+pub struct ProductFilter {
+    /** Only match these products. */
+    #[serde(default)]
+    pub ids: Option<Vec<ProductId>>,
+    /** Only match products with a title that contains this text, ignoring case. */
+    #[serde(default)]
+    pub title_contains: Option<String>,
+    /** Only match products with a price in the same currency that's no more than this. */
+    #[serde(default)]
+    pub max_price: Option<Currency>,
+    /** Whether to also match products that have been deactivated. */
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+impl ProductFilter {
+    pub(in crate::domain) fn matches(&self, product: &ProductData) -> bool {
+        let title_contains = self.title_contains.as_ref().map(|text| text.to_lowercase());
+
+        (self.include_inactive || product.active)
+            && self
+                .ids
+                .as_ref()
+                .map(|ids| ids.contains(&product.id))
+                .unwrap_or(true)
+            && title_contains
+                .map(|text| product.title.to_lowercase().contains(&text))
+                .unwrap_or(true)
+            && self
+                .max_price
+                .map(|max| {
+                    product.price.code() == max.code()
+                        && product.price.minor_units() <= max.minor_units()
+                })
+                .unwrap_or(true)
+    }
+}
+
+impl IdTag for ProductData {
+    const TAG: &'static str = "product";
 }
 
 impl Resolver {
     pub fn product_id(&self) -> impl IdProvider<ProductData> {
-        NextId::<ProductData>::new()
+        self.resolve(&self.products_resolver.product_id)
     }
 }
 
@@ -141,4 +306,36 @@ mod tests {
 
         assert!(product.set_title("").is_err());
     }
+
+    #[test]
+    fn legacy_ids_are_deterministic() {
+        assert_eq!(ProductId::from_legacy(42), ProductId::from_legacy(42));
+        assert_ne!(ProductId::from_legacy(42), ProductId::from_legacy(43));
+        assert_ne!(ProductId::from_legacy(-1), ProductId::from_legacy(1));
+    }
+
+    #[test]
+    fn entity_accessors_match_data() {
+        let product = Product::new(ProductId::new(), "A title", Currency::usd(100)).unwrap();
+
+        assert_eq!(product.to_data().id, product.id());
+        assert_eq!(product.to_data().version, product.version());
+    }
+
+    #[cfg(feature = "storage-adapter")]
+    #[test]
+    fn restored_products_are_validated() {
+        let id = ProductId::new();
+
+        assert!(ProductData::restore(id, ProductVersion::new(), "", Currency::usd(100)).is_err());
+
+        let mut data =
+            ProductData::restore(id, ProductVersion::new(), "A title", Currency::usd(100)).unwrap();
+
+        assert!(Product::restore(data.clone()).is_ok());
+
+        data.title = String::new();
+
+        assert!(Product::restore(data).is_err());
+    }
 }
\ No newline at end of file
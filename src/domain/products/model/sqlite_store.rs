@@ -0,0 +1,244 @@
+/*! Product storage on top of a SQLite database. */
+
+use std::{
+    ops::ControlFlow,
+    sync::Arc,
+};
+
+use rusqlite::params;
+
+use crate::{
+    domain::{
+        infra::{
+            sqlite::{
+                sqlite_error,
+                Database,
+                Row,
+            },
+            Flush,
+            InMemoryRepository,
+            Repository,
+        },
+        products::{
+            model::store::*,
+            *,
+        },
+        Error,
+    },
+    store::{
+        Transaction,
+        TransactionStore,
+    },
+};
+
+/**
+A product store that persists products to the `products` table of a SQLite database.
+
+Products are read from the database when the store is opened and kept in memory for reads.
+See the `sqlite` module for when changes are written.
+*/
+pub(in crate::domain) struct SqliteProductStore {
+    products: Arc<InMemoryRepository<ProductData>>,
+    database: Database,
+    transactions: TransactionStore,
+}
+
+impl SqliteProductStore {
+    /** Open a store backed by `database`, reading any products that are already in it. */
+    pub(in crate::domain) fn open(
+        database: Database,
+        transaction_store: TransactionStore,
+    ) -> Result<Self, Error> {
+        let store = SqliteProductStore {
+            products: Arc::new(InMemoryRepository::new(transaction_store.clone())),
+            database,
+            transactions: transaction_store,
+        };
+
+        let rows = store.database.read(|connection| {
+            connection
+                .prepare("SELECT data FROM products")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        for data in rows {
+            let data: ProductData = serde_json::from_str(&data)
+                .map_err(sqlite_error("a product in the database is corrupted"))?;
+
+            store.products.restore(Product::from_data(data))?;
+        }
+
+        Ok(store)
+    }
+}
+
+/** Write the state of a product as it's seen by the transaction that's committing. */
+fn write(
+    products: &InMemoryRepository<ProductData>,
+    transaction: &Transaction,
+    sqlite_transaction: &rusqlite::Transaction,
+    id: ProductId,
+) -> Result<(), Error> {
+    let data = match Repository::<Product>::get(products, transaction, id)? {
+        Some(product) => product.into_data(),
+        None => return Ok(()),
+    };
+
+    let json =
+        serde_json::to_string(&data).map_err(sqlite_error("failed to serialize a product"))?;
+
+    sqlite_transaction
+        .prepare_cached(
+            "INSERT INTO products (id, version, data) VALUES (?1, ?2, ?3)
+            ON CONFLICT (id) DO UPDATE SET
+                version = excluded.version,
+                data = excluded.data",
+        )
+        .and_then(|mut upsert| upsert.execute(params![data.id, data.version.to_string(), json]))
+        .map_err(sqlite_error("failed to write a product to the database"))?;
+
+    Ok(())
+}
+
+impl ProductStore for SqliteProductStore {
+    fn get_product(
+        &self,
+        transaction: &Transaction,
+        id: ProductId,
+    ) -> Result<Option<Product>, Error> {
+        self.products.get(transaction, id)
+    }
+
+    fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
+        let id = product.to_data().id;
+
+        self.products.save(transaction, product)?;
+
+        let products = self.products.clone();
+
+        self.database.write(
+            &self.transactions,
+            transaction,
+            Row::Product(id),
+            move |transaction, sqlite_transaction| {
+                write(&products, transaction, sqlite_transaction, id)
+            },
+        )
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error> {
+        self.products.scan(f);
+
+        Ok(())
+    }
+}
+
+impl Flush for SqliteProductStore {}
+
+impl ProductStoreFilter for SqliteProductStore {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<Iter, Error> {
+        Ok(self.products.filter(transaction, predicate).into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        infra::repository::test_support::assert_repository_round_trip,
+        products::model::test_data,
+    };
+
+    fn stored_ids(database: &Database) -> Vec<ProductId> {
+        database
+            .read(|connection| {
+                connection
+                    .prepare("SELECT id FROM products ORDER BY id")?
+                    .query_map([], |row| row.get(0))?
+                    .collect()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn sqlite_store_is_a_repository() {
+        let database = Database::open(":memory:").unwrap();
+        let store = SqliteProductStore::open(database, Default::default()).unwrap();
+
+        assert_repository_round_trip(&store, test_data::default_product());
+    }
+
+    #[test]
+    fn products_are_read_back_when_reopened() {
+        let database = Database::open(":memory:").unwrap();
+
+        let id = ProductId::new();
+
+        let store = SqliteProductStore::open(database.clone(), Default::default()).unwrap();
+        store
+            .set_product(
+                &Transaction::none(),
+                test_data::ProductBuilder::new()
+                    .id(id)
+                    .title("A title")
+                    .build(),
+            )
+            .unwrap();
+        drop(store);
+
+        let store = SqliteProductStore::open(database, Default::default()).unwrap();
+        let found = store
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("A title", found.to_data().title);
+
+        // The version is kept, so saving a stale product still fails
+        assert!(store
+            .set_product(
+                &Transaction::none(),
+                test_data::ProductBuilder::new().id(id).build()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn products_are_written_when_their_transaction_commits() {
+        let database = Database::open(":memory:").unwrap();
+        let transactions = TransactionStore::new();
+        let store = SqliteProductStore::open(database.clone(), transactions.clone()).unwrap();
+
+        let committed_id = ProductId::new();
+        let cancelled_id = ProductId::new();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+
+        store
+            .set_product(
+                &committed,
+                test_data::ProductBuilder::new().id(committed_id).build(),
+            )
+            .unwrap();
+        store
+            .set_product(
+                &cancelled,
+                test_data::ProductBuilder::new().id(cancelled_id).build(),
+            )
+            .unwrap();
+
+        assert!(stored_ids(&database).is_empty());
+
+        transactions.commit(committed).unwrap();
+        transactions.cancel(cancelled);
+
+        assert_eq!(vec![committed_id], stored_ids(&database));
+    }
+}
\ No newline at end of file
@@ -1,92 +1,416 @@
 /*! Persistent storage for products. */
 
-use std::vec::IntoIter;
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        self,
+        Write,
+    },
+    ops::ControlFlow,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    vec::IntoIter,
+};
 
 use crate::{
     domain::{
+        infra::{
+            Flush,
+            InMemoryRepository,
+            Repository,
+        },
         products::*,
         Error,
+        ErrorKind,
     },
     store::*,
 };
 
 /* A place to persist and fetch product entities. */
 #[auto_impl(&, Arc)]
-pub(in crate::domain) trait ProductStore {
-    fn get_product(&self, id: ProductId) -> Result<Option<Product>, Error>;
+pub trait ProductStore: Flush {
+    /** Get a product as it's seen by a transaction, including its own uncommitted changes. */
+    fn get_product(
+        &self,
+        transaction: &Transaction,
+        id: ProductId,
+    ) -> Result<Option<Product>, Error>;
     fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error>;
+
+    /**
+    Visit every committed product, in no particular order, until `f` breaks.
+
+    Products are borrowed rather than cloned, so walking the whole catalog is cheap.
+    `f` shouldn't write to the store it's scanning.
+    */
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error>;
+}
+
+impl<T> Repository<Product> for T
+where
+    T: ProductStore + ?Sized,
+{
+    fn get(&self, transaction: &Transaction, id: ProductId) -> Result<Option<Product>, Error> {
+        self.get_product(transaction, id)
+    }
+
+    fn save(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
+        self.set_product(transaction, product)
+    }
 }
 
 /**
 An additional store for fetching multiple product records at a time.
 
-This trait is an implementation detail that lets us fetch more than one product.
-It will probably need to be refactored or just removed when we add a proper database.
-The fact that it's internal to `domain::products` though means the scope of breakage is a bit smaller.
+This trait lets us fetch more than one product.
+It will probably need to be refactored when we add a proper database.
+Stores given to `App::with_product_store` implement it too, so changing it will break them.
 Commands and queries that depend on `ProductStoreFilter` won't need to break their public API.
 */
 #[auto_impl(&, Arc)]
-pub(in crate::domain) trait ProductStoreFilter {
-    fn filter<F>(&self, predicate: F) -> Result<Iter, Error>
-    where
-        F: Fn(&ProductData) -> bool;
+pub trait ProductStoreFilter {
+    /** Get the products that match a predicate, as they're seen by a transaction. */
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<Iter, Error>;
+
+    /**
+    Get a page of the products that match a filter, ordered by id.
+
+    Queries over products should go through here rather than building their own predicates,
+    so they all agree on what a filter means.
+    */
+    fn query(
+        &self,
+        transaction: &Transaction,
+        filter: &ProductFilter,
+        page: Page,
+    ) -> Result<Iter, Error> {
+        let mut products: Vec<_> = self
+            .filter(transaction, &|product| filter.matches(product))?
+            .collect();
+
+        products.sort_by_key(|product| product.id);
+
+        Ok(products
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>()
+            .into_iter())
+    }
+}
+
+pub type Iter = IntoIter<ProductData>;
+
+/**
+A page of results from a product query.
+
+The default page has every result.
+*/
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Page {
+    /** How many results to skip. */
+    pub offset: usize,
+    /** The most results to return. */
+    pub limit: Option<usize>,
 }
 
-pub(in crate::domain) type Iter = IntoIter<ProductData>;
+/** A store that can both persist and filter products. */
+pub trait ProductStoreWithFilter: ProductStore + ProductStoreFilter {}
+
+impl<T> ProductStoreWithFilter for T where T: ProductStore + ProductStoreFilter + ?Sized {}
+
+/** A shared product store that can be stored in a resolver. */
+pub(in crate::domain) type DynProductStore = Arc<dyn ProductStoreWithFilter + Send + Sync>;
 
 /** A test in-memory product store. */
-pub(in crate::domain) struct InMemoryStore(TransactionValueStore<ProductData>);
+pub(in crate::domain) struct InMemoryStore(InMemoryRepository<ProductData>);
 
 impl ProductStore for InMemoryStore {
-    fn get_product(&self, id: ProductId) -> Result<Option<Product>, Error> {
-        if let Some((version, data)) = self.0.get(id) {
-            assert_eq!(version, data.version.into());
+    fn get_product(
+        &self,
+        transaction: &Transaction,
+        id: ProductId,
+    ) -> Result<Option<Product>, Error> {
+        self.0.get(transaction, id)
+    }
+
+    fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
+        self.0.save(transaction, product)
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error> {
+        self.0.scan(f);
+
+        Ok(())
+    }
+}
+
+impl Flush for InMemoryStore {}
+
+impl ProductStoreFilter for InMemoryStore {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<Iter, Error> {
+        Ok(self.0.filter(transaction, predicate).into_iter())
+    }
+}
+
+/**
+A product store that keeps the catalog in a JSON file, so it survives restarts.
+
+Products are read from the file when the store is opened and kept in memory for reads.
+The whole catalog is written each time a product is set. It's written to a temporary file
+next to the real one first and then renamed over it, so a crash part way through a write
+can't leave the file corrupted.
+
+Only committed products are written. A product set in a transaction is written as part of
+committing it, so the transaction fails to commit if the catalog can't be written. A product set
+in a transaction that's cancelled is never written.
+*/
+pub(in crate::domain) struct FileProductStore {
+    catalog: Arc<Catalog>,
+    /** Products that have been set in transactions but not written to the file yet. */
+    pending: PendingWrites<ProductId>,
+}
+
+/** The products in a file store, shared with the writes waiting on transactions to commit. */
+struct Catalog {
+    products: InMemoryRepository<ProductData>,
+    path: PathBuf,
+    transactions: TransactionStore,
+    /** The transaction that last wrote the file, until it completes. */
+    writer: Mutex<Option<TransactionId>>,
+    completed: Condvar,
+}
+
+impl FileProductStore {
+    /**
+    Open a store backed by the file at `path`.
+
+    The catalog starts out empty if the file doesn't exist yet.
+    It's an error if the file exists but can't be read as a catalog.
+    */
+    pub(in crate::domain) fn open(
+        path: impl Into<PathBuf>,
+        transaction_store: TransactionStore,
+    ) -> Result<Self, Error> {
+        let path = path.into();
+
+        let catalog = Catalog {
+            products: InMemoryRepository::new(transaction_store.clone()),
+            path,
+            transactions: transaction_store.clone(),
+            writer: Mutex::new(None),
+            completed: Condvar::new(),
+        };
+
+        for data in catalog.read()? {
+            catalog.products.restore(Product::from_data(data))?;
+        }
+
+        Ok(FileProductStore {
+            catalog: Arc::new(catalog),
+            pending: PendingWrites::new(transaction_store),
+        })
+    }
+}
+
+impl Catalog {
+    fn read(&self) -> Result<Vec<ProductData>, Error> {
+        let json = match fs::read(&self.path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(Error::with_source(
+                    ErrorKind::Internal,
+                    format!("failed to read products from `{}`", self.path.display()),
+                    err,
+                ))
+            }
+        };
+
+        serde_json::from_slice(&json).map_err(|err| {
+            Error::with_source(
+                ErrorKind::Internal,
+                format!(
+                    "the products file `{}` is corrupted: {}",
+                    self.path.display(),
+                    err
+                ),
+                err,
+            )
+        })
+    }
+
+    /**
+    Write the whole catalog to the file, as it's seen by a transaction that's committing.
+
+    Writes are serialized so they don't race on the temporary file. A transaction that writes the
+    file also keeps other transactions from writing it until it completes. Otherwise one that
+    isn't committed yet could have its products overwritten by another that can't see them.
+    */
+    fn write(&self, transaction: &Transaction) -> Result<(), Error> {
+        let mut writer = lock::lock(&self.writer);
+
+        while let Some(other) = *writer {
+            if other == transaction.id() || !self.is_active(other) {
+                break;
+            }
 
-            Ok(Some(Product::from_data(data)))
-        } else {
-            Ok(None)
+            writer = lock::wait(&self.completed, writer);
         }
+
+        // A transaction that isn't tracked has already committed, so there's nothing to wait for
+        *writer = Some(transaction.id()).filter(|&id| self.is_active(id));
+
+        let products = self.products.filter(transaction, &|_| true);
+
+        serde_json::to_vec_pretty(&products)
+            .map_err(io::Error::from)
+            .and_then(|json| write_atomically(&self.path, &json))
+            .map_err(|err| {
+                Error::with_source(
+                    ErrorKind::Internal,
+                    format!("failed to write products to `{}`", self.path.display()),
+                    err,
+                )
+            })
+    }
+
+    /** Let other transactions write the file once the one that last wrote it has completed. */
+    fn completed(&self) {
+        let mut writer = lock::lock(&self.writer);
+
+        if let Some(id) = *writer {
+            if !self.is_active(id) {
+                *writer = None;
+                self.completed.notify_all();
+            }
+        }
+    }
+
+    fn is_active(&self, id: TransactionId) -> bool {
+        !self.transactions.is_committed(id) && !self.transactions.is_cancelled(id)
+    }
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let mut temp = File::create(&temp_path)?;
+    temp.write_all(contents)?;
+    temp.sync_all()?;
+
+    fs::rename(&temp_path, path)
+}
+
+impl ProductStore for FileProductStore {
+    fn get_product(
+        &self,
+        transaction: &Transaction,
+        id: ProductId,
+    ) -> Result<Option<Product>, Error> {
+        self.catalog.products.get(transaction, id)
     }
 
     fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
-        let mut data = product.into_data();
-        let id = data.id;
+        let id = product.to_data().id;
 
-        self.0.set(
-            transaction,
-            id,
-            Some(data.version),
-            data.version.next(),
-            data,
-        )?;
+        self.catalog.products.save(transaction, product)?;
+
+        let catalog = self.catalog.clone();
+        self.pending.push(transaction, id, move |transaction, _| {
+            catalog.write(transaction)
+        })?;
+
+        let catalog = self.catalog.clone();
+        self.catalog
+            .transactions
+            .on_complete(transaction, move |_| catalog.completed());
+
+        Ok(())
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error> {
+        self.catalog.products.scan(f);
 
         Ok(())
     }
 }
 
-impl ProductStoreFilter for InMemoryStore {
-    #[allow(clippy::needless_collect)]
-    fn filter<F>(&self, predicate: F) -> Result<Iter, Error>
-    where
-        F: Fn(&ProductData) -> bool,
-    {
-        let products: Vec<_> = self.0.get_all(predicate).map(|(_, data)| data).collect();
+impl Flush for FileProductStore {
+    fn flush(&self) -> Result<(), Error> {
+        self.catalog.write(&Transaction::none())
+    }
+}
 
-        Ok(products.into_iter())
+impl ProductStoreFilter for FileProductStore {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<Iter, Error> {
+        Ok(self
+            .catalog
+            .products
+            .filter(transaction, predicate)
+            .into_iter())
     }
 }
 
 pub(in crate::domain::products) fn in_memory_store(
     transaction_store: TransactionStore,
 ) -> InMemoryStore {
-    InMemoryStore(TransactionValueStore::new(transaction_store))
+    InMemoryStore(InMemoryRepository::new(transaction_store))
+}
+
+/**
+An in-memory product store where writers to different products don't block each other.
+
+It keeps the same rules as `in_memory_store`, but its products are locked in shards.
+*/
+#[cfg(feature = "concurrent")]
+pub(in crate::domain::products) fn concurrent_store(
+    transaction_store: TransactionStore,
+) -> InMemoryStore {
+    InMemoryStore(InMemoryRepository::concurrent(transaction_store))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::domain::products::model::test_data;
+    use crate::domain::{
+        infra::{
+            repository::test_support::assert_repository_round_trip,
+            Currency,
+        },
+        products::model::test_data,
+    };
+
+    #[test]
+    fn product_store_is_a_repository() {
+        let store = in_memory_store(Default::default());
+
+        assert_repository_round_trip(&store, test_data::default_product());
+    }
 
     #[test]
     fn test_in_memory_store() {
@@ -99,7 +423,10 @@ mod tests {
         store.set_product(&Transaction::none(), product).unwrap();
 
         // Get the product from the store
-        let found = store.get_product(id).unwrap().unwrap();
+        let found = store
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .unwrap();
         assert_eq!(id, found.data.id);
     }
 
@@ -125,4 +452,381 @@ mod tests {
             )
             .is_err());
     }
+
+    #[test]
+    fn scan_visits_committed_products() {
+        let transactions = TransactionStore::new();
+        let store = in_memory_store(transactions.clone());
+
+        for _ in 0..5 {
+            store
+                .set_product(&Transaction::none(), test_data::default_product())
+                .unwrap();
+        }
+
+        // Products in a transaction that hasn't committed aren't visited
+        let active = transactions.begin();
+        store
+            .set_product(&active, test_data::default_product())
+            .unwrap();
+
+        let mut visited = 0;
+        store
+            .scan(&mut |_| {
+                visited += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+
+        assert_eq!(5, visited);
+    }
+
+    #[test]
+    fn scan_stops_on_break() {
+        let store = in_memory_store(Default::default());
+
+        for _ in 0..5 {
+            store
+                .set_product(&Transaction::none(), test_data::default_product())
+                .unwrap();
+        }
+
+        let mut visited = 0;
+        store
+            .scan(&mut |_| {
+                visited += 1;
+
+                if visited == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+
+        assert_eq!(2, visited);
+    }
+
+    fn query_ids(store: &InMemoryStore, filter: &ProductFilter, page: Page) -> Vec<ProductId> {
+        store
+            .query(&Transaction::none(), filter, page)
+            .unwrap()
+            .map(|product| product.id)
+            .collect()
+    }
+
+    #[test]
+    fn query_combines_title_and_price() {
+        let store = in_memory_store(Default::default());
+
+        let cheap_hat = ProductId::new();
+        let dear_hat = ProductId::new();
+        let cheap_scarf = ProductId::new();
+        let inactive_hat = ProductId::new();
+
+        for product in [
+            test_data::ProductBuilder::new()
+                .id(cheap_hat)
+                .title("Winter hat")
+                .price(Currency::usd(1000)),
+            test_data::ProductBuilder::new()
+                .id(dear_hat)
+                .title("Fancy hat")
+                .price(Currency::usd(1001)),
+            test_data::ProductBuilder::new()
+                .id(cheap_scarf)
+                .title("Winter scarf")
+                .price(Currency::usd(500)),
+            test_data::ProductBuilder::new()
+                .id(inactive_hat)
+                .title("Summer hat")
+                .price(Currency::usd(500))
+                .inactive(),
+        ] {
+            store
+                .set_product(&Transaction::none(), product.build())
+                .unwrap();
+        }
+
+        let hats_up_to = |cents, include_inactive| ProductFilter {
+            title_contains: Some("HAT".into()),
+            max_price: Some(Currency::usd(cents)),
+            include_inactive,
+            ..Default::default()
+        };
+
+        // The maximum price is inclusive
+        assert_eq!(
+            vec![cheap_hat],
+            query_ids(&store, &hats_up_to(1000, false), Page::default())
+        );
+
+        let mut expected = vec![cheap_hat, inactive_hat];
+        expected.sort();
+
+        assert_eq!(
+            expected,
+            query_ids(&store, &hats_up_to(1000, true), Page::default())
+        );
+
+        // Without a title, only the price is used
+        let mut expected = vec![cheap_hat, cheap_scarf];
+        expected.sort();
+
+        assert_eq!(
+            expected,
+            query_ids(
+                &store,
+                &ProductFilter {
+                    max_price: Some(Currency::usd(1000)),
+                    ..Default::default()
+                },
+                Page::default()
+            )
+        );
+    }
+
+    #[test]
+    fn query_pages_are_ordered_by_id() {
+        let store = in_memory_store(Default::default());
+
+        let mut ids = vec![];
+        for _ in 0..5 {
+            let product = test_data::default_product();
+            ids.push(product.to_data().id);
+
+            store.set_product(&Transaction::none(), product).unwrap();
+        }
+        ids.sort();
+
+        let page = |offset, limit| Page {
+            offset,
+            limit: Some(limit),
+        };
+
+        let filter = ProductFilter::default();
+
+        assert_eq!(ids[..2], query_ids(&store, &filter, page(0, 2))[..]);
+        assert_eq!(ids[2..4], query_ids(&store, &filter, page(2, 2))[..]);
+        assert_eq!(ids[4..], query_ids(&store, &filter, page(4, 2))[..]);
+        assert!(query_ids(&store, &filter, page(5, 2)).is_empty());
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("shop-products-{}.json", ProductId::new()))
+    }
+
+    #[test]
+    fn file_store_is_a_repository() {
+        let path = temp_path();
+        let store = FileProductStore::open(&path, Default::default()).unwrap();
+
+        assert_repository_round_trip(&store, test_data::default_product());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn catalog_survives_reopening_file_store() {
+        let path = temp_path();
+
+        let hat_id = ProductId::new();
+        let scarf_id = ProductId::new();
+
+        {
+            let store = FileProductStore::open(&path, Default::default()).unwrap();
+
+            for (id, title) in [(hat_id, "Hat"), (scarf_id, "Scarf")] {
+                store
+                    .set_product(
+                        &Transaction::none(),
+                        test_data::ProductBuilder::new().id(id).title(title).build(),
+                    )
+                    .unwrap();
+            }
+
+            let mut hat = store
+                .get_product(&Transaction::none(), hat_id)
+                .unwrap()
+                .unwrap();
+            hat.set_title("Winter hat").unwrap();
+            store.set_product(&Transaction::none(), hat).unwrap();
+        }
+
+        let store = FileProductStore::open(&path, Default::default()).unwrap();
+
+        let hat = store
+            .get_product(&Transaction::none(), hat_id)
+            .unwrap()
+            .unwrap()
+            .into_data();
+        let scarf = store
+            .get_product(&Transaction::none(), scarf_id)
+            .unwrap()
+            .unwrap()
+            .into_data();
+
+        assert_eq!("Winter hat", hat.title);
+        assert_eq!("Scarf", scarf.title);
+
+        // Versions are kept, so stale writes are still caught after reopening
+        assert_eq!(2, hat.version.value());
+        assert!(store
+            .set_product(
+                &Transaction::none(),
+                test_data::ProductBuilder::new().id(scarf_id).build()
+            )
+            .is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_only_writes_committed_products() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store = FileProductStore::open(&path, transactions.clone()).unwrap();
+
+        let id = ProductId::new();
+
+        let transaction = transactions.begin();
+        store
+            .set_product(
+                &transaction,
+                test_data::ProductBuilder::new().id(id).build(),
+            )
+            .unwrap();
+
+        let reopened = FileProductStore::open(&path, Default::default()).unwrap();
+        assert!(reopened
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .is_none());
+
+        transactions.commit(transaction).unwrap();
+
+        let reopened = FileProductStore::open(&path, Default::default()).unwrap();
+        assert!(reopened
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .is_some());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn committed_products_survive_dropping_file_store() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store = FileProductStore::open(&path, transactions.clone()).unwrap();
+
+        let committed_id = ProductId::new();
+        let cancelled_id = ProductId::new();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+
+        store
+            .set_product(
+                &committed,
+                test_data::ProductBuilder::new().id(committed_id).build(),
+            )
+            .unwrap();
+        store
+            .set_product(
+                &cancelled,
+                test_data::ProductBuilder::new().id(cancelled_id).build(),
+            )
+            .unwrap();
+
+        transactions.commit(committed).unwrap();
+        transactions.cancel(cancelled);
+
+        // Nothing else is written and the store isn't flushed before it's dropped
+        drop(store);
+
+        let reopened = FileProductStore::open(&path, Default::default()).unwrap();
+
+        assert!(reopened
+            .get_product(&Transaction::none(), committed_id)
+            .unwrap()
+            .is_some());
+        assert!(reopened
+            .get_product(&Transaction::none(), cancelled_id)
+            .unwrap()
+            .is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn transaction_fails_to_commit_if_file_store_cant_write() {
+        // The file can't be written because its directory doesn't exist
+        let path = temp_path().join("products.json");
+        let transactions = TransactionStore::new();
+
+        let store = FileProductStore::open(&path, transactions.clone()).unwrap();
+
+        let id = ProductId::new();
+
+        let transaction = transactions.begin();
+        store
+            .set_product(
+                &transaction,
+                test_data::ProductBuilder::new().id(id).build(),
+            )
+            .unwrap();
+
+        let err = transactions.commit(transaction).unwrap_err();
+
+        assert!(err.to_string().contains("failed to write products"));
+        assert!(store
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn flushing_file_store_writes_the_catalog() {
+        let path = temp_path();
+        let store = FileProductStore::open(&path, Default::default()).unwrap();
+
+        let id = ProductId::new();
+
+        store
+            .set_product(
+                &Transaction::none(),
+                test_data::ProductBuilder::new().id(id).build(),
+            )
+            .unwrap();
+
+        fs::remove_file(&path).unwrap();
+        store.flush().unwrap();
+
+        let reopened = FileProductStore::open(&path, Default::default()).unwrap();
+        assert!(reopened
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .is_some());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn err_if_file_is_corrupted() {
+        let path = temp_path();
+        fs::write(&path, "[{\"id\": ").unwrap();
+
+        let err = match FileProductStore::open(&path, Default::default()) {
+            Ok(_) => panic!("expected the corrupted file to be rejected"),
+            Err(err) => err.to_string(),
+        };
+
+        assert!(err.contains("corrupted"));
+        assert!(err.contains(&path.display().to_string()));
+
+        fs::remove_file(path).unwrap();
+    }
 }
\ No newline at end of file
@@ -37,6 +37,26 @@ impl ProductBuilder {
         self
     }
 
+    pub fn title(mut self, title: &str) -> Self {
+        self.product.data.title = title.to_owned();
+        self
+    }
+
+    pub fn price(mut self, price: Currency) -> Self {
+        self.product.data.price = price;
+        self
+    }
+
+    pub fn weight_grams(mut self, weight_grams: u32) -> Self {
+        self.product.set_weight_grams(Some(weight_grams));
+        self
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.product.deactivate();
+        self
+    }
+
     pub fn build(self) -> Product {
         self.product
     }
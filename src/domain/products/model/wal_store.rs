@@ -0,0 +1,271 @@
+/*!
+Product storage that logs changes, so products survive restarts without a database.
+
+Products are kept in another store, usually an in-memory one, and each change is logged before
+it's made there. See `infra::wal` for how the log is written and replayed.
+*/
+
+use std::{
+    ops::ControlFlow,
+    path::Path,
+};
+
+use crate::{
+    domain::{
+        infra::wal::{
+            self,
+            WalChange,
+            WalStore,
+        },
+        products::{
+            model::store::*,
+            *,
+        },
+        Error,
+    },
+    store::Transaction,
+};
+
+/** A change to products that's written to the log. */
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", content = "payload", rename_all = "snake_case")]
+pub(in crate::domain) enum ProductChange {
+    SetProduct(ProductData),
+}
+
+/** A product store that logs each change before making it. */
+pub(in crate::domain) type ProductWalStore<S> = WalStore<S, ProductChange>;
+
+impl<S: ProductStore> WalChange<S> for ProductChange {
+    fn apply(self, store: &S) -> Result<(), Error> {
+        match self {
+            ProductChange::SetProduct(data) => {
+                store.set_product(&Transaction::none(), Product::checked(data)?)
+            }
+        }
+    }
+
+    fn snapshot(store: &S) -> Result<Vec<Self>, Error> {
+        let mut products = vec![];
+
+        store.scan(&mut |data| {
+            // Setting a product from its previous version brings it back to its current one
+            let mut data = data.clone();
+            data.version = data.version.previous();
+
+            products.push(ProductChange::SetProduct(data));
+
+            ControlFlow::Continue(())
+        })?;
+
+        Ok(products)
+    }
+}
+
+/** Replay the product changes logged at `path` into a store, returning how many there were. */
+pub(in crate::domain) fn replay(
+    path: impl AsRef<Path>,
+    store: &impl ProductStore,
+) -> Result<usize, Error> {
+    wal::replay::<_, ProductChange>(path, store)
+}
+
+impl<S: ProductStore> ProductStore for ProductWalStore<S> {
+    fn get_product(
+        &self,
+        transaction: &Transaction,
+        id: ProductId,
+    ) -> Result<Option<Product>, Error> {
+        self.store().get_product(transaction, id)
+    }
+
+    fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
+        let change = ProductChange::SetProduct(product.to_data().clone());
+
+        self.change(transaction, change, |store| {
+            store.set_product(transaction, product)
+        })
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error> {
+        self.store().scan(f)
+    }
+}
+
+impl<S: ProductStoreFilter> ProductStoreFilter for ProductWalStore<S> {
+    fn filter(
+        &self,
+        transaction: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<Iter, Error> {
+        self.store().filter(transaction, predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{
+            self,
+            OpenOptions,
+        },
+        path::PathBuf,
+    };
+
+    use super::*;
+
+    use crate::{
+        domain::{
+            infra::{
+                Currency,
+                Flush,
+            },
+            products::model::test_data::ProductBuilder,
+        },
+        store::{
+            TransactionStore,
+            Wal,
+        },
+    };
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("shop-products-{}.wal", ProductId::new()))
+    }
+
+    fn open(path: &Path, transactions: &TransactionStore) -> ProductWalStore<InMemoryStore> {
+        ProductWalStore::open(
+            path,
+            in_memory_store(transactions.clone()),
+            transactions.clone(),
+        )
+        .unwrap()
+    }
+
+    fn price_and_version(store: &impl ProductStore, id: ProductId) -> Option<(Currency, u64)> {
+        store
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .map(|product| (product.to_data().price, product.to_data().version.value()))
+    }
+
+    #[test]
+    fn replay_restores_committed_products() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store = open(&path, &transactions);
+
+        let kept_id = ProductId::new();
+        let cancelled_id = ProductId::new();
+        let torn_id = ProductId::new();
+
+        store
+            .set_product(
+                &Transaction::none(),
+                ProductBuilder::new()
+                    .id(kept_id)
+                    .price(Currency::usd(100))
+                    .build(),
+            )
+            .unwrap();
+
+        let mut product = store
+            .get_product(&Transaction::none(), kept_id)
+            .unwrap()
+            .unwrap();
+        product.set_price(Currency::usd(200)).unwrap();
+        store.set_product(&Transaction::none(), product).unwrap();
+
+        let transaction = transactions.begin();
+        store
+            .set_product(&transaction, ProductBuilder::new().id(cancelled_id).build())
+            .unwrap();
+        transactions.cancel(transaction);
+
+        store
+            .set_product(
+                &Transaction::none(),
+                ProductBuilder::new().id(torn_id).build(),
+            )
+            .unwrap();
+
+        // Simulate a crash part way through logging that the last product's change committed
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 5)
+            .unwrap();
+
+        let replayed = in_memory_store(Default::default());
+
+        assert_eq!(2, replay(&path, &replayed).unwrap());
+
+        assert_eq!(
+            price_and_version(&store, kept_id),
+            price_and_version(&replayed, kept_id)
+        );
+        assert_eq!(None, price_and_version(&replayed, cancelled_id));
+        assert_eq!(None, price_and_version(&replayed, torn_id));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn flush_compacts_the_log() {
+        let path = temp_path();
+        let transactions = TransactionStore::new();
+
+        let store = open(&path, &transactions);
+
+        let id = ProductId::new();
+
+        store
+            .set_product(&Transaction::none(), ProductBuilder::new().id(id).build())
+            .unwrap();
+
+        for cents in [200, 300, 400] {
+            let mut product = store
+                .get_product(&Transaction::none(), id)
+                .unwrap()
+                .unwrap();
+            product.set_price(Currency::usd(cents)).unwrap();
+
+            store.set_product(&Transaction::none(), product).unwrap();
+        }
+
+        assert_eq!(8, Wal::read(&path).unwrap().len());
+
+        store.flush().unwrap();
+
+        // The snapshot has a change for the product and a record that it committed
+        assert_eq!(2, Wal::read(&path).unwrap().len());
+
+        let replayed = in_memory_store(Default::default());
+        replay(&path, &replayed).unwrap();
+
+        assert_eq!(
+            Some((Currency::usd(400), 4)),
+            price_and_version(&replayed, id)
+        );
+
+        // Changes after compacting follow on from the snapshot
+        let mut product = store
+            .get_product(&Transaction::none(), id)
+            .unwrap()
+            .unwrap();
+        product.set_price(Currency::usd(500)).unwrap();
+        store.set_product(&Transaction::none(), product).unwrap();
+
+        let replayed = in_memory_store(Default::default());
+        replay(&path, &replayed).unwrap();
+
+        assert_eq!(
+            Some((Currency::usd(500), 5)),
+            price_and_version(&replayed, id)
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
\ No newline at end of file
@@ -0,0 +1,170 @@
+/*! Contains the `ExportProductsQuery` type. */
+
+use std::ops::ControlFlow;
+
+use crate::domain::{
+    infra::*,
+    products::*,
+    Error,
+};
+
+/** Input for an `ExportProductsQuery`. */
+#[derive(Default, Deserialize)]
+pub struct ExportProducts {
+    /** Whether to include products that have been deactivated. */
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+impl QueryArgs for ExportProducts {
+    type Output = Result<String, Error>;
+}
+
+/** The header row of an export. Prices are in the currency's smallest unit, like cents. */
+const HEADER: &str = "id,title,currency,price,active,weight_grams\n";
+
+/** Default implementation for an `ExportProductsQuery`. */
+async fn execute(query: ExportProducts, store: impl ProductStore) -> Result<String, Error> {
+    let mut rows = vec![];
+
+    store.scan(&mut |product| {
+        if query.include_inactive || product.active {
+            rows.push((product.id, row(product)));
+        }
+
+        ControlFlow::Continue(())
+    })?;
+
+    // Stores don't scan in any particular order, so rows are sorted to keep exports stable
+    rows.sort_by_key(|(id, _)| *id);
+
+    Ok(rows
+        .into_iter()
+        .fold(HEADER.to_owned(), |mut csv, (_, row)| {
+            csv.push_str(&row);
+            csv
+        }))
+}
+
+fn row(product: &ProductData) -> String {
+    format!(
+        "{},{},{},{},{},{}\n",
+        product.id,
+        field(&product.title),
+        product.price.code(),
+        product.price.minor_units(),
+        product.active,
+        product
+            .weight_grams
+            .map(|weight| weight.to_string())
+            .unwrap_or_default(),
+    )
+}
+
+/** Quote a field if it contains anything that would break the row apart. */
+fn field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+impl Resolver {
+    /** Export the product catalog as CSV, with a header row and one row per product. */
+    pub fn export_products_query(&self) -> impl Query<ExportProducts> {
+        self.query(|resolver, query: ExportProducts| async move {
+            let store = resolver.product_store();
+
+            execute(query, store).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::products::model::{
+        store::in_memory_store,
+        test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn export_every_active_product() {
+        let store = in_memory_store(Default::default());
+
+        let mut ids = vec![];
+        for i in 0..10 {
+            let product = ProductBuilder::new()
+                .title(&format!("Product {}", i))
+                .price(Currency::usd(100 + i))
+                .build();
+
+            ids.push(product.to_data().id);
+
+            store
+                .set_product(ActiveTransaction::auto_commit().get(), product)
+                .unwrap();
+        }
+
+        store
+            .set_product(
+                ActiveTransaction::auto_commit().get(),
+                ProductBuilder::new().inactive().build(),
+            )
+            .unwrap();
+
+        let csv = execute(ExportProducts::default(), &store).await.unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(Some(HEADER.trim_end()), lines.next());
+
+        ids.sort();
+        assert_eq!(
+            ids.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+            lines
+                .map(|line| line.split(',').next().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        );
+
+        let csv = execute(
+            ExportProducts {
+                include_inactive: true,
+            },
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(12, csv.lines().count());
+    }
+
+    #[tokio::test]
+    async fn fields_are_quoted_when_needed() {
+        let store = in_memory_store(Default::default());
+
+        let id = ProductId::new();
+
+        let mut product = ProductBuilder::new()
+            .id(id)
+            .title(r#"Hat, "woolly""#)
+            .price(Currency::usd(1250))
+            .build();
+        product.set_weight_grams(Some(90));
+
+        store
+            .set_product(ActiveTransaction::auto_commit().get(), product)
+            .unwrap();
+
+        let csv = execute(ExportProducts::default(), &store).await.unwrap();
+
+        assert_eq!(
+            format!(
+                "{}{},\"Hat, \"\"woolly\"\"\",USD,1250,true,90\n",
+                HEADER, id
+            ),
+            csv
+        );
+    }
+}
\ No newline at end of file
@@ -1,6 +1,7 @@
 /*! Contains the `GetProductQuery` type. */
 
 use crate::domain::{
+    events::DomainEvent,
     infra::*,
     products::*,
     Error,
@@ -10,15 +11,45 @@ use crate::domain::{
 #[derive(Deserialize)]
 pub struct GetProduct {
     pub id: ProductId,
+    /** Whether to return the product even if it's been deactivated. */
+    #[serde(default)]
+    pub include_inactive: bool,
 }
 
 impl QueryArgs for GetProduct {
     type Output = Result<Option<Product>, Error>;
 }
 
+impl QueryCacheKey for GetProduct {
+    type Key = (ProductId, bool);
+    type Value = Option<Product>;
+
+    fn cache_key(&self) -> (ProductId, bool) {
+        (self.id, self.include_inactive)
+    }
+
+    fn invalidated_by(event: &DomainEvent) -> Vec<(ProductId, bool)> {
+        match *event {
+            DomainEvent::ProductCreated { id }
+            | DomainEvent::ProductDeactivated { id }
+            | DomainEvent::ProductTitleSet { id }
+            | DomainEvent::ProductUpdated { id } => {
+                vec![(id, false), (id, true)]
+            }
+            _ => vec![],
+        }
+    }
+}
+
 /** Default implementation for a `GetProductQuery`. */
-async fn execute(query: GetProduct, store: impl ProductStore) -> Result<Option<Product>, Error> {
-    let product = store.get_product(query.id)?;
+async fn execute(
+    query: GetProduct,
+    transaction: ActiveTransaction,
+    store: impl ProductStore,
+) -> Result<Option<Product>, Error> {
+    let product = store
+        .get_product(transaction.read(), query.id)?
+        .filter(|product| query.include_inactive || product.to_data().active);
 
     Ok(product)
 }
@@ -28,8 +59,192 @@ impl Resolver {
     pub fn get_product_query(&self) -> impl Query<GetProduct> {
         self.query(|resolver, query: GetProduct| async move {
             let store = resolver.product_store();
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, store).await
+        })
+    }
+
+    /**
+    Get a product, reusing the results of recent queries for the same product.
+
+    Cached results are invalidated when a change to the product is committed.
+    Queries in a transaction aren't cached, because they see its changes before they're committed.
+    */
+    pub fn cached_get_product_query(&self) -> impl Query<GetProduct> {
+        if self.active_transaction().is_transactional() {
+            CachedQuery::uncached(self.get_product_query())
+        } else {
+            CachedQuery::new(self.get_product_query(), self.get_product_cache())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+    };
+
+    use super::*;
+
+    use crate::domain::products::model::{
+        store::in_memory_store,
+        test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn inactive_products_are_hidden_by_default() {
+        let store = in_memory_store(Default::default());
+
+        let id = ProductId::new();
+
+        store
+            .set_product(
+                ActiveTransaction::auto_commit().get(),
+                ProductBuilder::new().id(id).inactive().build(),
+            )
+            .unwrap();
 
-            execute(query, store).await
+        let hidden = execute(
+            GetProduct {
+                id,
+                include_inactive: false,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+        )
+        .await
+        .unwrap();
+
+        assert!(hidden.is_none());
+
+        let found = execute(
+            GetProduct {
+                id,
+                include_inactive: true,
+            },
+            ActiveTransaction::auto_commit(),
+            &store,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert!(!found.to_data().active);
+    }
+
+    #[tokio::test]
+    async fn cached_results_are_invalidated_when_title_is_set() {
+        let test = Resolver::for_tests();
+        let resolver = test.resolver();
+
+        let id = ProductId::new();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
         })
+        .await
+        .unwrap();
+
+        let executed = Arc::new(AtomicUsize::new(0));
+        let query = {
+            let executed = executed.clone();
+            let inner = Arc::new(resolver.get_product_query());
+
+            CachedQuery::new(
+                move |query: GetProduct| {
+                    executed.fetch_add(1, Ordering::SeqCst);
+
+                    let inner = inner.clone();
+                    async move { inner.execute(query).await }
+                },
+                resolver.get_product_cache(),
+            )
+        };
+
+        let title = |product: Option<Product>| product.unwrap().into_data().title;
+
+        assert_eq!(
+            "A title",
+            title(
+                query
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            "A title",
+            title(
+                query
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+                    .unwrap()
+            )
+        );
+
+        assert_eq!(1, executed.load(Ordering::SeqCst));
+
+        test.transaction(|resolver| async move {
+            resolver
+                .set_product_title_command()
+                .execute(SetProductTitle {
+                    id,
+                    title: "A new title".into(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            "A new title",
+            title(
+                query
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+                    .unwrap()
+            )
+        );
+
+        assert_eq!(2, executed.load(Ordering::SeqCst));
+
+        // The resolved query shares the same cache
+        assert_eq!(
+            "A new title",
+            title(
+                resolver
+                    .cached_get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+                    .unwrap()
+            )
+        );
+
+        assert_eq!(2, executed.load(Ordering::SeqCst));
     }
 }
\ No newline at end of file
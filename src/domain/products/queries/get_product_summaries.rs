@@ -10,6 +10,9 @@ use crate::domain::{
 #[derive(Deserialize)]
 pub struct GetProductSummaries {
     pub ids: Vec<ProductId>,
+    /** Whether to include products that have been deactivated. */
+    #[serde(default)]
+    pub include_inactive: bool,
 }
 
 /** An individual product summary. */
@@ -27,10 +30,17 @@ impl QueryArgs for GetProductSummaries {
 /** Default implementation for a `GetProductSummariesQuery`. */
 async fn execute(
     query: GetProductSummaries,
+    transaction: ActiveTransaction,
     store: impl ProductStoreFilter,
 ) -> Result<Vec<ProductSummary>, Error> {
+    let filter = ProductFilter {
+        ids: Some(query.ids),
+        include_inactive: query.include_inactive,
+        ..Default::default()
+    };
+
     store
-        .filter(|p| query.ids.iter().any(|id| p.id == *id))?
+        .query(transaction.read(), &filter, Page::default())?
         .map(|p| {
             Ok(ProductSummary {
                 id: p.id,
@@ -47,7 +57,66 @@ impl Resolver {
         self.query(|resolver, query: GetProductSummaries| async move {
             let store = resolver.product_store_filter();
 
-            execute(query, store).await
+            let transaction = resolver.active_transaction();
+
+            execute(query, transaction, store).await
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::products::model::{
+        store::in_memory_store,
+        test_data::ProductBuilder,
+    };
+
+    #[tokio::test]
+    async fn inactive_products_are_filtered_by_default() {
+        let store = in_memory_store(Default::default());
+
+        let active_id = ProductId::new();
+        let inactive_id = ProductId::new();
+
+        for product in [
+            ProductBuilder::new().id(active_id).build(),
+            ProductBuilder::new().id(inactive_id).inactive().build(),
+        ] {
+            store
+                .set_product(ActiveTransaction::auto_commit().get(), product)
+                .unwrap();
+        }
+
+        let ids = |include_inactive| {
+            let store = &store;
+
+            async move {
+                let mut ids: Vec<_> = execute(
+                    GetProductSummaries {
+                        ids: vec![active_id, inactive_id],
+                        include_inactive,
+                    },
+                    ActiveTransaction::auto_commit(),
+                    store,
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|p| p.id)
+                .collect();
+
+                ids.sort();
+                ids
+            }
+        };
+
+        assert_eq!(vec![active_id], ids(false).await);
+
+        let mut all = vec![active_id, inactive_id];
+        all.sort();
+
+        assert_eq!(all, ids(true).await);
+    }
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
 /*! Queries for fetching product state. */
 
+mod export_products;
 mod get_product;
 mod get_product_summaries;
 
 pub use self::{
+    export_products::*,
     get_product::*,
     get_product_summaries::*,
 };
\ No newline at end of file
@@ -1,17 +1,44 @@
 /*! Contains the `ProductsResolver` type. */
 
-use std::sync::Arc;
+use std::{
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::domain::{
-    infra::*,
-    products::model::store::{
-        self,
-        InMemoryStore,
-        ProductStore,
-        ProductStoreFilter,
+use crate::{
+    domain::{
+        infra::*,
+        products::{
+            model::{
+                store::{
+                    self,
+                    DynProductStore,
+                    ProductStore,
+                    ProductStoreFilter,
+                },
+                wal_store::{
+                    self,
+                    ProductWalStore,
+                },
+                NextProductId,
+                Product,
+                ProductData,
+            },
+            GetProduct,
+        },
+        Error,
     },
+    store::Transaction,
 };
 
+/** How long results of a `cached_get_product_query` are reused for. */
+const GET_PRODUCT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/** How many results of a `cached_get_product_query` are kept. */
+const GET_PRODUCT_CACHE_CAPACITY: usize = 1024;
+
 /**
 Resolver for products.
 
@@ -19,20 +46,188 @@ The `ProductsResolver` type wraps private implementation details and exposes the
 */
 #[derive(Clone)]
 pub(in crate::domain) struct ProductsResolver {
-    product_store: Register<Arc<InMemoryStore>>,
+    product_store: Register<DynProductStore>,
+    pub(in crate::domain::products) product_id: Register<DynIdProvider<ProductData>>,
+    get_product_cache: Register<Arc<QueryCache<GetProduct>>>,
 }
 
 impl Default for ProductsResolver {
     fn default() -> Self {
         ProductsResolver {
             product_store: Register::once(|resolver| {
-                Arc::new(store::in_memory_store(resolver.transaction_store()))
+                Arc::new(store::in_memory_store(resolver.transaction_store())) as DynProductStore
+            }),
+            product_id: Register::factory(|_| {
+                Arc::new(NextProductId::new()) as DynIdProvider<ProductData>
+            }),
+            get_product_cache: Register::once(|resolver| {
+                let cache = Arc::new(QueryCache::new(
+                    resolver.clock(),
+                    GET_PRODUCT_CACHE_TTL,
+                    GET_PRODUCT_CACHE_CAPACITY,
+                ));
+
+                cache.invalidate_on(&resolver.events());
+
+                cache
             }),
         }
     }
 }
 
+impl ProductsResolver {
+    pub(in crate::domain) fn with_product_store<S>(
+        &self,
+        store: impl Fn(&Resolver) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: store::ProductStoreWithFilter + Send + Sync + 'static,
+    {
+        ProductsResolver {
+            product_store: Register::once(move |resolver| {
+                Arc::new(store(resolver)) as DynProductStore
+            }),
+            ..self.clone()
+        }
+    }
+
+    #[cfg(feature = "concurrent")]
+    pub(in crate::domain) fn with_concurrent_stores(&self) -> Self {
+        self.with_product_store(|resolver| store::concurrent_store(resolver.transaction_store()))
+    }
+
+    pub(in crate::domain) fn with_id_strategy(&self, strategy: IdStrategy) -> Self {
+        ProductsResolver {
+            product_id: Register::factory(move |_| {
+                Arc::new(NextProductId::with_strategy(strategy)) as DynIdProvider<ProductData>
+            }),
+            ..self.clone()
+        }
+    }
+
+    pub(in crate::domain) fn with_seeded_ids(&self, seed: u64) -> Self {
+        ProductsResolver {
+            product_id: Register::once(move |_| {
+                Arc::new(SeededIdProvider::new(seed)) as DynIdProvider<ProductData>
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+impl App {
+    /**
+    Use a different store for products.
+
+    The store is created the first time it's needed. An in-memory store is used by default.
+    Applications can keep products somewhere else by implementing `ProductStore` and `ProductStoreFilter`.
+    */
+    pub fn with_product_store<S>(
+        self,
+        store: impl Fn(&Resolver) -> S + Send + Sync + 'static,
+    ) -> Self
+    where
+        S: store::ProductStoreWithFilter + Send + Sync + 'static,
+    {
+        App {
+            root_resolver: Resolver {
+                products_resolver: self
+                    .root_resolver
+                    .products_resolver
+                    .with_product_store(store),
+                ..self.root_resolver.by_ref()
+            },
+        }
+    }
+
+    /**
+    Keep products in a JSON file at `path`, so the catalog survives restarts.
+
+    Existing products are read from the file straight away, so a file that can't be read is
+    reported here rather than on the first request. Products are written as part of committing
+    the transactions that set them, so a transaction whose products can't be written fails to
+    commit.
+    */
+    pub fn with_product_file(self, path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let store = Arc::new(store::FileProductStore::open(
+            path,
+            self.root_resolver.transaction_store(),
+        )?);
+
+        Ok(App {
+            root_resolver: Resolver {
+                products_resolver: self
+                    .root_resolver
+                    .products_resolver
+                    .with_product_store(move |_| store.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        })
+    }
+
+    /**
+    Keep products in memory and log each change to `path`, so the catalog survives restarts.
+
+    The log is replayed straight away, so a log that can't be read is reported here rather than
+    on the first request. Transactions are logged as soon as they complete, and `App::shutdown`
+    compacts the log.
+    */
+    pub fn with_product_log(self, path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let transaction_store = self.root_resolver.transaction_store();
+
+        let products = store::in_memory_store(transaction_store.clone());
+        wal_store::replay(&path, &products)?;
+
+        let store = Arc::new(ProductWalStore::open(path, products, transaction_store)?);
+
+        Ok(App {
+            root_resolver: Resolver {
+                products_resolver: self
+                    .root_resolver
+                    .products_resolver
+                    .with_product_store(move |_| store.clone()),
+                ..self.root_resolver.by_ref()
+            },
+        })
+    }
+}
+
 impl Resolver {
+    pub(in crate::domain) fn shutdown_products(&self, shutdown: &mut Shutdown) {
+        shutdown.flushes("product_store", self.product_store());
+    }
+
+    pub(in crate::domain) fn self_check_products(&self, check: &mut SelfCheck) {
+        check.resolves("product_store", || self.product_store());
+        check.resolves("product_id", || self.product_id());
+        check.resolves("get_product_cache", || self.get_product_cache());
+    }
+
+    /** Get the data for every committed product, ordered by id. */
+    pub(in crate::domain) fn export_products(&self) -> Result<Vec<ProductData>, Error> {
+        let mut products = vec![];
+
+        self.product_store().scan(&mut |product| {
+            products.push(product.clone());
+            ControlFlow::Continue(())
+        })?;
+
+        products.sort_by_key(|product| product.id);
+
+        Ok(products)
+    }
+
+    /** Save a product that was exported from another resolver, keeping its id. */
+    pub(in crate::domain) fn import_product(
+        &self,
+        transaction: &Transaction,
+        product: ProductData,
+    ) -> Result<(), Error> {
+        self.product_store()
+            .set_product(transaction, Product::checked(product)?)
+    }
+
     pub(in crate::domain::products) fn product_store(&self) -> impl ProductStore {
         self.resolve(&self.products_resolver.product_store)
     }
@@ -40,4 +235,275 @@ impl Resolver {
     pub(in crate::domain::products) fn product_store_filter(&self) -> impl ProductStoreFilter {
         self.resolve(&self.products_resolver.product_store)
     }
+
+    pub(in crate::domain::products) fn get_product_cache(&self) -> Arc<QueryCache<GetProduct>> {
+        self.resolve(&self.products_resolver.get_product_cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    use super::*;
+
+    use crate::{
+        domain::{
+            customers::{
+                CreateCustomer,
+                CustomerId,
+            },
+            products::*,
+            Error,
+        },
+        store::Transaction,
+    };
+
+    struct CountingStore<S> {
+        store: S,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl<S: ProductStore> ProductStore for CountingStore<S> {
+        fn get_product(
+            &self,
+            transaction: &Transaction,
+            id: ProductId,
+        ) -> Result<Option<Product>, Error> {
+            self.store.get_product(transaction, id)
+        }
+
+        fn set_product(&self, transaction: &Transaction, product: Product) -> Result<(), Error> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            self.store.set_product(transaction, product)
+        }
+
+        fn scan(
+            &self,
+            f: &mut dyn FnMut(&ProductData) -> std::ops::ControlFlow<()>,
+        ) -> Result<(), Error> {
+            self.store.scan(f)
+        }
+    }
+
+    impl<S: Flush> Flush for CountingStore<S> {}
+
+    impl<S: ProductStoreFilter> ProductStoreFilter for CountingStore<S> {
+        fn filter(
+            &self,
+            transaction: &Transaction,
+            predicate: &dyn Fn(&ProductData) -> bool,
+        ) -> Result<store::Iter, Error> {
+            self.store.filter(transaction, predicate)
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_use_custom_product_store() {
+        let writes = Arc::new(AtomicUsize::new(0));
+
+        let app = {
+            let writes = writes.clone();
+
+            App::new().with_product_store(move |resolver| CountingStore {
+                store: store::in_memory_store(resolver.transaction_store()),
+                writes: writes.clone(),
+            })
+        };
+
+        let id = ProductId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(1, writes.load(Ordering::SeqCst));
+
+        let product = app
+            .transaction(|resolver| async move {
+                resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert!(product.is_some());
+    }
+
+    #[tokio::test]
+    async fn product_store_is_created_on_first_use() {
+        let created = Arc::new(AtomicUsize::new(0));
+
+        let app = {
+            let created = created.clone();
+
+            App::new().with_product_store(move |resolver| {
+                created.fetch_add(1, Ordering::SeqCst);
+
+                store::in_memory_store(resolver.transaction_store())
+            })
+        };
+
+        // Using other parts of the domain doesn't create the product store
+        app.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer {
+                    id: CustomerId::new(),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(0, created.load(Ordering::SeqCst));
+
+        let id = ProductId::new();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(1, created.load(Ordering::SeqCst));
+
+        // Later transactions share the same store
+        let product = app
+            .transaction(|resolver| async move {
+                resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert!(product.is_some());
+        assert_eq!(1, created.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn self_check_fails_if_product_store_fails() {
+        let app = App::new().with_product_store(|_| -> store::InMemoryStore {
+            panic!("failed to open the product store")
+        });
+
+        let err = app.self_check().unwrap_err();
+
+        assert!(err.to_string().contains("product_store"));
+    }
+
+    #[tokio::test]
+    async fn catalog_survives_restarting_with_product_file() {
+        let path = std::env::temp_dir().join(format!("shop-products-{}.json", ProductId::new()));
+        let id = ProductId::new();
+
+        let app = App::new().with_product_file(&path).unwrap();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        // The commit writes the catalog, so nothing needs to be flushed
+        drop(app);
+
+        let restarted = App::new().with_product_file(&path).unwrap();
+
+        let product = restarted
+            .transaction(|resolver| async move {
+                resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert_eq!("A title", product.unwrap().into_data().title);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn catalog_survives_restarting_with_product_log() {
+        let path = std::env::temp_dir().join(format!("shop-products-{}.wal", ProductId::new()));
+        let id = ProductId::new();
+
+        let app = App::new().with_product_log(&path).unwrap();
+
+        app.transaction(|resolver| async move {
+            resolver
+                .create_product_command()
+                .execute(CreateProduct {
+                    id,
+                    title: "A title".into(),
+                    price: Currency::usd(100),
+                })
+                .await
+        })
+        .await
+        .unwrap();
+
+        // The commit is logged as soon as it happens, so nothing needs to be flushed
+        drop(app);
+
+        let restarted = App::new().with_product_log(&path).unwrap();
+
+        let product = restarted
+            .transaction(|resolver| async move {
+                resolver
+                    .get_product_query()
+                    .execute(GetProduct {
+                        id,
+                        include_inactive: false,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        assert_eq!("A title", product.unwrap().into_data().title);
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
\ No newline at end of file
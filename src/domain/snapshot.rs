@@ -0,0 +1,410 @@
+/*!
+Contains the `Resolver::export_snapshot` and `Resolver::import_snapshot` methods.
+
+A snapshot is a single JSON document with every customer, product and order in a resolver's
+stores, along with each order's line items. It can be exported from one resolver and imported
+into another, which is handy for demos and for reproducing bug reports. Entities keep their ids,
+so anything that refers to one by id still finds it after importing.
+*/
+
+use std::{
+    collections::HashSet,
+    io::{
+        Read,
+        Write,
+    },
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::domain::{
+    customers::CustomerData,
+    error,
+    infra::*,
+    orders::{
+        LineItemData,
+        OrderData,
+    },
+    products::ProductData,
+    Error,
+    ErrorKind,
+};
+
+/**
+The version of the snapshot format.
+
+Snapshots with any other version are rejected rather than guessing how to read them.
+*/
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Snapshot {
+    schema_version: u32,
+    customers: Vec<CustomerData>,
+    products: Vec<ProductData>,
+    orders: Vec<OrderSnapshot>,
+}
+
+/** An order in a snapshot, along with its line items. */
+#[derive(Serialize, Deserialize)]
+struct OrderSnapshot {
+    order: OrderData,
+    line_items: Vec<LineItemData>,
+}
+
+/**
+A snapshot that's being imported.
+
+Records are read one at a time, so an invalid one can be reported by its section and position.
+*/
+#[derive(Deserialize)]
+struct RawSnapshot {
+    schema_version: u32,
+    customers: Vec<Value>,
+    products: Vec<Value>,
+    orders: Vec<Value>,
+}
+
+impl Resolver {
+    /**
+    Write every committed customer, product and order to a single JSON document.
+
+    Each store is read separately, so changes committed while exporting may only be partly included.
+    */
+    pub fn export_snapshot<W: Write>(&self, w: W) -> Result<(), Error> {
+        let snapshot = Snapshot {
+            schema_version: SCHEMA_VERSION,
+            customers: self.export_customers()?,
+            products: self.export_products()?,
+            orders: self
+                .export_orders()?
+                .into_iter()
+                .map(|(order, line_items)| OrderSnapshot { order, line_items })
+                .collect(),
+        };
+
+        serde_json::to_writer_pretty(w, &snapshot).map_err(|err| {
+            Error::with_source(ErrorKind::Internal, "failed to write the snapshot", err)
+        })
+    }
+
+    /**
+    Read a document written by `export_snapshot` and save everything in it.
+
+    Everything is saved in a single transaction of its own. If any record is invalid, refers to
+    something that isn't in the snapshot, or conflicts with something that's already stored, then
+    nothing is saved and the error names the section and position of the record. Entities keep
+    their ids, so the stores being imported into should start out empty.
+
+    Imported entities aren't new to the domain, so no events are published for them.
+    */
+    pub fn import_snapshot<R: Read>(&self, r: R) -> Result<(), Error> {
+        let snapshot: RawSnapshot = serde_json::from_reader(r).map_err(|err| {
+            Error::with_source(
+                ErrorKind::BadInput,
+                format!("failed to read the snapshot: {}", err),
+                err,
+            )
+        })?;
+
+        if snapshot.schema_version != SCHEMA_VERSION {
+            return Err(error::bad_input(format!(
+                "snapshot schema version {} isn't supported, expected version {}",
+                snapshot.schema_version, SCHEMA_VERSION
+            )));
+        }
+
+        let unit_of_work = self.unit_of_work();
+
+        match import(unit_of_work.resolver(), snapshot) {
+            Ok(()) => unit_of_work.commit(),
+            Err(err) => {
+                let _ = unit_of_work.cancel();
+
+                Err(err.context("import_snapshot"))
+            }
+        }
+    }
+}
+
+fn import(resolver: &Resolver, snapshot: RawSnapshot) -> Result<(), Error> {
+    let active_transaction = resolver.active_transaction();
+    let transaction = active_transaction.get();
+
+    let mut customer_ids = HashSet::new();
+    for (index, customer) in snapshot.customers.into_iter().enumerate() {
+        let customer: CustomerData = record("customers", index, customer)?;
+        let id = customer.id;
+
+        resolver
+            .import_customer(transaction, customer)
+            .map_err(|err| err.context(format!("customers[{}]", index)))?;
+
+        customer_ids.insert(id);
+    }
+
+    let mut product_ids = HashSet::new();
+    for (index, product) in snapshot.products.into_iter().enumerate() {
+        let product: ProductData = record("products", index, product)?;
+        let id = product.id;
+
+        resolver
+            .import_product(transaction, product)
+            .map_err(|err| err.context(format!("products[{}]", index)))?;
+
+        product_ids.insert(id);
+    }
+
+    for (index, order) in snapshot.orders.into_iter().enumerate() {
+        let OrderSnapshot { order, line_items } = record("orders", index, order)?;
+
+        let imported = if !customer_ids.contains(&order.customer_id) {
+            Err(error::bad_input(format!(
+                "customer `{}` isn't in the snapshot",
+                order.customer_id
+            )))
+        } else if let Some(line_item) = line_items
+            .iter()
+            .find(|line_item| !product_ids.contains(&line_item.product_id))
+        {
+            Err(error::bad_input(format!(
+                "product `{}` isn't in the snapshot",
+                line_item.product_id
+            )))
+        } else {
+            resolver.import_order(transaction, order, line_items)
+        };
+
+        imported.map_err(|err| err.context(format!("orders[{}]", index)))?;
+    }
+
+    Ok(())
+}
+
+fn record<T: DeserializeOwned>(section: &str, index: usize, record: Value) -> Result<T, Error> {
+    serde_json::from_value(record).map_err(|err| {
+        Error::with_source(ErrorKind::BadInput, format!("invalid record: {}", err), err)
+            .context(format!("{}[{}]", section, index))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::domain::{
+        customers::*,
+        infra::test_support::TestResolver,
+        orders::*,
+        products::*,
+    };
+
+    struct Seeded {
+        customer_id: CustomerId,
+        product_id: ProductId,
+        inactive_id: ProductId,
+        order_id: OrderId,
+    }
+
+    async fn seed(test: &TestResolver) -> Seeded {
+        let seeded = Seeded {
+            customer_id: CustomerId::new(),
+            product_id: ProductId::new(),
+            inactive_id: ProductId::new(),
+            order_id: OrderId::new(),
+        };
+
+        let Seeded {
+            customer_id,
+            product_id,
+            inactive_id,
+            order_id,
+        } = seeded;
+
+        test.transaction(|resolver| async move {
+            resolver
+                .create_customer_command()
+                .execute(CreateCustomer { id: customer_id })
+                .await?;
+
+            for (id, title) in [(product_id, "A hat"), (inactive_id, "A scarf")] {
+                resolver
+                    .create_product_command()
+                    .execute(CreateProduct {
+                        id,
+                        title: title.into(),
+                        price: Currency::usd(1250),
+                    })
+                    .await?;
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .deactivate_product_command()
+                .execute(DeactivateProduct { id: inactive_id })
+                .await?;
+
+            resolver
+                .create_order_command()
+                .execute(CreateOrder {
+                    id: order_id,
+                    customer_id,
+                    client_token: None,
+                    guest: false,
+                })
+                .await?;
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .add_or_update_product_command()
+                .execute(AddOrUpdateProduct {
+                    id: order_id,
+                    product_id,
+                    quantity: 3,
+                })
+                .await?;
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        test.transaction(|resolver| async move {
+            resolver
+                .place_order_command()
+                .execute(PlaceOrder { id: order_id })
+                .await
+        })
+        .await
+        .unwrap();
+
+        seeded
+    }
+
+    fn export(test: &TestResolver) -> Vec<u8> {
+        let mut snapshot = vec![];
+
+        test.resolver().export_snapshot(&mut snapshot).unwrap();
+
+        snapshot
+    }
+
+    #[tokio::test]
+    async fn snapshot_round_trips_through_another_resolver() {
+        let from = Resolver::for_tests();
+        let seeded = seed(&from).await;
+
+        let to = Resolver::for_tests();
+        to.resolver().import_snapshot(&export(&from)[..]).unwrap();
+
+        let resolver = to.resolver();
+
+        let customer = resolver
+            .get_customer_query()
+            .execute(GetCustomer {
+                id: seeded.customer_id,
+            })
+            .await
+            .unwrap();
+        assert!(customer.is_some());
+
+        for (id, title, active) in [
+            (seeded.product_id, "A hat", true),
+            (seeded.inactive_id, "A scarf", false),
+        ] {
+            let product = resolver
+                .get_product_query()
+                .execute(GetProduct {
+                    id,
+                    include_inactive: true,
+                })
+                .await
+                .unwrap()
+                .unwrap()
+                .into_data();
+
+            assert_eq!(title, product.title);
+            assert_eq!(Currency::usd(1250), product.price);
+            assert_eq!(active, product.active);
+        }
+
+        let order = resolver
+            .get_order_query()
+            .execute(GetOrder {
+                id: seeded.order_id,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(OrderStatus::Placed, order.status());
+
+        let (order, line_items) = order.into_data();
+
+        assert_eq!(seeded.customer_id, order.customer_id);
+        assert_eq!(1, line_items.len());
+        assert_eq!(seeded.product_id, line_items[0].product_id);
+        assert_eq!(3, line_items[0].quantity);
+
+        // Importing the same snapshot again conflicts with what's already there
+        let err = resolver.import_snapshot(&export(&from)[..]).unwrap_err();
+        assert_eq!(ErrorKind::Conflict, err.kind());
+    }
+
+    #[tokio::test]
+    async fn invalid_record_imports_nothing() {
+        let from = Resolver::for_tests();
+        let seeded = seed(&from).await;
+
+        let mut snapshot: Value = serde_json::from_slice(&export(&from)).unwrap();
+        snapshot["products"][1]["title"] = "".into();
+
+        let to = Resolver::for_tests();
+        let err = to
+            .resolver()
+            .import_snapshot(snapshot.to_string().as_bytes())
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(err.to_string().contains("products[1]"));
+
+        // Customers come before products in a snapshot, but they aren't kept either
+        let customer = to
+            .resolver()
+            .get_customer_query()
+            .execute(GetCustomer {
+                id: seeded.customer_id,
+            })
+            .await
+            .unwrap();
+        assert!(customer.is_none());
+    }
+
+    #[tokio::test]
+    async fn err_if_schema_version_is_unknown() {
+        let from = Resolver::for_tests();
+        seed(&from).await;
+
+        let mut snapshot: Value = serde_json::from_slice(&export(&from)).unwrap();
+        snapshot["schema_version"] = (SCHEMA_VERSION + 1).into();
+
+        let err = Resolver::for_tests()
+            .resolver()
+            .import_snapshot(snapshot.to_string().as_bytes())
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::BadInput, err.kind());
+        assert!(err.to_string().contains("schema version"));
+    }
+}
\ No newline at end of file
@@ -65,4 +65,62 @@ where
     S: Serializer,
 {
     s.collect_str(ts)
+}
+
+/**
+A logger for tests that keeps every message in memory.
+
+The global logger can only be set once, so all tests share the same captured messages.
+Tests should look for messages that are unique to them, like ones containing an id.
+*/
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::sync::{
+        Mutex,
+        Once,
+    };
+
+    use log::{
+        LevelFilter,
+        Log,
+        Metadata,
+        Record,
+    };
+
+    static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct CaptureLogger;
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            MESSAGES.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    /** Start capturing log messages. */
+    pub(crate) fn init() {
+        static INIT: Once = Once::new();
+
+        INIT.call_once(|| {
+            log::set_logger(&CaptureLogger).unwrap();
+            log::set_max_level(LevelFilter::Trace);
+        });
+    }
+
+    /** Get all captured messages that contain the given text. */
+    pub(crate) fn messages_containing(text: &str) -> Vec<String> {
+        MESSAGES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.contains(text))
+            .cloned()
+            .collect()
+    }
 }
\ No newline at end of file
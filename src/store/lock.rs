@@ -0,0 +1,45 @@
+/*!
+Helpers for acquiring locks that recover from poisoning.
+
+A lock is poisoned when a thread panics while holding it. The stores only change their data
+once they know a change is valid, so a panic can't leave a half-applied change behind it.
+Any changes that were made for a transaction still need that transaction to commit before
+they're observable, so it's safe to carry on using the data rather than failing every call
+that comes after the panic.
+*/
+
+use std::sync::{
+    Condvar,
+    Mutex,
+    MutexGuard,
+    PoisonError,
+    RwLock,
+    RwLockReadGuard,
+    RwLockWriteGuard,
+};
+
+fn recover<G>(poisoned: PoisonError<G>) -> G {
+    warn!("recovering from a poisoned lock");
+
+    poisoned.into_inner()
+}
+
+/** Acquire a read lock, recovering it if it's poisoned. */
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(recover)
+}
+
+/** Acquire a write lock, recovering it if it's poisoned. */
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(recover)
+}
+
+/** Acquire a mutex, recovering it if it's poisoned. */
+pub(crate) fn lock<T>(lock: &Mutex<T>) -> MutexGuard<'_, T> {
+    lock.lock().unwrap_or_else(recover)
+}
+
+/** Wait on a condition variable, recovering the mutex if it's poisoned. */
+pub(crate) fn wait<'a, T>(condvar: &Condvar, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+    condvar.wait(guard).unwrap_or_else(recover)
+}
\ No newline at end of file
@@ -8,12 +8,17 @@ itself is committed. The transaction store keeps track of whether or not the dat
 with a given transaction should be surfaced to callers or not.
 */
 
+pub(crate) mod lock;
+mod pending;
 mod transaction;
 mod value;
+mod wal;
 
 pub use self::{
+    pending::*,
     transaction::*,
     value::*,
+    wal::*,
 };
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
\ No newline at end of file
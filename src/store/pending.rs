@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use crate::store::{
+    lock,
+    transaction::{
+        Transaction,
+        TransactionId,
+        TransactionStore,
+    },
+    Error,
+};
+
+/**
+Values changed in transactions that haven't been written somewhere durable yet.
+
+Stores that keep their data in memory and write it somewhere else can only write a change once
+they know its transaction is committing. They note each change here as it's made, and the values
+changed in a transaction are written together as part of committing it.
+*/
+pub struct PendingWrites<T> {
+    transactions: TransactionStore,
+    pending: Arc<Mutex<HashMap<TransactionId, Vec<T>>>>,
+}
+
+impl<T> PendingWrites<T>
+where
+    T: Send + 'static,
+{
+    pub fn new(transactions: TransactionStore) -> Self {
+        PendingWrites {
+            transactions,
+            pending: Default::default(),
+        }
+    }
+
+    /**
+    Note that a value was changed in a transaction, to be written by `write` as it commits.
+
+    The values changed in a transaction are passed to the `write` given with the first of them,
+    in the order they were changed, just before the transaction commits. `write` is also passed
+    the transaction, so it can read the values as they're about to be committed. If it fails then
+    the transaction is cancelled instead, and committing it returns the error. Values changed in
+    a transaction that's cancelled are never written.
+
+    A transaction that isn't tracked, like an empty one, has already committed, so the value is
+    written before this returns along with any error.
+    */
+    pub fn push<E>(
+        &self,
+        transaction: &Transaction,
+        value: T,
+        write: impl FnOnce(&Transaction, Vec<T>) -> Result<(), E> + Send + 'static,
+    ) -> Result<(), E>
+    where
+        E: Into<Error> + From<Error>,
+    {
+        let transaction_id = transaction.id();
+
+        if self.transactions.is_committed(transaction_id) {
+            return write(transaction, vec![value]);
+        }
+
+        let mut pending = lock::lock(&self.pending);
+
+        if let Some(values) = pending.get_mut(&transaction_id) {
+            values.push(value);
+
+            return Ok(());
+        }
+
+        pending.insert(transaction_id, vec![value]);
+        drop(pending);
+
+        let pending = self.pending.clone();
+        self.transactions
+            .on_prepare(transaction, move |transaction| {
+                let values = lock::lock(&pending)
+                    .remove(&transaction_id)
+                    .unwrap_or_default();
+
+                write(transaction, values).map_err(Into::into)
+            })
+            .map_err(E::from)?;
+
+        // Values changed in a transaction that's cancelled are dropped
+        let pending = self.pending.clone();
+        self.transactions.on_complete(transaction, move |_| {
+            lock::lock(&pending).remove(&transaction_id);
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Written = Arc<Mutex<Vec<Vec<i32>>>>;
+
+    fn push(
+        pending: &PendingWrites<i32>,
+        written: &Written,
+        transaction: &Transaction,
+        value: i32,
+    ) {
+        let written = written.clone();
+
+        pending
+            .push(transaction, value, move |_, values| {
+                written.lock().unwrap().push(values);
+
+                Ok::<_, Error>(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn values_are_written_when_their_transaction_commits() {
+        let transactions = TransactionStore::new();
+        let pending = PendingWrites::new(transactions.clone());
+        let written = Written::default();
+
+        let committed = transactions.begin();
+        let cancelled = transactions.begin();
+
+        push(&pending, &written, &committed, 1);
+        push(&pending, &written, &cancelled, 2);
+        push(&pending, &written, &committed, 3);
+        push(&pending, &written, &Transaction::none(), 4);
+
+        // Values changed outside of a transaction are written straight away
+        assert_eq!(vec![vec![4]], *written.lock().unwrap());
+
+        transactions.cancel(cancelled);
+        transactions.commit(committed).unwrap();
+
+        assert_eq!(vec![vec![4], vec![1, 3]], *written.lock().unwrap());
+        assert!(lock::lock(&pending.pending).is_empty());
+    }
+
+    #[test]
+    fn error_writing_auto_committed_value_is_returned() {
+        let pending = PendingWrites::new(TransactionStore::new());
+
+        let err = pending
+            .push(&Transaction::none(), 1, |_, _| {
+                Err(Error::from("failed to write"))
+            })
+            .unwrap_err();
+
+        assert_eq!("failed to write", err.to_string());
+    }
+
+    #[test]
+    fn error_writing_committed_values_fails_the_commit() {
+        let transactions = TransactionStore::new();
+        let pending = PendingWrites::new(transactions.clone());
+
+        let transaction = transactions.begin();
+        let id = transaction.id();
+
+        pending
+            .push(&transaction, 1, |_, _| Err(Error::from("failed to write")))
+            .unwrap();
+
+        let err = transactions.commit(transaction).unwrap_err();
+
+        assert_eq!("failed to write", err.to_string());
+        assert!(transactions.is_cancelled(id));
+        assert!(lock::lock(&pending.pending).is_empty());
+    }
+}
\ No newline at end of file
@@ -1,5 +1,7 @@
 use std::{
     collections::HashMap,
+    fmt,
+    mem,
     ops::Drop,
     sync::{
         Arc,
@@ -9,6 +11,11 @@ use std::{
 
 use uuid::Uuid;
 
+use crate::store::{
+    lock,
+    Error,
+};
+
 /**
 An identifier for a transaction.
 
@@ -21,13 +28,29 @@ transaction to the one that was created immediately before it.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TransactionId(Uuid);
 
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 struct TransactionEntry {
     status: TransactionStatus,
+    parent: Option<TransactionId>,
+    // Children that were committed into this transaction
+    merged: Vec<TransactionId>,
+    on_prepare: Vec<OnPrepare>,
+    on_complete: Vec<OnComplete>,
 }
 
+type OnPrepare = Box<dyn FnOnce(&Transaction) -> Result<(), Error> + Send>;
+type OnComplete = Box<dyn FnOnce(bool) + Send>;
+
 enum TransactionStatus {
     Active,
     Cancelled,
+    // The transaction was committed into its parent, so it commits or cancels along with it
+    Merged,
 }
 
 /**
@@ -46,10 +69,13 @@ pub struct Transaction {
 impl Transaction {
     /**
     An "empty" transaction that makes all changes immediately observable.
+
+    The transaction still gets a unique id so its changes can be told apart.
+    Transaction stores consider ids they aren't tracking to be committed.
     */
     pub(crate) fn none() -> Self {
         Transaction {
-            id: TransactionId(Uuid::default()),
+            id: TransactionId(Uuid::new_v4()),
             complete_guard: None,
         }
     }
@@ -109,7 +135,22 @@ impl TransactionStore {
     The transaction will need to be passed back to this store to commit or cancel.
     */
     pub fn begin(&self) -> Transaction {
-        let mut transactions = self.active.lock().unwrap();
+        self.begin_with_parent(None)
+    }
+
+    /**
+    Begin a child of an active transaction.
+
+    The child sees the changes its parent has made, and its own changes can be cancelled without
+    affecting its parent. Committing the child doesn't make its changes observable. They're
+    committed or cancelled along with its parent instead.
+    */
+    pub fn begin_child(&self, parent: &Transaction) -> Transaction {
+        self.begin_with_parent(Some(parent.id()))
+    }
+
+    fn begin_with_parent(&self, parent: Option<TransactionId>) -> Transaction {
+        let mut transactions = lock::lock(&self.active);
 
         let id = Uuid::new_v4();
 
@@ -117,6 +158,10 @@ impl TransactionStore {
             TransactionId(id),
             TransactionEntry {
                 status: TransactionStatus::Active,
+                parent,
+                merged: vec![],
+                on_prepare: vec![],
+                on_complete: vec![],
             },
         );
 
@@ -126,30 +171,152 @@ impl TransactionStore {
                 let transactions = self.clone();
 
                 Some(Box::new(move || {
-                    let id = TransactionId(id);
-                    let mut transactions = transactions.active.lock().unwrap();
-
-                    if let Some(transaction) = transactions.get_mut(&id) {
-                        transaction.status = TransactionStatus::Cancelled;
-                    }
+                    transactions.cancel_id(TransactionId(id));
                 }))
             },
         }
     }
 
+    /**
+    Run a function once a transaction completes, passing whether or not it committed.
+
+    Functions run in the order they were registered, after the transaction is committed or
+    cancelled, including when it's cancelled by being dropped. Functions registered for a child
+    transaction that's committed run when its parent completes.
+    If the transaction isn't tracked by this store then it's already committed, so the function
+    runs straight away.
+    */
+    pub fn on_complete(&self, transaction: &Transaction, f: impl FnOnce(bool) + Send + 'static) {
+        let mut transactions = lock::lock(&self.active);
+
+        match transactions.get_mut(&transaction.id) {
+            Some(entry) => entry.on_complete.push(Box::new(f)),
+            None => {
+                drop(transactions);
+
+                f(true)
+            }
+        }
+    }
+
+    /**
+    Run a function just before a transaction commits, so it can fail the commit.
+
+    Functions are passed the transaction being committed, so they can still read its changes,
+    and run in the order they were registered. If one of them fails the transaction is cancelled
+    instead and `commit` returns the error. Functions registered for a child transaction that's
+    committed run when its parent commits, and are passed the parent.
+    If the transaction isn't tracked by this store then it's already committed, so the function
+    runs straight away and its error is returned here.
+    */
+    pub fn on_prepare(
+        &self,
+        transaction: &Transaction,
+        f: impl FnOnce(&Transaction) -> Result<(), Error> + Send + 'static,
+    ) -> Result<(), Error> {
+        let mut transactions = lock::lock(&self.active);
+
+        match transactions.get_mut(&transaction.id) {
+            Some(entry) => {
+                entry.on_prepare.push(Box::new(f));
+
+                Ok(())
+            }
+            None => {
+                drop(transactions);
+
+                f(transaction)
+            }
+        }
+    }
+
     /**
     Commit a transaction, making its changes atomically observable.
+
+    Committing a child transaction hands its changes to its parent instead, so they're only
+    observable once the parent is committed.
+
+    Any functions registered with `on_prepare` run first. If one of them fails then the
+    transaction is cancelled and its error is returned.
     */
-    pub fn commit(&self, mut transaction: Transaction) {
+    pub fn commit(&self, mut transaction: Transaction) -> Result<(), Error> {
         drop(transaction.complete_guard.take());
 
-        let mut transactions = self.active.lock().unwrap();
+        let mut transactions = lock::lock(&self.active);
+
+        let parent = transactions
+            .get(&transaction.id)
+            .and_then(|entry| entry.parent)
+            .filter(|parent| transactions.contains_key(parent));
+
+        if let Some(parent) = parent {
+            let (mut merged, on_prepare, on_complete) = match transactions.get_mut(&transaction.id)
+            {
+                Some(entry) => {
+                    entry.status = TransactionStatus::Merged;
+
+                    (
+                        mem::take(&mut entry.merged),
+                        mem::take(&mut entry.on_prepare),
+                        mem::take(&mut entry.on_complete),
+                    )
+                }
+                None => (vec![], vec![], vec![]),
+            };
+            merged.push(transaction.id);
+
+            if let Some(parent) = transactions.get_mut(&parent) {
+                parent.merged.extend(merged);
+                parent.on_prepare.extend(on_prepare);
+                parent.on_complete.extend(on_complete);
+            }
+
+            return Ok(());
+        }
+
+        let on_prepare = match transactions.get_mut(&transaction.id) {
+            Some(entry) => mem::take(&mut entry.on_prepare),
+            None => vec![],
+        };
+
+        // The functions may need to read through the store, so it can't be locked while they run
+        if !on_prepare.is_empty() {
+            drop(transactions);
+
+            for f in on_prepare {
+                if let Err(err) = f(&transaction) {
+                    self.cancel_id(transaction.id);
+
+                    return Err(err);
+                }
+            }
+
+            transactions = lock::lock(&self.active);
+        }
 
         // NOTE: Only removing transactions when they commit means we'll eventually run out of
         // space if they fail. In a degenerate scenario where everything fails this might not
         // take very long. We could avoid this by tracking whether or not transactions are still
         // reachable and whether or not their ids appear in any data stores.
-        let _ = transactions.remove(&transaction.id);
+        let on_complete = match transactions.remove(&transaction.id) {
+            Some(entry) => {
+                for merged in entry.merged {
+                    let _ = transactions.remove(&merged);
+                }
+
+                entry.on_complete
+            }
+            None => vec![],
+        };
+
+        // The transaction is committed before its functions run, so they can see its changes
+        drop(transactions);
+
+        for f in on_complete {
+            f(true);
+        }
+
+        Ok(())
     }
 
     /**
@@ -158,33 +325,105 @@ impl TransactionStore {
     pub fn cancel(&self, mut transaction: Transaction) {
         drop(transaction.complete_guard.take());
 
-        let mut transactions = self.active.lock().unwrap();
+        self.cancel_id(transaction.id);
+    }
+
+    fn cancel_id(&self, id: TransactionId) {
+        let mut transactions = lock::lock(&self.active);
+
+        let on_complete = match transactions.get_mut(&id) {
+            Some(transaction) => {
+                transaction.status = TransactionStatus::Cancelled;
+                transaction.on_prepare.clear();
 
-        if let Some(transaction) = transactions.get_mut(&transaction.id) {
-            transaction.status = TransactionStatus::Cancelled;
+                mem::take(&mut transaction.on_complete)
+            }
+            None => vec![],
+        };
+
+        drop(transactions);
+
+        for f in on_complete {
+            f(false);
         }
     }
 
     /**
     Whether or not a given transaction was committed.
-    */
-    pub fn is_committed(&self, id: TransactionId) -> bool {
-        let transactions = self.active.lock().unwrap();
 
-        // If a transaction is missing then it was committed
-        !transactions.contains_key(&id)
+    A child transaction is only committed once its parent is.
+    */
+    pub fn is_committed(&self, mut id: TransactionId) -> bool {
+        let transactions = lock::lock(&self.active);
+
+        loop {
+            match transactions.get(&id) {
+                // If a transaction is missing then it was committed
+                None => return true,
+                Some(TransactionEntry {
+                    status: TransactionStatus::Merged,
+                    parent: Some(parent),
+                    ..
+                }) => id = *parent,
+                Some(_) => return false,
+            }
+        }
     }
 
     /**
     Whether or not a given transaction was cancelled.
+
+    A child transaction is cancelled if its parent is.
     */
-    pub fn is_cancelled(&self, id: TransactionId) -> bool {
-        let transactions = self.active.lock().unwrap();
+    pub fn is_cancelled(&self, mut id: TransactionId) -> bool {
+        let transactions = lock::lock(&self.active);
+
+        loop {
+            match transactions.get(&id) {
+                None => return false,
+                Some(TransactionEntry {
+                    status: TransactionStatus::Cancelled,
+                    ..
+                }) => return true,
+                Some(TransactionEntry {
+                    parent: Some(parent),
+                    ..
+                }) => id = *parent,
+                Some(_) => return false,
+            }
+        }
+    }
+
+    /**
+    Whether the uncommitted changes made in `writer` are observable to `reader`.
+
+    A transaction sees its own changes, the changes of its parents, and the changes of any
+    children that were committed into them.
+    */
+    pub fn sees(&self, reader: TransactionId, writer: TransactionId) -> bool {
+        let transactions = lock::lock(&self.active);
+
+        let mut owner = writer;
+        while let Some(TransactionEntry {
+            status: TransactionStatus::Merged,
+            parent: Some(parent),
+            ..
+        }) = transactions.get(&owner)
+        {
+            owner = *parent;
+        }
+
+        let mut reader = reader;
+        loop {
+            if reader == owner {
+                return true;
+            }
 
-        transactions
-            .get(&id)
-            .map(|transaction| matches!(transaction.status, TransactionStatus::Cancelled))
-            .unwrap_or(false)
+            match transactions.get(&reader).and_then(|entry| entry.parent) {
+                Some(parent) => reader = parent,
+                None => return false,
+            }
+        }
     }
 }
 
@@ -239,8 +478,218 @@ mod tests {
         let transaction = store.begin();
         let id = transaction.id();
 
-        store.commit(transaction);
+        store.commit(transaction).unwrap();
 
         assert!(store.is_committed(id));
     }
+
+    #[test]
+    fn committed_child_is_committed_with_parent() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+        let parent_id = parent.id();
+
+        let child = store.begin_child(&parent);
+        let child_id = child.id();
+
+        store.commit(child).unwrap();
+
+        assert!(!store.is_committed(child_id));
+
+        store.commit(parent).unwrap();
+
+        assert!(store.is_committed(parent_id));
+        assert!(store.is_committed(child_id));
+    }
+
+    #[test]
+    fn cancelled_child_leaves_parent_active() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+        let parent_id = parent.id();
+
+        let child = store.begin_child(&parent);
+        let child_id = child.id();
+
+        store.cancel(child);
+
+        assert!(store.is_cancelled(child_id));
+        assert!(!store.is_cancelled(parent_id));
+
+        store.commit(parent).unwrap();
+
+        assert!(store.is_committed(parent_id));
+        assert!(!store.is_committed(child_id));
+    }
+
+    #[test]
+    fn cancelled_parent_cancels_committed_child() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+
+        let child = store.begin_child(&parent);
+        let child_id = child.id();
+
+        store.commit(child).unwrap();
+        store.cancel(parent);
+
+        assert!(store.is_cancelled(child_id));
+        assert!(!store.is_committed(child_id));
+    }
+
+    #[test]
+    fn child_sees_parent_changes() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+        let child = store.begin_child(&parent);
+        let sibling = store.begin_child(&parent);
+        let other = store.begin();
+
+        assert!(store.sees(child.id(), parent.id()));
+        assert!(!store.sees(parent.id(), child.id()));
+        assert!(!store.sees(sibling.id(), child.id()));
+        assert!(!store.sees(other.id(), parent.id()));
+
+        // Once the child is committed its changes belong to its parent
+        let child_id = child.id();
+        store.commit(child).unwrap();
+
+        assert!(store.sees(parent.id(), child_id));
+        assert!(store.sees(sibling.id(), child_id));
+        assert!(!store.sees(other.id(), child_id));
+    }
+
+    fn record(store: &TransactionStore, transaction: &Transaction) -> Arc<Mutex<Vec<bool>>> {
+        let completed = Arc::new(Mutex::new(vec![]));
+
+        store.on_complete(transaction, {
+            let completed = completed.clone();
+
+            move |committed| completed.lock().unwrap().push(committed)
+        });
+
+        completed
+    }
+
+    #[test]
+    fn on_complete_runs_when_transaction_completes() {
+        let store = TransactionStore::new();
+
+        let committed = store.begin();
+        let cancelled = store.begin();
+        let dropped = store.begin();
+
+        let committed_calls = record(&store, &committed);
+        let cancelled_calls = record(&store, &cancelled);
+        let dropped_calls = record(&store, &dropped);
+
+        assert!(committed_calls.lock().unwrap().is_empty());
+
+        store.commit(committed).unwrap();
+        store.cancel(cancelled);
+        drop(dropped);
+
+        assert_eq!(vec![true], *committed_calls.lock().unwrap());
+        assert_eq!(vec![false], *cancelled_calls.lock().unwrap());
+        assert_eq!(vec![false], *dropped_calls.lock().unwrap());
+
+        // Transactions the store isn't tracking are already committed
+        assert_eq!(
+            vec![true],
+            *record(&store, &Transaction::none()).lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn on_complete_for_committed_child_runs_with_parent() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+
+        let committed = store.begin_child(&parent);
+        let cancelled = store.begin_child(&parent);
+
+        let committed_calls = record(&store, &committed);
+        let cancelled_calls = record(&store, &cancelled);
+
+        store.commit(committed).unwrap();
+        store.cancel(cancelled);
+
+        assert!(committed_calls.lock().unwrap().is_empty());
+        assert_eq!(vec![false], *cancelled_calls.lock().unwrap());
+
+        store.commit(parent).unwrap();
+
+        assert_eq!(vec![true], *committed_calls.lock().unwrap());
+    }
+    #[test]
+    fn on_prepare_runs_before_transaction_commits() {
+        let store = TransactionStore::new();
+
+        let parent = store.begin();
+        let parent_id = parent.id();
+
+        let committed = store.begin_child(&parent);
+        let cancelled = store.begin_child(&parent);
+
+        let prepared = Arc::new(Mutex::new(vec![]));
+
+        for (transaction, call) in [(&parent, 1), (&committed, 2), (&cancelled, 3)] {
+            let prepared = prepared.clone();
+            let transactions = store.clone();
+
+            store
+                .on_prepare(transaction, move |transaction| {
+                    // Functions are passed the outermost transaction, which hasn't committed yet
+                    assert_eq!(parent_id, transaction.id());
+                    assert!(!transactions.is_committed(transaction.id()));
+
+                    prepared.lock().unwrap().push(call);
+
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        store.commit(committed).unwrap();
+        store.cancel(cancelled);
+
+        assert!(prepared.lock().unwrap().is_empty());
+
+        store.commit(parent).unwrap();
+
+        assert_eq!(vec![1, 2], *prepared.lock().unwrap());
+        assert!(store.is_committed(parent_id));
+    }
+
+    #[test]
+    fn failed_on_prepare_cancels_transaction() {
+        let store = TransactionStore::new();
+
+        let transaction = store.begin();
+        let id = transaction.id();
+
+        let completed = record(&store, &transaction);
+
+        store
+            .on_prepare(&transaction, |_| Err("failed to prepare".into()))
+            .unwrap();
+
+        let err = store.commit(transaction).unwrap_err();
+
+        assert_eq!("failed to prepare", err.to_string());
+        assert!(store.is_cancelled(id));
+        assert_eq!(vec![false], *completed.lock().unwrap());
+
+        // Transactions the store isn't tracking are already committed
+        let err = store
+            .on_prepare(&Transaction::none(), |_| Err("failed to prepare".into()))
+            .unwrap_err();
+
+        assert_eq!("failed to prepare", err.to_string());
+    }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
 use std::{
-    collections::{
-        hash_map,
-        HashMap,
-    },
+    collections::HashMap,
+    error,
+    fmt,
+    ops::ControlFlow,
     sync::RwLock,
 };
 
+#[cfg(feature = "concurrent")]
+use dashmap::DashMap;
 use uuid::Uuid;
 
 use crate::store::{
+    lock,
     transaction::{
         Transaction,
         TransactionId,
@@ -60,9 +63,135 @@ impl Version {
     }
 }
 
+/**
+The error returned when a value is set using a version that doesn't match its current one.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub expected: Option<Version>,
+    pub actual: Option<Version>,
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "version mismatch")
+    }
+}
+
+impl error::Error for VersionMismatch {}
+
+// A value of `None` is a removed value
 struct TransactionalValue<T> {
-    current: Option<(TransactionId, Version, T)>,
-    prior: Option<(TransactionId, Version, T)>,
+    current: Option<(TransactionId, Version, Option<T>)>,
+    prior: Option<(TransactionId, Version, Option<T>)>,
+    // Values set by transactions that were replaced by their children before committing.
+    // If a child is cancelled then the value its parent set becomes current again
+    shadowed: Vec<(TransactionId, Version, Option<T>)>,
+}
+
+impl<T> TransactionalValue<T> {
+    fn empty() -> Self {
+        TransactionalValue {
+            current: None,
+            prior: None,
+            shadowed: vec![],
+        }
+    }
+
+    /**
+    Get the latest value that wasn't set by a cancelled transaction.
+
+    This is the current value, unless it was set by a cancelled child transaction.
+    */
+    fn latest(
+        &self,
+        transactions: &TransactionStore,
+    ) -> Option<&(TransactionId, Version, Option<T>)> {
+        self.staged()
+            .find(|(transaction, _, _)| !transactions.is_cancelled(*transaction))
+    }
+
+    /** The current value, followed by the values it replaced, latest first. */
+    fn staged(&self) -> impl Iterator<Item = &(TransactionId, Version, Option<T>)> {
+        self.current.iter().chain(self.shadowed.iter().rev())
+    }
+
+    /**
+    Drop the values set by cancelled child transactions, so the value set by their parent
+    is current again.
+    */
+    fn unshadow(&mut self, transactions: &TransactionStore) {
+        while !self.shadowed.is_empty()
+            && matches!(self.current, Some((transaction, _, _)) if transactions.is_cancelled(transaction))
+        {
+            self.current = self.shadowed.pop();
+        }
+    }
+
+    /**
+    Get the value as it's seen by a transaction.
+
+    A transaction sees its own changes, and those of its parents, before they're committed.
+    Everyone else only sees the latest committed value.
+    */
+    fn get_in(
+        &self,
+        transactions: &TransactionStore,
+        transaction: &Transaction,
+    ) -> Option<(Version, &T)> {
+        // A parent doesn't see the changes of its active children, but does see its own
+        // changes that they've replaced
+        let seen = self
+            .staged()
+            .filter(|(existing_transaction, _, _)| {
+                !transactions.is_cancelled(*existing_transaction)
+            })
+            .find(|(existing_transaction, _, _)| {
+                transactions.sees(transaction.id(), *existing_transaction)
+            });
+
+        if let Some((_, existing_version, existing_value)) = seen {
+            return existing_value
+                .as_ref()
+                .map(|existing_value| (*existing_version, existing_value));
+        }
+
+        self.get(transactions)
+    }
+
+    fn get(&self, transactions: &TransactionStore) -> Option<(Version, &T)> {
+        if let Some((existing_transaction, existing_version, existing_value)) =
+            self.latest(transactions)
+        {
+            if transactions.is_committed(*existing_transaction) {
+                return existing_value
+                    .as_ref()
+                    .map(|existing_value| (*existing_version, existing_value));
+            }
+        }
+
+        if let Some((prior_transaction, prior_version, ref prior_value)) = self.prior {
+            assert!(transactions.is_committed(prior_transaction));
+
+            return prior_value
+                .as_ref()
+                .map(|prior_value| (prior_version, prior_value));
+        }
+
+        None
+    }
+}
+
+/**
+The map of values in a store.
+
+Locked values share a single lock, so setting any value blocks readers and writers of all the
+others. Sharded values are split across a `DashMap`, so only values in the same shard contend.
+*/
+enum Data<T> {
+    Locked(RwLock<HashMap<Id, TransactionalValue<T>>>),
+    #[cfg(feature = "concurrent")]
+    Sharded(DashMap<Id, TransactionalValue<T>>),
 }
 
 /**
@@ -72,7 +201,7 @@ This store can participate in transactions with other disconnected stores.
  */
 pub struct TransactionValueStore<T> {
     transactions: TransactionStore,
-    data: RwLock<HashMap<Id, TransactionalValue<T>>>,
+    data: Data<T>,
 }
 
 impl<T> TransactionValueStore<T>
@@ -88,7 +217,21 @@ where
     pub fn new(transactions: TransactionStore) -> Self {
         TransactionValueStore {
             transactions,
-            data: RwLock::new(HashMap::new()),
+            data: Data::Locked(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /**
+    Create a new transactional value store that locks its values in shards.
+
+    Writers that set different values don't block each other unless the values land in the
+    same shard, which makes this store a better fit when many threads write at once.
+    */
+    #[cfg(feature = "concurrent")]
+    pub fn concurrent(transactions: TransactionStore) -> Self {
+        TransactionValueStore {
+            transactions,
+            data: Data::Sharded(DashMap::new()),
         }
     }
 
@@ -102,63 +245,88 @@ where
     }
 
     /**
-    Get a value for the given id.
+    Get a value for the given id, as it's seen by the given transaction.
+
+    A transaction sees the changes it's made itself, even though they aren't observable to
+    anybody else until it's committed. Reading with an empty transaction only sees committed values.
 
     This will also return the current version of the value that will be needed to update it.
     */
-    pub fn get(&self, id: impl Into<Id>) -> Option<(Version, T)> {
+    pub fn get(&self, transaction: &Transaction, id: impl Into<Id>) -> Option<(Version, T)> {
         let id = id.into();
 
-        let data = self.data.read().unwrap();
+        let get = |existing: &TransactionalValue<T>| {
+            existing
+                .get_in(&self.transactions, transaction)
+                .map(|(version, value)| (version, value.clone()))
+        };
 
-        Self::get_sync(id, &self.transactions, &*data)
-            .map(|(version, value)| (version, value.clone()))
+        match &self.data {
+            Data::Locked(data) => lock::read(data).get(&id).and_then(get),
+            #[cfg(feature = "concurrent")]
+            Data::Sharded(data) => data.get(&id).and_then(|existing| get(&existing)),
+        }
     }
 
     /**
-    Get all values that match a given filter.
+    Get all values that match a given filter, as they're seen by the given transaction.
+
+    Like `get`, the transaction's own changes are matched along with committed values.
+    Reading with an empty transaction only matches committed values.
     */
     pub fn get_all(
         &self,
+        transaction: &Transaction,
         mut filter: impl FnMut(&T) -> bool,
     ) -> impl Iterator<Item = (Version, T)> {
-        let data = self.data.read().unwrap();
+        let mut matching = vec![];
 
-        data.keys()
-            .filter_map(|id| Self::get_sync(*id, &self.transactions, &*data))
-            .filter_map(|(version, value)| {
-                if filter(value) {
-                    Some((version, value.clone()))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
+        self.visit(transaction, |version, value| {
+            if filter(value) {
+                matching.push((version, value.clone()));
+            }
+
+            ControlFlow::Continue(())
+        });
+
+        matching.into_iter()
     }
 
-    fn get_sync<'a>(
-        id: Id,
-        transactions: &TransactionStore,
-        data: &'a HashMap<Id, TransactionalValue<T>>,
-    ) -> Option<(Version, &'a T)> {
-        if let Some(existing) = data.get(&id) {
-            if let Some((existing_transaction, existing_version, ref existing_value)) =
-                existing.current
-            {
-                if transactions.is_committed(existing_transaction) {
-                    return Some((existing_version, existing_value));
-                }
+    /**
+    Visit every committed value without cloning it, until `f` breaks.
 
-                if let Some((prior_transaction, prior_version, ref prior_value)) = existing.prior {
-                    assert!(transactions.is_committed(prior_transaction));
+    Values are visited in no particular order. The store is locked for reading while it's
+    scanned, so `f` mustn't try to set or remove values in the same store.
+    */
+    pub fn scan(&self, mut f: impl FnMut(&T) -> ControlFlow<()>) {
+        self.visit(&Transaction::none(), |_, value| f(value))
+    }
 
-                    return Some((prior_version, prior_value));
+    fn visit(&self, transaction: &Transaction, mut f: impl FnMut(Version, &T) -> ControlFlow<()>) {
+        match &self.data {
+            Data::Locked(data) => {
+                for existing in lock::read(data).values() {
+                    if let Some((version, value)) = existing.get_in(&self.transactions, transaction)
+                    {
+                        if f(version, value).is_break() {
+                            return;
+                        }
+                    }
+                }
+            }
+            // Only one shard is locked at a time, so writers to other shards can carry on
+            #[cfg(feature = "concurrent")]
+            Data::Sharded(data) => {
+                for existing in data.iter() {
+                    if let Some((version, value)) = existing.get_in(&self.transactions, transaction)
+                    {
+                        if f(version, value).is_break() {
+                            return;
+                        }
+                    }
                 }
             }
         }
-
-        None
     }
 
     /**
@@ -178,92 +346,179 @@ where
         new_version: impl Into<Version>,
         new_value: T,
     ) -> Result<(), Error> {
-        let id = id.into();
-        let old_version = old_version.map(Into::into);
-        let new_version = new_version.into();
+        self.set_value(
+            transaction,
+            id.into(),
+            old_version.map(Into::into),
+            new_version.into(),
+            Some(new_value),
+        )
+    }
+
+    /**
+    Remove the value for the given id.
+
+    Like setting a value, the removal is associated with an active transaction and not
+    observable until the transaction is committed. The value can be set again afterwards
+    without needing to know the version it was removed at.
+    */
+    pub fn remove(
+        &self,
+        transaction: &Transaction,
+        id: impl Into<Id>,
+        old_version: impl Into<Version>,
+    ) -> Result<(), Error> {
+        self.set_value(
+            transaction,
+            id.into(),
+            Some(old_version.into()),
+            Version::new(),
+            None,
+        )
+    }
 
+    fn set_value(
+        &self,
+        transaction: &Transaction,
+        id: Id,
+        old_version: Option<Version>,
+        new_version: Version,
+        new_value: Option<T>,
+    ) -> Result<(), Error> {
         assert_ne!(
             old_version,
             Some(new_version),
             "a new value must use a different version"
         );
 
-        let mut data = self.data.write().unwrap();
-
-        match data.entry(id) {
-            hash_map::Entry::Occupied(mut occupied) => {
-                let existing = occupied.get_mut();
-
-                match &mut existing.current {
-                    // If the value already exists then we need to update it, without making
-                    // that new version visible to anybody currently looking at the value.
-                    // We do this by updating a pair of values: one for the new version of the
-                    // value and one for the prior version. While this transaction is active,
-                    // callers will get the prior value, but will perform their version checks
-                    // against the current. Since versions are independent that means a conflicting
-                    // transaction can't clobber this one if it got in first. It won't know what
-                    // version it should be using to update the current value set by the other transaction.
-                    Some((existing_transaction, existing_version, existing_value)) => {
-                        // First, we need to check the versions to make sure they line up
-
-                        // If the existing value is not for a cancelled transaction
-                        // then use it to check the version. This means an active transaction
-                        // that sets a value will prevent any other transactions from setting
-                        // that same value
-                        let version_to_check =
-                            if !self.transactions.is_cancelled(*existing_transaction) {
-                                Some(*existing_version)
-                            }
-                            // If the existing value is for a cancelled transaction then use
-                            // the prior version to check. This prevents a cancelled transaction
-                            // from blocking the value from ever being set again
-                            else {
-                                existing.prior.as_ref().map(
-                                    |(prior_transaction, prior_version, _)| {
-                                        assert!(self.transactions.is_committed(*prior_transaction));
-
-                                        *prior_version
-                                    },
-                                )
-                            };
-
-                        if old_version != version_to_check {
-                            return Err(Error::from("version mismatch"));
-                        }
+        match &self.data {
+            Data::Locked(data) => {
+                let mut data = lock::write(data);
+                let existing = data.entry(id).or_insert_with(TransactionalValue::empty);
 
-                        // Now, we're going to set the value
+                self.set_existing(existing, transaction, old_version, new_version, new_value)
+            }
+            // Only the shard the value is in is locked while it's set
+            #[cfg(feature = "concurrent")]
+            Data::Sharded(data) => {
+                let mut existing = data.entry(id).or_insert_with(TransactionalValue::empty);
+
+                self.set_existing(
+                    &mut existing,
+                    transaction,
+                    old_version,
+                    new_version,
+                    new_value,
+                )
+            }
+        }
+    }
 
-                        // If the existing value is for a committed transaction then move it
-                        // into the prior value and set the new value in its place
-                        if self.transactions.is_committed(*existing_transaction) {
-                            let old_transaction =
-                                std::mem::replace(existing_transaction, transaction.id());
-                            let old_version = std::mem::replace(existing_version, new_version);
-                            let old_value = std::mem::replace(existing_value, new_value);
+    fn set_existing(
+        &self,
+        existing: &mut TransactionalValue<T>,
+        transaction: &Transaction,
+        old_version: Option<Version>,
+        new_version: Version,
+        new_value: Option<T>,
+    ) -> Result<(), Error> {
+        existing.unshadow(&self.transactions);
+
+        match &mut existing.current {
+            // If the value already exists then we need to update it, without making
+            // that new version visible to anybody currently looking at the value.
+            // We do this by updating a pair of values: one for the new version of the
+            // value and one for the prior version. While this transaction is active,
+            // callers will get the prior value, but will perform their version checks
+            // against the current. Since versions are independent that means a conflicting
+            // transaction can't clobber this one if it got in first. It won't know what
+            // version it should be using to update the current value set by the other transaction.
+            Some((existing_transaction, existing_version, existing_value)) => {
+                // First, we need to check the versions to make sure they line up
+
+                // If the existing value is not for a cancelled transaction
+                // then use it to check the version. This means an active transaction
+                // that sets a value will prevent any other transactions from setting
+                // that same value
+                let version_to_check = if !self.transactions.is_cancelled(*existing_transaction) {
+                    Some(*existing_version)
+                }
+                // If the existing value is for a cancelled transaction then use
+                // the prior version to check. This prevents a cancelled transaction
+                // from blocking the value from ever being set again
+                else {
+                    existing
+                        .prior
+                        .as_ref()
+                        .map(|(prior_transaction, prior_version, _)| {
+                            assert!(self.transactions.is_committed(*prior_transaction));
+
+                            *prior_version
+                        })
+                };
+
+                // If the value has been removed, or was only ever set by a cancelled
+                // transaction, then treat it like it doesn't exist
+                let missing = if self.transactions.is_committed(*existing_transaction)
+                    || self
+                        .transactions
+                        .sees(transaction.id(), *existing_transaction)
+                {
+                    existing_value.is_none()
+                } else if self.transactions.is_cancelled(*existing_transaction) {
+                    matches!(existing.prior, None | Some((_, _, None)))
+                } else {
+                    false
+                };
+
+                if !missing && old_version != version_to_check {
+                    return Err(Box::new(VersionMismatch {
+                        expected: old_version,
+                        actual: version_to_check,
+                    }));
+                }
 
-                            existing.prior = Some((old_transaction, old_version, old_value));
-                        }
-                        // If the existing value is for an active or cancelled transaction then
-                        // update it without touching the prior value
-                        else {
-                            *existing_transaction = transaction.id();
-                            *existing_version = new_version;
-                            *existing_value = new_value;
-                        }
-                    }
-                    // If the value doesn't exist then set it
-                    // We explicitly don't check the old version for `None` here to make life easier
-                    // for consumers that can't tell whether they're looking at the first version
-                    // of a value or not
-                    None => existing.current = Some((transaction.id(), new_version, new_value)),
+                // Now, we're going to set the value
+
+                // If the existing value is for a committed transaction then move it
+                // into the prior value and set the new value in its place
+                if self.transactions.is_committed(*existing_transaction) {
+                    let old_transaction = std::mem::replace(existing_transaction, transaction.id());
+                    let old_version = std::mem::replace(existing_version, new_version);
+                    let old_value = std::mem::replace(existing_value, new_value);
+
+                    existing.prior = Some((old_transaction, old_version, old_value));
+                    existing.shadowed.clear();
+                }
+                // If the existing value is for a parent of this transaction then keep it
+                // so it becomes current again if this transaction is cancelled
+                else if *existing_transaction != transaction.id()
+                    && !self.transactions.is_cancelled(*existing_transaction)
+                    && self
+                        .transactions
+                        .sees(transaction.id(), *existing_transaction)
+                {
+                    let old_transaction = std::mem::replace(existing_transaction, transaction.id());
+                    let old_version = std::mem::replace(existing_version, new_version);
+                    let old_value = std::mem::replace(existing_value, new_value);
+
+                    existing
+                        .shadowed
+                        .push((old_transaction, old_version, old_value));
+                }
+                // If the existing value is for an active or cancelled transaction then
+                // update it without touching the prior value
+                else {
+                    *existing_transaction = transaction.id();
+                    *existing_version = new_version;
+                    *existing_value = new_value;
                 }
             }
-            hash_map::Entry::Vacant(vacant) => {
-                vacant.insert(TransactionalValue {
-                    current: Some((transaction.id(), new_version, new_value)),
-                    prior: None,
-                });
-            }
+            // If the value doesn't exist then set it
+            // We explicitly don't check the old version for `None` here to make life easier
+            // for consumers that can't tell whether they're looking at the first version
+            // of a value or not
+            None => existing.current = Some((transaction.id(), new_version, new_value)),
         }
 
         Ok(())
@@ -272,6 +527,11 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::panic::{
+        self,
+        AssertUnwindSafe,
+    };
+
     use super::*;
 
     #[test]
@@ -295,7 +555,7 @@ mod tests {
         let store = TransactionValueStore::<String>::new(TransactionStore::new());
 
         let id = Id::new();
-        assert!(store.get(id).is_none());
+        assert!(store.get(&Transaction::none(), id).is_none());
     }
 
     #[test]
@@ -315,14 +575,78 @@ mod tests {
                 String::from("1"),
             )
             .unwrap();
-        store.transactions.commit(transaction);
+        store.transactions.commit(transaction).unwrap();
 
-        let (current_version, current_value) = store.get(id).unwrap();
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
 
         assert_eq!(version, current_version);
         assert_eq!("1", current_value);
     }
 
+    #[test]
+    fn transaction_value_store_recovers_from_poisoning() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("1"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        #[cfg(not(feature = "concurrent"))]
+        let Data::Locked(data) = &store.data;
+        #[cfg(feature = "concurrent")]
+        let Data::Locked(data) = &store.data
+        else {
+            unreachable!("the store was created with a single lock")
+        };
+
+        // Panic while holding the lock, which poisons it
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _data = data.write().unwrap();
+
+            panic!("failed while writing");
+        }));
+
+        assert!(data.is_poisoned());
+
+        // Reads and writes carry on as if nothing happened
+        assert_eq!(
+            Some((version, String::from("1"))),
+            store.get(&Transaction::none(), id)
+        );
+
+        let next_version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                Some(version),
+                next_version,
+                String::from("2"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        assert_eq!(
+            vec![(next_version, String::from("2"))],
+            store
+                .get_all(&Transaction::none(), |_| true)
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn transaction_value_store_set_ignores_old_version_initially() {
         let store = TransactionValueStore::<String>::new(TransactionStore::new());
@@ -361,7 +685,236 @@ mod tests {
             )
             .unwrap();
 
-        assert!(store.get(id).is_none());
+        // The value is only observable to the transaction that set it
+        assert!(store.get(&Transaction::none(), id).is_none());
+        assert_eq!(
+            Some((version, String::from("1"))),
+            store.get(&transaction, id)
+        );
+    }
+
+    #[test]
+    fn transaction_value_store_set_twice_in_transaction() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("1"),
+            )
+            .unwrap();
+
+        // The transaction reads its own change, so it can change the value again
+        let (staged_version, _) = store.get(&transaction, id).unwrap();
+        let next_version = Version::new();
+
+        store
+            .set(
+                &transaction,
+                id,
+                Some(staged_version),
+                next_version,
+                String::from("2"),
+            )
+            .unwrap();
+
+        store.remove(&transaction, id, next_version).unwrap();
+        assert!(store.get(&transaction, id).is_none());
+
+        // A value the transaction removed can be set again without knowing its old version
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                Version::new(),
+                String::from("3"),
+            )
+            .unwrap();
+
+        assert!(store.get(&Transaction::none(), id).is_none());
+
+        store.transactions.commit(transaction).unwrap();
+
+        assert_eq!(
+            Some(String::from("3")),
+            store.get(&Transaction::none(), id).map(|(_, value)| value)
+        );
+    }
+
+    #[test]
+    fn transaction_value_store_cancelled_child_restores_parent_value() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let added_id = Id::new();
+
+        store
+            .set(
+                &Transaction::none(),
+                id,
+                None::<Version>,
+                Version::new(),
+                String::from("0"),
+            )
+            .unwrap();
+
+        let set = |transaction: &Transaction, value: &str| {
+            let old_version = store.get(transaction, id).map(|(version, _)| version);
+
+            store
+                .set(
+                    transaction,
+                    id,
+                    old_version,
+                    Version::new(),
+                    String::from(value),
+                )
+                .unwrap();
+        };
+        let get =
+            |transaction: &Transaction, id: Id| store.get(transaction, id).map(|(_, value)| value);
+
+        let parent = store.transactions.begin();
+        set(&parent, "1");
+
+        let child = store.transactions.begin_child(&parent);
+        set(&child, "2");
+        store
+            .set(
+                &child,
+                added_id,
+                None::<Version>,
+                Version::new(),
+                String::from("added"),
+            )
+            .unwrap();
+
+        let grandchild = store.transactions.begin_child(&child);
+        assert_eq!(Some(String::from("2")), get(&grandchild, id));
+
+        set(&grandchild, "3");
+        store.transactions.commit(grandchild).unwrap();
+
+        // The child sees the grandchild's change, but the parent doesn't yet
+        assert_eq!(Some(String::from("3")), get(&child, id));
+        assert_eq!(Some(String::from("1")), get(&parent, id));
+
+        // Cancelling the child also discards the grandchild committed into it
+        store.transactions.cancel(child);
+
+        assert_eq!(Some(String::from("1")), get(&parent, id));
+        assert_eq!(None, get(&parent, added_id));
+        assert_eq!(Some(String::from("0")), get(&Transaction::none(), id));
+
+        // The parent can carry on changing the value after its child is cancelled
+        set(&parent, "4");
+        store.transactions.commit(parent).unwrap();
+
+        assert_eq!(Some(String::from("4")), get(&Transaction::none(), id));
+        assert_eq!(None, get(&Transaction::none(), added_id));
+    }
+
+    #[test]
+    fn transaction_value_store_cancelled_parent_discards_committed_child() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        store
+            .set(
+                &Transaction::none(),
+                id,
+                None::<Version>,
+                version,
+                String::from("0"),
+            )
+            .unwrap();
+
+        let parent = store.transactions.begin();
+        let child = store.transactions.begin_child(&parent);
+
+        store
+            .set(&child, id, Some(version), Version::new(), String::from("1"))
+            .unwrap();
+        store.transactions.commit(child).unwrap();
+
+        assert_eq!(
+            Some(String::from("1")),
+            store.get(&parent, id).map(|(_, value)| value)
+        );
+
+        store.transactions.cancel(parent);
+
+        assert_eq!(
+            Some((version, String::from("0"))),
+            store.get(&Transaction::none(), id)
+        );
+
+        // The value can be set again from its committed version
+        store
+            .set(
+                &Transaction::none(),
+                id,
+                Some(version),
+                Version::new(),
+                String::from("2"),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn transaction_value_store_get_all_during_transaction() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let (committed_id, staged_id) = (Id::new(), Id::new());
+
+        store
+            .set(
+                &Transaction::none(),
+                committed_id,
+                None::<Version>,
+                Version::new(),
+                String::from("committed"),
+            )
+            .unwrap();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                staged_id,
+                None::<Version>,
+                Version::new(),
+                String::from("staged"),
+            )
+            .unwrap();
+
+        let values = |transaction: &Transaction| {
+            let mut values: Vec<_> = store
+                .get_all(transaction, |_| true)
+                .map(|(_, value)| value)
+                .collect();
+            values.sort();
+
+            values
+        };
+
+        // The transaction matches its own staged value, but nobody else does
+        assert_eq!(vec!["committed", "staged"], values(&transaction));
+        assert_eq!(vec!["committed"], values(&Transaction::none()));
+
+        store.transactions.commit(transaction).unwrap();
+
+        assert_eq!(vec!["committed", "staged"], values(&Transaction::none()));
     }
 
     #[test]
@@ -383,7 +936,7 @@ mod tests {
             .unwrap();
         store.transactions.cancel(transaction);
 
-        assert!(store.get(id).is_none());
+        assert!(store.get(&Transaction::none(), id).is_none());
     }
 
     #[test]
@@ -404,7 +957,7 @@ mod tests {
                 String::from("1"),
             )
             .unwrap();
-        store.transactions.commit(transaction);
+        store.transactions.commit(transaction).unwrap();
 
         let old_version = version;
 
@@ -423,7 +976,7 @@ mod tests {
             store.transactions.cancel(transaction);
         }
 
-        let (current_version, current_value) = store.get(id).unwrap();
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
 
         assert_eq!(old_version, current_version);
         assert_eq!("1", current_value);
@@ -441,14 +994,53 @@ mod tests {
                 String::from("3"),
             )
             .unwrap();
-        store.transactions.commit(transaction);
+        store.transactions.commit(transaction).unwrap();
 
-        let (current_version, current_value) = store.get(id).unwrap();
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
 
         assert_eq!(version, current_version);
         assert_eq!("3", current_value);
     }
 
+    #[test]
+    fn transaction_value_store_cancel_set_ignores_old_version() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                Some(Version::new()),
+                Version::new(),
+                String::from("1"),
+            )
+            .unwrap();
+        store.transactions.cancel(transaction);
+
+        // The value was never committed, so it still doesn't exist
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                Some(Version::new()),
+                version,
+                String::from("2"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
+
+        assert_eq!(version, current_version);
+        assert_eq!("2", current_value);
+    }
+
     #[test]
     fn transaction_value_store_multi_set_get() {
         let transactions = TransactionStore::new();
@@ -483,13 +1075,13 @@ mod tests {
             )
             .unwrap();
 
-        assert!(store1.get(id1).is_none());
-        assert!(store2.get(id2).is_none());
+        assert!(store1.get(&Transaction::none(), id1).is_none());
+        assert!(store2.get(&Transaction::none(), id2).is_none());
 
-        transactions.commit(transaction);
+        transactions.commit(transaction).unwrap();
 
-        let (current_version1, current_value1) = store1.get(id1).unwrap();
-        let (current_version2, current_value2) = store2.get(id2).unwrap();
+        let (current_version1, current_value1) = store1.get(&Transaction::none(), id1).unwrap();
+        let (current_version2, current_value2) = store2.get(&Transaction::none(), id2).unwrap();
 
         assert_eq!(version1, current_version1);
         assert_eq!("a1", current_value1);
@@ -518,12 +1110,113 @@ mod tests {
         // An empty transaction doesn't need to be committed
         // The transaction store never sees it
 
-        let (current_version, current_value) = store.get(id).unwrap();
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
+
+        assert_eq!(version, current_version);
+        assert_eq!("1", current_value);
+    }
+
+    #[test]
+    fn transaction_value_store_remove_get() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("1"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        let transaction = store.transactions.begin();
+        store.remove(&transaction, id, version).unwrap();
+
+        // The removal isn't observable until it's committed
+        assert!(store.get(&Transaction::none(), id).is_some());
+
+        store.transactions.commit(transaction).unwrap();
+
+        assert!(store.get(&Transaction::none(), id).is_none());
+        assert_eq!(0, store.get_all(&Transaction::none(), |_| true).count());
+    }
+
+    #[test]
+    fn transaction_value_store_remove_cancel_get() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("1"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        let transaction = store.transactions.begin();
+        store.remove(&transaction, id, version).unwrap();
+        store.transactions.cancel(transaction);
+
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
 
         assert_eq!(version, current_version);
         assert_eq!("1", current_value);
     }
 
+    #[test]
+    fn transaction_value_store_remove_set_get() {
+        let store = TransactionValueStore::<String>::new(TransactionStore::new());
+
+        let id = Id::new();
+        let version = Version::new();
+
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("1"),
+            )
+            .unwrap();
+        store.remove(&transaction, id, version).unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        let version = Version::new();
+
+        // A removed value can be set again without knowing its old version
+        let transaction = store.transactions.begin();
+        store
+            .set(
+                &transaction,
+                id,
+                None::<Version>,
+                version,
+                String::from("2"),
+            )
+            .unwrap();
+        store.transactions.commit(transaction).unwrap();
+
+        let (current_version, current_value) = store.get(&Transaction::none(), id).unwrap();
+
+        assert_eq!(version, current_version);
+        assert_eq!("2", current_value);
+    }
+
     #[test]
     fn err_transaction_value_store_set_version_mismatch() {
         let store = TransactionValueStore::<String>::new(TransactionStore::new());
@@ -541,7 +1234,7 @@ mod tests {
                 String::from("1"),
             )
             .unwrap();
-        store.transactions.commit(transaction);
+        store.transactions.commit(transaction).unwrap();
 
         let transaction = store.transactions.begin();
 
@@ -554,7 +1247,10 @@ mod tests {
             String::from("2"),
         );
 
-        assert!(r.is_err());
+        let mismatch = r.unwrap_err().downcast::<VersionMismatch>().unwrap();
+
+        assert_eq!(None, mismatch.expected);
+        assert_eq!(Some(version), mismatch.actual);
     }
 
     #[test]
@@ -574,7 +1270,7 @@ mod tests {
                 String::from("1"),
             )
             .unwrap();
-        store.transactions.commit(transaction);
+        store.transactions.commit(transaction).unwrap();
 
         let transaction1 = store.transactions.begin();
 
@@ -601,4 +1297,53 @@ mod tests {
 
         assert!(r.is_err());
     }
+
+    #[cfg(feature = "concurrent")]
+    #[test]
+    fn concurrent_store_writers_to_different_values_do_not_block() {
+        const THREADS: usize = 8;
+        const VALUES: usize = 200;
+
+        let store = TransactionValueStore::<usize>::concurrent(TransactionStore::new());
+
+        let ids: Vec<Vec<Id>> = (0..THREADS)
+            .map(|_| (0..VALUES).map(|_| Id::new()).collect())
+            .collect();
+
+        std::thread::scope(|scope| {
+            for ids in &ids {
+                let store = &store;
+
+                scope.spawn(move || {
+                    for (i, id) in ids.iter().enumerate() {
+                        // Set each value, then update it from the version that was just set
+                        let version = Version::new();
+
+                        let transaction = store.transactions.begin();
+                        store
+                            .set(&transaction, *id, None::<Version>, version, i)
+                            .unwrap();
+                        store.transactions.commit(transaction).unwrap();
+
+                        let transaction = store.transactions.begin();
+                        store
+                            .set(&transaction, *id, Some(version), Version::new(), i + 1)
+                            .unwrap();
+                        store.transactions.commit(transaction).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            THREADS * VALUES,
+            store.get_all(&Transaction::none(), |_| true).count()
+        );
+
+        for ids in &ids {
+            for (i, id) in ids.iter().enumerate() {
+                assert_eq!(i + 1, store.get(&Transaction::none(), *id).unwrap().1);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Mutex,
+};
+
+use crate::store::lock;
+
+/** The length and checksum that come before each record. */
+const HEADER_LEN: usize = 8;
+
+/**
+An append-only log of records in a file.
+
+Each record is framed by its length and a checksum of its contents. A record that was only
+partly written when the process stopped, or that's been corrupted since, fails its checksum.
+Reading stops at the first record like that, since nothing after it can be trusted.
+*/
+pub struct Wal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Wal {
+    /**
+    Open the log at `path` for appending, creating it if it doesn't exist.
+
+    If the log ends with a torn record then it's cut off, so new records follow the last
+    complete one.
+    */
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+
+        let (_, len) = read_records(&path)?;
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.set_len(len)?;
+
+        Ok(Wal {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /**
+    Read the complete records in the log at `path`, in the order they were appended.
+
+    A log that doesn't exist has no records.
+    */
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<Vec<u8>>> {
+        read_records(path.as_ref()).map(|(records, _)| records)
+    }
+
+    /** Read the complete records in this log. */
+    pub fn records(&self) -> io::Result<Vec<Vec<u8>>> {
+        let _file = lock::lock(&self.file);
+
+        Self::read(&self.path)
+    }
+
+    /**
+    Append a record to the log.
+
+    The record is durable once this returns.
+    */
+    pub fn append(&self, record: &[u8]) -> io::Result<()> {
+        let frame = frame(record)?;
+
+        let mut file = lock::lock(&self.file);
+
+        file.write_all(&frame)?;
+        file.sync_data()
+    }
+
+    /**
+    Replace every record in the log.
+
+    The new records are written to a temporary file next to the log first and then renamed
+    over it, so a crash part way through leaves either the old log or the new one.
+    */
+    pub fn replace<R>(&self, records: impl IntoIterator<Item = R>) -> io::Result<()>
+    where
+        R: AsRef<[u8]>,
+    {
+        let mut file = lock::lock(&self.file);
+
+        let mut temp_path = self.path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut temp = File::create(&temp_path)?;
+        for record in records {
+            temp.write_all(&frame(record.as_ref())?)?;
+        }
+        temp.sync_all()?;
+
+        fs::rename(&temp_path, &self.path)?;
+
+        *file = OpenOptions::new().append(true).open(&self.path)?;
+
+        Ok(())
+    }
+}
+
+fn frame(record: &[u8]) -> io::Result<Vec<u8>> {
+    let len = u32::try_from(record.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the record is too large"))?;
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + record.len());
+
+    frame.extend_from_slice(&len.to_le_bytes());
+    frame.extend_from_slice(&checksum(record).to_le_bytes());
+    frame.extend_from_slice(record);
+
+    Ok(frame)
+}
+
+/** Read the complete records in a log, along with the length of the log that they cover. */
+fn read_records(path: &Path) -> io::Result<(Vec<Vec<u8>>, u64)> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((vec![], 0)),
+        Err(err) => return Err(err),
+    };
+
+    let mut records = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        match unframe(&bytes[offset..]) {
+            Some(record) => {
+                offset += HEADER_LEN + record.len();
+                records.push(record.to_vec());
+            }
+            None => {
+                warn!(
+                    "skipping a torn record at byte {} of `{}`",
+                    offset,
+                    path.display()
+                );
+
+                break;
+            }
+        }
+    }
+
+    Ok((records, offset as u64))
+}
+
+/** Get the record at the start of `bytes`, if it's complete. */
+fn unframe(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..HEADER_LEN)?;
+
+    let len = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+    let expected = u32::from_le_bytes(header[4..].try_into().ok()?);
+
+    let record = bytes.get(HEADER_LEN..HEADER_LEN.checked_add(len)?)?;
+
+    if checksum(record) == expected {
+        Some(record)
+    } else {
+        None
+    }
+}
+
+/** A CRC-32 of `bytes`. */
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("shop-wal-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn checksum_matches_crc32() {
+        assert_eq!(0xCBF4_3926, checksum(b"123456789"));
+    }
+
+    #[test]
+    fn records_survive_reopening() {
+        let path = temp_path();
+
+        {
+            let wal = Wal::open(&path).unwrap();
+
+            wal.append(b"first").unwrap();
+            wal.append(b"").unwrap();
+            wal.append(b"third").unwrap();
+        }
+
+        let wal = Wal::open(&path).unwrap();
+        wal.append(b"fourth").unwrap();
+
+        assert_eq!(
+            vec![
+                b"first".to_vec(),
+                vec![],
+                b"third".to_vec(),
+                b"fourth".to_vec()
+            ],
+            Wal::read(&path).unwrap()
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn torn_record_is_skipped_and_cut_off() {
+        let path = temp_path();
+
+        {
+            let wal = Wal::open(&path).unwrap();
+
+            wal.append(b"first").unwrap();
+            wal.append(b"second").unwrap();
+        }
+
+        // Simulate a crash part way through writing the second record
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        assert_eq!(vec![b"first".to_vec()], Wal::read(&path).unwrap());
+
+        // New records follow the last complete one rather than the torn one
+        let wal = Wal::open(&path).unwrap();
+        wal.append(b"third").unwrap();
+
+        assert_eq!(
+            vec![b"first".to_vec(), b"third".to_vec()],
+            Wal::read(&path).unwrap()
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_record_is_skipped_with_everything_after_it() {
+        let path = temp_path();
+
+        {
+            let wal = Wal::open(&path).unwrap();
+
+            wal.append(b"first").unwrap();
+            wal.append(b"second").unwrap();
+            wal.append(b"third").unwrap();
+        }
+
+        let mut bytes = fs::read(&path).unwrap();
+        let second = HEADER_LEN + b"first".len() + HEADER_LEN;
+        bytes[second] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(vec![b"first".to_vec()], Wal::read(&path).unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replace_swaps_every_record() {
+        let path = temp_path();
+
+        let wal = Wal::open(&path).unwrap();
+
+        wal.append(b"first").unwrap();
+        wal.append(b"second").unwrap();
+
+        wal.replace([b"compacted"]).unwrap();
+        wal.append(b"third").unwrap();
+
+        assert_eq!(
+            vec![b"compacted".to_vec(), b"third".to_vec()],
+            wal.records().unwrap()
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
\ No newline at end of file
@@ -71,4 +71,33 @@ async fn set_get() {
             .expect("invalid order")
             .len()
     );
+}
+
+#[async_test]
+async fn errors_only_return_a_client_message() {
+    let app = Client::untracked(shop::api::init())
+        .await
+        .expect("invalid app");
+
+    let order_id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    let product_id = "0fc4f6c2-4ee5-4f3b-a7bd-6f3d1d3d6d5c";
+
+    let post = app
+        .post(format!("/orders/{}/products/{}", order_id, product_id))
+        .json(&json!({
+            "quantity": 1
+        }))
+        .dispatch()
+        .await;
+
+    assert_eq!(Status::NotFound, post.status());
+    let err: serde_json::Value =
+        serde_json::from_str(&post.into_string().await.expect("missing body"))
+            .expect("invalid value");
+
+    // The command's context is only logged
+    assert_eq!(
+        json!({ "msg": format!("order `{}` not found", order_id) }),
+        err
+    );
 }
\ No newline at end of file
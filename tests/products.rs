@@ -4,11 +4,95 @@ extern crate rocket;
 #[macro_use]
 extern crate serde_json;
 
+use std::{
+    collections::HashMap,
+    ops::ControlFlow,
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    vec::IntoIter,
+};
+
 use rocket::{
     http::Status,
     local::asynchronous::Client,
 };
 
+use shop::{
+    domain::{
+        infra::{
+            Command,
+            Currency,
+            Flush,
+            Query,
+        },
+        products::*,
+        App,
+        Error,
+    },
+    store::Transaction,
+};
+
+/** A product store outside the domain that counts how many products are set in it. */
+#[derive(Default)]
+struct CountingStore {
+    products: Mutex<HashMap<ProductId, Product>>,
+    sets: AtomicUsize,
+}
+
+impl ProductStore for CountingStore {
+    fn get_product(&self, _: &Transaction, id: ProductId) -> Result<Option<Product>, Error> {
+        Ok(self.products.lock().unwrap().get(&id).cloned())
+    }
+
+    fn set_product(&self, _: &Transaction, product: Product) -> Result<(), Error> {
+        self.sets.fetch_add(1, Ordering::SeqCst);
+
+        self.products
+            .lock()
+            .unwrap()
+            .insert(product.to_data().id, product);
+
+        Ok(())
+    }
+
+    fn scan(&self, f: &mut dyn FnMut(&ProductData) -> ControlFlow<()>) -> Result<(), Error> {
+        for product in self.products.lock().unwrap().values() {
+            if f(product.to_data()).is_break() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ProductStoreFilter for CountingStore {
+    fn filter(
+        &self,
+        _: &Transaction,
+        predicate: &dyn Fn(&ProductData) -> bool,
+    ) -> Result<IntoIter<ProductData>, Error> {
+        Ok(self
+            .products
+            .lock()
+            .unwrap()
+            .values()
+            .map(|product| product.to_data())
+            .filter(|product| predicate(product))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter())
+    }
+}
+
+impl Flush for CountingStore {}
+
 #[async_test]
 async fn set_get() {
     let app = Client::untracked(shop::api::init())
@@ -43,4 +127,47 @@ async fn set_get() {
         "A new product",
         product.as_object().expect("invalid product")["title"]
     );
+}
+
+#[async_test]
+async fn commands_use_a_custom_product_store() {
+    let store = Arc::new(CountingStore::default());
+
+    let app = App::new().with_product_store({
+        let store = store.clone();
+        move |_| store.clone()
+    });
+
+    let id = ProductId::new();
+
+    app.transaction(|resolver| async move {
+        resolver
+            .create_product_command()
+            .execute(CreateProduct {
+                id,
+                title: "A new product".into(),
+                price: Currency::usd(123),
+            })
+            .await
+    })
+    .await
+    .expect("failed to create product");
+
+    let product = app
+        .transaction(|resolver| async move {
+            resolver
+                .get_product_query()
+                .execute(GetProduct {
+                    id,
+                    include_inactive: false,
+                })
+                .await
+        })
+        .await
+        .expect("failed to get product")
+        .expect("missing product");
+
+    assert_eq!("A new product", product.to_data().title);
+    assert_eq!(1, store.sets.load(Ordering::SeqCst));
+    assert!(store.products.lock().unwrap().contains_key(&id));
 }
\ No newline at end of file